@@ -1,5 +1,5 @@
 use criterion::{black_box, BenchmarkId, BenchmarkGroup, Criterion, Throughput, measurement::Measurement};
-use gif::Decoder;
+use gif::{Decoder, SliceDecoder};
 
 fn read_image(image: &[u8]) -> Option<Vec<u8>> {
     let decoder = Decoder::new(black_box(image));
@@ -19,6 +19,74 @@ fn read_metadata(image: &[u8]) {
     decoder.unwrap();
 }
 
+fn discard_frames(image: &[u8]) -> usize {
+    let mut decoder = Decoder::new(black_box(image)).unwrap();
+    let mut frames = 0;
+    while decoder.next_frame_info().unwrap().is_some() {
+        frames += 1;
+    }
+    frames
+}
+
+fn read_all_frames(image: &[u8]) -> usize {
+    let mut decoder = Decoder::new(black_box(image)).unwrap();
+    let mut frames = 0;
+    while decoder.read_next_frame().unwrap().is_some() {
+        frames += 1;
+    }
+    frames
+}
+
+fn read_all_frames_from_slice(image: &[u8]) -> usize {
+    let mut decoder = SliceDecoder::new(black_box(image)).unwrap();
+    let mut frames = 0;
+    while decoder.read_next_frame().unwrap().is_some() {
+        frames += 1;
+    }
+    frames
+}
+
+/// Returns at most `chunk` bytes per read, like an unbuffered pipe or serial port
+struct SmallReads<'a> {
+    data: &'a [u8],
+    chunk: usize,
+}
+
+impl std::io::Read for SmallReads<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.chunk.min(buf.len()).min(self.data.len());
+        buf[..n].copy_from_slice(&self.data[..n]);
+        self.data = &self.data[n..];
+        Ok(n)
+    }
+}
+
+fn read_all_frames_in_chunks(image: &[u8], chunk: usize) -> usize {
+    let mut decoder = Decoder::new(SmallReads { data: black_box(image), chunk }).unwrap();
+    let mut frames = 0;
+    while decoder.read_next_frame().unwrap().is_some() {
+        frames += 1;
+    }
+    frames
+}
+
+/// Reads the way the decoder did before it staged small reads, which is still what it does for a
+/// `BufRead` that returns only a few bytes
+fn read_all_frames_in_chunks_unstaged(image: &[u8], chunk: usize) -> usize {
+    let reader = std::io::BufReader::new(SmallReads { data: black_box(image), chunk });
+    let mut decoder = Decoder::new_buffered(reader).unwrap();
+    let mut frames = 0;
+    while decoder.read_next_frame().unwrap().is_some() {
+        frames += 1;
+    }
+    frames
+}
+
+fn seek_and_read(decoder: &mut Decoder<std::io::Cursor<&[u8]>>, frame: usize) {
+    decoder.seek_to_frame(black_box(frame)).unwrap();
+    black_box(decoder.read_next_frame().unwrap());
+}
+
 fn main() {
     struct BenchDef {
         data: &'static [u8],
@@ -77,5 +145,77 @@ fn main() {
 
     group.finish();
 
+    let mut group = c.benchmark_group("discard-frames");
+    for (id, image) in [
+        ("photo.gif", &include_bytes!("photo.gif")[..]),
+        ("gifplayer-muybridge.gif", &include_bytes!("../tests/samples/gifplayer-muybridge.gif")[..]),
+    ] {
+        group.throughput(Throughput::Bytes(image.len() as u64));
+        group.bench_with_input(BenchmarkId::new("next_frame_info", id), image, |b, input| {
+            b.iter(|| discard_frames(input));
+        });
+        // The baseline the discard path is compared to
+        group.bench_with_input(BenchmarkId::new("memcpy", id), image, |b, input| {
+            b.iter(|| black_box(input).to_vec());
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("read-from-slice");
+    for (id, image) in [
+        ("photo.gif", &include_bytes!("photo.gif")[..]),
+        ("gifplayer-muybridge.gif", &include_bytes!("../tests/samples/gifplayer-muybridge.gif")[..]),
+    ] {
+        group.sample_size(20).throughput(Throughput::Bytes(image.len() as u64));
+        group.bench_with_input(BenchmarkId::new("Decoder", id), image, |b, input| {
+            b.iter(|| read_all_frames(input));
+        });
+        group.bench_with_input(BenchmarkId::new("SliceDecoder", id), image, |b, input| {
+            b.iter(|| read_all_frames_from_slice(input));
+        });
+    }
+    // Without decoding any pixels, the overhead of reading the input dominates
+    let image: &[u8] = include_bytes!("note.gif");
+    group.bench_with_input("Decoder/extract-metadata-note", image, |b, input| {
+        b.iter(|| read_metadata(input));
+    });
+    group.bench_with_input("SliceDecoder/extract-metadata-note", image, |b, input| {
+        b.iter(|| SliceDecoder::new(black_box(input)).unwrap());
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("small-reads");
+    for (id, image) in [
+        ("photo.gif", &include_bytes!("photo.gif")[..]),
+        ("gifplayer-muybridge.gif", &include_bytes!("../tests/samples/gifplayer-muybridge.gif")[..]),
+    ] {
+        group.sample_size(20).throughput(Throughput::Bytes(image.len() as u64));
+        // 1 and 4 bytes per read are compared to the same reads without staging. Whole reads are
+        // the time decoding takes with any read size, which staging can't go below.
+        for chunk in [usize::MAX, 1, 4] {
+            let name = if chunk == usize::MAX { "whole".to_string() } else { chunk.to_string() };
+            group.bench_with_input(BenchmarkId::new(id, name), image, |b, input| {
+                b.iter(|| read_all_frames_in_chunks(input, chunk));
+            });
+            if chunk != usize::MAX {
+                group.bench_with_input(BenchmarkId::new(id, format!("{chunk}-unstaged")), image, |b, input| {
+                    b.iter(|| read_all_frames_in_chunks_unstaged(input, chunk));
+                });
+            }
+        }
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("seek-to-frame");
+    let image: &[u8] = include_bytes!("../tests/samples/gifplayer-muybridge.gif");
+    let mut decoder = Decoder::new(std::io::Cursor::new(image)).unwrap();
+    let num_frames = decoder.build_frame_index().unwrap().len();
+    for frame in [0, num_frames / 2, num_frames - 1] {
+        group.bench_with_input(BenchmarkId::new("gifplayer-muybridge.gif", frame), &frame, |b, &frame| {
+            b.iter(|| seek_and_read(&mut decoder, frame));
+        });
+    }
+    group.finish();
+
     c.final_summary();
 }
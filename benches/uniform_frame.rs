@@ -0,0 +1,31 @@
+use criterion::{black_box, BenchmarkId, Criterion, Throughput};
+use gif::{Encoder, Frame};
+
+fn encode(width: u16, height: u16, pixels: &[u8]) -> Vec<u8> {
+    let mut encoder = Encoder::new(Vec::new(), width, height, &[0, 0, 0, 0xFF, 0xFF, 0xFF]).unwrap();
+    encoder.write_frame(&Frame::from_indexed_pixels(width, height, pixels.to_vec(), None)).unwrap();
+    encoder.into_inner().unwrap()
+}
+
+fn main() {
+    let mut c = Criterion::default().configure_from_args();
+    let mut group = c.benchmark_group("uniform_frame");
+
+    for (width, height) in [(256, 256), (1920, 1080), (4096, 4096)] {
+        let len = usize::from(width) * usize::from(height);
+        // A blank frame, and one with a single other pixel, which takes the general path
+        let blank = vec![0; len];
+        let mut almost_blank = blank.clone();
+        almost_blank[len - 1] = 1;
+
+        group
+            .sample_size(20)
+            .throughput(Throughput::Elements(len as u64));
+        group.bench_with_input(BenchmarkId::new("blank", len), &blank, |b, pixels| {
+            b.iter(|| encode(width, height, black_box(pixels)));
+        });
+        group.bench_with_input(BenchmarkId::new("almost_blank", len), &almost_blank, |b, pixels| {
+            b.iter(|| encode(width, height, black_box(pixels)));
+        });
+    }
+}
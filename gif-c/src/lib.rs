@@ -265,6 +265,7 @@ impl<'a> ErrPtr<'a> {
         self.catch_err(result.map_err(|_| err))
     }
 
+    /// Turns a panic into an error. With `panic = "abort"`, a panic aborts before it gets here.
     fn catch_unwind<F, T>(self, then: F) -> Option<T>
     where
         F: FnOnce() -> Result<T, c_int> + panic::UnwindSafe,
@@ -1,9 +1,12 @@
 use alloc::{borrow::Cow, vec::Vec};
+use core::fmt;
+use no_std_io::error;
 #[cfg(feature = "color_quant")]
 use std::collections::{HashMap, HashSet};
 
 /// Disposal method
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum DisposalMethod {
     /// `StreamingDecoder` is not required to take any action.
@@ -17,17 +20,189 @@ pub enum DisposalMethod {
 }
 
 impl DisposalMethod {
+    /// Position of the disposal method in the packed field of the graphic control extension
+    const SHIFT: u8 = 2;
+    /// Bits of the disposal method in the packed field of the graphic control extension
+    const MASK: u8 = 0b111 << Self::SHIFT;
+
     /// Converts `u8` to `Option<Self>`
     #[must_use]
     pub const fn from_u8(n: u8) -> Option<Self> {
-        match n {
-            0 => Some(Self::Any),
-            1 => Some(Self::Keep),
-            2 => Some(Self::Background),
-            3 => Some(Self::Previous),
-            _ => None,
+        if n > Self::MASK >> Self::SHIFT {
+            return None;
+        }
+        match Self::from_bits(n << Self::SHIFT) {
+            Ok(method) => Some(method),
+            Err(_) => None,
+        }
+    }
+
+    /// Reads the disposal method from the packed field of a graphic control extension.
+    ///
+    /// The other flags in `bits` are ignored. Use [`ReservedDisposal::lenient`] to decode
+    /// reserved values the way decoders commonly do.
+    pub const fn from_bits(bits: u8) -> Result<Self, ReservedDisposal> {
+        match (bits & Self::MASK) >> Self::SHIFT {
+            0 => Ok(Self::Any),
+            1 => Ok(Self::Keep),
+            2 => Ok(Self::Background),
+            3 => Ok(Self::Previous),
+            value => Err(ReservedDisposal(value)),
         }
     }
+
+    /// The bits of the disposal method in the packed field of a graphic control extension,
+    /// with all other flags unset
+    #[must_use]
+    pub const fn into_bits(self) -> u8 {
+        let value = match self {
+            Self::Any => 0,
+            Self::Keep => 1,
+            Self::Background => 2,
+            Self::Previous => 3,
+        };
+        value << Self::SHIFT
+    }
+}
+
+/// A disposal method that is reserved by the GIF specification, see [`DisposalMethod::from_bits`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ReservedDisposal(u8);
+
+impl ReservedDisposal {
+    /// The reserved value, from 4 to 7
+    #[must_use]
+    pub const fn value(self) -> u8 {
+        self.0
+    }
+
+    /// The disposal method that decoders commonly use instead.
+    ///
+    /// Some encoders write 4 to restore to the previous frame, so it becomes
+    /// [`DisposalMethod::Previous`]. The other values become [`DisposalMethod::Any`].
+    #[must_use]
+    pub const fn lenient(self) -> DisposalMethod {
+        match self.0 {
+            4 => DisposalMethod::Previous,
+            _ => DisposalMethod::Any,
+        }
+    }
+}
+
+impl fmt::Display for ReservedDisposal {
+    #[cold]
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "the disposal method {} is reserved", self.0)
+    }
+}
+
+impl error::Error for ReservedDisposal {}
+
+/// How a frame's delay becomes a duration, see [`Frame::effective_delay_ms`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DelayPolicy {
+    /// The delay as it is stored, in units of 10 ms, also if it is 0.
+    Spec,
+    /// Delays of 0 and 1 are 100 ms, like in browsers, because such animations would otherwise
+    /// run much faster than their authors have seen them.
+    Browser,
+}
+
+/// Position and size of a frame on the logical screen, in pixels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rect {
+    /// Offset from the left border of the canvas.
+    pub left: u16,
+    /// Offset from the top border of the canvas.
+    pub top: u16,
+    /// Width of the frame.
+    pub width: u16,
+    /// Height of the frame.
+    pub height: u16,
+}
+
+impl Rect {
+    /// Returns `true` if the rectangle lies completely within a `width`×`height` canvas.
+    #[must_use]
+    pub fn fits_within(&self, width: u16, height: u16) -> bool {
+        validate_frame_in_screen(*self, (width, height), FrameConsistency::Error).is_ok()
+    }
+}
+
+impl From<&Frame<'_>> for Rect {
+    fn from(frame: &Frame<'_>) -> Self {
+        Self { left: frame.left, top: frame.top, width: frame.width, height: frame.height }
+    }
+}
+
+/// How frames that don't fit within the logical screen are handled, see
+/// [`DecodeOptions::set_frame_consistency`](crate::DecodeOptions::set_frame_consistency)
+/// and [`validate_frame_in_screen`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameConsistency {
+    /// Frames are decoded with their size and offset as they are.
+    Ignore,
+    /// Decoding fails at the descriptor of the frame.
+    Error,
+    /// The size of the frame is reduced to the part within the screen, and the pixels outside of
+    /// it are discarded while decoding.
+    Clamp,
+}
+
+impl Default for FrameConsistency {
+    fn default() -> Self {
+        Self::Ignore
+    }
+}
+
+/// A frame that doesn't fit within the logical screen, see [`validate_frame_in_screen`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GeometryError {
+    /// Position and size of the frame.
+    pub rect: Rect,
+    /// Width and height of the logical screen.
+    pub screen: (u16, u16),
+}
+
+impl fmt::Display for GeometryError {
+    #[cold]
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { rect, screen } = self;
+        write!(fmt, "frame ({}x{} at {},{}) is outside of the {}x{} screen",
+            rect.width, rect.height, rect.left, rect.top, screen.0, screen.1)
+    }
+}
+
+impl error::Error for GeometryError {}
+
+/// Checks whether a frame lies within the logical screen, by the rule that the decoder and the
+/// encoder share.
+///
+/// A frame may end exactly at the right or bottom border of the `(width, height)` screen. Its
+/// last column is `left + width - 1` then, which is the last column of the screen, so such a
+/// frame is within the screen and there is no stricter rule to choose: rejecting it would reject
+/// every frame that covers the whole screen. For the same reason, the policy is the
+/// [`FrameConsistency`] of the decoder, and there is no separate policy for edges.
+///
+/// Returns the part of the frame that is shown: the whole frame if it fits or `policy` is
+/// [`FrameConsistency::Ignore`], and the part within the screen, which may be empty, with
+/// [`FrameConsistency::Clamp`]. With [`FrameConsistency::Error`], a frame that doesn't fit is
+/// an error.
+pub fn validate_frame_in_screen(rect: Rect, screen: (u16, u16), policy: FrameConsistency) -> Result<Rect, GeometryError> {
+    let (width, height) = screen;
+    let fits = u32::from(rect.left) + u32::from(rect.width) <= u32::from(width)
+        && u32::from(rect.top) + u32::from(rect.height) <= u32::from(height);
+    match policy {
+        _ if fits => Ok(rect),
+        FrameConsistency::Ignore => Ok(rect),
+        FrameConsistency::Error => Err(GeometryError { rect, screen }),
+        FrameConsistency::Clamp => Ok(Rect {
+            width: rect.width.min(width.saturating_sub(rect.left)),
+            height: rect.height.min(height.saturating_sub(rect.top)),
+            ..rect
+        }),
+    }
 }
 
 /// Known GIF block labels.
@@ -130,10 +305,84 @@ impl Extension {
     }
 }
 
+/// The logical screen descriptor, which follows the signature at the start of a file
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct ScreenDescriptor {
+    /// Width of the logical screen.
+    pub width: u16,
+    /// Height of the logical screen.
+    pub height: u16,
+    /// True if a global palette follows the descriptor.
+    pub has_global_palette: bool,
+    /// Bits per primary color of the original image, from 1 to 8.
+    pub color_resolution: u8,
+    /// True if the global palette is sorted by decreasing importance.
+    pub sort_flag: bool,
+    /// Index of the background color in the global palette.
+    pub bg_index: u8,
+    /// Pixel aspect ratio as stored. If it isn't 0, the width of a pixel divided by its height
+    /// is `(aspect_ratio + 15) / 64`.
+    pub aspect_ratio: u8,
+}
+
+/// Fields of a graphic control extension, which applies to the frame after it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GraphicControl {
+    /// Disposal method. Reserved values are decoded with [`ReservedDisposal::lenient`].
+    pub disposal: DisposalMethod,
+    /// True if the frame needs user input to be displayed.
+    pub needs_user_input: bool,
+    /// Transparent index, if the flag for it is set.
+    pub transparent_index: Option<u8>,
+    /// Delay in units of 10 ms.
+    pub delay_cs: u16,
+    /// Packed fields as they are in the file, including the reserved bits.
+    pub flags: u8,
+}
+
+impl GraphicControl {
+    pub(crate) fn from_extension(flags: u8, delay: u16, transparent: u8) -> Self {
+        Self {
+            disposal: DisposalMethod::from_bits(flags).unwrap_or_else(ReservedDisposal::lenient),
+            needs_user_input: flags & 0b10 != 0,
+            transparent_index: (flags & 1 != 0).then(|| transparent),
+            delay_cs: delay,
+            flags,
+        }
+    }
+}
+
+/// What the bytes of [`Frame::buffer`] are
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PixelFormat {
+    /// Not known, for example because the frame has been constructed by hand.
+    Unknown,
+    /// One palette index per pixel, which [`Encoder::write_frame`](crate::Encoder::write_frame)
+    /// takes.
+    Indexed,
+    /// Three bytes per pixel, red, green and blue.
+    Rgb,
+    /// Four bytes per pixel, red, green, blue and alpha.
+    Rgba,
+    /// The minimum LZW code size, followed by the LZW data, which
+    /// [`Encoder::write_lzw_pre_encoded_frame`](crate::Encoder::write_lzw_pre_encoded_frame)
+    /// takes.
+    Lzw,
+}
+
+impl Default for PixelFormat {
+    fn default() -> Self {
+        Self::Unknown
+    }
+}
+
 /// A GIF frame
 #[derive(Debug, Clone)]
 pub struct Frame<'a> {
     /// Frame delay in units of 10 ms.
+    ///
+    /// Players show frames with very short delays longer, see [`Frame::effective_delay_ms`].
     pub delay: u16,
     /// Disposal method.
     pub dispose: DisposalMethod,
@@ -153,9 +402,33 @@ pub struct Frame<'a> {
     pub interlaced: bool,
     /// Frame local color palette if available.
     pub palette: Option<Vec<u8>>,
+    /// The encoder writes the palette of
+    /// [`Encoder::set_shared_local_palette`](crate::Encoder::set_shared_local_palette) as the
+    /// local palette of this frame, if `palette` is `None`. Without it, a frame whose `palette`
+    /// is `None` uses the global palette.
+    ///
+    /// The decoder doesn't set it, since it gives each frame a copy of its local palette.
+    pub shared_palette: bool,
+    /// The graphic control extension before the frame, if there is one. `delay`, `dispose`,
+    /// `transparent` and `needs_user_input` have been taken from it.
+    ///
+    /// The encoder ignores it, and writes those fields instead.
+    pub graphic_control: Option<GraphicControl>,
+    /// Packed fields of the image descriptor, as they have been decoded. It's 0 for frames that
+    /// haven't been decoded.
+    ///
+    /// The encoder keeps the sort flag `0b0010_0000` of frames with a local palette, and
+    /// computes the other fields.
+    pub descriptor_flags: u8,
     /// Buffer containing the image data.
     /// Only indices unless configured differently.
     pub buffer: Cow<'a, [u8]>,
+    /// What `buffer` holds. The decoder and the constructors of `Frame` set it, and it's
+    /// [`PixelFormat::Unknown`] otherwise.
+    ///
+    /// The encoder and [`FrameDecoder`](crate::streaming_decoder::FrameDecoder) refuse frames
+    /// that are known to hold something else than what they take.
+    pub pixel_format: PixelFormat,
 }
 
 impl Default for Frame<'_> {
@@ -171,9 +444,95 @@ impl Default for Frame<'_> {
             height: 0,
             interlaced: false,
             palette: None,
+            shared_palette: false,
+            graphic_control: None,
+            descriptor_flags: 0,
             buffer: Cow::Borrowed(&[]),
+            pixel_format: PixelFormat::Unknown,
+        }
+    }
+}
+
+/// Number of entries at the end of an RGB palette that are exactly `(0, 0, 0)`
+pub(crate) fn palette_padding(palette: &[u8]) -> usize {
+    palette.chunks_exact(3).rev().take_while(|color| *color == [0; 3]).count()
+}
+
+impl Frame<'_> {
+    /// How long the frame is shown in milliseconds, according to `policy`.
+    ///
+    /// `delay` keeps the value as it is stored in the file.
+    #[must_use]
+    pub fn effective_delay_ms(&self, policy: DelayPolicy) -> u32 {
+        match (policy, self.delay) {
+            (DelayPolicy::Browser, 0 | 1) => 100,
+            (_, delay) => u32::from(delay) * 10,
         }
     }
+
+    /// True if the frame has no local palette, so its indices refer to the global palette
+    #[must_use]
+    pub fn uses_global_palette(&self) -> bool {
+        self.palette.is_none()
+    }
+
+    /// True if the image descriptor declares the local palette sorted by decreasing importance.
+    /// It's `false` for frames without a local palette.
+    #[must_use]
+    pub fn palette_sorted(&self) -> bool {
+        self.descriptor_flags & 0b1010_0000 == 0b1010_0000
+    }
+
+    /// Number of black entries at the end of the local palette, which an encoder may have added
+    /// only to round the palette up to a power of two. It's `0` for frames without a local
+    /// palette.
+    #[must_use]
+    pub fn palette_padding(&self) -> usize {
+        self.palette.as_deref().map_or(0, palette_padding)
+    }
+
+    /// Number of colors of the local palette that the image descriptor declares, or `None` if it
+    /// declares none. A decoded `palette` has exactly this many colors.
+    #[must_use]
+    pub fn declared_palette_len(&self) -> Option<usize> {
+        if self.descriptor_flags & 0b1000_0000 != 0 {
+            Some(2 << (self.descriptor_flags & 0b0000_0111))
+        } else {
+            None
+        }
+    }
+
+    /// Number of bytes of the frame with one palette index per pixel, or `None` if it doesn't
+    /// fit in `usize`
+    #[must_use]
+    pub fn required_bytes_indexed(&self) -> Option<usize> {
+        buffer_len(self.width, self.height, 1)
+    }
+
+    /// Number of bytes of the frame in RGBA format, or `None` if it doesn't fit in `usize`.
+    ///
+    /// On 32-bit platforms, this is `None` for the largest frames.
+    #[must_use]
+    pub fn required_bytes_rgba(&self) -> Option<usize> {
+        buffer_len(self.width, self.height, 4)
+    }
+
+    /// Number of bytes of the frame in RGB format, or `None` if it doesn't fit in `usize`.
+    #[must_use]
+    pub fn required_bytes_rgb(&self) -> Option<usize> {
+        buffer_len(self.width, self.height, 3)
+    }
+
+    /// Rows of a frame with one palette index per pixel, from the top, the inverse of
+    /// [`Frame::from_rows`].
+    ///
+    /// If the buffer has fewer than `width * height` pixels, the rows end with the last complete
+    /// one.
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        let width = usize::from(self.width);
+        let buffer: &[u8] = &self.buffer;
+        (0..usize::from(self.height)).map_while(move |row| buffer.get(row * width..(row + 1) * width))
+    }
 }
 
 impl Frame<'static> {
@@ -194,6 +553,24 @@ impl Frame<'static> {
         Frame::from_rgba_speed(width, height, pixels, 1)
     }
 
+    /// Creates a frame from pixels in RGBA format, placed at `left` and `top` on the canvas.
+    ///
+    /// Otherwise the same as [`Frame::from_rgba`].
+    ///
+    /// # Panics:
+    /// *   If the length of pixels does not equal `width * height * 4`.
+    /// *   If `left + width` or `top + height` is larger than `u16::MAX`.
+    #[cfg(feature = "color_quant")]
+    #[track_caller]
+    pub fn from_rgba_at(left: u16, top: u16, width: u16, height: u16, pixels: &mut [u8]) -> Self {
+        assert_edges_fit(left, top, width, height);
+        Frame {
+            left,
+            top,
+            ..Frame::from_rgba(width, height, pixels)
+        }
+    }
+
     /// Creates a frame from pixels in RGBA format.
     ///
     /// `speed` is a value in the range [1, 30].
@@ -211,7 +588,7 @@ impl Frame<'static> {
     #[cfg(feature = "color_quant")]
     #[track_caller]
     pub fn from_rgba_speed(width: u16, height: u16, pixels: &mut [u8], speed: i32) -> Self {
-        assert_eq!(width as usize * height as usize * 4, pixels.len(), "Too much or too little pixel data for the given width and height to create a GIF Frame");
+        assert_eq!(buffer_len(width, height, 4), Some(pixels.len()), "Too much or too little pixel data for the given width and height to create a GIF Frame");
         assert!(speed >= 1 && speed <= 30, "speed needs to be in the range [1, 30]");
         let mut transparent = None;
         for pix in pixels.chunks_exact_mut(4) {
@@ -236,6 +613,7 @@ impl Frame<'static> {
                     buffer: Cow::Owned(pixels.chunks_exact(4).map(|pix| nq.index_of(pix) as u8).collect()),
                     palette: Some(nq.color_map_rgb()),
                     transparent: transparent.map(|t| nq.index_of(&t) as u8),
+                    pixel_format: PixelFormat::Indexed,
                     ..Frame::default()
                 };
             }
@@ -256,6 +634,7 @@ impl Frame<'static> {
             buffer: Cow::Owned(pixels.chunks_exact(4).map(index_of).collect()),
             palette: Some(palette),
             transparent: transparent.map(|t| index_of(&t)),
+            pixel_format: PixelFormat::Indexed,
             ..Frame::default()
         }
     }
@@ -269,7 +648,7 @@ impl Frame<'static> {
     pub fn from_palette_pixels(width: u16, height: u16, pixels: impl Into<Vec<u8>>, palette: impl Into<Vec<u8>>, transparent: Option<u8>) -> Self {
         let pixels = pixels.into();
         let palette = palette.into();
-        assert_eq!(width as usize * height as usize, pixels.len(), "Too many or too little pixels for the given width and height to create a GIF Frame");
+        assert_eq!(buffer_len(width, height, 1), Some(pixels.len()), "Too many or too little pixels for the given width and height to create a GIF Frame");
         assert!(palette.len() <= 256*3, "Too many palette values to create a GIF Frame");
 
         Frame {
@@ -278,6 +657,7 @@ impl Frame<'static> {
             buffer: Cow::Owned(pixels),
             palette: Some(palette),
             transparent,
+            pixel_format: PixelFormat::Indexed,
             ..Frame::default()
         }
     }
@@ -289,7 +669,7 @@ impl Frame<'static> {
     #[track_caller]
     pub fn from_indexed_pixels(width: u16, height: u16, pixels: impl Into<Vec<u8>>, transparent: Option<u8>) -> Self {
         let pixels = pixels.into();
-        assert_eq!(width as usize * height as usize, pixels.len(), "Too many or too little pixels for the given width and height to create a GIF Frame");
+        assert_eq!(buffer_len(width, height, 1), Some(pixels.len()), "Too many or too little pixels for the given width and height to create a GIF Frame");
 
         Frame {
             width,
@@ -297,10 +677,52 @@ impl Frame<'static> {
             buffer: Cow::Owned(pixels),
             palette: None,
             transparent,
+            pixel_format: PixelFormat::Indexed,
             ..Frame::default()
         }
     }
 
+    /// Creates a frame from indexed pixels in the global palette, placed at `left` and `top` on
+    /// the canvas.
+    ///
+    /// # Panics:
+    /// *   If the length of pixels does not equal `width * height`.
+    /// *   If `left + width` or `top + height` is larger than `u16::MAX`.
+    #[track_caller]
+    pub fn from_indexed_pixels_at(left: u16, top: u16, width: u16, height: u16, pixels: impl Into<Vec<u8>>, transparent: Option<u8>) -> Self {
+        assert_edges_fit(left, top, width, height);
+        Frame {
+            left,
+            top,
+            ..Frame::from_indexed_pixels(width, height, pixels, transparent)
+        }
+    }
+
+    /// Creates a frame from rows of indexed pixels in the global palette, such as a
+    /// `[[u8; W]; H]` or a `Vec<Vec<u8>>`.
+    ///
+    /// The width is the length of the rows, and the height is their number. An empty slice
+    /// becomes a frame of 0×0 pixels.
+    ///
+    /// # Errors
+    ///
+    /// If the rows don't all have the same length, or there are more than `u16::MAX` rows or
+    /// pixels in a row.
+    pub fn from_rows(rows: &[impl AsRef<[u8]>]) -> Result<Self, FrameError> {
+        let row_len = rows.first().map_or(0, |row| row.as_ref().len());
+        let width = u16::try_from(row_len).map_err(|_| FrameError::TooWide(row_len))?;
+        let height = u16::try_from(rows.len()).map_err(|_| FrameError::TooTall(rows.len()))?;
+        let mut pixels = Vec::with_capacity(row_len * rows.len());
+        for (index, row) in rows.iter().enumerate() {
+            let row = row.as_ref();
+            if row.len() != row_len {
+                return Err(FrameError::RaggedRow { row: index, len: row.len(), width: row_len });
+            }
+            pixels.extend_from_slice(row);
+        }
+        Ok(Frame::from_indexed_pixels(width, height, pixels, None))
+    }
+
     /// Creates a frame from pixels in RGB format.
     ///
     /// This is a lossy method. In the `gif` format only 256 colors can appear in a single frame.
@@ -336,9 +758,9 @@ impl Frame<'static> {
     #[must_use]
     #[track_caller]
     pub fn from_rgb_speed(width: u16, height: u16, pixels: &[u8], speed: i32) -> Self {
-        assert_eq!(width as usize * height as usize * 3, pixels.len(), "Too much or too little pixel data for the given width and height to create a GIF Frame");
+        assert_eq!(buffer_len(width, height, 3), Some(pixels.len()), "Too much or too little pixel data for the given width and height to create a GIF Frame");
         let mut vec: Vec<u8> = Vec::new();
-        vec.try_reserve_exact(pixels.len() + width as usize * height as usize).expect("OOM");
+        vec.try_reserve_exact(buffer_len(width, height, 4).expect("OOM")).expect("OOM");
         for v in pixels.chunks_exact(3) {
             vec.extend_from_slice(&[v[0], v[1], v[2], 0xFF]);
         }
@@ -359,7 +781,160 @@ impl Frame<'static> {
             height: self.height,
             interlaced: self.interlaced,
             palette: core::mem::take(&mut self.palette),
+            shared_palette: self.shared_palette,
+            graphic_control: self.graphic_control,
+            descriptor_flags: self.descriptor_flags,
             buffer: core::mem::replace(&mut self.buffer, Cow::Borrowed(&[])),
+            pixel_format: self.pixel_format,
+        }
+    }
+}
+
+/// Error of [`Frame::from_rows`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FrameError {
+    /// A row has a different number of pixels than the first one.
+    RaggedRow {
+        /// Index of the row, from the top.
+        row: usize,
+        /// Number of pixels of the row.
+        len: usize,
+        /// Number of pixels of the first row.
+        width: usize,
+    },
+    /// The rows have more pixels than a frame can be wide.
+    TooWide(usize),
+    /// There are more rows than a frame can be high.
+    TooTall(usize),
+}
+
+impl fmt::Display for FrameError {
+    #[cold]
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RaggedRow { row, len, width } => write!(fmt, "row {row} has {len} pixels, but the first row has {width}"),
+            Self::TooWide(width) => write!(fmt, "rows of {width} pixels are too wide for a frame"),
+            Self::TooTall(height) => write!(fmt, "{height} rows are too many for a frame"),
+        }
+    }
+}
+
+impl error::Error for FrameError {}
+
+/// Number of bytes of `width * height` pixels, or `None` if it isn't representable as `T`
+///
+/// The product is computed as `u64`, which can't overflow. On 32-bit platforms, `usize` can't hold
+/// the size of the largest RGBA frames.
+pub(crate) fn buffer_len<T: TryFrom<u64>>(width: u16, height: u16, bytes_per_pixel: u8) -> Option<T> {
+    T::try_from(u64::from(width) * u64::from(height) * u64::from(bytes_per_pixel)).ok()
+}
+
+/// The far edges of a frame must be representable in the `u16` coordinates of the canvas
+#[track_caller]
+fn assert_edges_fit(left: u16, top: u16, width: u16, height: u16) {
+    assert!(left.checked_add(width).is_some(), "The right edge of the GIF Frame is beyond the maximum canvas width");
+    assert!(top.checked_add(height).is_some(), "The bottom edge of the GIF Frame is beyond the maximum canvas height");
+}
+
+#[test]
+fn buffer_len_of_largest_frames() {
+    assert_eq!(buffer_len::<u64>(0xFFFF, 0xFFFF, 4), Some(0x3_FFF8_0004));
+    // `u32` stands in for the `usize` of 32-bit platforms
+    assert_eq!(buffer_len::<u32>(0xFFFF, 0xFFFF, 1), Some(0xFFFE_0001));
+    assert_eq!(buffer_len::<u32>(0xFFFF, 0xFFFF, 3), None);
+    assert_eq!(buffer_len::<u32>(0xFFFF, 0xFFFF, 4), None);
+    assert_eq!(buffer_len::<u32>(0x8000, 0x7FFF, 4), Some(0xFFFE_0000));
+    assert_eq!(buffer_len::<u32>(0x8000, 0x8000, 4), None);
+}
+
+#[test]
+fn frame_in_screen_boundaries() {
+    // Counts the pixels of a range that are within the screen, one by one
+    let visible = |start: u16, len: u16, screen: u16| (u32::from(start)..u32::from(start) + u32::from(len))
+        .filter(|&i| i < u32::from(screen)).count() as u16;
+    let values = [0, 1, 2, 3, 4, 5, 0xFFFE, 0xFFFF];
+    for screen in [(0, 0), (3, 2), (0xFFFF, 0xFFFF)] {
+        let mut rects = Vec::new();
+        for left in values {
+            for top in values {
+                for width in values {
+                    rects.extend(values.map(|height| Rect { left, top, width, height }));
+                }
+            }
+        }
+        for rect in rects {
+            let Rect { left, top, width, height } = rect;
+            let shown = Rect { width: visible(left, width, screen.0), height: visible(top, height, screen.1), ..rect };
+            let fits = shown.width == width && shown.height == height
+                && u32::from(left) <= u32::from(screen.0) && u32::from(top) <= u32::from(screen.1);
+            assert_eq!(rect.fits_within(screen.0, screen.1), fits, "{rect:?} {screen:?}");
+            assert_eq!(validate_frame_in_screen(rect, screen, FrameConsistency::Ignore), Ok(rect));
+            let error = validate_frame_in_screen(rect, screen, FrameConsistency::Error);
+            assert_eq!(error, if fits { Ok(rect) } else { Err(GeometryError { rect, screen }) });
+            assert_eq!(validate_frame_in_screen(rect, screen, FrameConsistency::Clamp), Ok(if fits { rect } else { shown }));
+        }
+    }
+    // Exact fits at the edges
+    assert!(Rect { left: 1, top: 0, width: 2, height: 2 }.fits_within(3, 2));
+    assert!(Rect { left: 3, top: 2, width: 0, height: 0 }.fits_within(3, 2));
+    assert!(!Rect { left: 4, top: 0, width: 0, height: 0 }.fits_within(3, 2));
+}
+
+#[test]
+fn effective_delays() {
+    let delays = [0, 1, 2, 10, 0xFFFF].map(|delay| Frame { delay, ..Frame::default() });
+    let ms = |policy| delays.iter().map(|frame| frame.effective_delay_ms(policy)).collect::<Vec<_>>();
+    assert_eq!(ms(DelayPolicy::Browser), [100, 100, 20, 100, 655_350]);
+    assert_eq!(ms(DelayPolicy::Spec), [0, 10, 20, 100, 655_350]);
+}
+
+#[test]
+fn frame_from_rows() {
+    let frame = Frame::from_rows(&[[0, 1, 2], [3, 4, 5]]).unwrap();
+    assert_eq!((frame.width, frame.height), (3, 2));
+    assert_eq!(*frame.buffer, [0, 1, 2, 3, 4, 5]);
+    assert!(frame.rows().eq([&[0, 1, 2][..], &[3, 4, 5]]));
+
+    let rows = vec![vec![0, 1], vec![2, 3], vec![4]];
+    assert_eq!(Frame::from_rows(&rows).unwrap_err(), FrameError::RaggedRow { row: 2, len: 1, width: 2 });
+    let rows = vec![vec![0, 1], vec![2, 3, 4]];
+    assert_eq!(Frame::from_rows(&rows).unwrap_err(), FrameError::RaggedRow { row: 1, len: 3, width: 2 });
+    assert_eq!(Frame::from_rows(&[vec![0; 0x1_0000]]).unwrap_err(), FrameError::TooWide(0x1_0000));
+    assert_eq!(Frame::from_rows(&vec![[0; 1]; 0x1_0000]).unwrap_err(), FrameError::TooTall(0x1_0000));
+
+    let empty = Frame::from_rows(&Vec::<Vec<u8>>::new()).unwrap();
+    assert_eq!((empty.width, empty.height, empty.rows().count()), (0, 0, 0));
+    // Rows of no pixels still count towards the height
+    let empty = Frame::from_rows(&[[0; 0]; 3]).unwrap();
+    assert_eq!((empty.width, empty.height, empty.rows().count()), (0, 3, 3));
+    // A truncated buffer has fewer rows
+    let mut frame = frame;
+    frame.buffer.to_mut().pop();
+    assert_eq!(frame.rows().count(), 1);
+}
+
+#[test]
+fn disposal_bits_round_trip() {
+    for flags in 0..=u8::MAX {
+        let value = (flags >> 2) & 0b111;
+        let method = DisposalMethod::from_bits(flags);
+        assert_eq!(method.ok(), DisposalMethod::from_u8(value));
+        // Strict policy: only the defined methods are accepted, and written back unchanged
+        match method {
+            Ok(method) => assert_eq!(method.into_bits(), flags & 0b11100),
+            Err(reserved) => {
+                assert_eq!(reserved.value(), value);
+                assert!(value >= 4);
+            },
+        }
+        // Lenient policy: reserved values are replaced by a defined method
+        let lenient = method.unwrap_or_else(ReservedDisposal::lenient);
+        assert_eq!(DisposalMethod::from_bits(lenient.into_bits()), Ok(lenient));
+        if value == 4 {
+            assert_eq!(lenient, DisposalMethod::Previous);
+        } else if value > 4 {
+            assert_eq!(lenient, DisposalMethod::Any);
         }
     }
 }
@@ -0,0 +1,255 @@
+//! Changing the colors of a file in memory, keeping everything else as it was
+//!
+//! Colors are replaced in the palettes, so only their bytes change. Pixels are only rewritten
+//! if a new color is in the same palette already, see [`ReplaceMode`].
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+use no_std_io::{error, io};
+
+use crate::encoder::lzw_encode;
+use crate::reader::{DecodeOptions, Decoder, DecodingError, DecodingFormatErrorKind, FrameIndexEntry, PLTE_CHANNELS};
+
+/// What [`replace_colors`] may change
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReplaceMode {
+    /// Only the bytes of palette entries change, and the file keeps its size.
+    ///
+    /// Fails with [`EditError::RemapRequired`] if a new color is in a palette already, or if two
+    /// colors of a palette are replaced with the same one, because the palette would contain it
+    /// twice. Such a file is valid, but tools that look up the index of a color, e.g. to make it
+    /// transparent, find only one of them.
+    PaletteOnly,
+    /// Pixels of replaced colors that are in their palette already are moved to the existing
+    /// entry, and their frames are compressed again. The replaced entries keep their old color,
+    /// and no pixel refers to them anymore. Of several colors replaced with the same one, the
+    /// first entry gets the new color, and the pixels of the others are moved to it.
+    ///
+    /// A frame whose transparent index is the existing entry can't be moved to it. The replaced
+    /// entry gets the new color then, as with [`ReplaceMode::PaletteOnly`].
+    Remap,
+}
+
+/// Error of [`replace_colors`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum EditError {
+    /// The input isn't a valid GIF.
+    Decoding(DecodingError),
+    /// The same color is replaced with two different colors.
+    ConflictingReplacements([u8; 3]),
+    /// A new color is in the palette already, or two colors of it are replaced with the same one,
+    /// which [`ReplaceMode::PaletteOnly`] can't handle.
+    RemapRequired {
+        /// Index of the frame with the local palette, or `None` for the global palette.
+        frame: Option<usize>,
+    },
+}
+
+impl fmt::Display for EditError {
+    #[cold]
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decoding(err) => err.fmt(fmt),
+            Self::ConflictingReplacements([r, g, b]) => write!(fmt, "color #{r:02x}{g:02x}{b:02x} is replaced with two different colors"),
+            Self::RemapRequired { frame: None } => write!(fmt, "the global palette has a new color already"),
+            Self::RemapRequired { frame: Some(frame) } => write!(fmt, "the palette of frame {frame} has a new color already"),
+        }
+    }
+}
+
+impl error::Error for EditError {
+    #[cold]
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Decoding(err) => Some(err),
+            Self::ConflictingReplacements(_) | Self::RemapRequired { .. } => None,
+        }
+    }
+}
+
+impl From<DecodingError> for EditError {
+    #[cold]
+    fn from(err: DecodingError) -> Self {
+        Self::Decoding(err)
+    }
+}
+
+/// Offset of the background color index in the logical screen descriptor
+const BACKGROUND_OFFSET: usize = 11;
+/// The signature and the logical screen descriptor come before the global palette
+const GLOBAL_PALETTE_OFFSET: usize = 13;
+/// Length of an image descriptor, including the introducer. The local palette follows it.
+const IMAGE_DESCRIPTOR_LEN: usize = 10;
+/// Offset of the flags in an image descriptor, which has the position and the size before them
+const DESCRIPTOR_FLAGS_OFFSET: usize = 9;
+
+/// A frame as it is in the file
+struct FrameLayout {
+    /// Position of the local palette and its number of colors
+    palette: Option<(usize, usize)>,
+    /// The LZW code size, the sub-blocks and the block terminator
+    data: Range<usize>,
+}
+
+impl FrameLayout {
+    fn new(input: &[u8], entry: &FrameIndexEntry) -> Self {
+        let descriptor = entry.descriptor as usize;
+        let flags = input[descriptor + DESCRIPTOR_FLAGS_OFFSET];
+        let palette = (flags & 0b1000_0000 != 0)
+            .then(|| (descriptor + IMAGE_DESCRIPTOR_LEN, 2 << (flags & 0b0111)));
+        let data_start = descriptor + IMAGE_DESCRIPTOR_LEN + palette.map_or(0, |(_, len)| len * PLTE_CHANNELS);
+        Self { palette, data: data_start..entry.end as usize }
+    }
+}
+
+/// The pixels of a frame, once they have been decoded
+struct FramePixels {
+    transparent: Option<u8>,
+    /// Palette indices in the order of the file, so interlaced frames stay interlaced
+    indices: Vec<u8>,
+    /// Pixels have been moved to another palette entry
+    changed: bool,
+}
+
+/// Replaces every palette entry that has the first color of a pair with the second one, in the
+/// global palette and in local ones.
+///
+/// Replacing a color with itself changes nothing, and colors that no palette contains are
+/// ignored. Interlaced frames stay interlaced, and all blocks that aren't frame data are kept as
+/// they are.
+pub fn replace_colors(input: &[u8], replacements: &[([u8; 3], [u8; 3])], mode: ReplaceMode) -> Result<Vec<u8>, EditError> {
+    for (i, &(from, to)) in replacements.iter().enumerate() {
+        if replacements[..i].iter().any(|&(prev_from, prev_to)| prev_from == from && prev_to != to) {
+            return Err(EditError::ConflictingReplacements(from));
+        }
+    }
+    let replacement = |color: &[u8]| replacements.iter()
+        .find(|(from, to)| from == color && to != from)
+        .map(|&(_, to)| to);
+
+    let mut options = DecodeOptions::new();
+    options.allow_unknown_extensions(true);
+    // The pixels are compressed again in the order of the file
+    options.deinterlace(false);
+    let mut decoder = options.read_info_buffered(io::Cursor::new(input))?;
+    // No frame has been reached, so it's not the fallback palette
+    let global_palette = decoder.global_palette().map(|palette| palette.len() / PLTE_CHANNELS);
+    let frames: Vec<FrameLayout> = decoder.build_frame_index()?.iter()
+        .map(|entry| FrameLayout::new(input, entry))
+        .collect();
+    let mut pixels: Vec<Option<FramePixels>> = frames.iter().map(|_| None).collect();
+    let mut output = input.to_vec();
+
+    let mut palettes: Vec<(Option<usize>, usize, usize)> = Vec::new();
+    if let Some(len) = global_palette {
+        palettes.push((None, GLOBAL_PALETTE_OFFSET, len));
+    }
+    palettes.extend(frames.iter().enumerate()
+        .filter_map(|(i, frame)| frame.palette.map(|(offset, len)| (Some(i), offset, len))));
+
+    for (owner, offset, len) in palettes {
+        let colors: Vec<&[u8]> = input[offset..offset + len * PLTE_CHANNELS].chunks_exact(PLTE_CHANNELS).collect();
+        // Replaced entries that have been given their new color
+        let mut given = vec![false; len];
+        for (index, &color) in colors.iter().enumerate() {
+            let to = match replacement(color) {
+                Some(to) => to,
+                None => continue,
+            };
+            // An entry that has the new color, because it keeps it or has been given it
+            let existing = (0..len).find(|&i| match replacement(colors[i]) {
+                None => colors[i] == to,
+                Some(new) => given[i] && new == to,
+            });
+            let moved = match (existing, mode) {
+                (None, _) => false,
+                (Some(_), ReplaceMode::PaletteOnly) => return Err(EditError::RemapRequired { frame: owner }),
+                (Some(existing), ReplaceMode::Remap) => {
+                    let users = frames.iter().enumerate().filter(|(i, frame)| match owner {
+                        Some(owner) => *i == owner,
+                        None => frame.palette.is_none(),
+                    });
+                    let mut moved = true;
+                    for (i, _) in users {
+                        moved &= move_pixels(&mut decoder, i, &mut pixels[i], index as u8, existing as u8)?;
+                    }
+                    if moved && owner.is_none() && usize::from(input[BACKGROUND_OFFSET]) == index {
+                        output[BACKGROUND_OFFSET] = existing as u8;
+                    }
+                    moved
+                },
+            };
+            if !moved {
+                let start = offset + index * PLTE_CHANNELS;
+                output[start..start + PLTE_CHANNELS].copy_from_slice(&to);
+                given[index] = true;
+            }
+        }
+    }
+
+    if !pixels.iter().flatten().any(|pixels| pixels.changed) {
+        return Ok(output);
+    }
+    let mut edited = Vec::with_capacity(output.len());
+    let mut pos = 0;
+    let mut lzw = Vec::new();
+    for (frame, pixels) in frames.iter().zip(&pixels) {
+        if let Some(pixels) = pixels.as_ref().filter(|pixels| pixels.changed) {
+            edited.extend_from_slice(&output[pos..frame.data.start]);
+            lzw.clear();
+            lzw_encode(&pixels.indices, &mut lzw);
+            write_sub_blocks(&lzw, &mut edited);
+            pos = frame.data.end;
+        }
+    }
+    edited.extend_from_slice(&output[pos..]);
+    Ok(edited)
+}
+
+/// Changes the pixels of the frame with the index `frame` from the palette entry `from` to `to`,
+/// unless `to` is the transparent index.
+///
+/// The pixels are decoded once, and kept in `pixels` with the earlier changes. Returns `false`
+/// if pixels with the index `from` had to be kept.
+fn move_pixels(decoder: &mut Decoder<io::Cursor<&[u8]>>, frame: usize, pixels: &mut Option<FramePixels>, from: u8, to: u8) -> Result<bool, DecodingError> {
+    let pixels = match pixels {
+        Some(pixels) => pixels,
+        None => {
+            decoder.seek_to_frame(frame)?;
+            let frame = decoder.read_next_frame()?
+                .ok_or_else(|| DecodingError::format(DecodingFormatErrorKind::InvalidState, "frame index doesn't match the file"))?;
+            pixels.insert(FramePixels {
+                transparent: frame.transparent,
+                indices: frame.buffer.to_vec(),
+                changed: false,
+            })
+        },
+    };
+    // Transparent pixels don't show their color
+    if pixels.transparent == Some(from) || !pixels.indices.contains(&from) {
+        return Ok(true);
+    }
+    if pixels.transparent == Some(to) {
+        return Ok(false);
+    }
+    for pixel in &mut pixels.indices {
+        if *pixel == from {
+            *pixel = to;
+        }
+    }
+    pixels.changed = true;
+    Ok(true)
+}
+
+/// Writes the code size and the LZW data in sub-blocks, and the block terminator
+fn write_sub_blocks(lzw: &[u8], out: &mut Vec<u8>) {
+    let (&min_code_size, data) = lzw.split_first().unwrap_or((&2, &[]));
+    out.push(min_code_size);
+    for block in data.chunks(255) {
+        out.push(block.len() as u8);
+        out.extend_from_slice(block);
+    }
+    out.push(0);
+}
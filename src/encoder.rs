@@ -20,14 +20,19 @@ use no_std_io::io::{
 use core::{
     fmt,
 };
+use core::mem;
 use alloc::borrow::Cow;
 #[cfg(not(feature = "std"))]
+use alloc::boxed::Box;
+#[cfg(not(feature = "std"))]
 use alloc::vec::Vec;
+#[cfg(feature = "color_quant")]
+use std::collections::HashSet;
 
 use weezl::{BitOrder, encode::Encoder as LzwEncoder};
 
 use crate::traits::WriteBytesExt;
-use crate::common::{AnyExtension, Block, DisposalMethod, Extension, Frame};
+use crate::common::{buffer_len, validate_frame_in_screen, AnyExtension, Block, DisposalMethod, Extension, Frame, FrameConsistency, PixelFormat, Rect};
 
 /// The image has incorrect properties, making it impossible to encode as a gif.
 #[derive(Debug)]
@@ -39,6 +44,16 @@ pub enum EncodingFormatError {
     MissingColorPalette,
     /// LZW data is not valid for GIF. This may happen when wrong buffer is given to `write_lzw_pre_encoded_frame`
     InvalidMinCodeSize,
+    /// The frame has more pixels than this platform can address.
+    DimensionsTooLarge,
+    /// The length of an RGB palette isn't a multiple of 3.
+    InvalidPaletteLength {
+        /// Length of the palette in bytes.
+        len: usize,
+    },
+    /// The [`Frame::pixel_format`] isn't what the method takes, for example RGBA pixels from a
+    /// decoder given to [`Encoder::write_frame`].
+    UnexpectedPixelFormat(PixelFormat),
 }
 
 impl error::Error for EncodingFormatError {}
@@ -49,6 +64,9 @@ impl fmt::Display for EncodingFormatError {
             Self::TooManyColors => write!(fmt, "the image has too many colors"),
             Self::MissingColorPalette => write!(fmt, "the GIF format requires a color palette but none was given"),
             Self::InvalidMinCodeSize => write!(fmt, "LZW data is invalid"),
+            Self::DimensionsTooLarge => write!(fmt, "the frame is too large for this platform"),
+            Self::InvalidPaletteLength { len } => write!(fmt, "the palette has {len} bytes, which is not a multiple of 3 for RGB colors"),
+            Self::UnexpectedPixelFormat(format) => write!(fmt, "the frame buffer has the pixel format {format:?}, which this method doesn't take"),
         }
     }
 }
@@ -60,6 +78,20 @@ pub enum EncodingError {
     Format(EncodingFormatError),
     /// Wraps `std::io::Error`.
     Io(io::Error),
+    /// A frame does not fit within the logical screen.
+    ///
+    /// Only reported by [`Encoder::into_inner`] when [`Encoder::check_frame_consistency`] is enabled.
+    FrameOutsideCanvas {
+        /// Index of the first offending frame among those given to the encoder, counting from 0.
+        ///
+        /// The frame of [`Encoder::pad_first_frame`] isn't counted, so in the file, the frame
+        /// may come one later.
+        frame_index: usize,
+        /// Position and size of the offending frame.
+        rect: Rect,
+        /// Width and height of the logical screen.
+        canvas: (u16, u16),
+    },
 }
 
 impl fmt::Display for EncodingError {
@@ -68,6 +100,9 @@ impl fmt::Display for EncodingError {
         match self {
             Self::Io(err) => err.fmt(fmt),
             Self::Format(err) => err.fmt(fmt),
+            Self::FrameOutsideCanvas { frame_index, rect, canvas } => write!(fmt,
+                "frame {frame_index} ({}x{} at {},{}) is outside of the {}x{} canvas",
+                rect.width, rect.height, rect.left, rect.top, canvas.0, canvas.1),
         }
     }
 }
@@ -78,6 +113,7 @@ impl error::Error for EncodingError {
         match self {
             Self::Io(err) => Some(err),
             Self::Format(err) => Some(err),
+            Self::FrameOutsideCanvas { .. } => None,
         }
     }
 }
@@ -96,8 +132,269 @@ impl From<EncodingFormatError> for EncodingError {
     }
 }
 
+/// Background written under the first frame, see [`Encoder::pad_first_frame`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PadFirstFrame {
+    /// Fill the logical screen with this index of the global palette.
+    Index(u8),
+    /// Fill the logical screen with this color, using a local palette.
+    Color([u8; 3]),
+}
+
+/// Details of the file layout that decoders ignore, to reproduce files of a specific encoder.
+///
+/// [`CompatProfile::new`] describes the files this crate writes by default, and the presets like
+/// [`CompatProfile::gifsicle`] the files of other encoders. Each can be adjusted with the setters.
+/// Pass the profile to [`Encoder::with_compat_profile`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompatProfile {
+    sub_block_size: u8,
+    always_write_control_ext: bool,
+    full_palettes: bool,
+    color_resolution: Option<u8>,
+    global_palette_sorted: bool,
+    promote_first_local_palette: bool,
+}
+
+impl Default for CompatProfile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CompatProfile {
+    /// The layout of files written by this crate
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            sub_block_size: 0xFF,
+            always_write_control_ext: true,
+            full_palettes: false,
+            color_resolution: None,
+            global_palette_sorted: false,
+            promote_first_local_palette: false,
+        }
+    }
+
+    /// The layout recommended for new code, which the next major version is going to use.
+    ///
+    /// It differs from [`Self::new`] in:
+    /// *   [`Self::always_write_control_ext`] is turned off, so frames only have a graphic
+    ///     control extension if one of its fields isn't the default.
+    ///
+    /// Decoders read the files the same way.
+    #[must_use]
+    pub fn v2_defaults() -> Self {
+        let mut profile = Self::new();
+        profile.always_write_control_ext(false);
+        profile
+    }
+
+    /// The layout of files written by Gifsicle.
+    ///
+    /// It differs from [`Self::new`] in:
+    /// *   [`Self::always_write_control_ext`] is turned off, because Gifsicle only writes a
+    ///     graphic control extension for frames that need one.
+    ///
+    /// Gifsicle writes version `87a` into files without any extension, which this crate doesn't.
+    #[must_use]
+    pub fn gifsicle() -> Self {
+        let mut profile = Self::new();
+        profile.always_write_control_ext(false);
+        profile
+    }
+
+    /// The layout of files written by the encoders of the Netscape era, which many old
+    /// animations on the web come from.
+    ///
+    /// It differs from [`Self::new`] in:
+    /// *   [`Self::full_palettes`] is turned on, so every color table has 256 colors.
+    /// *   [`Self::color_resolution`] is 7, for 8 bits per primary color.
+    #[must_use]
+    pub fn old_netscape() -> Self {
+        let mut profile = Self::new();
+        profile.full_palettes(true);
+        profile.color_resolution(Some(7));
+        profile
+    }
+
+    /// The layout that follows every recommendation of the GIF89a specification.
+    ///
+    /// It differs from [`Self::new`] in:
+    /// *   [`Self::color_resolution`] is 7, the bits per primary color of the RGB palettes of
+    ///     this crate, instead of the size of the global color table.
+    #[must_use]
+    pub fn strict_89a() -> Self {
+        let mut profile = Self::new();
+        profile.color_resolution(Some(7));
+        profile
+    }
+
+    /// The layout of files written by version 0.13 of this crate and earlier, which is the same
+    /// as [`Self::new`].
+    ///
+    /// Unlike [`Self::new`], it won't change in the next major version.
+    #[must_use]
+    pub fn this_crate_legacy() -> Self {
+        Self::new()
+    }
+
+    /// Configure the length of the sub-blocks of the image data, from 1 to 255.
+    ///
+    /// The default is 255. Only the last sub-block of a frame is shorter.
+    ///
+    /// # Panics:
+    /// *   If `size` is 0.
+    pub fn sub_block_size(&mut self, size: u8) {
+        assert!(size > 0, "sub-blocks can't be empty");
+        self.sub_block_size = size;
+    }
+
+    /// Configure if every frame has a graphic control extension.
+    ///
+    /// The default is `true`.
+    ///
+    /// When turned off, it's left out for frames with no delay, no transparency,
+    /// `DisposalMethod::Any` and no user input, which is what its absence means.
+    pub fn always_write_control_ext(&mut self, always: bool) {
+        self.always_write_control_ext = always;
+    }
+
+    /// Configure if all color tables have 256 colors.
+    ///
+    /// The default is `false`, and color tables are padded to the next power of two.
+    pub fn full_palettes(&mut self, full: bool) {
+        self.full_palettes = full;
+    }
+
+    /// Configure the color resolution field of the logical screen descriptor, from 0 to 7.
+    ///
+    /// The default is `None`, which uses the size field of the global color table.
+    ///
+    /// # Panics:
+    /// *   If `resolution` is greater than 7.
+    pub fn color_resolution(&mut self, resolution: Option<u8>) {
+        assert!(resolution.map_or(true, |resolution| resolution <= 7), "the color resolution has 3 bits");
+        self.color_resolution = resolution;
+    }
+
+    /// Configure the sort flag of the global color table, see [`ScreenDescriptor::sort_flag`].
+    ///
+    /// The default is `false`. The flag is only written if there is a global palette, and the
+    /// encoder doesn't sort the palette itself.
+    ///
+    /// [`ScreenDescriptor::sort_flag`]: crate::ScreenDescriptor::sort_flag
+    pub fn global_palette_sorted(&mut self, sorted: bool) {
+        self.global_palette_sorted = sorted;
+    }
+
+    /// Configure if the local palette of the first frame becomes the global palette.
+    ///
+    /// The default is `false`.
+    ///
+    /// When turned on, and the encoder is created without a global palette, the header is held
+    /// back until the first frame is written, together with the extensions written before it
+    /// like the loop count. The local palette of that frame is then written as the global
+    /// palette, and the frame itself has none. Later frames whose palette starts with the same
+    /// colors leave theirs out too, the others keep their local palette. Some tools expect a
+    /// global palette.
+    pub fn promote_first_local_palette(&mut self, promote: bool) {
+        self.promote_first_local_palette = promote;
+    }
+}
+
+/// Colors of a palette, each of them `[r, g, b]`
+///
+/// Unlike a `&[u8]` of RGB bytes, it can't end with a partial color.
+#[derive(Copy, Clone, Debug)]
+pub struct Palette<'a> {
+    colors: Colors<'a>,
+}
+
+/// The slice a [`Palette`] has been made from, which is kept as it is, since flattening
+/// `[[u8; 3]]` without `unsafe` needs a newer Rust than the minimum supported one
+#[derive(Copy, Clone, Debug)]
+enum Colors<'a> {
+    Rgb(&'a [u8]),
+    Triples(&'a [[u8; 3]]),
+}
+
+impl<'a> Palette<'a> {
+    /// Palette of the given colors.
+    #[must_use]
+    pub fn new(colors: &'a [[u8; 3]]) -> Self {
+        Self { colors: Colors::Triples(colors) }
+    }
+
+    /// Palette in the format `[r, g, b, ...]`.
+    ///
+    /// Fails with [`EncodingFormatError::InvalidPaletteLength`] if the length of `rgb` isn't a multiple of 3.
+    pub fn from_rgb(rgb: &'a [u8]) -> Result<Self, EncodingError> {
+        if rgb.len() % 3 != 0 {
+            return Err(EncodingFormatError::InvalidPaletteLength { len: rgb.len() }.into());
+        }
+        Ok(Self { colors: Colors::Rgb(rgb) })
+    }
+
+    /// Number of colors.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        match self.colors {
+            Colors::Rgb(rgb) => rgb.len() / 3,
+            Colors::Triples(colors) => colors.len(),
+        }
+    }
+
+    /// Returns `true` if there are no colors.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The colors, each of them `[r, g, b]`.
+    pub fn colors(&self) -> impl Iterator<Item = [u8; 3]> + 'a {
+        let (rgb, colors): (&'a [u8], &'a [[u8; 3]]) = match self.colors {
+            Colors::Rgb(rgb) => (rgb, &[]),
+            Colors::Triples(colors) => (&[], colors),
+        };
+        rgb.chunks_exact(3).map(|color| [color[0], color[1], color[2]]).chain(colors.iter().copied())
+    }
+
+    /// Writes the colors in the format `[r, g, b, ...]`.
+    fn write_rgb(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self.colors {
+            Colors::Rgb(rgb) => writer.write_all(rgb),
+            Colors::Triples(colors) => colors.iter().try_for_each(|color| writer.write_all(color)),
+        }
+    }
+
+    /// Copies the colors in the format `[r, g, b, ...]`, with room for `padding` more colors.
+    fn to_rgb(self, padding: usize) -> io::Result<Vec<u8>> {
+        let len = (self.len() + padding) * 3;
+        let mut rgb = Vec::new();
+        rgb.try_reserve_exact(len).map_err(|_| io::Error::from(io::ErrorKind::Other))?;
+        rgb.extend(self.colors().flatten());
+        Ok(rgb)
+    }
+}
+
+impl Default for Palette<'_> {
+    fn default() -> Self {
+        Self { colors: Colors::Rgb(&[]) }
+    }
+}
+
+impl PartialEq for Palette<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.colors().eq(other.colors())
+    }
+}
+
+impl Eq for Palette<'_> {}
+
 /// Number of repetitions
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Repeat {
     /// Finite number of repetitions
     Finite(u16),
@@ -142,7 +439,7 @@ impl ExtensionData {
             None => 0,
         };
         flags |= u8::from(needs_user_input) << 1;
-        flags |= (dispose as u8) << 2;
+        flags |= dispose.into_bits();
         Self::Control { flags, delay, trns }
     }
 }
@@ -151,14 +448,121 @@ impl<W: Write> Encoder<W> {
     /// Creates a new encoder.
     ///
     /// `global_palette` gives the global color palette in the format `[r, g, b, ...]`,
-    /// if no global palette shall be used an empty slice may be supplied.
+    /// if no global palette shall be used an empty slice may be supplied. Then the file has no
+    /// global color table, and every frame needs a palette of its own.
+    ///
+    /// Fails with [`EncodingFormatError::InvalidPaletteLength`] if the length of the palette isn't a multiple of 3.
     pub fn new(w: W, width: u16, height: u16, global_palette: &[u8]) -> Result<Self, EncodingError> {
-        Self {
-            w: Some(w),
+        Self::with_palette(w, width, height, Palette::from_rgb(global_palette)?)
+    }
+
+    /// Creates a new encoder with a global color palette, which may be empty.
+    pub fn with_palette(w: W, width: u16, height: u16, global_palette: Palette<'_>) -> Result<Self, EncodingError> {
+        Self::with_compat_profile(w, width, height, global_palette, CompatProfile::new())
+    }
+
+    /// Creates a new encoder that lays out the file according to `profile`.
+    pub fn with_compat_profile(w: W, width: u16, height: u16, global_palette: Palette<'_>, profile: CompatProfile) -> Result<Self, EncodingError> {
+        Self::with_sink_observers(w, width, height, global_palette, profile, Vec::new())
+    }
+
+    /// Creates a new encoder that passes everything it writes on to `observers` too.
+    ///
+    /// The observers see the bytes once they have been accepted by `w`, including the header
+    /// that is written right away, and are told when each block of the file is complete. This
+    /// is cheaper than wrapping `w`, because the encoder makes many small writes. Blocks held
+    /// back by [`CompatProfile::promote_first_local_palette`] are seen once they're written.
+    ///
+    /// Each write goes to `w` first, and the observers are called with the part of it that `w`
+    /// has accepted, which may be less than the encoder has tried to write. So an observer never
+    /// sees bytes that `w` doesn't have, and `w` has the bytes an observer is called with even if
+    /// it panics.
+    ///
+    /// If an observer panics, the call that was writing fails with an [`io::Error`], and so do
+    /// all calls after it, because the observers would have missed some bytes. This needs the
+    /// `std` feature and unwinding: with `panic = "abort"` the panic aborts the process, and
+    /// without `std` it propagates to the caller.
+    pub fn with_sink_observers(w: W, width: u16, height: u16, global_palette: Palette<'_>, profile: CompatProfile, observers: Vec<Box<dyn SinkObserver>>) -> Result<Self, EncodingError> {
+        let mut encoder = Self {
+            w: Some(Sink { w, observers, poisoned: false, held: None, written: 0 }),
             global_palette: false,
+            promoted_palette: None,
             width, height,
             buffer: Vec::new(),
-        }.write_global_palette(global_palette)
+            check_frame_consistency: false,
+            frames_written: 0,
+            frame_outside_canvas: None,
+            extent: (0, 0),
+            frame_outside_max_canvas: None,
+            pad_first_frame: None,
+            wrote_frame: false,
+            shared_palette: None,
+            profile,
+        };
+        if global_palette.is_empty() && encoder.profile.promote_first_local_palette {
+            encoder.writer()?.held = Some(HeldBlocks::default());
+        } else {
+            encoder.write_header(global_palette)?;
+        }
+        Ok(encoder)
+    }
+
+    /// Configure if all frames must be within the logical screen.
+    ///
+    /// The default is `false`.
+    ///
+    /// When turned on, every frame written is checked against the screen size given to
+    /// [`Encoder::new`], and [`Encoder::into_inner`] reports the first one that doesn't fit as
+    /// [`EncodingError::FrameOutsideCanvas`]. Frames written before this was turned on are not
+    /// checked, and turning it off forgets a frame found earlier.
+    ///
+    /// The check can't be reported when the encoder is only dropped. To grow the screen to fit
+    /// the frames instead, finish a seekable writer with [`Encoder::into_inner_grown`].
+    pub fn check_frame_consistency(&mut self, check: bool) {
+        self.check_frame_consistency = check;
+        if !check {
+            self.frame_outside_canvas = None;
+        }
+    }
+
+    /// Configure a background frame to write under the first frame, if that one doesn't cover
+    /// the whole logical screen.
+    ///
+    /// The default is `None`.
+    ///
+    /// Players that ignore the background color show garbage where the first frame doesn't
+    /// cover the screen. With this set, such a first frame is preceded by a full-screen frame
+    /// with no delay and `DisposalMethod::Keep`, so the duration of the animation is unchanged.
+    /// It has no effect after the first frame has been written.
+    ///
+    /// The background frame is an image block of its own for decoders and for the observers of
+    /// [`Encoder::with_sink_observers`], so the frames written after it have one index more in
+    /// the file. The frame indices the encoder reports, like in [`EncodingError::FrameOutsideCanvas`],
+    /// count only the frames given to it.
+    pub fn pad_first_frame(&mut self, pad: Option<PadFirstFrame>) {
+        self.pad_first_frame = pad;
+    }
+
+    /// Configure a local palette for the frames marked with [`Frame::shared_palette`].
+    ///
+    /// The default is `None`, and writing a marked frame fails with
+    /// [`EncodingFormatError::MissingColorPalette`].
+    ///
+    /// The palette is checked and padded once here, and then written as the local palette of
+    /// every marked frame whose `palette` is `None`. This saves cloning the same palette into
+    /// each of many frames. Frames with a palette of their own are unaffected, and unmarked
+    /// frames without one use the global palette.
+    pub fn set_shared_local_palette(&mut self, palette: Option<Palette<'_>>) -> Result<(), EncodingError> {
+        self.shared_palette = match palette {
+            Some(palette) => {
+                let (padding, table_size) = self.check_color_table(palette)?;
+                let mut table = palette.to_rgb(padding)?;
+                table.resize((palette.len() + padding) * 3, 0);
+                Some((table, table_size))
+            },
+            None => None,
+        };
+        Ok(())
     }
 
     /// Write an extension block that signals a repeat behaviour.
@@ -166,54 +570,194 @@ impl<W: Write> Encoder<W> {
         self.write_extension(ExtensionData::Repetitions(repeat))
     }
 
-    /// Writes the global color palette.
-    fn write_global_palette(mut self, palette: &[u8]) -> Result<Self, EncodingError> {
+    /// Writes the header, with the global color palette.
+    fn write_header(&mut self, palette: Palette<'_>) -> Result<(), EncodingError> {
         let mut flags = 0;
-        flags |= 0b1000_0000;
-        let (palette, padding, table_size) = Self::check_color_table(palette)?;
+        let (padding, table_size) = self.check_color_table(palette)?;
         self.global_palette = !palette.is_empty();
-        // Size of global color table.
-        flags |= table_size;
-        // Color resolution .. FIXME. This is mostly ignored (by ImageMagick at least) but hey, we
-        // should use some sensible value here or even allow configuring it?
-        flags |= table_size << 4; // wtf flag
+        if self.global_palette {
+            flags |= 0b1000_0000;
+            // Size of global color table.
+            flags |= table_size;
+            if self.profile.global_palette_sorted {
+                flags |= 0b0000_1000;
+            }
+        }
+        // Color resolution. This is mostly ignored (by ImageMagick at least), so it's only
+        // configurable to reproduce files of other encoders.
+        flags |= self.profile.color_resolution.unwrap_or(table_size) << 4;
         self.write_screen_desc(flags)?;
-        Self::write_color_table(self.writer()?, palette, padding)?;
-        Ok(self)
+        if self.global_palette {
+            Self::write_color_table(self.writer()?, palette, padding)?;
+        }
+        self.writer()?.block_boundary(BlockKind::Header)?;
+        Ok(())
+    }
+
+    /// Writes the header that has been held back, with `palette` as the global palette, and
+    /// then the blocks written since. Does nothing if the header has been written.
+    fn write_held_header(&mut self, palette: &[u8]) -> Result<(), EncodingError> {
+        if self.writer()?.held.is_none() {
+            return Ok(());
+        }
+        let palette = Palette::from_rgb(palette)?;
+        self.check_color_table(palette)?;
+        let held = self.writer()?.held.take().unwrap_or_default();
+        self.write_header(palette)?;
+        if self.global_palette {
+            self.promoted_palette = Some(palette.to_rgb(0)?);
+        }
+        self.writer()?.write_held(held).map_err(Into::into)
     }
 
     /// Writes a frame to the image.
     ///
+    /// The rows of the `frame.buffer` are in display order. If `frame.interlaced` is set, they're
+    /// reordered when they're written. A buffer of any [`Frame::pixel_format`] other than
+    /// `Indexed` or `Unknown` is an error.
+    ///
     /// Note: This function also writes a control extension if necessary.
     pub fn write_frame(&mut self, frame: &Frame<'_>) -> Result<(), EncodingError> {
-        if usize::from(frame.width).checked_mul(usize::from(frame.height)).map_or(true, |size| frame.buffer.len() < size) {
+        if !matches!(frame.pixel_format, PixelFormat::Indexed | PixelFormat::Unknown) {
+            return Err(EncodingFormatError::UnexpectedPixelFormat(frame.pixel_format).into());
+        }
+        let size = buffer_len::<usize>(frame.width, frame.height, 1).ok_or(EncodingFormatError::DimensionsTooLarge)?;
+        if frame.buffer.len() < size {
             return Err(io::Error::new(io::ErrorKind::InvalidInput, "frame.buffer is too small for its width/height").into());
         }
         debug_assert!((frame.width > 0 && frame.height > 0) || frame.buffer.is_empty(), "the frame has 0 pixels, but non-empty buffer");
+        let _span = span!(DEBUG, "frame", left = frame.left, top = frame.top, width = frame.width, height = frame.height, interlaced = frame.interlaced);
         self.write_frame_header(frame)?;
-        self.write_image_block(&frame.buffer)
+        if frame.interlaced {
+            let mut rows = Vec::new();
+            rows.try_reserve_exact(frame.buffer.len())
+                .map_err(|_| io::Error::from(io::ErrorKind::Other))?;
+            interlace_into(&frame.buffer, frame.width, frame.height, &mut rows);
+            self.write_image_block(&rows)
+        } else {
+            self.write_image_block(&frame.buffer)
+        }
+    }
+
+    /// Writes a true color image as a stack of frames, each with its own local palette.
+    ///
+    /// A single GIF frame can't have more than 256 colors. This splits the RGBA image into at
+    /// most `max_subframes` horizontal strips, which are written as frames with
+    /// `DisposalMethod::Keep` and no delay, except for the last one which gets `delay`. Viewers
+    /// that composite frames show the strips together as one image.
+    ///
+    /// The strips are chosen so that each of them has at most 256 colors, and then the output is
+    /// lossless, except that like in [`Frame::from_rgba`] any non-zero alpha is fully opaque. If
+    /// that would need more than `max_subframes` strips, the image is split evenly instead, and
+    /// each strip with more than 256 colors is reduced to 255 opaque colors and a transparent
+    /// one. The colors are the ones the NeuQuant algorithm finds, or evenly spaced ones if that
+    /// leaves a pixel further off. Either way, each color channel of an opaque pixel differs by
+    /// at most 25 from the input, and transparent pixels stay transparent.
+    ///
+    /// Returns the number of frames written, which is 0 for an image without pixels.
+    ///
+    /// *Note: This method is not optimized for speed.*
+    ///
+    /// # Panics:
+    /// *   If the length of `rgba` does not equal `width * height * 4`.
+    #[cfg(feature = "color_quant")]
+    #[track_caller]
+    pub fn write_truecolor_frame(&mut self, width: u16, height: u16, rgba: &[u8], max_subframes: u8, delay: u16) -> Result<usize, EncodingError> {
+        assert_eq!(buffer_len(width, height, 4), Some(rgba.len()), "Too much or too little pixel data for the given width and height to create a GIF Frame");
+        if width == 0 || height == 0 {
+            return Ok(0);
+        }
+        let line_length = width as usize * 4;
+        let strips = truecolor_strips(rgba, line_length, height, max_subframes.max(1));
+        let mut pixels = Vec::new();
+        for (i, &(top, strip_height)) in strips.iter().enumerate() {
+            pixels.clear();
+            pixels.extend_from_slice(&rgba[top as usize * line_length..][..strip_height as usize * line_length]);
+            // All fully transparent pixels need to share one palette entry
+            for pix in pixels.chunks_exact_mut(4) {
+                if pix[3] == 0 {
+                    pix.copy_from_slice(&[0; 4]);
+                } else {
+                    pix[3] = 0xFF;
+                }
+            }
+            let mut colors = HashSet::new();
+            let exact = pixels.chunks_exact(4).filter(|pix| colors.insert([pix[0], pix[1], pix[2], pix[3]])).nth(256).is_none();
+            let mut frame = if exact {
+                Frame::from_rgba(width, strip_height, &mut pixels)
+            } else {
+                quantize_strip(width, strip_height, &pixels)
+            };
+            frame.top = top;
+            frame.dispose = DisposalMethod::Keep;
+            frame.delay = if i + 1 == strips.len() { delay } else { 0 };
+            self.write_frame(&frame)?;
+        }
+        Ok(strips.len())
     }
 
     fn write_frame_header(&mut self, frame: &Frame<'_>) -> Result<(), EncodingError> {
-        self.write_extension(ExtensionData::new_control_ext(
-            frame.delay,
-            frame.dispose,
-            frame.needs_user_input,
-            frame.transparent,
-        ))?;
+        self.write_held_header(frame.palette.as_deref().unwrap_or_default())?;
+        if !mem::replace(&mut self.wrote_frame, true) {
+            let covers_screen = frame.left == 0 && frame.top == 0
+                && frame.width >= self.width && frame.height >= self.height;
+            match self.pad_first_frame {
+                Some(pad) if !covers_screen => self.write_padding_frame(pad)?,
+                _ => {},
+            }
+        }
+        let frame_index = self.frames_written;
+        self.frames_written += 1;
+        let rect = Rect::from(frame);
+        // The screen size is fixed, so only the first frame outside of it is reported
+        if self.check_frame_consistency && self.frame_outside_canvas.is_none()
+            && validate_frame_in_screen(rect, (self.width, self.height), FrameConsistency::Error).is_err() {
+            self.frame_outside_canvas = Some((frame_index, rect));
+        }
+        self.extent.0 = self.extent.0.max(u32::from(rect.left) + u32::from(rect.width));
+        self.extent.1 = self.extent.1.max(u32::from(rect.top) + u32::from(rect.height));
+        if self.frame_outside_max_canvas.is_none()
+            && validate_frame_in_screen(rect, (u16::MAX, u16::MAX), FrameConsistency::Error).is_err() {
+            self.frame_outside_max_canvas = Some((frame_index, rect));
+        }
+        let needs_control_ext = frame.delay != 0 || frame.dispose != DisposalMethod::Any
+            || frame.needs_user_input || frame.transparent.is_some();
+        if self.profile.always_write_control_ext || needs_control_ext {
+            self.write_extension(ExtensionData::new_control_ext(
+                frame.delay,
+                frame.dispose,
+                frame.needs_user_input,
+                frame.transparent,
+            ))?;
+        }
         let mut flags = 0;
         if frame.interlaced {
             flags |= 0b0100_0000;
         }
+        // Colors of the promoted global palette don't need a local palette
+        let in_promoted_palette = |palette: &[u8]| {
+            palette.len() % 3 == 0 && self.promoted_palette.as_deref().map_or(false, |global| global.starts_with(palette))
+        };
         let palette = match frame.palette {
+            Some(ref palette) if in_promoted_palette(palette) => None,
             Some(ref palette) => {
                 flags |= 0b1000_0000;
-                let (palette, padding, table_size) = Self::check_color_table(palette)?;
+                flags |= frame.descriptor_flags & 0b0010_0000;
+                let palette = Palette::from_rgb(palette)?;
+                let (padding, table_size) = self.check_color_table(palette)?;
                 flags |= table_size;
                 Some((palette, padding))
             },
+            None if frame.shared_palette => match &self.shared_palette {
+                Some((table, table_size)) => {
+                    flags |= 0b1000_0000;
+                    flags |= table_size;
+                    Some((Palette { colors: Colors::Rgb(table) }, 0))
+                },
+                None => return Err(EncodingError::from(EncodingFormatError::MissingColorPalette)),
+            },
             None if self.global_palette => None,
-            _ => return Err(EncodingError::from(EncodingFormatError::MissingColorPalette)),
+            None => return Err(EncodingError::from(EncodingFormatError::MissingColorPalette)),
         };
         let mut tmp = tmp_buf::<10>();
         tmp.write_le(Block::Image as u8)?;
@@ -222,7 +766,7 @@ impl<W: Write> Encoder<W> {
         tmp.write_le(frame.width)?;
         tmp.write_le(frame.height)?;
         tmp.write_le(flags)?;
-        let writer = self.writer()?;
+        let writer = self.w.as_mut().ok_or(io::Error::from(io::ErrorKind::Other))?;
         tmp.finish(&mut *writer)?;
         if let Some((palette, padding)) = palette {
             Self::write_color_table(writer, palette, padding)?;
@@ -230,6 +774,30 @@ impl<W: Write> Encoder<W> {
         Ok(())
     }
 
+    fn write_padding_frame(&mut self, pad: PadFirstFrame) -> Result<(), EncodingError> {
+        let (index, palette) = match pad {
+            PadFirstFrame::Index(index) => (index, None),
+            PadFirstFrame::Color(color) => (0, Some(color.to_vec())),
+        };
+        let len = buffer_len::<usize>(self.width, self.height, 1).ok_or(EncodingFormatError::DimensionsTooLarge)?;
+        let mut pixels = Vec::new();
+        pixels.try_reserve_exact(len).map_err(|_| io::Error::from(io::ErrorKind::Other))?;
+        pixels.resize(len, index);
+        let frames_written = self.frames_written;
+        // The index is one of the global palette
+        self.write_frame(&Frame {
+            width: self.width,
+            height: self.height,
+            dispose: DisposalMethod::Keep,
+            palette,
+            buffer: Cow::Owned(pixels),
+            ..Frame::default()
+        })?;
+        // It's not one of the frames that the indices in errors count
+        self.frames_written = frames_written;
+        Ok(())
+    }
+
     fn write_image_block(&mut self, data: &[u8]) -> Result<(), EncodingError> {
         self.buffer.clear();
         self.buffer.try_reserve(data.len() / 4)
@@ -237,18 +805,18 @@ impl<W: Write> Encoder<W> {
         lzw_encode(data, &mut self.buffer);
 
         let writer = self.w.as_mut().ok_or(io::Error::from(io::ErrorKind::Other))?;
-        Self::write_encoded_image_block(writer, &self.buffer)
+        Self::write_encoded_image_block(writer, &self.buffer, self.profile.sub_block_size)
     }
 
-    fn write_encoded_image_block(writer: &mut W, data_with_min_code_size: &[u8]) -> Result<(), EncodingError> {
+    fn write_encoded_image_block(writer: &mut Sink<W>, data_with_min_code_size: &[u8], sub_block_size: u8) -> Result<(), EncodingError> {
         let (&min_code_size, data) = data_with_min_code_size.split_first().unwrap_or((&2, &[]));
         writer.write_le(min_code_size)?;
 
         // Write blocks. `chunks_exact` seems to be slightly faster
         // than `chunks` according to both Rust docs and benchmark results.
-        let mut iter = data.chunks_exact(0xFF);
+        let mut iter = data.chunks_exact(usize::from(sub_block_size));
         for full_block in iter.by_ref() {
-            writer.write_le(0xFFu8)?;
+            writer.write_le(sub_block_size)?;
             writer.write_all(full_block)?;
         }
         let last_block = iter.remainder();
@@ -256,11 +824,14 @@ impl<W: Write> Encoder<W> {
             writer.write_le(last_block.len() as u8)?;
             writer.write_all(last_block)?;
         }
-        writer.write_le(0u8).map_err(Into::into)
+        // The code size, the data with a length byte per sub-block, and the terminator
+        event!(DEBUG, size = data.len() + data.len() / usize::from(sub_block_size) + usize::from(!last_block.is_empty()) + 2, "image data written");
+        writer.write_le(0u8)?;
+        writer.block_boundary(BlockKind::Image).map_err(Into::into)
     }
 
-    fn write_color_table(writer: &mut W, table: &[u8], padding: usize) -> Result<(), EncodingError> {
-        writer.write_all(table)?;
+    fn write_color_table(writer: &mut Sink<W>, table: Palette<'_>, padding: usize) -> Result<(), EncodingError> {
+        table.write_rgb(writer)?;
         // Waste some space as of gif spec
         for _ in 0..padding {
             writer.write_all(&[0, 0, 0])?;
@@ -268,15 +839,15 @@ impl<W: Write> Encoder<W> {
         Ok(())
     }
 
-    /// returns rounded palette size, number of missing colors, and table size flag
-    fn check_color_table(table: &[u8]) -> Result<(&[u8], usize, u8), EncodingError> {
-        let num_colors = table.len() / 3;
+    /// returns number of missing colors, and table size flag
+    fn check_color_table(&self, table: Palette<'_>) -> Result<(usize, u8), EncodingError> {
+        let num_colors = table.len();
         if num_colors > 256 {
             return Err(EncodingError::from(EncodingFormatError::TooManyColors));
         }
-        let table_size = flag_size(num_colors);
+        let table_size = if self.profile.full_palettes { 7 } else { flag_size(num_colors) };
         let padding = (2 << table_size) - num_colors;
-        Ok((&table[..num_colors * 3], padding, table_size))
+        Ok((padding, table_size))
     }
 
     /// Writes an extension to the image.
@@ -289,58 +860,66 @@ impl<W: Write> Encoder<W> {
         if let Repetitions(Repeat::Finite(0)) = extension {
             return Ok(());
         }
-        let writer = self.writer()?;
-        writer.write_le(Block::Extension as u8)?;
         match extension {
             Control { flags, delay, trns } => {
-                let mut tmp = tmp_buf::<6>();
-                tmp.write_le(Extension::Control as u8)?;
-                tmp.write_le(4u8)?;
-                tmp.write_le(flags)?;
-                tmp.write_le(delay)?;
-                tmp.write_le(trns)?;
-                tmp.finish(&mut *writer)?;
+                let [delay1, delay2] = delay.to_le_bytes();
+                self.write_extension_blocks(AnyExtension(Extension::Control as u8), [&[flags, delay1, delay2, trns][..]])
             }
             Repetitions(repeat) => {
-                let mut tmp = tmp_buf::<17>();
-                tmp.write_le(Extension::Application as u8)?;
-                tmp.write_le(11u8)?;
-                tmp.write_all(b"NETSCAPE2.0")?;
-                tmp.write_le(3u8)?;
-                tmp.write_le(1u8)?;
-                tmp.write_le(match repeat {
+                let count = match repeat {
                     Repeat::Finite(no) => no,
                     Repeat::Infinite => 0u16,
-                })?;
-                tmp.finish(&mut *writer)?;
+                };
+                let [count1, count2] = count.to_le_bytes();
+                self.write_extension_blocks(AnyExtension(Extension::Application as u8), [&b"NETSCAPE2.0"[..], &[1, count1, count2]])
             }
         }
-        writer.write_le(0u8).map_err(Into::into)
+    }
+
+    /// Writes an extension with the given label and data to the image.
+    ///
+    /// This method can be used to write an extension that [`ExtensionData`] doesn't cover. Each
+    /// of `blocks` becomes a sub-block, and those longer than 255 bytes are divided into several.
+    /// Empty blocks are left out, since a sub-block of length 0 terminates the extension. Without
+    /// any blocks, the extension only has the block terminator.
+    pub fn write_extension_blocks<'a>(&mut self, label: AnyExtension, blocks: impl IntoIterator<Item = &'a [u8]>) -> Result<(), EncodingError> {
+        Self::write_extension_framed(self.writer()?, label, blocks).map_err(Into::into)
     }
 
     /// Writes a raw extension to the image.
     ///
     /// This method can be used to write an unsupported extension to the file. `func` is the extension
     /// identifier (e.g. `Extension::Application as u8`). `data` are the extension payload blocks. If any
-    /// contained slice has a lenght > 255 it is automatically divided into sub-blocks.
+    /// contained slice has a lenght > 255 it is automatically divided into sub-blocks.
+    #[deprecated(since = "0.13.2", note = "use `write_extension_blocks`, which writes the same bytes")]
     pub fn write_raw_extension(&mut self, func: AnyExtension, data: &[&[u8]]) -> io::Result<()> {
-        let writer = self.writer()?;
+        Self::write_extension_framed(self.writer()?, func, data.iter().copied())
+    }
+
+    /// Writes the introducer, the label, the sub-blocks of `blocks` and the block terminator of
+    /// an extension
+    fn write_extension_framed<'a>(writer: &mut Sink<W>, label: AnyExtension, blocks: impl IntoIterator<Item = &'a [u8]>) -> io::Result<()> {
         writer.write_le(Block::Extension as u8)?;
-        writer.write_le(func.0)?;
-        for block in data {
+        writer.write_le(label.0)?;
+        for block in blocks {
             for chunk in block.chunks(0xFF) {
                 writer.write_le(chunk.len() as u8)?;
                 writer.write_all(chunk)?;
             }
         }
-        writer.write_le(0u8)
+        writer.write_le(0u8)?;
+        writer.block_boundary(BlockKind::Extension)
     }
 
     /// Writes a frame to the image, but expects `Frame.buffer` to contain LZW-encoded data
-    /// from [`Frame::make_lzw_pre_encoded`].
+    /// from [`Frame::make_lzw_pre_encoded`]. A buffer of any [`Frame::pixel_format`] other than
+    /// `Lzw` or `Unknown` is an error.
     ///
     /// Note: This function also writes a control extension if necessary.
     pub fn write_lzw_pre_encoded_frame(&mut self, frame: &Frame<'_>) -> Result<(), EncodingError> {
+        if !matches!(frame.pixel_format, PixelFormat::Lzw | PixelFormat::Unknown) {
+            return Err(EncodingFormatError::UnexpectedPixelFormat(frame.pixel_format).into());
+        }
         // empty data is allowed
         if let Some(&min_code_size) = frame.buffer.first() {
             if min_code_size > 11 || min_code_size < 2 {
@@ -348,9 +927,10 @@ impl<W: Write> Encoder<W> {
             }
         }
 
+        let _span = span!(DEBUG, "frame", left = frame.left, top = frame.top, width = frame.width, height = frame.height, interlaced = frame.interlaced);
         self.write_frame_header(frame)?;
-        let writer = self.writer()?;
-        Self::write_encoded_image_block(writer, &frame.buffer)
+        let writer = self.w.as_mut().ok_or(io::Error::from(io::ErrorKind::Other))?;
+        Self::write_encoded_image_block(writer, &frame.buffer, self.profile.sub_block_size)
     }
 
     /// Writes the logical screen desriptor
@@ -367,37 +947,150 @@ impl<W: Write> Encoder<W> {
 
     /// Gets a reference to the writer instance used by this encoder.
     pub fn get_ref(&self) -> &W {
-        self.w.as_ref().unwrap()
+        &self.w.as_ref().unwrap().w
     }
 
     /// Gets a mutable reference to the writer instance used by this encoder.
     ///
     /// It is inadvisable to directly write to the underlying writer.
     pub fn get_mut(&mut self) -> &mut W {
-        self.w.as_mut().unwrap()
+        &mut self.w.as_mut().unwrap().w
     }
 
     /// Finishes writing, and returns the `io::Write` instance used by this encoder
-    pub fn into_inner(mut self) -> io::Result<W> {
+    ///
+    /// If no frame has been written, the file is valid but has no frames.
+    ///
+    /// If [`Encoder::check_frame_consistency`] is enabled, this fails if any frame written was
+    /// outside of the logical screen.
+    pub fn into_inner(mut self) -> Result<W, EncodingError> {
+        if let Some((frame_index, rect)) = self.frame_outside_canvas {
+            return Err(EncodingError::FrameOutsideCanvas { frame_index, rect, canvas: (self.width, self.height) });
+        }
         self.write_trailer()?;
-        self.w.take().ok_or(io::Error::from(io::ErrorKind::Other))
+        Ok(self.w.take().ok_or(io::Error::from(io::ErrorKind::Other))?.w)
     }
 
     /// Write the final tailer.
-    fn write_trailer(&mut self) -> io::Result<()> {
-        self.writer()?.write_le(Block::Trailer as u8)
+    fn write_trailer(&mut self) -> Result<(), EncodingError> {
+        // A file without frames has no global palette
+        self.write_held_header(&[])?;
+        let writer = self.writer()?;
+        writer.write_le(Block::Trailer as u8)?;
+        writer.block_boundary(BlockKind::Trailer).map_err(Into::into)
     }
 
     #[inline]
-    fn writer(&mut self) -> io::Result<&mut W> {
+    fn writer(&mut self) -> io::Result<&mut Sink<W>> {
         self.w.as_mut().ok_or(io::Error::from(io::ErrorKind::Other))
     }
 }
 
+/// Largest difference in a color channel between an opaque pixel and its palette entry in the
+/// frames of [`Encoder::write_truecolor_frame`]. The palette of evenly spaced colors never exceeds it.
+#[cfg(feature = "color_quant")]
+const TRUECOLOR_MAX_ERROR: u8 = 25;
+
+/// Levels of red, green and blue in the palette of evenly spaced colors, 252 colors in total
+#[cfg(feature = "color_quant")]
+const TRUECOLOR_LEVELS: [u8; 3] = [6, 7, 6];
+
+/// Reduces a strip of RGBA pixels with more than 256 colors, where alpha is either 0 or 0xFF,
+/// to a frame of opaque colors and one transparent entry after them.
+///
+/// The NeuQuant palette is used if every pixel is within `TRUECOLOR_MAX_ERROR` of its entry, and
+/// otherwise the palette of evenly spaced colors.
+#[cfg(feature = "color_quant")]
+fn quantize_strip(width: u16, height: u16, rgba: &[u8]) -> Frame<'static> {
+    let opaque: Vec<u8> = rgba.chunks_exact(4).filter(|pix| pix[3] != 0).flatten().copied().collect();
+    let nq = color_quant::NeuQuant::new(10, 255, &opaque);
+    let mut palette = nq.color_map_rgb();
+    let mut transparent = palette.len() / 3;
+    let mut buffer: Vec<u8> = rgba.chunks_exact(4)
+        .map(|pix| if pix[3] == 0 { transparent } else { nq.index_of(pix) } as u8)
+        .collect();
+    let within_bound = rgba.chunks_exact(4).zip(&buffer)
+        .filter(|(pix, _)| pix[3] != 0)
+        .all(|(pix, &index)| palette[usize::from(index) * 3..][..3].iter().zip(pix).all(|(&a, &b)| a.abs_diff(b) <= TRUECOLOR_MAX_ERROR));
+    if !within_bound {
+        // Rounded to the closest level, each channel is off by at most half the distance
+        // between two levels, 25.5 for 6 of them
+        let level = |value: u8, levels: u8| (u16::from(value) * u16::from(levels - 1) + 127) / 255;
+        palette.clear();
+        for r in 0..TRUECOLOR_LEVELS[0] {
+            for g in 0..TRUECOLOR_LEVELS[1] {
+                for b in 0..TRUECOLOR_LEVELS[2] {
+                    palette.extend(TRUECOLOR_LEVELS.iter().zip([r, g, b]).map(|(&levels, i)| {
+                        ((u16::from(i) * 255 + u16::from(levels - 1) / 2) / u16::from(levels - 1)) as u8
+                    }));
+                }
+            }
+        }
+        transparent = palette.len() / 3;
+        let [_, g_levels, b_levels] = TRUECOLOR_LEVELS.map(u16::from);
+        buffer.clear();
+        buffer.extend(rgba.chunks_exact(4).map(|pix| if pix[3] == 0 {
+            transparent as u8
+        } else {
+            let [r, g, b] = [0, 1, 2].map(|c| level(pix[c], TRUECOLOR_LEVELS[c]));
+            ((r * g_levels + g) * b_levels + b) as u8
+        }));
+    }
+    let has_transparent = rgba.chunks_exact(4).any(|pix| pix[3] == 0);
+    if has_transparent {
+        palette.extend_from_slice(&[0; 3]);
+    }
+    Frame::from_palette_pixels(width, height, buffer, palette, has_transparent.then(|| transparent as u8))
+}
+
+/// Splits the image into strips of `(top, height)` with at most 256 colors each, or into `max_strips` even strips.
+#[cfg(feature = "color_quant")]
+fn truecolor_strips(rgba: &[u8], line_length: usize, height: u16, max_strips: u8) -> Vec<(u16, u16)> {
+    let color_of = |pix: &[u8]| if pix[3] == 0 { [0; 4] } else { [pix[0], pix[1], pix[2], 0xFF] };
+
+    let mut strips = Vec::new();
+    let mut colors = HashSet::new();
+    let mut top = 0;
+    let mut fits = true;
+    for y in 0..height {
+        let line = &rgba[y as usize * line_length..][..line_length];
+        let line_colors: HashSet<[u8; 4]> = line.chunks_exact(4).map(color_of).collect();
+        if colors.len() + line_colors.difference(&colors).count() > 256 {
+            if line_colors.len() > 256 || strips.len() + 1 >= usize::from(max_strips) {
+                fits = false;
+                break;
+            }
+            strips.push((top, y - top));
+            top = y;
+            colors.clear();
+        }
+        colors.extend(line_colors);
+    }
+    if fits {
+        strips.push((top, height - top));
+        return strips;
+    }
+
+    let max_strips = u16::from(max_strips);
+    let strip_height = (height / max_strips + u16::from(height % max_strips != 0)).max(1);
+    (0..height).step_by(strip_height.into())
+        .map(|top| (top, strip_height.min(height - top)))
+        .collect()
+}
+
 /// Encodes the data into the provided buffer.
 ///
 /// The first byte is the minimum code size, followed by LZW data.
-fn lzw_encode(data: &[u8], buffer: &mut Vec<u8>) {
+pub(crate) fn lzw_encode(data: &[u8], buffer: &mut Vec<u8>) {
+    // Frames of one color, like blank ones, don't need a dictionary
+    if let Some((&index, rest)) = data.split_first() {
+        if rest.iter().all(|&byte| byte == index) {
+            let min_code_size = (u32::from(index) + 1).max(4).next_power_of_two().trailing_zeros() as u8;
+            buffer.push(min_code_size);
+            lzw_encode_run(index, data.len(), min_code_size, buffer);
+            return;
+        }
+    }
     let mut max_byte = 0;
     for &byte in data {
         if byte > max_byte {
@@ -417,39 +1110,177 @@ fn lzw_encode(data: &[u8], buffer: &mut Vec<u8>) {
     buffer.truncate(len + 1);
 }
 
+/// Appends the LZW data of `len` copies of `index`, the same codes as those of the general encoder.
+///
+/// LZW adds a string one pixel longer than the last one for every code, so the codes of a run
+/// are those of strings of 1, 2, 3… pixels, and are computed without a dictionary.
+fn lzw_encode_run(index: u8, mut len: usize, min_code_size: u8, buffer: &mut Vec<u8>) {
+    const MAX_CODE: u16 = (1 << 12) - 1;
+    let clear = 1u16 << min_code_size;
+    // The width of the codes follows the dictionary of the decoder, which adds a string for every
+    // code after the first one, and is one string behind the encoder
+    let mut code_size = min_code_size + 1;
+    let mut next_code = clear + 2;
+    let mut first = true;
+    // Length of the longest string in the encoder's dictionary
+    let mut longest = 1;
+    let mut bits = BitWriter { buffer, acc: 0, len: 0 };
+    bits.write(clear, code_size);
+    while len > 0 {
+        if longest > usize::from(MAX_CODE - clear) && len >= longest {
+            // The dictionary is full
+            bits.write(clear, code_size);
+            code_size = min_code_size + 1;
+            next_code = clear + 2;
+            first = true;
+            longest = 1;
+        }
+        let n = longest.min(len);
+        // Strings of 2 or more pixels follow the clear and end codes
+        let code = if n == 1 { u16::from(index) } else { clear + n as u16 };
+        bits.write(code, code_size);
+        len -= n;
+        if !first && next_code <= MAX_CODE {
+            next_code += 1;
+            if next_code == 1 << code_size && code_size < 12 {
+                code_size += 1;
+            }
+        }
+        first = false;
+        longest += 1;
+    }
+    bits.write(clear + 1, code_size);
+    bits.flush();
+}
+
+/// Packs codes into bytes, least significant bit first
+struct BitWriter<'a> {
+    buffer: &'a mut Vec<u8>,
+    acc: u32,
+    len: u8,
+}
+
+impl BitWriter<'_> {
+    fn write(&mut self, code: u16, size: u8) {
+        self.acc |= u32::from(code) << self.len;
+        self.len += size;
+        while self.len >= 8 {
+            self.buffer.push(self.acc as u8);
+            self.acc >>= 8;
+            self.len -= 8;
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.len > 0 {
+            self.buffer.push(self.acc as u8);
+        }
+    }
+}
+
+/// Appends the rows of the pixels in the order in which they're stored in an interlaced frame
+fn interlace_into(pixels: &[u8], width: u16, height: u16, out: &mut Vec<u8>) {
+    let width = usize::from(width);
+    for (first_row, step) in [(0, 8), (4, 8), (2, 4), (1, 2)] {
+        for row in (first_row..usize::from(height)).step_by(step) {
+            if let Some(line) = pixels.get(row * width..).and_then(|line| line.get(..width)) {
+                out.extend_from_slice(line);
+            }
+        }
+    }
+}
+
 impl Frame<'_> {
     /// Replace frame's buffer with a LZW-compressed one for use with [`Encoder::write_lzw_pre_encoded_frame`].
     ///
     /// Frames can be compressed in any order, separately from the `Encoder`, which can be used to compress frames in parallel.
+    /// Like in [`Encoder::write_frame`], the rows of interlaced frames are reordered.
     pub fn make_lzw_pre_encoded(&mut self) {
         let mut buffer = Vec::new();
         buffer.try_reserve(self.buffer.len() / 2).expect("OOM");
-        lzw_encode(&self.buffer, &mut buffer);
+        if self.interlaced {
+            let mut rows = Vec::new();
+            rows.try_reserve_exact(self.buffer.len()).expect("OOM");
+            interlace_into(&self.buffer, self.width, self.height, &mut rows);
+            lzw_encode(&rows, &mut buffer);
+        } else {
+            lzw_encode(&self.buffer, &mut buffer);
+        }
         self.buffer = Cow::Owned(buffer);
+        self.pixel_format = PixelFormat::Lzw;
     }
 }
 
 /// GIF encoder.
+///
+/// Dropping the encoder writes the trailer, and ignores any error, so `Drop` never panics. Use
+/// [`Encoder::into_inner`] to find out whether the file has been finished.
 pub struct Encoder<W: Write> {
-    w: Option<W>,
+    w: Option<Sink<W>>,
     global_palette: bool,
+    /// The global palette, if it's the promoted palette of the first frame
+    promoted_palette: Option<Vec<u8>>,
     width: u16,
     height: u16,
     buffer: Vec<u8>,
+    check_frame_consistency: bool,
+    /// Number of frames written, without the padding frame
+    frames_written: usize,
+    /// The first frame outside of the screen, found only if `check_frame_consistency` is on
+    frame_outside_canvas: Option<(usize, Rect)>,
+    /// Right and bottom edge of the frames written, the smallest screen that contains them
+    extent: (u32, u32),
+    /// The first frame that doesn't fit into any screen, see `into_inner_grown`
+    frame_outside_max_canvas: Option<(usize, Rect)>,
+    pad_first_frame: Option<PadFirstFrame>,
+    wrote_frame: bool,
+    /// Padded color table and its size flag, for frames without a palette
+    shared_palette: Option<(Vec<u8>, u8)>,
+    profile: CompatProfile,
 }
 
-impl<W: Write> Drop for Encoder<W> {
-    #[cfg(feature = "raii_no_panic")]
-    fn drop(&mut self) {
-        if self.w.is_some() {
-            let _ = self.write_trailer();
+impl<W: Write + Seek> Encoder<W> {
+    /// Writes the trailer like [`Encoder::into_inner`], and then grows the logical screen in
+    /// the header to contain every frame that has been written.
+    ///
+    /// The screen never shrinks, so an encoder created with a screen of 0×0 pixels gets the
+    /// smallest one that contains the frames. That way, the size doesn't need to be known up
+    /// front. [`Encoder::pad_first_frame`] only knows the size given to the encoder, and
+    /// [`Encoder::check_frame_consistency`] has no effect.
+    ///
+    /// The header is overwritten by seeking back to it, so `w` must not have been moved or
+    /// written to by anything else while the encoder had it. Afterwards, `w` is at the end of
+    /// the file again. The observers of [`Encoder::with_sink_observers`] don't see the new size.
+    ///
+    /// Fails with [`EncodingError::FrameOutsideCanvas`] if a frame doesn't fit into any screen,
+    /// because it extends past 65535 pixels.
+    pub fn into_inner_grown(mut self) -> Result<W, EncodingError> {
+        if let Some((frame_index, rect)) = self.frame_outside_max_canvas {
+            return Err(EncodingError::FrameOutsideCanvas { frame_index, rect, canvas: (u16::MAX, u16::MAX) });
         }
+        self.write_trailer()?;
+        // Frames beyond the largest screen have been reported above
+        let width = self.width.max(self.extent.0 as u16);
+        let height = self.height.max(self.extent.1 as u16);
+        let mut sink = self.w.take().ok_or(io::Error::from(io::ErrorKind::Other))?;
+        if (width, height) != (self.width, self.height) {
+            // The screen size follows the signature of 6 bytes
+            let back = i64::try_from(sink.written - 6).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+            sink.w.seek(io::SeekFrom::Current(-back))?;
+            let mut tmp = tmp_buf::<4>();
+            tmp.write_le(width)?;
+            tmp.write_le(height)?;
+            tmp.finish(&mut sink.w)?;
+            sink.w.seek(io::SeekFrom::Current(back - 4))?;
+        }
+        Ok(sink.w)
     }
+}
 
-    #[cfg(not(feature = "raii_no_panic"))]
+impl<W: Write> Drop for Encoder<W> {
     fn drop(&mut self) {
         if self.w.is_some() {
-            self.write_trailer().unwrap();
+            let _ = self.write_trailer();
         }
     }
 }
@@ -488,6 +1319,122 @@ fn test_flag_size() {
     }
 }
 
+/// Receives everything that an [`Encoder`] writes, see [`Encoder::with_sink_observers`]
+///
+/// Panics in the methods are caught and turned into errors only if they unwind, see
+/// [`Encoder::with_sink_observers`].
+pub trait SinkObserver: Send {
+    /// Called with bytes right after the writer of the encoder has accepted them.
+    fn on_bytes(&mut self, bytes: &[u8]);
+
+    /// Called after a block of the file has been written completely.
+    fn on_block_boundary(&mut self, kind: BlockKind) {
+        let _ = kind;
+    }
+}
+
+/// Parts of the file, see [`SinkObserver::on_block_boundary`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BlockKind {
+    /// The signature, the logical screen descriptor and the global palette.
+    Header,
+    /// An extension, including the graphic control extension of a frame.
+    Extension,
+    /// The image descriptor, local palette and data of a frame.
+    Image,
+    /// The trailer, which ends the file.
+    Trailer,
+}
+
+/// The writer of the encoder, which passes what has been written on to the observers
+struct Sink<W> {
+    w: W,
+    observers: Vec<Box<dyn SinkObserver>>,
+    /// An observer has panicked, and may have missed some bytes
+    poisoned: bool,
+    /// Blocks that are held back until the header is written
+    held: Option<HeldBlocks>,
+    /// Number of bytes that `w` has accepted, which start with the header
+    written: u64,
+}
+
+/// Bytes written to a [`Sink`] while it holds them back, and where each block ends
+#[derive(Default)]
+struct HeldBlocks {
+    bytes: Vec<u8>,
+    boundaries: Vec<(usize, BlockKind)>,
+}
+
+impl<W: Write> Sink<W> {
+    fn notify(&mut self, mut call: impl FnMut(&mut dyn SinkObserver)) -> io::Result<()> {
+        if self.poisoned {
+            return Err(observer_panicked());
+        }
+        for observer in &mut self.observers {
+            #[cfg(feature = "std")]
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| call(&mut **observer)));
+            #[cfg(not(feature = "std"))]
+            let result: Result<(), ()> = Ok(call(&mut **observer));
+            if result.is_err() {
+                self.poisoned = true;
+                return Err(observer_panicked());
+            }
+        }
+        Ok(())
+    }
+
+    fn block_boundary(&mut self, kind: BlockKind) -> io::Result<()> {
+        if let Some(held) = &mut self.held {
+            held.boundaries.try_reserve(1).map_err(|_| io::Error::from(io::ErrorKind::Other))?;
+            held.boundaries.push((held.bytes.len(), kind));
+            return Ok(());
+        }
+        self.notify(|observer| observer.on_block_boundary(kind))
+    }
+
+    /// Writes the blocks that have been held back, block by block
+    fn write_held(&mut self, held: HeldBlocks) -> io::Result<()> {
+        let mut start = 0;
+        for (end, kind) in held.boundaries {
+            self.write_all(&held.bytes[start..end])?;
+            self.block_boundary(kind)?;
+            start = end;
+        }
+        self.write_all(&held.bytes[start..])
+    }
+}
+
+impl<W: Write> Write for Sink<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.poisoned {
+            return Err(observer_panicked());
+        }
+        if let Some(held) = &mut self.held {
+            held.bytes.try_reserve(buf.len()).map_err(|_| io::Error::from(io::ErrorKind::Other))?;
+            held.bytes.extend_from_slice(buf);
+            return Ok(buf.len());
+        }
+        // Only the writer knows how much of `buf` it takes, so the observers come second
+        let len = self.w.write(buf)?;
+        self.written += len as u64;
+        let written = &buf[..len];
+        self.notify(|observer| observer.on_bytes(written))?;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+#[cold]
+// `no_std_io` has no `io::Error::other`
+#[allow(clippy::io_other_error)]
+fn observer_panicked() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "a sink observer has panicked")
+}
+
 struct Buf<const N: usize> {
     buf: [u8; N],
     pos: usize,
@@ -520,6 +1467,22 @@ impl<const N: usize> Buf<N> {
     }
 }
 
+#[test]
+fn uniform_frames_encode_like_the_general_encoder() {
+    // Around sub-block sizes and changes of the code size, and past a full dictionary
+    let lens: Vec<usize> = (1..600).chain([4093, 4094, 4095, 4096, 4097, 1 << 16]).collect();
+    for index in [0, 1, 5, 200, 255] {
+        let full = if index == 0 || index == 200 { &[8_390_000][..] } else { &[] };
+        for &len in lens.iter().chain(full) {
+            let data = alloc::vec![index; len];
+            let mut buffer = Vec::new();
+            lzw_encode(&data, &mut buffer);
+            let expected = LzwEncoder::new(BitOrder::Lsb, buffer[0]).encode(&data).unwrap();
+            assert_eq!(buffer[1..], expected[..], "{len} pixels of {index}");
+        }
+    }
+}
+
 #[test]
 fn error_cast() {
     let _: Box<dyn error::Error> = EncodingError::from(EncodingFormatError::MissingColorPalette).into();
@@ -112,25 +112,32 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 #![deny(missing_docs)]
 
+#[macro_use]
+mod trace;
 mod traits;
 mod common;
 mod reader;
 mod encoder;
+pub mod edit;
+pub mod retime;
+pub mod synth;
 
 extern crate alloc;
 
-pub use crate::common::{AnyExtension, Extension, DisposalMethod, Frame};
+pub use crate::common::{validate_frame_in_screen, AnyExtension, DelayPolicy, Extension, DisposalMethod, Frame, FrameError, GeometryError, GraphicControl, PixelFormat, Rect, ReservedDisposal, ScreenDescriptor};
 
-pub use crate::reader::{DecodingError, DecodingFormatError};
-pub use crate::reader::{ColorOutput, MemoryLimit};
-pub use crate::reader::{DecodeOptions, Decoder, Version};
+pub use crate::reader::{DecodingError, DecodingFormatError, DecodingFormatErrorKind, DecodingWarning, Incomplete, UsageError};
+pub use crate::reader::{ColorOutput, FrameConsistency, MemoryLimit};
+pub use crate::reader::{probe, DecodeOptions, Decoder, FrameIndexEntry, FrameMetadata, GifInfo, Index, IndexedFrames, LzwStats, SliceDecoder, Version};
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+pub use crate::reader::AsyncDecoder;
 
-pub use crate::encoder::{Encoder, ExtensionData, Repeat, EncodingError, EncodingFormatError};
+pub use crate::encoder::{BlockKind, CompatProfile, Encoder, ExtensionData, PadFirstFrame, Palette, Repeat, EncodingError, EncodingFormatError, SinkObserver};
 
 /// Low-level, advanced decoder. Prefer [`Decoder`] instead, which can stream frames too.
 pub mod streaming_decoder {
     pub use crate::common::Block;
-    pub use crate::reader::{Decoded, FrameDataType, FrameDecoder, OutputBuffer, StreamingDecoder};
+    pub use crate::reader::{Decoded, ExtensionBlock, FrameDataType, FrameDecoder, OutputBuffer, StreamingDecoder};
 }
 
 #[cfg(feature = "color_quant")]
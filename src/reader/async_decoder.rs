@@ -0,0 +1,241 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use core::future::poll_fn;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use no_std_io::io::{self, Read};
+
+use crate::common::{Frame, GraphicControl, ScreenDescriptor};
+use crate::Repeat;
+
+use super::{DecodeOptions, Decoder, DecodingError, DecodingWarning, Input, LzwStats, StreamingDecoder};
+
+/// Size of the chunks read from the async reader
+const CHUNK_SIZE: usize = 8000;
+
+type PollRead<R> = fn(Pin<&mut R>, &mut Context<'_>, &mut [u8]) -> Poll<std::io::Result<usize>>;
+
+/// Data read from the async reader, which the synchronous decoder hasn't consumed yet
+///
+/// Reading returns `WouldBlock` when it runs dry, so that the decoder keeps its progress
+/// while the next chunk is awaited.
+struct PendingInput {
+    buf: Box<[u8]>,
+    start: usize,
+    end: usize,
+    at_eof: bool,
+}
+
+impl PendingInput {
+    fn new() -> Self {
+        Self {
+            buf: vec![0; CHUNK_SIZE].into_boxed_slice(),
+            start: 0,
+            end: 0,
+            at_eof: false,
+        }
+    }
+}
+
+impl Read for PendingInput {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = &self.buf[self.start..self.end];
+        if available.is_empty() && !self.at_eof {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let len = available.len().min(out.len());
+        out[..len].copy_from_slice(&available[..len]);
+        self.start += len;
+        Ok(len)
+    }
+}
+
+/// Converts an error of the async reader, which is a `std` one, into the error type of the decoder
+#[cold]
+fn read_error(err: std::io::Error) -> DecodingError {
+    use std::io::ErrorKind as Std;
+    let kind = match err.kind() {
+        Std::NotFound => io::ErrorKind::NotFound,
+        Std::PermissionDenied => io::ErrorKind::PermissionDenied,
+        Std::ConnectionRefused => io::ErrorKind::ConnectionRefused,
+        Std::ConnectionReset => io::ErrorKind::ConnectionReset,
+        Std::ConnectionAborted => io::ErrorKind::ConnectionAborted,
+        Std::NotConnected => io::ErrorKind::NotConnected,
+        Std::BrokenPipe => io::ErrorKind::BrokenPipe,
+        Std::WouldBlock => io::ErrorKind::WouldBlock,
+        Std::InvalidInput => io::ErrorKind::InvalidInput,
+        Std::InvalidData => io::ErrorKind::InvalidData,
+        Std::TimedOut => io::ErrorKind::TimedOut,
+        Std::Interrupted => io::ErrorKind::Interrupted,
+        Std::UnexpectedEof => io::ErrorKind::UnexpectedEof,
+        _ => io::ErrorKind::Other,
+    };
+    // The message of the `no_std_io` error has to be static, so the original one can't be kept
+    io::Error::new(kind, "reading from the async reader failed").into()
+}
+
+/// GIF decoder reading from an async reader
+///
+/// This drives the same state machine as [`Decoder`], and fetches more data from the reader
+/// whenever the decoder runs out of it.
+///
+/// The futures returned by this decoder are cancellation-safe: dropping one of them keeps
+/// everything read so far, and the next call continues where the dropped one stopped.
+/// Like [`Decoder`], dropping the decoder itself never reads from the reader.
+pub struct AsyncDecoder<R> {
+    decoder: Decoder<PendingInput>,
+    reader: R,
+    poll_read: PollRead<R>,
+}
+
+#[cfg(feature = "tokio")]
+impl<R: tokio::io::AsyncRead + Unpin> AsyncDecoder<R> {
+    /// Reads the header of a GIF from a `tokio` reader
+    pub async fn from_tokio(reader: R, options: DecodeOptions) -> Result<Self, DecodingError> {
+        Self::with_reader(reader, poll_read_tokio::<R>, options).await
+    }
+}
+
+#[cfg(feature = "tokio")]
+fn poll_read_tokio<R: tokio::io::AsyncRead>(reader: Pin<&mut R>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<std::io::Result<usize>> {
+    let mut buf = tokio::io::ReadBuf::new(buf);
+    reader.poll_read(cx, &mut buf).map_ok(|()| buf.filled().len())
+}
+
+#[cfg(feature = "futures-io")]
+impl<R: futures_io::AsyncRead + Unpin> AsyncDecoder<R> {
+    /// Reads the header of a GIF from a `futures-io` reader
+    pub async fn from_futures_io(reader: R, options: DecodeOptions) -> Result<Self, DecodingError> {
+        Self::with_reader(reader, <R as futures_io::AsyncRead>::poll_read, options).await
+    }
+}
+
+impl<R: Unpin> AsyncDecoder<R> {
+    async fn with_reader(reader: R, poll_read: PollRead<R>, options: DecodeOptions) -> Result<Self, DecodingError> {
+        let decoder = Decoder::with_no_init(Input::buffered(PendingInput::new()), StreamingDecoder::with_options(&options), options);
+        let mut this = Self { decoder, reader, poll_read };
+        loop {
+            match this.decoder.read_header() {
+                Ok(()) => return Ok(this),
+                Err(err) if err.is_would_block() => this.fill().await?,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Reads the next frame from the image, see [`Decoder::read_next_frame`]
+    pub async fn read_next_frame(&mut self) -> Result<Option<&Frame<'static>>, DecodingError> {
+        let has_frame = loop {
+            match self.decoder.read_next_frame() {
+                Ok(frame) => break frame.is_some(),
+                Err(err) if err.is_would_block() => self.fill().await?,
+                Err(err) => return Err(err),
+            }
+        };
+        Ok(if has_frame { Some(&self.decoder.current_frame) } else { None })
+    }
+
+    /// Reads the next chunk from the async reader.
+    ///
+    /// Nothing is lost if this is cancelled, because the chunk is stored in the same poll that
+    /// reads it.
+    // The async features require Rust 1.64, which has `poll_fn`
+    #[allow(clippy::incompatible_msrv)]
+    async fn fill(&mut self) -> Result<(), DecodingError> {
+        let Self { decoder, reader, poll_read } = self;
+        let poll_read = *poll_read;
+        let input = decoder.get_mut();
+        debug_assert_eq!(input.start, input.end);
+        let len = poll_fn(|cx| poll_read(Pin::new(&mut *reader), cx, &mut input.buf)).await.map_err(read_error)?;
+        input.start = 0;
+        input.end = len;
+        input.at_eof = len == 0;
+        Ok(())
+    }
+
+    /// Returns the color palette relevant for the frame that has been decoded
+    #[inline]
+    pub fn palette(&self) -> Result<&[u8], DecodingError> {
+        self.decoder.palette()
+    }
+
+    /// The global color palette
+    #[inline]
+    pub fn global_palette(&self) -> Option<&[u8]> {
+        self.decoder.global_palette()
+    }
+
+    /// True if the file has no global palette, and `Self::global_palette` is a generated one
+    /// because a frame without a local palette has been reached
+    #[inline]
+    pub fn has_fallback_palette(&self) -> bool {
+        self.decoder.has_fallback_palette()
+    }
+
+    /// Width of the image
+    #[inline]
+    pub fn width(&self) -> u16 {
+        self.decoder.width()
+    }
+
+    /// Height of the image
+    #[inline]
+    pub fn height(&self) -> u16 {
+        self.decoder.height()
+    }
+
+    /// The logical screen descriptor from the header
+    #[inline]
+    pub fn screen_descriptor(&self) -> &ScreenDescriptor {
+        self.decoder.screen_descriptor()
+    }
+
+    /// The last graphic control extension that has been decoded, see
+    /// [`StreamingDecoder::last_graphic_control`](crate::streaming_decoder::StreamingDecoder::last_graphic_control)
+    #[inline]
+    pub fn last_graphic_control(&self) -> Option<&GraphicControl> {
+        self.decoder.last_graphic_control()
+    }
+
+    /// Statistics of the LZW data of the frame read last, see
+    /// [`StreamingDecoder::last_frame_lzw_stats`](crate::streaming_decoder::StreamingDecoder::last_frame_lzw_stats)
+    #[inline]
+    pub fn last_frame_lzw_stats(&self) -> Option<LzwStats> {
+        self.decoder.last_frame_lzw_stats()
+    }
+
+    /// Deviations that have been accepted so far, see
+    /// [`StreamingDecoder::warnings`](crate::streaming_decoder::StreamingDecoder::warnings)
+    #[inline]
+    pub fn warnings(&self) -> &[DecodingWarning] {
+        self.decoder.warnings()
+    }
+
+    /// The signature and the logical screen descriptor as they have been read, see
+    /// [`StreamingDecoder::descriptor_bytes`](crate::streaming_decoder::StreamingDecoder::descriptor_bytes)
+    #[inline]
+    pub fn descriptor_bytes(&self) -> Option<&[u8; 13]> {
+        self.decoder.descriptor_bytes()
+    }
+
+    /// The image descriptor of the current frame as it has been read, and its position, see
+    /// [`StreamingDecoder::image_descriptor_bytes`](crate::streaming_decoder::StreamingDecoder::image_descriptor_bytes)
+    #[inline]
+    pub fn image_descriptor_bytes(&self) -> Option<(u64, &[u8; 10])> {
+        self.decoder.image_descriptor_bytes()
+    }
+
+    /// Number of loop repetitions
+    #[inline]
+    pub fn repeat(&self) -> Repeat {
+        self.decoder.repeat()
+    }
+
+    /// Returns the async reader.
+    ///
+    /// Data that has been read from it but not decoded yet is lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
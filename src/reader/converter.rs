@@ -1,16 +1,18 @@
 use alloc::borrow::Cow;
 use alloc::vec;
 use alloc::vec::Vec;
-use no_std_io::io;
 use core::mem;
 use core::iter;
-use crate::common::Frame;
+use crate::common::{Frame, PixelFormat};
 use crate::MemoryLimit;
 
-use super::decoder::{DecodingError, OutputBuffer, PLTE_CHANNELS};
+use super::decoder::{DecodingError, DecodingFormatErrorKind, OutputBuffer, PLTE_CHANNELS};
 
 pub(crate) const N_CHANNELS: usize = 4;
 
+/// Number of indexed pixels decoded at a time before they are converted to RGBA or RGB
+const CONVERSION_CHUNK_LEN: usize = 1 << 14;
+
 /// Output mode for the image data
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[repr(u8)]
@@ -23,32 +25,123 @@ pub enum ColorOutput {
     RGBA = 0,
     /// The decoder returns the raw indexed data.
     Indexed = 1,
+    /// The decoder expands the image data to 24bit RGB, without alpha.
+    ///
+    /// Transparent pixels get the matte color, see [`DecodeOptions::set_matte_color`](crate::DecodeOptions::set_matte_color).
+    /// It affects the same methods as `RGBA`.
+    RGB = 2,
+}
+
+impl From<ColorOutput> for PixelFormat {
+    fn from(color_output: ColorOutput) -> Self {
+        match color_output {
+            ColorOutput::RGBA => Self::Rgba,
+            ColorOutput::Indexed => Self::Indexed,
+            ColorOutput::RGB => Self::Rgb,
+        }
+    }
+}
+
+/// Number of colors of the fallback palette, so that every index has a color
+pub(crate) const FALLBACK_PALETTE_COLORS: usize = 256;
+
+type Grayscale = [u8; FALLBACK_PALETTE_COLORS * PLTE_CHANNELS];
+
+/// `shades` evenly spaced shades of gray from black to white, as RGB, followed by white for the
+/// rest of the indices
+const fn grayscale(shades: usize) -> Grayscale {
+    let mut palette = [0xFF; FALLBACK_PALETTE_COLORS * PLTE_CHANNELS];
+    let mut i = 0;
+    while i < shades * PLTE_CHANNELS {
+        palette[i] = (i / PLTE_CHANNELS * 0xFF / (shades - 1)) as u8;
+        i += 1;
+    }
+    palette
+}
+
+static GRAYSCALE_1: Grayscale = grayscale(2);
+static GRAYSCALE_2: Grayscale = grayscale(4);
+static GRAYSCALE_3: Grayscale = grayscale(8);
+static GRAYSCALE_4: Grayscale = grayscale(16);
+static GRAYSCALE_5: Grayscale = grayscale(32);
+static GRAYSCALE_6: Grayscale = grayscale(64);
+static GRAYSCALE_7: Grayscale = grayscale(128);
+static GRAYSCALE_8: Grayscale = grayscale(256);
+
+/// Palette for files without any, with a shade of gray for each value of `bits` per color. It
+/// has `FALLBACK_PALETTE_COLORS` colors, since the frames may use indices past `bits`.
+pub(crate) fn fallback_palette(bits: u8) -> &'static [u8] {
+    match bits {
+        0 | 1 => &GRAYSCALE_1,
+        2 => &GRAYSCALE_2,
+        3 => &GRAYSCALE_3,
+        4 => &GRAYSCALE_4,
+        5 => &GRAYSCALE_5,
+        6 => &GRAYSCALE_6,
+        7 => &GRAYSCALE_7,
+        _ => &GRAYSCALE_8,
+    }
+}
+
+/// The decoder of a frame's palette indices, which also has the global palette, so that the
+/// converter can borrow it for each chunk of pixels instead of keeping a copy
+pub(crate) trait PixelSource {
+    /// Decodes palette indices into the buffer, and returns the number of pixels, or 0 at the end of the frame
+    fn decode_next_pixels(&mut self, out: &mut OutputBuffer<'_>) -> Result<usize, DecodingError>;
+
+    /// Palette of the frames without a local one
+    fn global_palette(&self) -> Option<&[u8]>;
+}
+
+/// A [`PixelSource`] made of a function decoding the indices, and a global palette that isn't
+/// borrowed from it
+pub(crate) struct WithPalette<'p, F> {
+    pub(crate) global_palette: Option<&'p [u8]>,
+    pub(crate) decode: F,
+}
+
+impl<F: FnMut(&mut OutputBuffer<'_>) -> Result<usize, DecodingError>> PixelSource for WithPalette<'_, F> {
+    fn decode_next_pixels(&mut self, out: &mut OutputBuffer<'_>) -> Result<usize, DecodingError> {
+        (self.decode)(out)
+    }
+
+    fn global_palette(&self) -> Option<&[u8]> {
+        self.global_palette
+    }
 }
 
-pub(crate) type FillBufferCallback<'a> = &'a mut dyn FnMut(&mut OutputBuffer<'_>) -> Result<usize, DecodingError>;
+/// Gets the index of a row in the deinterlaced frame, and its pixels
+pub(crate) type RowCallback<'a, E> = &'a mut dyn FnMut(u16, &[u8]) -> Result<(), E>;
 
-/// Deinterlaces and expands to RGBA if needed
+/// Deinterlaces and expands to RGBA or RGB if needed
 pub(crate) struct PixelConverter {
     memory_limit: MemoryLimit,
     color_output: ColorOutput,
+    /// Color of transparent pixels in RGB, instead of the background color
+    matte_color: Option<[u8; PLTE_CHANNELS]>,
+    /// Index of the background color in the global palette
+    background: Option<u8>,
+    deinterlace: bool,
+    error_on_index_outside_palette: bool,
     buffer: Vec<u8>,
-    global_palette: Option<Vec<u8>>,
 }
 
 impl PixelConverter {
-    pub(crate) fn new(color_output: ColorOutput, memory_limit: MemoryLimit) -> Self {
+    pub(crate) fn new(color_output: ColorOutput, matte_color: Option<[u8; PLTE_CHANNELS]>, memory_limit: MemoryLimit, deinterlace: bool, error_on_index_outside_palette: bool) -> Self {
         Self {
             memory_limit,
             color_output,
+            matte_color,
+            background: None,
+            deinterlace,
+            error_on_index_outside_palette,
             buffer: Vec::new(),
-            global_palette: None,
         }
     }
 
     pub(crate) fn check_buffer_size(&mut self, frame: &Frame<'_>) -> Result<usize, DecodingError> {
         let pixel_bytes = self.memory_limit
-            .buffer_size(self.color_output, frame.width, frame.height)
-            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "image is too large"))?;
+            .buffer_size(self.color_output, frame)?;
 
         debug_assert_eq!(
             pixel_bytes, self.buffer_size(frame).unwrap(),
@@ -57,72 +150,106 @@ impl PixelConverter {
         Ok(pixel_bytes)
     }
 
+    /// Reads the frame's pixels into `frame.buffer`.
+    ///
+    /// `progress` is the number of pixels that have already been read by an earlier, interrupted call.
     #[inline]
-    pub(crate) fn read_frame(&mut self, frame: &mut Frame<'_>, data_callback: FillBufferCallback<'_>) -> Result<(), DecodingError> {
+    pub(crate) fn read_frame(&mut self, frame: &mut Frame<'_>, progress: &mut usize, source: &mut dyn PixelSource) -> Result<(), DecodingError> {
         let pixel_bytes = self.check_buffer_size(frame)?;
         let mut vec = match mem::replace(&mut frame.buffer, Cow::Borrowed(&[])) {
-            // reuse buffer if possible without reallocating
+            // continue with the partially read frame
+            Cow::Owned(vec) if *progress > 0 && vec.len() == pixel_bytes => vec,
+            // reuse buffer if possible without reallocating, but none of its pixels
             Cow::Owned(mut vec) if vec.capacity() >= pixel_bytes => {
+                vec.clear();
                 vec.resize(pixel_bytes, 0);
                 vec
             },
             // resizing would realloc anyway, and 0-init is faster than a copy
             _ => vec![0; pixel_bytes],
         };
-        self.read_into_buffer(frame, &mut vec, data_callback)?;
+        let result = self.read_into_buffer_from(frame, &mut vec, progress, source);
         frame.buffer = Cow::Owned(vec);
-        frame.interlaced = false;
+        frame.pixel_format = self.color_output.into();
+        result?;
+        frame.interlaced &= !self.deinterlace;
         Ok(())
     }
 
     #[inline]
     pub(crate) fn buffer_size(&self, frame: &Frame<'_>) -> Option<usize> {
-        self.line_length(frame).checked_mul(frame.height as usize)
+        match self.color_output {
+            ColorOutput::RGBA => frame.required_bytes_rgba(),
+            ColorOutput::RGB => frame.required_bytes_rgb(),
+            ColorOutput::Indexed => frame.required_bytes_indexed(),
+        }
     }
 
     #[inline]
     pub(crate) fn line_length(&self, frame: &Frame<'_>) -> usize {
-        use self::ColorOutput::*;
+        frame.width as usize * self.bytes_per_pixel()
+    }
+
+    #[inline]
+    fn bytes_per_pixel(&self) -> usize {
         match self.color_output {
-            RGBA => frame.width as usize * N_CHANNELS,
-            Indexed => frame.width as usize,
+            ColorOutput::RGBA => N_CHANNELS,
+            ColorOutput::RGB => PLTE_CHANNELS,
+            ColorOutput::Indexed => 1,
         }
     }
 
+    /// Color of transparent pixels in RGB: the configured one, or else the background color, or black
+    fn matte_color(&self, global_palette: Option<&[u8]>) -> [u8; PLTE_CHANNELS] {
+        self.matte_color.or_else(|| {
+            let offset = PLTE_CHANNELS * usize::from(self.background?);
+            let color = global_palette?.get(offset..offset + PLTE_CHANNELS)?;
+            Some([color[0], color[1], color[2]])
+        }).unwrap_or_default()
+    }
+
     /// Use `read_into_buffer` to deinterlace
+    ///
+    /// Adds the number of pixels written to `buf` to `progress`, also when an error is returned.
     #[inline(never)]
-    pub(crate) fn fill_buffer(&mut self, current_frame: &Frame<'_>, mut buf: &mut [u8], data_callback: FillBufferCallback<'_>) -> Result<bool, DecodingError> {
+    pub(crate) fn fill_buffer(&mut self, current_frame: &Frame<'_>, mut buf: &mut [u8], progress: &mut usize, source: &mut dyn PixelSource) -> Result<bool, DecodingError> {
+        // e.g. a frame with a width or height of 0, whose data is skipped with the next frame
+        if buf.is_empty() {
+            return Ok(true);
+        }
         loop {
             let decode_into = match self.color_output {
                 // When decoding indexed data, LZW can write the pixels directly
                 ColorOutput::Indexed => &mut buf[..],
-                // When decoding RGBA, the pixel data will be expanded by a factor of 4,
+                // When decoding RGBA or RGB, the pixel data will be expanded by a factor of 4 or 3,
                 // and it's simpler to decode indexed pixels to another buffer first
-                ColorOutput::RGBA => {
-                    let buffer_size = buf.len() / N_CHANNELS;
+                ColorOutput::RGBA | ColorOutput::RGB => {
+                    let buffer_size = (buf.len() / self.bytes_per_pixel()).min(CONVERSION_CHUNK_LEN);
                     if buffer_size == 0 {
-                        return Err(DecodingError::format("odd-sized buffer"));
+                        return Err(DecodingError::format(DecodingFormatErrorKind::BufferTooSmall, "odd-sized buffer"));
                     }
+                    // Allocated once, so that larger frames later on don't grow it
                     if self.buffer.len() < buffer_size {
-                        self.buffer.resize(buffer_size, 0);
+                        self.buffer.resize(CONVERSION_CHUNK_LEN, 0);
                     }
                     &mut self.buffer[..buffer_size]
                 }
             };
-            match data_callback(&mut OutputBuffer::Slice(decode_into))? {
+            match source.decode_next_pixels(&mut OutputBuffer::Slice(decode_into))? {
                 0 => return Ok(false),
-                bytes_decoded => {
+                pixels_decoded => {
+                    let global_palette = source.global_palette();
+                    let palette: &[u8] = current_frame.palette.as_deref()
+                        .or(global_palette)
+                        .unwrap_or_default(); // next_frame_info already checked it won't happen
                     match self.color_output {
                         ColorOutput::RGBA => {
                             let transparent = current_frame.transparent;
-                            let palette: &[u8] = current_frame.palette.as_deref()
-                                .or(self.global_palette.as_deref())
-                                .unwrap_or_default(); // next_frame_info already checked it won't happen
 
-                            let (pixels, rest) = buf.split_at_mut(bytes_decoded * N_CHANNELS);
+                            let (pixels, rest) = buf.split_at_mut(pixels_decoded * N_CHANNELS);
                             buf = rest;
 
-                            for (rgba, idx) in pixels.chunks_exact_mut(N_CHANNELS).zip(self.buffer.iter().copied().take(bytes_decoded)) {
+                            for (i, (rgba, idx)) in pixels.chunks_exact_mut(N_CHANNELS).zip(self.buffer.iter().copied().take(pixels_decoded)).enumerate() {
                                 let plte_offset = PLTE_CHANNELS * idx as usize;
                                 if let Some(colors) = palette.get(plte_offset..plte_offset+PLTE_CHANNELS) {
                                     rgba[0] = colors[0];
@@ -133,13 +260,45 @@ impl PixelConverter {
                                     } else {
                                         0xFF
                                     };
+                                } else if self.error_on_index_outside_palette {
+                                    *progress += i;
+                                    return Err(index_outside_palette());
+                                } else {
+                                    rgba.fill(0);
+                                }
+                            }
+                        },
+                        ColorOutput::RGB => {
+                            let transparent = current_frame.transparent;
+                            let matte = self.matte_color(global_palette);
+
+                            let (pixels, rest) = buf.split_at_mut(pixels_decoded * PLTE_CHANNELS);
+                            buf = rest;
+
+                            for (i, (rgb, idx)) in pixels.chunks_exact_mut(PLTE_CHANNELS).zip(self.buffer.iter().copied().take(pixels_decoded)).enumerate() {
+                                let plte_offset = PLTE_CHANNELS * idx as usize;
+                                if let Some(colors) = palette.get(plte_offset..plte_offset+PLTE_CHANNELS) {
+                                    rgb.copy_from_slice(if transparent == Some(idx) { &matte } else { colors });
+                                } else if self.error_on_index_outside_palette {
+                                    *progress += i;
+                                    return Err(index_outside_palette());
+                                } else {
+                                    rgb.fill(0);
                                 }
                             }
                         },
                         ColorOutput::Indexed => {
-                            buf = &mut buf[bytes_decoded..];
+                            if self.error_on_index_outside_palette {
+                                let colors = palette.len() / PLTE_CHANNELS;
+                                if let Some(i) = buf[..pixels_decoded].iter().position(|&idx| usize::from(idx) >= colors) {
+                                    *progress += i;
+                                    return Err(index_outside_palette());
+                                }
+                            }
+                            buf = &mut buf[pixels_decoded..];
                         }
                     }
+                    *progress += pixels_decoded;
                     if buf.is_empty() {
                         return Ok(true);
                     }
@@ -148,45 +307,148 @@ impl PixelConverter {
         }
     }
 
-    pub(crate) fn global_palette(&self) -> Option<&[u8]> {
-        self.global_palette.as_deref()
+    /// Fills the pixels after the first `progress` ones in decoding order, for a frame that ended early.
+    ///
+    /// Indexed pixels are set to `index`, RGBA pixels to transparent black, and RGB pixels to the
+    /// matte color.
+    pub(crate) fn fill_rest(&self, frame: &Frame<'_>, global_palette: Option<&[u8]>, buf: &mut [u8], progress: usize, index: u8) {
+        let matte = self.matte_color(global_palette);
+        let fill = |bytes: &mut [u8]| match self.color_output {
+            ColorOutput::RGBA => bytes.fill(0),
+            ColorOutput::RGB => bytes.chunks_exact_mut(PLTE_CHANNELS).for_each(|rgb| rgb.copy_from_slice(&matte)),
+            ColorOutput::Indexed => bytes.fill(index),
+        };
+        let bytes_per_pixel = self.bytes_per_pixel();
+        if frame.interlaced && self.deinterlace {
+            let width = self.line_length(frame);
+            let pixels_per_line = usize::from(frame.width).max(1);
+            let rows = InterlaceIterator { len: frame.height, next: 0, pass: 0 }.skip(progress / pixels_per_line);
+            for (i, row) in rows.enumerate() {
+                let skip = if i == 0 { (progress % pixels_per_line) * bytes_per_pixel } else { 0 };
+                if let Some(line) = buf.get_mut(row * width + skip..(row + 1) * width) {
+                    fill(line);
+                }
+            }
+        } else if let Some(rest) = buf.get_mut(progress * bytes_per_pixel..) {
+            fill(rest);
+        }
     }
 
-    pub(crate) fn set_global_palette(&mut self, palette: Vec<u8>) {
-        self.global_palette = if !palette.is_empty() {
-            Some(palette)
-        } else {
-            None
-        };
+    /// Whether interlaced frames are deinterlaced by `read_frame` and `read_into_buffer`
+    pub(crate) fn deinterlaces(&self) -> bool {
+        self.deinterlace
+    }
+
+    /// What `read_frame` and `read_into_buffer` convert the indices into
+    pub(crate) fn color_output(&self) -> ColorOutput {
+        self.color_output
+    }
+
+    /// Sets the index of the background color, which is the default matte color
+    pub(crate) fn set_background(&mut self, background: Option<u8>) {
+        self.background = background;
     }
 
-    /// Applies deinterlacing
+    /// Applies deinterlacing, unless it is turned off
     ///
     /// Set `frame.interlaced = false` afterwards if you're putting the buffer back into the `Frame`
-    pub(crate) fn read_into_buffer(&mut self, frame: &Frame<'_>, buf: &mut [u8], data_callback: FillBufferCallback<'_>) -> Result<(), DecodingError> {
-        if frame.interlaced {
+    pub(crate) fn read_into_buffer(&mut self, frame: &Frame<'_>, buf: &mut [u8], source: &mut dyn PixelSource) -> Result<(), DecodingError> {
+        self.read_into_buffer_from(frame, buf, &mut 0, source)
+    }
+
+    /// Decodes the frame one row at a time into `line`, and passes each row to `row_callback`
+    /// with its index in the deinterlaced frame
+    pub(crate) fn read_rows<E: From<DecodingError>>(&mut self, frame: &Frame<'_>, line: &mut [u8], source: &mut dyn PixelSource, row_callback: RowCallback<'_, E>) -> Result<(), E> {
+        let mut interlaced = InterlaceIterator { len: frame.height, next: 0, pass: 0 };
+        let mut sequential = 0..usize::from(frame.height);
+        let rows: &mut dyn Iterator<Item = usize> = if frame.interlaced { &mut interlaced } else { &mut sequential };
+        let mut progress = 0;
+        for row in rows {
+            if !self.fill_buffer(frame, line, &mut progress, source).map_err(|err| err.with_completed_pixels(progress))? {
+                return Err(DecodingError::format(DecodingFormatErrorKind::TruncatedImage, "image truncated")
+                    .with_completed_pixels(progress).into());
+            }
+            // rows are always less than the height
+            row_callback(row as u16, line)?;
+        }
+        Ok(())
+    }
+
+    /// Like `read_into_buffer`, but calls `pass_complete` after each of the four passes of an
+    /// interlaced frame
+    pub(crate) fn read_into_buffer_by_pass(&mut self, frame: &Frame<'_>, buf: &mut [u8], source: &mut dyn PixelSource, pass_complete: &mut dyn FnMut(u8, &[u8])) -> Result<(), DecodingError> {
+        if !frame.interlaced {
+            return self.read_into_buffer(frame, buf, source);
+        }
+        let width = self.line_length(frame);
+        let mut rows = InterlaceIterator { len: frame.height, next: 0, pass: 0 };
+        let mut progress = 0;
+        for (pass, pass_rows) in (1..).zip(interlace_pass_rows(frame.height)) {
+            for row in rows.by_ref().take(pass_rows) {
+                let line = buf.get_mut(row * width..).and_then(|b| b.get_mut(..width))
+                    .ok_or_else(|| DecodingError::format(DecodingFormatErrorKind::BufferTooSmall, "buffer too small"))?;
+                if !self.fill_buffer(frame, line, &mut progress, source).map_err(|err| err.with_completed_pixels(progress))? {
+                    return Err(DecodingError::format(DecodingFormatErrorKind::TruncatedImage, "image truncated")
+                        .with_completed_pixels(progress));
+                }
+            }
+            pass_complete(pass, buf);
+        }
+        Ok(())
+    }
+
+    /// Like `read_into_buffer`, but skips the first `progress` pixels (in decoding order), and
+    /// updates it with the number of pixels read, also when an error is returned.
+    fn read_into_buffer_from(&mut self, frame: &Frame<'_>, buf: &mut [u8], progress: &mut usize, source: &mut dyn PixelSource) -> Result<(), DecodingError> {
+        self.read_pixels_from(frame, buf, progress, source)
+            .map_err(|err| err.with_completed_pixels(*progress))
+    }
+
+    fn read_pixels_from(&mut self, frame: &Frame<'_>, buf: &mut [u8], progress: &mut usize, source: &mut dyn PixelSource) -> Result<(), DecodingError> {
+        let bytes_per_pixel = self.bytes_per_pixel();
+        let skip = *progress;
+        if frame.interlaced && self.deinterlace {
             let width = self.line_length(frame);
-            for row in (InterlaceIterator { len: frame.height, next: 0, pass: 0 }) {
+            let pixels_per_line = usize::from(frame.width).max(1);
+            let (lines_done, line_progress) = (skip / pixels_per_line, skip % pixels_per_line);
+            let rows = InterlaceIterator { len: frame.height, next: 0, pass: 0 }.skip(lines_done);
+            for (i, row) in rows.enumerate() {
                 // this can't overflow 32-bit, because row never equals (maximum) height
                 let start = row * width;
                 // Handle a too-small buffer and 32-bit usize overflow without panicking
-                let line = buf.get_mut(start..).and_then(|b| b.get_mut(..width))
-                    .ok_or_else(|| DecodingError::format("buffer too small"))?;
-                if !self.fill_buffer(frame, line, data_callback)? {
-                    return Err(DecodingError::format("image truncated"));
+                let mut line = buf.get_mut(start..).and_then(|b| b.get_mut(..width))
+                    .ok_or_else(|| DecodingError::format(DecodingFormatErrorKind::BufferTooSmall, "buffer too small"))?;
+                if i == 0 {
+                    line = &mut line[line_progress * bytes_per_pixel..];
+                }
+                if !self.fill_buffer(frame, line, progress, source)? {
+                    return Err(DecodingError::format(DecodingFormatErrorKind::TruncatedImage, "image truncated"));
                 }
             }
         } else {
             let buf = self.buffer_size(frame).and_then(|buffer_size| buf.get_mut(..buffer_size))
-                .ok_or_else(|| DecodingError::format("buffer too small"))?;
-            if !self.fill_buffer(frame, buf, data_callback)? {
-                return Err(DecodingError::format("image truncated"));
+                .and_then(|buf| buf.get_mut(skip * bytes_per_pixel..))
+                .ok_or_else(|| DecodingError::format(DecodingFormatErrorKind::BufferTooSmall, "buffer too small"))?;
+            if !self.fill_buffer(frame, buf, progress, source)? {
+                return Err(DecodingError::format(DecodingFormatErrorKind::TruncatedImage, "image truncated"));
             }
         };
         Ok(())
     }
 }
 
+#[cold]
+fn index_outside_palette() -> DecodingError {
+    DecodingError::format(DecodingFormatErrorKind::IndexOutsidePalette, "pixel index outside of the palette")
+}
+
+/// Number of rows in each of the four passes of an interlaced frame.
+/// Later passes are empty if the frame is short.
+pub(crate) fn interlace_pass_rows(height: u16) -> [usize; 4] {
+    let rows = |first_row: usize, step: usize| (usize::from(height) + step - 1).saturating_sub(first_row) / step;
+    [rows(0, 8), rows(4, 8), rows(2, 4), rows(1, 2)]
+}
+
 struct InterlaceIterator {
     len: u16,
     next: usize,
@@ -216,7 +478,20 @@ impl iter::Iterator for InterlaceIterator {
 
 #[cfg(test)]
 mod test {
-    use super::InterlaceIterator;
+    use super::{fallback_palette, interlace_pass_rows, InterlaceIterator};
+
+    #[test]
+    fn fallback_palettes() {
+        assert_eq!(fallback_palette(1)[..6], [0, 0, 0, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(fallback_palette(2)[..12], [0, 0, 0, 0x55, 0x55, 0x55, 0xAA, 0xAA, 0xAA, 0xFF, 0xFF, 0xFF]);
+        for bits in 1..=8 {
+            let palette = fallback_palette(bits);
+            assert_eq!(palette.len(), 256 * 3);
+            // White from the last shade on
+            assert!(palette[(3 << bits) - 3..].iter().all(|&value| value == 0xFF));
+        }
+        assert!(fallback_palette(8).chunks(3).enumerate().all(|(i, rgb)| rgb == [i as u8; 3]));
+    }
 
     #[test]
     fn test_interlace_iterator() {
@@ -251,4 +526,23 @@ mod test {
         let iter = InterlaceIterator { len: 0xFFFF, next: 0, pass: 0 };
         assert_eq!(65533, iter.last().unwrap());
     }
+
+    #[test]
+    fn interlace_pass_sizes() {
+        assert_eq!(interlace_pass_rows(0), [0, 0, 0, 0]);
+        assert_eq!(interlace_pass_rows(1), [1, 0, 0, 0]);
+        assert_eq!(interlace_pass_rows(2), [1, 0, 0, 1]);
+        assert_eq!(interlace_pass_rows(3), [1, 0, 1, 1]);
+        assert_eq!(interlace_pass_rows(5), [1, 1, 1, 2]);
+        assert_eq!(interlace_pass_rows(17), [3, 2, 4, 8]);
+        for len in 0..100 {
+            let passes = interlace_pass_rows(len);
+            assert_eq!(passes.iter().sum::<usize>(), usize::from(len));
+            let mut iter = InterlaceIterator { len, next: 0, pass: 0 };
+            for (first_row, rows) in [0, 4, 2, 1].into_iter().zip(passes) {
+                let pass: Vec<_> = iter.by_ref().take(rows).collect();
+                assert!(pass.first().map_or(true, |&row| row == first_row));
+            }
+        }
+    }
 }
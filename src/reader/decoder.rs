@@ -6,24 +6,313 @@ use core::cmp;
 use no_std_io::error;
 use core::fmt;
 use no_std_io::io;
-use core::mem;
 use core::default::Default;
 use core::num::NonZeroUsize;
+use core::ops::Range;
 
 use crate::Repeat;
 use crate::MemoryLimit;
-use crate::common::{AnyExtension, Block, DisposalMethod, Extension, Frame};
+use crate::common::{palette_padding, AnyExtension, Block, Extension, Frame, GraphicControl, PixelFormat, ScreenDescriptor};
 use crate::reader::DecodeOptions;
 
 use weezl::{BitOrder, decode::Decoder as LzwDecoder, LzwError, LzwStatus};
 
+/// Sets the state of a parser, and returns the number of bytes consumed (one unless given) and
+/// what has been decoded
+macro_rules! goto (
+    ($this:ident, $n:expr, $state:expr) => ({
+        $this.state = $state;
+        Ok(($n, None))
+    });
+    ($this:ident, $state:expr) => ({
+        $this.state = $state;
+        Ok((1, None))
+    });
+    ($this:ident, $n:expr, $state:expr, emit $res:expr) => ({
+        $this.state = $state;
+        Ok(($n, Some($res)))
+    });
+    ($this:ident, $state:expr, emit $res:expr) => ({
+        $this.state = $state;
+        Ok((1, Some($res)))
+    })
+);
+
+mod extension;
+mod header;
+mod image_data;
+mod lzw_stats;
+
+pub use self::extension::ExtensionBlock;
+pub use self::lzw_stats::LzwStats;
+
 /// GIF palettes are RGB
 pub const PLTE_CHANNELS: usize = 3;
 
+/// Length of the signature and the logical screen descriptor
+const HEADER_LEN: usize = 13;
+/// Length of an image descriptor, including the introducer
+const IMAGE_DESCRIPTOR_LEN: usize = 10;
+
+/// Most steps in a row that any well-formed state machine takes in `update` without consuming
+/// input or emitting anything. The longest chain in practice is only a few steps long.
+const MAX_STALLED_STEPS: usize = 16;
+
+/// Most steps that `update` takes per byte consumed or pixel declared by default, see
+/// [`WorkCounter`]. A step either consumes input or emits something, apart from short chains of
+/// steps that don't, and the corpus and the pathological files take at most about 1.2 steps per unit.
+#[cfg(any(debug_assertions, feature = "work_bound"))]
+const WORK_PER_UNIT: u64 = 2;
+
+/// Steps of the state machine since the decoder was created, which are bounded by the bytes
+/// consumed and the pixels of the frames declared so far. Rewinding adds to all of them, so
+/// decoding a frame again counts as more work.
+#[cfg(any(debug_assertions, feature = "work_bound"))]
+#[derive(Debug)]
+struct WorkCounter {
+    steps: u64,
+    bytes: u64,
+    pixels: u64,
+    per_unit: u64,
+}
+
+#[cfg(any(debug_assertions, feature = "work_bound"))]
+impl Default for WorkCounter {
+    fn default() -> Self {
+        Self { steps: 0, bytes: 0, pixels: 0, per_unit: WORK_PER_UNIT }
+    }
+}
+
+#[cfg(any(debug_assertions, feature = "work_bound"))]
+impl WorkCounter {
+    #[track_caller]
+    fn step(&mut self, consumed: usize) {
+        self.steps += 1;
+        self.bytes += consumed as u64;
+        let limit = self.per_unit * (self.bytes + self.pixels + 1);
+        assert!(self.steps <= limit, "{} steps for {} bytes and {} pixels", self.steps, self.bytes, self.pixels);
+    }
+}
+
+/// What is wrong with the input, or with how the decoder was used, see [`DecodingFormatError::kind`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DecodingFormatErrorKind {
+    /// The file doesn't start with the `GIF` signature.
+    MalformedHeader,
+    /// The version in the header is neither `87a` nor `89a`, and doesn't start with two digits
+    /// unless [`DecodeOptions::strict_version`] is turned on.
+    UnsupportedVersion,
+    /// The header has version `87a`, but the file has an extension of version `89a`, see
+    /// [`DecodeOptions::strict_version`].
+    VersionMismatch,
+    /// A block starts with an unknown introducer, see [`DecodeOptions::allow_unknown_blocks`].
+    UnknownBlock,
+    /// An extension has an unknown label, see [`DecodeOptions::allow_unknown_extensions`].
+    UnknownExtension,
+    /// A known extension has the wrong size.
+    MalformedExtension,
+    /// An image has more than one graphic control extension before it, see
+    /// [`DecodeOptions::error_on_repeated_graphic_control`].
+    RepeatedGraphicControl,
+    /// The minimum LZW code size of a frame is outside of 1 to 11.
+    InvalidMinCodeSize,
+    /// The LZW data of a frame contains a code that hasn't been defined yet.
+    InvalidLzwCode,
+    /// The LZW data of a frame ends without an end code, see [`DecodeOptions::check_lzw_end_code`].
+    NoEndCode,
+    /// A frame isn't within the logical screen, see [`DecodeOptions::check_frame_consistency`].
+    FrameOutOfBounds,
+    /// A frame has no local palette, and the file has no global one.
+    MissingColorTable,
+    /// The file ends without any frames.
+    NoImageData,
+    /// There is more data after the trailer, see [`DecodeOptions::error_on_trailing_data`].
+    DataAfterTrailer,
+    /// A buffer would exceed the [`MemoryLimit`](crate::MemoryLimit).
+    MemoryLimitExceeded,
+    /// The data of a frame ends before all of its pixels have been decoded.
+    TruncatedImage,
+    /// The input ends within the given part of the file, see [`StreamingDecoder::finish`].
+    UnexpectedEof(Incomplete),
+    /// A pixel has an index outside of the frame's palette, see
+    /// [`DecodeOptions::error_on_index_outside_palette`].
+    IndexOutsidePalette,
+    /// The transparent index of a frame is outside of its palette, see
+    /// [`DecodeOptions::error_on_transparent_outside_palette`].
+    TransparentOutsidePalette,
+    /// The buffer passed to the decoder can't hold the pixels.
+    BufferTooSmall,
+    /// The frame passed to the decoder doesn't hold LZW data, see [`Frame::pixel_format`].
+    UnexpectedPixelFormat,
+    /// The decoder was used in a way that its current state doesn't allow.
+    InvalidState,
+}
+
+/// The part of the file that the input ends within, see [`DecodingFormatErrorKind::UnexpectedEof`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Incomplete {
+    /// The `GIF` signature and version.
+    Signature,
+    /// The logical screen descriptor after the signature.
+    ScreenDescriptor,
+    /// The global palette.
+    GlobalPalette,
+    /// The fields of a graphic control extension.
+    GraphicControl,
+    /// An extension, or a block of unknown type.
+    Extension,
+    /// An image descriptor.
+    ImageDescriptor,
+    /// A local palette.
+    LocalPalette,
+    /// The LZW data of a frame, up to its block terminator.
+    ImageData,
+    /// All blocks are complete, but the trailer is missing.
+    Trailer,
+}
+
+impl Incomplete {
+    /// Message of the error, which names the incomplete part
+    fn message(self) -> &'static str {
+        match self {
+            Self::Signature => "file ends within the signature",
+            Self::ScreenDescriptor => "file ends within the logical screen descriptor",
+            Self::GlobalPalette => "file ends within the global palette",
+            Self::GraphicControl => "file ends within a graphic control extension",
+            Self::Extension => "file ends within an extension",
+            Self::ImageDescriptor => "file ends within an image descriptor",
+            Self::LocalPalette => "file ends within a local palette",
+            Self::ImageData => "file ends within the data of a frame",
+            Self::Trailer => "file ends without a trailer",
+        }
+    }
+}
+
+/// Something unusual in the input that the decoder has accepted, see [`StreamingDecoder::warnings`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DecodingWarning {
+    /// The version in the header is neither `87a` nor `89a`, but starts with two digits, see
+    /// [`DecodeOptions::strict_version`]. Those starting with `87` are decoded as version `87a`,
+    /// the others as `89a`.
+    UnknownVersion([u8; 3]),
+    /// The header has version `87a`, but the file has an extension that version `89a`
+    /// introduced, see [`DecodeOptions::strict_version`]. Many encoders write `87a` regardless
+    /// of the blocks that follow.
+    V87aWithExtension(Extension),
+    /// A looping application extension has a sub-block with an id other than 1 for the loop
+    /// count or 2 for the buffer size. It's skipped.
+    UnknownLoopSubBlock(u8),
+    /// A sub-block with the loop count doesn't have exactly 2 bytes after its id. It's skipped.
+    MalformedLoopCount,
+    /// A looping application extension has several sub-blocks with different loop counts. The
+    /// first one is used.
+    ConflictingLoopCounts,
+    /// The data of a frame ends without a block terminator, and the next block starts right away,
+    /// see [`DecodeOptions::allow_missing_block_terminator`].
+    MissingBlockTerminator,
+    /// A graphic control extension declares more than the 4 bytes of its fields, see
+    /// [`DecodeOptions::allow_oversized_control_extension`]. The bytes after the fields are
+    /// skipped.
+    OversizedControlExtension(u8),
+    /// A pixel of the frame with this index refers to one of the black entries at the end of
+    /// its palette, which may only pad the palette to a power of two colors, see
+    /// [`DecodeOptions::check_palette_padding`]. It's listed for each such frame whose pixels
+    /// have been decoded.
+    PaletteEntryPaddingReferenced(usize),
+}
+
+/// A call that the decoder doesn't allow in its current state, see [`DecodingError::Usage`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UsageError {
+    /// Seeking to a frame needs a frame index, see [`Decoder::build_frame_index`](crate::Decoder::build_frame_index).
+    NoFrameIndex,
+    /// The frame to seek to isn't in the frame index, which has this many frames.
+    FrameOutOfRange {
+        /// Index of the frame, counting from 0.
+        frame: usize,
+        /// Number of frames in the frame index.
+        frames: usize,
+    },
+    /// The frame index belongs to another file.
+    FrameIndexMismatch,
+}
+
+impl fmt::Display for UsageError {
+    #[cold]
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::NoFrameIndex => fmt.write_str("frame index has not been built"),
+            Self::FrameOutOfRange { frame, frames } => write!(fmt,
+                "frame {frame} is out of range of the frame index with {frames} frames"),
+            Self::FrameIndexMismatch => fmt.write_str("frame index is for another file"),
+        }
+    }
+}
+
+impl error::Error for UsageError {}
+
 /// An error returned in the case of the image not being formatted properly.
 #[derive(Debug)]
 pub struct DecodingFormatError {
+    kind: DecodingFormatErrorKind,
     underlying: Box<dyn error::Error + Send + Sync + 'static>,
+    offset: Option<u64>,
+    frame_index: Option<usize>,
+    state: Option<&'static str>,
+    completed_pixels: Option<usize>,
+}
+
+impl DecodingFormatError {
+    /// What went wrong, for handling errors without matching on their message.
+    ///
+    /// The kinds that aren't about the input, like [`BufferTooSmall`](DecodingFormatErrorKind::BufferTooSmall),
+    /// are caused by how the decoder has been called.
+    #[must_use]
+    pub fn kind(&self) -> DecodingFormatErrorKind {
+        self.kind
+    }
+
+    /// Position in the input of the byte the decoder was at when it found the error.
+    ///
+    /// Some values are only checked once the byte after them has been read, so the malformed
+    /// data may end right before this position. `None` if the error wasn't found by the
+    /// [`StreamingDecoder`], for example because of a missing palette.
+    #[must_use]
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    /// Index of the frame that the error belongs to, counting from 0.
+    ///
+    /// Errors in the extensions before a frame belong to that frame. `None` for errors in the
+    /// header, and if the offset is unknown.
+    #[must_use]
+    pub fn frame_index(&self) -> Option<usize> {
+        self.frame_index
+    }
+
+    /// The part of the file the decoder was in, like `"global palette"` or `"image data"`.
+    ///
+    /// The names are for messages only, and may change.
+    #[must_use]
+    pub fn state(&self) -> Option<&'static str> {
+        self.state
+    }
+
+    /// Number of pixels of the frame that had been decoded, in decoding order, when decoding the
+    /// frame failed.
+    ///
+    /// [`Decoder::read_next_frame`](crate::Decoder::read_next_frame) discards the pixels of a
+    /// frame that failed, so this is for diagnostics. `None` if the error didn't happen while
+    /// reading the pixels of a frame, or happened in [`Decoder::fill_buffer`](crate::Decoder::fill_buffer).
+    #[must_use]
+    pub fn completed_pixels(&self) -> Option<usize> {
+        self.completed_pixels
+    }
 }
 
 impl fmt::Display for DecodingFormatError {
@@ -42,20 +331,60 @@ impl error::Error for DecodingFormatError {
 
 #[derive(Debug)]
 /// Decoding error.
+#[non_exhaustive]
 pub enum DecodingError {
     /// Returned if the image is found to be malformed.
     Format(DecodingFormatError),
     /// Wraps `std::io::Error`.
     Io(io::Error),
+    /// The pixels of a frame don't fit in the address space of this platform.
+    ///
+    /// Only 32-bit and smaller platforms can't address the largest frames.
+    DimensionsTooLarge {
+        /// Width of the frame.
+        width: u16,
+        /// Height of the frame.
+        height: u16,
+    },
+    /// An allocation failed.
+    ///
+    /// The palettes, the extension data and the output buffers are reserved without aborting,
+    /// so a file that declares more data than the system can hold ends up here rather than in
+    /// [`DecodingError::Io`].
+    OutOfMemory,
+    /// The decoder has been called in a way that its current state doesn't allow.
+    Usage(UsageError),
 }
 
 impl DecodingError {
     #[cold]
-    pub(crate) fn format(err: &'static str) -> Self {
+    pub(crate) fn format(kind: DecodingFormatErrorKind, err: &'static str) -> Self {
         Self::Format(DecodingFormatError {
+            kind,
             underlying: err.into(),
+            offset: None,
+            frame_index: None,
+            state: None,
+            completed_pixels: None,
         })
     }
+
+    /// Adds the number of pixels of the frame that were decoded before a format error
+    #[cold]
+    pub(crate) fn with_completed_pixels(self, pixels: usize) -> Self {
+        match self {
+            Self::Format(mut err) => {
+                err.completed_pixels = Some(pixels);
+                Self::Format(err)
+            },
+            err => err,
+        }
+    }
+
+    /// The reader had no data available yet, and the call can be repeated later
+    pub(crate) fn is_would_block(&self) -> bool {
+        matches!(self, Self::Io(err) if err.kind() == io::ErrorKind::WouldBlock)
+    }
 }
 
 impl fmt::Display for DecodingError {
@@ -64,6 +393,10 @@ impl fmt::Display for DecodingError {
         match *self {
             Self::Format(ref d) => d.fmt(fmt),
             Self::Io(ref err) => err.fmt(fmt),
+            Self::DimensionsTooLarge { width, height } => write!(fmt,
+                "a {width}x{height} frame is too large for this platform"),
+            Self::OutOfMemory => fmt.write_str("out of memory"),
+            Self::Usage(ref err) => err.fmt(fmt),
         }
     }
 }
@@ -74,6 +407,8 @@ impl error::Error for DecodingError {
         match *self {
             Self::Format(ref err) => Some(err),
             Self::Io(ref err) => Some(err),
+            Self::Usage(ref err) => Some(err),
+            Self::DimensionsTooLarge { .. } | Self::OutOfMemory => None,
         }
     }
 }
@@ -99,6 +434,13 @@ impl From<DecodingFormatError> for DecodingError {
     }
 }
 
+impl From<UsageError> for DecodingError {
+    #[cold]
+    fn from(err: UsageError) -> Self {
+        Self::Usage(err)
+    }
+}
+
 /// Varies depending on `skip_frame_decoding`
 #[derive(Debug, Copy, Clone)]
 pub enum FrameDataType {
@@ -117,8 +459,12 @@ pub enum FrameDataType {
 pub enum Decoded {
     /// Decoded nothing.
     Nothing,
-    /// Global palette.
-    GlobalPalette(Box<[u8]>),
+    /// The global palette has been read.
+    ///
+    /// Ends the header, and is emitted even if the file has no global palette. From then on,
+    /// [`StreamingDecoder::global_palette`] and [`StreamingDecoder::screen_descriptor`] are
+    /// available.
+    GlobalPalette,
     /// Index of the background color in the global palette.
     BackgroundColor(u8),
     /// Loop count is known
@@ -127,7 +473,7 @@ pub enum Decoded {
     /// reached frame data.
     HeaderEnd,
     /// The start of a block.
-    /// `BlockStart(Block::Trailer)` is the very last decode event
+    /// `BlockStart(Block::Trailer)` is the last block, and is followed only by `Finished`
     BlockStart(Block),
     /// Decoded a sub-block. More sub-block are available.
     ///
@@ -147,77 +493,58 @@ pub enum Decoded {
     ///
     /// The returned frame does **not** contain any owned image data.
     ///
-    /// Call `current_frame_mut()` to access the frame info.
+    /// Call `current_frame_mut()` to access the frame info, which is gone once `DataEnd` is emitted.
     FrameMetadata(FrameDataType),
-    /// Decoded some data of the current frame. Size is in bytes, always > 0
-    BytesDecoded(NonZeroUsize),
+    /// Decoded some pixels of the current frame, always > 0.
+    ///
+    /// The pixels are palette indices, one byte each, regardless of the `ColorOutput`.
+    PixelsDecoded(NonZeroUsize),
     /// Copied (or consumed and discarded) compressed data of the current frame. In bytes.
     LzwDataCopied(usize),
     /// No more data available the current frame.
     DataEnd,
+    /// The trailer has been decoded, and the file is complete.
+    ///
+    /// Every call to `update` after `BlockStart(Block::Trailer)` returns this. It consumes and
+    /// discards all of the input, which [`StreamingDecoder::trailing_bytes`] counts. With
+    /// [`DecodeOptions::error_on_trailing_data`], more input is an error instead.
+    ///
+    /// With [`DecodeOptions::continue_past_trailer`], `update` first looks at the next few bytes,
+    /// and continues with the next block if one starts there. Otherwise, it returns this once
+    /// it has consumed those bytes.
+    Finished,
 }
 
 /// Internal state of the GIF decoder
 #[derive(Debug, Copy, Clone)]
 enum State {
-    Magic(u8, [u8; 6]),
-    U16Byte1(U16Value, u8),
-    U16(U16Value),
-    Byte(ByteValue),
-    GlobalPalette(usize),
+    /// Handled by `HeaderParser`
+    Header,
     BlockStart(u8),
     BlockEnd,
-    ExtensionBlock(AnyExtension),
-    /// Collects data in ext.data
-    ExtensionDataBlock(usize),
-    ApplicationExtension,
-    LocalPalette(usize),
-    LzwInit(u8),
-    /// Decompresses LZW
-    DecodeSubBlock(usize),
-    /// Keeps LZW compressed
-    CopySubBlock(usize),
-    FrameDecoded,
+    /// Handled by `ExtensionParser`
+    ExtensionBlock,
+    /// Handled by `ImageDataReader`
+    ImageBlock,
+    /// Bytes after a trailer that `continue_past_trailer` skips, looking for another block
+    AfterTrailer(u8),
     Trailer,
 }
 use self::State::*;
 
-use super::converter::PixelConverter;
+/// How many bytes after a trailer `continue_past_trailer` looks for another block
+const TRAILER_WINDOW: u8 = 8;
 
-/// U16 values that may occur in a GIF image
-#[derive(Debug, Copy, Clone)]
-enum U16Value {
-    /// Logical screen descriptor width
-    ScreenWidth,
-    /// Logical screen descriptor height
-    ScreenHeight,
-    /// Delay time
-    Delay,
-    /// Left frame offset
-    ImageLeft,
-    /// Top frame offset
-    ImageTop,
-    /// Frame width
-    ImageWidth,
-    /// Frame height
-    ImageHeight,
-}
-
-/// Single byte screen descriptor values
-#[derive(Debug, Copy, Clone)]
-enum ByteValue {
-    GlobalFlags,
-    Background { global_flags: u8 },
-    AspectRatio { global_flags: u8 },
-    ControlFlags,
-    ImageFlags,
-    TransparentIdx,
-}
+use self::extension::{ExtensionEvent, ExtensionParser};
+use self::header::{HeaderEvent, HeaderParser};
+use self::image_data::{ImageDataEvent, ImageDataReader};
+use super::converter::{PixelConverter, WithPalette};
 
 /// Decoder for `Frame::make_lzw_pre_encoded`
 pub struct FrameDecoder {
     lzw_reader: LzwReader,
     pixel_converter: PixelConverter,
+    global_palette: Option<Vec<u8>>,
 }
 
 impl FrameDecoder {
@@ -227,53 +554,67 @@ impl FrameDecoder {
     pub fn new(options: DecodeOptions) -> Self {
         Self {
             lzw_reader: LzwReader::new(options.check_for_end_code),
-            pixel_converter: PixelConverter::new(options.color_output, options.memory_limit),
+            pixel_converter: PixelConverter::new(options.color_output, options.matte_color, options.memory_limit, options.deinterlace, options.error_on_index_outside_palette),
+            global_palette: None,
         }
     }
 
-    /// Palette used for RGBA conversion
+    /// Palette used for RGBA and RGB conversion
     #[inline]
     pub fn set_global_palette(&mut self, palette: Vec<u8>) {
-        self.pixel_converter.set_global_palette(palette);
+        self.global_palette = Some(palette).filter(|palette| !palette.is_empty());
     }
 
     /// Converts the frame in-place, replacing its LZW buffer with pixels.
     ///
     /// If you get an error about invalid min code size, the buffer was probably pixels, not compressed data.
+    /// A frame whose [`Frame::pixel_format`] is known to be pixels is refused right away.
     #[inline]
     pub fn decode_lzw_encoded_frame(&mut self, frame: &mut Frame<'_>) -> Result<(), DecodingError> {
         let pixel_bytes = self.pixel_converter.check_buffer_size(frame)?;
         let mut vec = vec![0; pixel_bytes];
         self.decode_lzw_encoded_frame_into_buffer(frame, &mut vec)?;
         frame.buffer = Cow::Owned(vec);
-        frame.interlaced = false;
+        frame.pixel_format = self.pixel_converter.color_output().into();
+        frame.interlaced &= !self.pixel_converter.deinterlaces();
         Ok(())
     }
 
     /// Converts into the given buffer. It must be [`buffer_size()`] bytes large.
     ///
-    /// Pixels are always deinterlaced, so update `frame.interlaced` afterwards if you're putting the buffer back into the frame.
+    /// Pixels are deinterlaced unless turned off with [`DecodeOptions::deinterlace`], so update `frame.interlaced` afterwards if you're putting the buffer back into the frame.
     pub fn decode_lzw_encoded_frame_into_buffer(&mut self, frame: &Frame<'_>, buf: &mut [u8]) -> Result<(), DecodingError> {
+        if !matches!(frame.pixel_format, PixelFormat::Lzw | PixelFormat::Unknown) {
+            return Err(DecodingError::format(DecodingFormatErrorKind::UnexpectedPixelFormat, "frame buffer is not LZW data"));
+        }
         let (&min_code_size, mut data) = frame.buffer.split_first().unwrap_or((&2, &[]));
         self.lzw_reader.reset(min_code_size)?;
         let lzw_reader = &mut self.lzw_reader;
-        self.pixel_converter.read_into_buffer(frame, buf, &mut move |out| {
-            loop {
-                let (bytes_read, bytes_written) = lzw_reader.decode_bytes(data, out)?;
-                data = data.get(bytes_read..).unwrap_or_default();
-                if bytes_written > 0 || bytes_read == 0 || data.is_empty() {
-                    return Ok(bytes_written)
+        let mut source = WithPalette {
+            global_palette: self.global_palette.as_deref(),
+            decode: move |out: &mut OutputBuffer<'_>| {
+                loop {
+                    let (bytes_read, bytes_written) = lzw_reader.decode_bytes(data, out)?;
+                    data = data.get(bytes_read..).unwrap_or_default();
+                    // The LZW decoder may keep pixels of the last bytes until it's called again
+                    if bytes_written > 0 || bytes_read == 0 {
+                        return Ok(bytes_written)
+                    }
                 }
-            }
-        })?;
+            },
+        };
+        self.pixel_converter.read_into_buffer(frame, buf, &mut source)?;
         Ok(())
     }
 
     /// Number of bytes required for `decode_lzw_encoded_frame_into_buffer`
+    ///
+    /// Saturates at `usize::MAX` if the frame is too large for this platform, which decoding
+    /// reports as [`DecodingError::DimensionsTooLarge`].
     #[inline]
     #[must_use]
     pub fn buffer_size(&self, frame: &Frame<'_>) -> usize {
-        self.pixel_converter.buffer_size(frame).unwrap()
+        self.pixel_converter.buffer_size(frame).unwrap_or(usize::MAX)
     }
 }
 
@@ -281,6 +622,8 @@ struct LzwReader {
     decoder: Option<LzwDecoder>,
     min_code_size: u8,
     check_for_end_code: bool,
+    /// Some of the current frame's data has been decoded, not just skipped
+    has_decoded: bool,
 }
 
 impl LzwReader {
@@ -289,6 +632,7 @@ impl LzwReader {
             decoder: None,
             min_code_size: 0,
             check_for_end_code,
+            has_decoded: false,
         }
     }
 
@@ -296,20 +640,21 @@ impl LzwReader {
         // LZW spec: max 12 bits per code. This check helps catch confusion
         // between LZW-compressed buffers and raw pixel data
         if min_code_size > 11 || min_code_size < 1 {
-            return Err(DecodingError::format("invalid minimal code size"));
+            return Err(DecodingError::format(DecodingFormatErrorKind::InvalidMinCodeSize, "invalid minimal code size"));
         }
         Ok(())
     }
 
     pub fn reset(&mut self, min_code_size: u8) -> Result<(), DecodingError> {
         Self::check_code_size(min_code_size)?;
+        self.has_decoded = false;
 
         // The decoder can be reused if the code size stayed the same
         if self.min_code_size != min_code_size || self.decoder.is_none() {
             self.min_code_size = min_code_size;
             self.decoder = Some(LzwDecoder::new(BitOrder::Lsb, min_code_size));
         } else {
-            self.decoder.as_mut().ok_or_else(|| DecodingError::format("bad state"))?.reset();
+            self.decoder.as_mut().ok_or_else(|| DecodingError::format(DecodingFormatErrorKind::InvalidState, "bad state"))?.reset();
         }
 
         Ok(())
@@ -319,26 +664,52 @@ impl LzwReader {
         self.decoder.as_ref().map_or(true, |e| e.has_ended())
     }
 
-    pub fn decode_bytes(&mut self, lzw_data: &[u8], decode_buffer: &mut OutputBuffer<'_>) -> io::Result<(usize, usize)> {
+    /// Decodes the codes that the LZW decoder holds from earlier input, without treating their
+    /// absence as a missing end code. Returns the number of pixels written.
+    pub fn decode_buffered(&mut self, decode_buffer: &mut OutputBuffer<'_>) -> Result<usize, DecodingError> {
         let decoder = self.decoder.as_mut().ok_or(io::ErrorKind::Other)?;
-
         let decode_buffer = match decode_buffer {
             OutputBuffer::Slice(buf) => &mut **buf,
             OutputBuffer::None => &mut [],
-            OutputBuffer::Vec(_) => return Err(io::Error::from(io::ErrorKind::Other)),
+            OutputBuffer::Vec(_) => return Err(io::Error::from(io::ErrorKind::Other).into()),
         };
+        let decoded = decoder.decode_bytes(&[], decode_buffer);
+        match decoded.status {
+            Ok(_) => Ok(decoded.consumed_out),
+            Err(LzwError::InvalidCode) => Err(DecodingError::format(DecodingFormatErrorKind::InvalidLzwCode, "invalid")),
+        }
+    }
+
+    pub fn decode_bytes(&mut self, lzw_data: &[u8], decode_buffer: &mut OutputBuffer<'_>) -> Result<(usize, usize), DecodingError> {
+        let decoder = self.decoder.as_mut().ok_or(io::ErrorKind::Other)?;
 
-        let decoded = decoder.decode_bytes(lzw_data, decode_buffer);
+        let decode_buffer = match decode_buffer {
+            OutputBuffer::Slice(buf) => &mut **buf,
+            OutputBuffer::None => &mut [],
+            OutputBuffer::Vec(_) => return Err(io::Error::from(io::ErrorKind::Other).into()),
+        };
+        self.has_decoded |= !lzw_data.is_empty();
+
+        let mut decoded = decoder.decode_bytes(lzw_data, decode_buffer);
+        // After returning the rest of a partially written code, the LZW decoder may stop
+        // without progress once, even though it has more pixels. Ask again, so that this isn't
+        // mistaken for the end of the data.
+        if decoded.consumed_in == 0 && decoded.consumed_out == 0 && !decode_buffer.is_empty() {
+            decoded = decoder.decode_bytes(lzw_data, decode_buffer);
+        }
 
         match decoded.status {
             Ok(LzwStatus::Done | LzwStatus::Ok) => {},
+            // Pixels that were kept from earlier input count as progress, also if no input has
+            // been consumed
+            Ok(LzwStatus::NoProgress) if decoded.consumed_out > 0 => {},
             Ok(LzwStatus::NoProgress) => {
                 if self.check_for_end_code {
-                    return Err(io::Error::new(io::ErrorKind::InvalidData, "no end code in lzw stream"));
+                    return Err(DecodingError::format(DecodingFormatErrorKind::NoEndCode, "no end code in lzw stream"));
                 }
             },
-            Err(err @ LzwError::InvalidCode) => {
-                return Err(io::Error::new(io::ErrorKind::InvalidData,"invalid"));
+            Err(LzwError::InvalidCode) => {
+                return Err(DecodingError::format(DecodingFormatErrorKind::InvalidLzwCode, "invalid"));
             }
         }
         Ok((decoded.consumed_in, decoded.consumed_out))
@@ -350,21 +721,41 @@ impl LzwReader {
 /// To just get GIF frames, use [`crate::Decoder`] instead.
 pub struct StreamingDecoder {
     state: State,
-    lzw_reader: LzwReader,
-    skip_frame_decoding: bool,
-    check_frame_consistency: bool,
+    header: HeaderParser,
+    extension: ExtensionParser,
+    image_data: ImageDataReader,
     allow_unknown_blocks: bool,
-    memory_limit: MemoryLimit,
-    version: Version,
-    width: u16,
-    height: u16,
-    global_color_table: Vec<u8>,
-    /// ext buffer
-    ext: ExtensionData,
+    continue_past_trailer: bool,
+    error_on_trailing_data: bool,
+    error_on_repeated_graphic_control: bool,
+    error_on_transparent_outside_palette: bool,
+    check_palette_padding: bool,
+    strict_version: bool,
+    /// Index of the first padding entry of the current frame's palette, if it's checked and
+    /// has padding
+    padding_start: Option<usize>,
+    /// Accepted deviations, each once
+    warnings: Vec<DecodingWarning>,
+    /// Number of bytes consumed
+    position: u64,
+    /// Number of bytes after the trailer that have been discarded
+    trailing_bytes: u64,
+    /// Number of trailers that decoding has continued past
+    skipped_trailers: usize,
+    /// Number of image blocks that have been started
+    frames: usize,
     /// Frame data
     current: Option<Frame<'static>>,
+    /// The last graphic control extension, also if no frame has followed it
+    graphic_control: Option<GraphicControl>,
     /// Needs to emit `HeaderEnd` once
     header_end_reached: bool,
+    /// The signature and the logical screen descriptor, copied as they're consumed
+    header_bytes: [u8; HEADER_LEN],
+    /// Position of the introducer of the last image descriptor, and the descriptor, copied as it's consumed
+    image_descriptor: Option<(u64, [u8; IMAGE_DESCRIPTOR_LEN])>,
+    #[cfg(any(debug_assertions, feature = "work_bound"))]
+    work: WorkCounter,
 }
 
 /// One version number of the GIF standard.
@@ -376,12 +767,6 @@ pub enum Version {
     V89a,
 }
 
-struct ExtensionData {
-    id: AnyExtension,
-    data: Vec<u8>,
-    is_block_end: bool,
-}
-
 /// Destination to write to for `StreamingDecoder::update`
 pub enum OutputBuffer<'a> {
     /// Overwrite bytes
@@ -404,7 +789,7 @@ impl OutputBuffer<'_> {
                 let vec: &mut Vec<u8> = vec;
                 let len = buf.len();
                 memory_limit.check_size(vec.len() + len)?;
-                vec.try_reserve(len).map_err(|_| io::ErrorKind::Other)?;
+                vec.try_reserve(len).map_err(|_| DecodingError::OutOfMemory)?;
                 if vec.capacity() - vec.len() >= len {
                     vec.extend_from_slice(buf);
                 }
@@ -426,25 +811,49 @@ impl StreamingDecoder {
         Self::with_options(&options)
     }
 
-    pub(crate) fn with_options(options: &DecodeOptions) -> Self {
+    /// Creates a new streaming decoder with the given options
+    ///
+    /// Only the options that concern the file format are used, not those of [`Decoder`](crate::Decoder)
+    /// like the color output.
+    #[must_use]
+    pub fn with_options(options: &DecodeOptions) -> Self {
         Self {
-            state: Magic(0, [0; 6]),
-            lzw_reader: LzwReader::new(options.check_for_end_code),
-            skip_frame_decoding: options.skip_frame_decoding,
-            check_frame_consistency: options.check_frame_consistency,
+            state: Header,
+            header: HeaderParser::new(options.strict_version),
+            extension: ExtensionParser::new(
+                options.allow_unknown_extensions,
+                options.allow_oversized_control_extension,
+                options.keep_extension_data,
+                options.memory_limit.clone(),
+            ),
+            image_data: ImageDataReader::new(
+                LzwReader::new(options.check_for_end_code),
+                options.skip_frame_decoding,
+                options.frame_consistency,
+                options.memory_limit.clone(),
+                options.collect_lzw_stats,
+                options.allow_missing_block_terminator,
+            ),
             allow_unknown_blocks: options.allow_unknown_blocks,
-            memory_limit: options.memory_limit.clone(),
-            version: Version::V87a,
-            width: 0,
-            height: 0,
-            global_color_table: Vec::new(),
-            ext: ExtensionData {
-                id: AnyExtension(0),
-                data: Vec::with_capacity(256), // 0xFF + 1 byte length
-                is_block_end: true,
-            },
+            continue_past_trailer: options.continue_past_trailer,
+            error_on_trailing_data: options.error_on_trailing_data,
+            error_on_repeated_graphic_control: options.error_on_repeated_graphic_control,
+            error_on_transparent_outside_palette: options.error_on_transparent_outside_palette,
+            check_palette_padding: options.check_palette_padding,
+            strict_version: options.strict_version,
+            padding_start: None,
+            warnings: Vec::new(),
+            position: 0,
+            trailing_bytes: 0,
+            skipped_trailers: 0,
+            frames: 0,
             current: None,
+            graphic_control: None,
             header_end_reached: false,
+            header_bytes: [0; HEADER_LEN],
+            image_descriptor: None,
+            #[cfg(any(debug_assertions, feature = "work_bound"))]
+            work: WorkCounter::default(),
         }
     }
 
@@ -452,414 +861,598 @@ impl StreamingDecoder {
     ///
     /// Returns the number of bytes consumed from the input buffer
     /// and the last decoding result.
+    ///
+    /// Unless `buf` is empty, every call either consumes input, returns a result other than
+    /// `Decoded::Nothing`, or fails, so a loop that feeds it the rest of the input can't stall.
+    ///
+    /// The work is linear: the number of steps the decoder takes, over all calls, is at most a
+    /// small multiple of the bytes it has consumed plus the pixels of the frames it has started.
+    /// Builds with debug assertions or the `work_bound` feature check this on every step, see
+    /// `StreamingDecoder::set_work_bound`.
     pub fn update(
         &mut self,
         mut buf: &[u8],
         write_into: &mut OutputBuffer<'_>,
     ) -> Result<(usize, Decoded), DecodingError> {
+        if buf.is_empty() && matches!(self.state, Trailer) {
+            return Ok((0, Decoded::Finished));
+        }
         let len = buf.len();
+        let mut stalled = 0;
         while !buf.is_empty() {
-            let (bytes, decoded) = self.next_state(buf, write_into)?;
-            buf = buf.get(bytes..).unwrap_or_default();
-            match decoded {
-                Decoded::Nothing => {},
-                result => {
-                    return Ok((len-buf.len(), result));
-                },
+            let start = self.position + (len - buf.len()) as u64;
+            let (bytes, decoded) = match self.next_state(buf, write_into) {
+                Ok(result) => result,
+                Err(err) => return Err(self.add_context(err, len - buf.len())),
             };
+            // Each step consumes input, emits something, or moves to a state that will. Anything
+            // else would loop forever on the same input.
+            if bytes == 0 && decoded.is_none() {
+                stalled += 1;
+                debug_assert!(stalled < MAX_STALLED_STEPS, "decoder makes no progress in {}", self.state_name());
+                if stalled >= MAX_STALLED_STEPS {
+                    let err = DecodingError::format(DecodingFormatErrorKind::InvalidState, "decoder makes no progress");
+                    return Err(self.add_context(err, len - buf.len()));
+                }
+            } else {
+                stalled = 0;
+            }
+            #[cfg(any(debug_assertions, feature = "work_bound"))]
+            {
+                if let Some(Decoded::FrameMetadata(_)) = decoded {
+                    if let Some(frame) = &self.current {
+                        self.work.pixels += u64::from(frame.width) * u64::from(frame.height);
+                    }
+                }
+                self.work.step(bytes);
+            }
+            if let Some(Decoded::BlockStart(Block::Image)) = decoded {
+                // The introducer has been consumed by the step before
+                let mut descriptor = [0; IMAGE_DESCRIPTOR_LEN];
+                descriptor[0] = Block::Image as u8;
+                self.image_descriptor = Some((start.saturating_sub(1), descriptor));
+            }
+            self.capture_descriptors(start, buf.get(..bytes).unwrap_or(buf));
+            buf = buf.get(bytes..).unwrap_or_default();
+            if let Some(result) = decoded {
+                self.position += (len - buf.len()) as u64;
+                return Ok((len-buf.len(), result));
+            }
         }
+        self.position += (len - buf.len()) as u64;
         Ok((len - buf.len(), Decoded::Nothing))
     }
 
+    /// Adds the position of the decoder to a format error found `consumed` bytes after the
+    /// start of the input of the current `update`
+    #[cold]
+    fn add_context(&self, err: DecodingError, consumed: usize) -> DecodingError {
+        match err {
+            DecodingError::Format(mut err) => {
+                err.offset = Some(self.position + consumed as u64);
+                err.frame_index = match self.state {
+                    Header => None,
+                    ImageBlock => Some(self.frames - 1),
+                    _ => Some(self.frames),
+                };
+                err.state = Some(self.state_name());
+                DecodingError::Format(err)
+            },
+            err => err,
+        }
+    }
+
+    /// Name of the part of the file the decoder is in, for error messages
+    fn state_name(&self) -> &'static str {
+        match self.state {
+            Header => self.header.state_name(),
+            BlockStart(_) | BlockEnd => "block introducer",
+            ExtensionBlock => self.extension.state_name(),
+            ImageBlock => self.image_data.state_name(),
+            AfterTrailer(_) | Trailer => "trailer",
+        }
+    }
+
+    /// Number of bytes that any well-formed file will have the decoder consume before the current
+    /// block ends, at least 1. Reading ahead that far can't block on a stream that ends later.
+    pub(crate) fn bytes_needed(&self) -> usize {
+        match self.state {
+            Header => self.header.bytes_needed(),
+            ExtensionBlock => self.extension.bytes_needed(),
+            ImageBlock => self.image_data.bytes_needed(),
+            BlockStart(_) | BlockEnd | AfterTrailer(_) | Trailer => 1,
+        }
+    }
+
     /// Returns the data of the last extension that has been decoded.
+    ///
+    /// The data is there until the next extension starts. If it's longer than 64 KiB, it's
+    /// dropped in the step after the extension has finished, to free the memory. Without
+    /// [`DecodeOptions::keep_extension_data`], only the parts that the decoder reads itself are
+    /// collected.
     #[must_use]
     pub fn last_ext(&self) -> (AnyExtension, &[u8], bool) {
-        (self.ext.id, &self.ext.data, self.ext.is_block_end)
+        self.extension.last_ext()
     }
 
-    /// Current frame info as a mutable ref.
+    /// The last extension that has been decoded, interpreted according to its label.
+    ///
+    /// It's built from the data that [`Self::last_ext`] returns, so it's there as long as that
+    /// is. `None` before the first extension, and until the block terminator of each one has
+    /// been decoded. Without [`DecodeOptions::keep_extension_data`] it's `None` for all but
+    /// graphic control extensions and looping application extensions, whose data the decoder
+    /// collects anyway.
     #[must_use]
-    #[track_caller]
-    pub fn current_frame_mut(&mut self) -> &mut Frame<'static> {
-        self.current.as_mut().unwrap()
+    pub fn last_extension(&self) -> Option<ExtensionBlock<'_>> {
+        self.extension.last_extension()
     }
 
-    /// Current frame info as a ref.
-    #[track_caller]
+    /// Number of black entries at the end of the global palette, once it has been decoded, see
+    /// [`Frame::palette_padding`]
+    #[must_use]
+    pub fn global_palette_padding(&self) -> usize {
+        self.global_palette().map_or(0, palette_padding)
+    }
+
+    /// Length of the longest data that has been collected for one extension, in bytes, see
+    /// [`StreamingDecoder::last_ext`]
     #[must_use]
-    pub fn current_frame(&self) -> &Frame<'static> {
-        self.current.as_ref().unwrap()
+    pub fn peak_extension_len(&self) -> usize {
+        self.extension.peak_len()
+    }
+
+    /// Sets the most steps that `update` may take per byte consumed or pixel declared, which is 2
+    /// by default. Exceeding it panics.
+    ///
+    /// Meant for tests that check a tighter or looser bound than the default.
+    #[cfg(feature = "work_bound")]
+    pub fn set_work_bound(&mut self, steps_per_unit: u64) {
+        self.work.per_unit = steps_per_unit;
     }
 
     /// Current frame info as a mutable ref.
+    ///
+    /// The frame exists from its graphic control extension or image descriptor on, until its
+    /// data has been decoded. It's `None` outside of that, for example before the first frame.
+    #[must_use]
+    pub fn current_frame_mut(&mut self) -> Option<&mut Frame<'static>> {
+        self.current.as_mut()
+    }
+
+    /// Current frame info as a ref, see [`StreamingDecoder::current_frame_mut`].
+    #[must_use]
+    pub fn current_frame(&self) -> Option<&Frame<'static>> {
+        self.current.as_ref()
+    }
+
+    /// Moves the current frame info out, without cloning its palette.
+    ///
+    /// Meant to be called after `FrameMetadata`, to keep the frame while decoding its data. The
+    /// decoder only needs the position and size of the frame from there on, which it keeps. Its
+    /// palette and buffer are left empty, so `current_frame` returns a frame without them until
+    /// `DataEnd`.
+    #[must_use]
+    pub fn take_current_frame(&mut self) -> Option<Frame<'static>> {
+        self.current.as_mut().map(Frame::take)
+    }
+
+    /// Current frame info as a mutable ref, for when there has to be one, like after `FrameMetadata`.
     #[inline(always)]
-    fn try_current_frame(&mut self) -> Result<&mut Frame<'static>, DecodingError> {
-        self.current.as_mut().ok_or_else(|| DecodingError::format("bad state"))
+    pub(crate) fn try_current_frame(&mut self) -> Result<&mut Frame<'static>, DecodingError> {
+        self.current.as_mut().ok_or_else(|| DecodingError::format(DecodingFormatErrorKind::InvalidState, "bad state"))
+    }
+
+    /// Ends decoding at the end of the input, as if a trailer followed.
+    ///
+    /// Many files end after their last block without a trailer. Afterwards, `update` returns
+    /// `Decoded::Finished`. It's an error if the input ended within a block. The error has the
+    /// kind [`DecodingFormatErrorKind::UnexpectedEof`], which names the part of the file that is
+    /// incomplete, like the global palette or the data of a frame.
+    pub fn finish(&mut self) -> Result<(), DecodingError> {
+        match self.state {
+            BlockEnd => {
+                self.state = Trailer;
+                Ok(())
+            },
+            AfterTrailer(skipped) if skipped > 0 && self.error_on_trailing_data => {
+                Err(DecodingError::format(DecodingFormatErrorKind::DataAfterTrailer, "data after the trailer"))
+            },
+            AfterTrailer(_) => {
+                self.state = Trailer;
+                Ok(())
+            },
+            Trailer => Ok(()),
+            _ => Err(self.unexpected_eof()),
+        }
+    }
+
+    /// The part of the file that input ending here leaves incomplete
+    fn incomplete(&self) -> Incomplete {
+        match self.state {
+            // A complete header without any blocks after it
+            Header => self.header.incomplete().unwrap_or(Incomplete::Trailer),
+            BlockStart(type_) if type_ == Block::Image as u8 => Incomplete::ImageDescriptor,
+            BlockStart(_) => Incomplete::Extension,
+            ExtensionBlock => self.extension.incomplete(),
+            ImageBlock => self.image_data.incomplete(),
+            BlockEnd | AfterTrailer(_) | Trailer => Incomplete::Trailer,
+        }
+    }
+
+    /// Error for input that ends here, which names the block it ends within
+    #[cold]
+    pub(crate) fn unexpected_eof(&self) -> DecodingError {
+        let incomplete = self.incomplete();
+        let err = DecodingError::format(DecodingFormatErrorKind::UnexpectedEof(incomplete), incomplete.message());
+        self.add_context(err, 0)
+    }
+
+    /// Number of bytes after the trailer that `update` has consumed and discarded, see
+    /// [`Decoded::Finished`].
+    #[must_use]
+    pub fn trailing_bytes(&self) -> u64 {
+        self.trailing_bytes
+    }
+
+    /// Number of trailers that decoding has continued past, see
+    /// [`DecodeOptions::continue_past_trailer`].
+    pub(crate) fn skipped_trailers(&self) -> usize {
+        self.skipped_trailers
+    }
+
+    /// A trailer has been decoded, and `continue_past_trailer` looks for a block after it
+    pub(crate) fn is_looking_past_trailer(&self) -> bool {
+        matches!(self.state, AfterTrailer(_))
+    }
+
+    /// Skips the rest of the data of the current frame without decoding it.
+    ///
+    /// The following calls to `update` consume the remaining sub-blocks as they are, and then
+    /// emit `Decoded::DataEnd` like at the end of a decoded frame.
+    ///
+    /// Returns `false`, and does nothing, if the decoder isn't within the data of a frame.
+    pub fn skip_frame_data(&mut self) -> bool {
+        matches!(self.state, ImageBlock) && self.image_data.skip_frame_data()
+    }
+
+    /// Switches a frame whose data hasn't been read yet from decoding to copying its LZW data.
+    ///
+    /// With `sub_block_lengths`, the length of every sub-block after the first one is copied as
+    /// well, and the terminator.
+    ///
+    /// Returns the minimum code size and the length of the first sub-block. It's an error if the
+    /// decoder isn't within the data of a frame, or if the frame has been clamped to the screen.
+    pub(crate) fn copy_frame_data(&mut self, sub_block_lengths: bool) -> Result<(u8, u8), DecodingError> {
+        if !matches!(self.state, ImageBlock) {
+            return Err(DecodingError::format(DecodingFormatErrorKind::InvalidState, "bad state"));
+        }
+        self.image_data.copy_frame_data(sub_block_lengths)
     }
 
     /// Width of the image
     #[must_use]
     pub fn width(&self) -> u16 {
-        self.width
+        self.header.screen().width
     }
 
     /// Height of the image
     #[must_use]
     pub fn height(&self) -> u16 {
-        self.height
+        self.header.screen().height
+    }
+
+    /// The global palette, once it has been decoded. `None` if the file has none.
+    ///
+    /// The decoder keeps it until it's dropped, it isn't moved out with [`Decoded::GlobalPalette`].
+    #[must_use]
+    pub fn global_palette(&self) -> Option<&[u8]> {
+        self.header.is_done().then(|| self.header.global_palette()).filter(|palette| !palette.is_empty())
+    }
+
+    /// The logical screen descriptor, once the header has been decoded up to the end of the
+    /// global palette
+    #[must_use]
+    pub fn screen_descriptor(&self) -> Option<&ScreenDescriptor> {
+        self.header.is_done().then(|| self.header.screen())
+    }
+
+    /// The last graphic control extension that has been decoded.
+    ///
+    /// It's also set for an extension that no image follows, e.g. one right before the trailer,
+    /// which `Frame::graphic_control` can't show.
+    #[must_use]
+    pub fn last_graphic_control(&self) -> Option<&GraphicControl> {
+        self.graphic_control.as_ref()
+    }
+
+    /// Statistics of the LZW data of the frame whose data has been read last, if
+    /// [`DecodeOptions::collect_lzw_stats`] is turned on.
+    ///
+    /// While the data of a frame is read, they cover the data up to there. They're all 0 before
+    /// the first frame.
+    #[must_use]
+    pub fn last_frame_lzw_stats(&self) -> Option<LzwStats> {
+        self.image_data.lzw_stats()
+    }
+
+    /// Deviations from the GIF standard that have been accepted so far, in the order they have
+    /// been found.
+    ///
+    /// Each warning is listed once, also if it applies to several blocks. Those that name a
+    /// frame are listed once per frame. Options like
+    /// [`DecodeOptions::strict_version`] turn them into errors instead.
+    #[must_use]
+    pub fn warnings(&self) -> &[DecodingWarning] {
+        &self.warnings
+    }
+
+    /// The first 13 bytes of the file, the signature and the logical screen descriptor, exactly
+    /// as they have been read.
+    ///
+    /// `None` until all of them have been read.
+    #[must_use]
+    pub fn descriptor_bytes(&self) -> Option<&[u8; HEADER_LEN]> {
+        if self.position >= HEADER_LEN as u64 {
+            Some(&self.header_bytes)
+        } else {
+            None
+        }
+    }
+
+    /// The image descriptor of the frame read last, exactly as it has been read, and its
+    /// position in the input.
+    ///
+    /// The 10 bytes start with the introducer. `None` until all of them have been read.
+    #[must_use]
+    pub fn image_descriptor_bytes(&self) -> Option<(u64, &[u8; IMAGE_DESCRIPTOR_LEN])> {
+        match &self.image_descriptor {
+            Some((offset, bytes)) if self.position >= offset + IMAGE_DESCRIPTOR_LEN as u64 => Some((*offset, bytes)),
+            _ => None,
+        }
+    }
+
+    /// Position of the global palette in the input, once it has been decoded
+    pub(crate) fn global_palette_range(&self) -> Option<Range<u64>> {
+        let len = self.global_palette()?.len();
+        Some(HEADER_LEN as u64..(HEADER_LEN + len) as u64)
+    }
+
+    /// Position of the local palette of the current frame in the input, once it has been decoded
+    pub(crate) fn local_palette_range(&self) -> Option<Range<u64>> {
+        let (offset, _) = self.image_descriptor_bytes()?;
+        let len = self.current.as_ref()?.palette.as_ref()?.len();
+        let start = offset + IMAGE_DESCRIPTOR_LEN as u64;
+        Some(start..start + len as u64)
+    }
+
+    /// Copies the parts of `consumed`, which starts at `offset` in the input, that belong to the
+    /// header or the last image descriptor
+    fn capture_descriptors(&mut self, offset: u64, consumed: &[u8]) {
+        copy_overlap(&mut self.header_bytes, 0, offset, consumed);
+        if let Some((start, descriptor)) = &mut self.image_descriptor {
+            copy_overlap(descriptor, *start, offset, consumed);
+        }
+    }
+
+    /// The logical screen descriptor, with 0 in the fields that haven't been decoded yet
+    pub(crate) fn screen(&self) -> &ScreenDescriptor {
+        self.header.screen()
     }
 
     /// The version number of the GIF standard used in this image.
     ///
     /// We suppose a minimum of `V87a` compatibility. This value will be reported until we have
-    /// read the version information in the magic header bytes.
+    /// read the version information in the magic header bytes. An unknown version is reported as
+    /// the closest one, see [`DecodingWarning::UnknownVersion`].
     #[must_use]
     pub fn version(&self) -> Version {
-        self.version
+        self.header.version()
     }
 
-    #[inline]
-    fn next_state(&mut self, buf: &[u8], write_into: &mut OutputBuffer<'_>) -> Result<(usize, Decoded), DecodingError> {
-        macro_rules! goto (
-            ($n:expr, $state:expr) => ({
-                self.state = $state;
-                Ok(($n, Decoded::Nothing))
-            });
-            ($state:expr) => ({
-                self.state = $state;
-                Ok((1, Decoded::Nothing))
-            });
-            ($n:expr, $state:expr, emit $res:expr) => ({
-                self.state = $state;
-                Ok(($n, $res))
-            });
-            ($state:expr, emit $res:expr) => ({
-                self.state = $state;
-                Ok((1, $res))
-            })
-        );
+    /// Keeps the allocation of a palette that is no longer used for the next local palette
+    pub(crate) fn recycle_palette(&mut self, palette: Vec<u8>) {
+        self.image_data.recycle_palette(palette);
+    }
 
+    /// Feeds the parser of the current part of the file, and moves on to the next block once
+    /// it's done.
+    #[inline]
+    fn next_state(&mut self, buf: &[u8], write_into: &mut OutputBuffer<'_>) -> Result<(usize, Option<Decoded>), DecodingError> {
         let b = *buf.first().ok_or(io::ErrorKind::UnexpectedEof)?;
 
         match self.state {
-            Magic(i, mut version) => if i < 6 {
-                version[i as usize] = b;
-                goto!(Magic(i+1, version))
-            } else if &version[..3] == b"GIF" {
-                self.version = match &version[3..] {
-                    b"87a" => Version::V87a,
-                    b"89a" => Version::V89a,
-                    _ => return Err(DecodingError::format("malformed GIF header"))
-                };
-                goto!(U16Byte1(U16Value::ScreenWidth, b))
-            } else {
-                Err(DecodingError::format("malformed GIF header"))
-            },
-            Byte(value) => {
-                use self::ByteValue::*;
-                match value {
-                    GlobalFlags => {
-                        goto!(Byte(Background { global_flags: b }))
-                    },
-                    Background { global_flags } => {
-                        goto!(
-                            Byte(AspectRatio { global_flags }),
-                            emit Decoded::BackgroundColor(b)
-                        )
-                    },
-                    AspectRatio { global_flags } => {
-                        let global_table = global_flags & 0x80 != 0;
-                        let table_size = if global_table {
-                            let table_size = PLTE_CHANNELS * (1 << ((global_flags & 0b111) + 1) as usize);
-                            self.global_color_table.try_reserve_exact(table_size).map_err(|_| io::ErrorKind::Other)?;
-                            table_size
-                        } else {
-                            0usize
-                        };
-                        goto!(GlobalPalette(table_size))
-                    },
-                    ControlFlags => {
-                        self.ext.data.push(b);
-                        let frame = self.try_current_frame()?;
-                        let control_flags = b;
-                        if control_flags & 1 != 0 {
-                            // Set to Some(...), gets overwritten later
-                            frame.transparent = Some(0);
-                        }
-                        frame.needs_user_input =
-                            control_flags & 0b10 != 0;
-                        frame.dispose = match DisposalMethod::from_u8(
-                            (control_flags & 0b11100) >> 2
-                        ) {
-                            Some(method) => method,
-                            None => DisposalMethod::Any
-                        };
-                        goto!(U16(U16Value::Delay))
-                    }
-                    TransparentIdx => {
-                        self.ext.data.push(b);
-                        if let Some(ref mut idx) = self.try_current_frame()?.transparent {
-                            *idx = b;
-                        }
-                        goto!(ExtensionDataBlock(0))
-                    }
-                    ImageFlags => {
-                        let local_table = (b & 0b1000_0000) != 0;
-                        let interlaced = (b & 0b0100_0000) != 0;
-                        let table_size = b & 0b0000_0111;
-                        let check_frame_consistency = self.check_frame_consistency;
-                        let (width, height) = (self.width, self.height);
-
-                        let frame = self.try_current_frame()?;
-
-                        frame.interlaced = interlaced;
-                        if check_frame_consistency {
-                            // Consistency checks.
-                            if width.checked_sub(frame.width) < Some(frame.left)
-                                || height.checked_sub(frame.height) < Some(frame.top)
-                            {
-                                return Err(DecodingError::format("frame descriptor is out-of-bounds"))
-                            }
-                        }
-
-                        if local_table {
-                            let pal_len = PLTE_CHANNELS * (1 << (table_size + 1));
-                            frame.palette.get_or_insert_with(Vec::new)
-                                .try_reserve_exact(pal_len).map_err(|_| io::ErrorKind::Other)?;
-                            goto!(LocalPalette(pal_len))
-                        } else {
-                            goto!(LocalPalette(0))
-                        }
-                    }
+            Header => {
+                let (consumed, event) = self.header.feed(buf)?;
+                if let Some(warning) = self.header.take_warning() {
+                    self.warn(warning);
                 }
-            }
-            GlobalPalette(left) => {
-                // the global_color_table is guaranteed to have the exact capacity required
-                if left > 0 {
-                    let n = cmp::min(left, buf.len());
-                    if n <= self.global_color_table.capacity() - self.global_color_table.len() {
-                        self.global_color_table.extend_from_slice(&buf[..n]);
-                    }
-                    goto!(n, GlobalPalette(left - n))
-                } else {
-                    goto!(BlockStart(b), emit Decoded::GlobalPalette(
-                        mem::take(&mut self.global_color_table).into_boxed_slice()
-                    ))
+                if self.header.is_done() {
+                    // `BlockEnd` handles the introducer, which may be the trailer of a file without frames
+                    self.state = BlockEnd;
                 }
-            }
+                Ok((consumed, event.map(|event| match event {
+                    HeaderEvent::BackgroundColor(index) => Decoded::BackgroundColor(index),
+                    HeaderEvent::GlobalPalette => Decoded::GlobalPalette,
+                })))
+            },
             BlockStart(type_) => {
                 if !self.header_end_reached && type_ != Block::Extension as u8 {
                     self.header_end_reached = true;
-                    return goto!(0, BlockStart(type_), emit Decoded::HeaderEnd);
+                    return goto!(self, 0, BlockStart(type_), emit Decoded::HeaderEnd);
                 }
 
                 match Block::from_u8(type_) {
                     Some(Block::Image) => {
+                        self.frames += 1;
                         self.add_frame();
-                        goto!(U16Byte1(U16Value::ImageLeft, b), emit Decoded::BlockStart(Block::Image))
+                        self.image_data.start(b, (self.width(), self.height()));
+                        goto!(self, ImageBlock, emit Decoded::BlockStart(Block::Image))
                     }
                     Some(Block::Extension) => {
-                        goto!(ExtensionBlock(AnyExtension(b)), emit Decoded::BlockStart(Block::Extension))
+                        if let (Version::V87a, Some(extension)) = (self.version(), Extension::from_u8(b)) {
+                            if self.strict_version {
+                                return Err(DecodingError::format(
+                                    DecodingFormatErrorKind::VersionMismatch, "extension of version 89a in a file of version 87a",
+                                ));
+                            }
+                            event!(WARN, label = b, "extension of version 89a in a file of version 87a");
+                            self.warn(DecodingWarning::V87aWithExtension(extension));
+                        }
+                        self.extension.start(AnyExtension(b));
+                        goto!(self, ExtensionBlock, emit Decoded::BlockStart(Block::Extension))
+                    }
+                    Some(Block::Trailer) if self.continue_past_trailer => {
+                        goto!(self, AfterTrailer(0), emit Decoded::BlockStart(Block::Trailer))
                     }
                     Some(Block::Trailer) => {
                         // The `Trailer` is the final state, and isn't reachable without extraneous data after the end of file
-                        goto!(Trailer, emit Decoded::BlockStart(Block::Trailer))
+                        goto!(self, Trailer, emit Decoded::BlockStart(Block::Trailer))
                     }
                     None => {
                         if self.allow_unknown_blocks {
-                            goto!(ExtensionDataBlock(b as usize))
+                            event!(WARN, block = type_, "skipping unknown block");
+                            self.extension.skip_unknown_block(b as usize);
+                            goto!(self, ExtensionBlock)
                         } else {
-                            Err(DecodingError::format("unknown block type encountered"))
+                            Err(DecodingError::format(DecodingFormatErrorKind::UnknownBlock, "unknown block type encountered"))
                         }
                     }
                 }
             },
             BlockEnd => {
+                // A long extension has been reported with `BlockFinished` in the last step
+                self.extension.release_large_buffer();
                 if b == Block::Trailer as u8 {
                     // can't consume yet, because the trailer is not a real block,
                     // and won't have futher data for BlockStart
-                    goto!(0, BlockStart(b))
+                    goto!(self, 0, BlockStart(b))
                 } else {
-                    goto!(BlockStart(b))
+                    goto!(self, BlockStart(b))
                 }
-            }
-            ExtensionBlock(id) => {
-                use Extension::*;
-                self.ext.id = id;
-                self.ext.data.clear();
-                self.ext.data.push(b);
-                if let Some(ext) = Extension::from_u8(id.0) {
-                    match ext {
-                        Control => {
-                            goto!(self.read_control_extension(b)?)
+            },
+            ExtensionBlock => {
+                let (consumed, event) = self.extension.feed(buf)?;
+                if let Some(warning) = self.extension.take_warning() {
+                    self.warn(warning);
+                }
+                if self.extension.is_done() {
+                    self.state = BlockEnd;
+                }
+                let id = self.extension.id();
+                let decoded = match event {
+                    Some(ExtensionEvent::Control { flags, delay, transparent }) => {
+                        self.add_frame();
+                        let error_on_repeated = self.error_on_repeated_graphic_control;
+                        let frame = self.try_current_frame()?;
+                        // The last extension before an image applies to it, and replaces all
+                        // fields of the earlier ones
+                        if frame.graphic_control.is_some() && error_on_repeated {
+                            return Err(DecodingError::format(
+                                DecodingFormatErrorKind::RepeatedGraphicControl, "more than one graphic control extension before an image",
+                            ));
                         }
-                        Text | Comment | Application => {
-                            goto!(ExtensionDataBlock(b as usize))
+                        let control = GraphicControl::from_extension(flags, delay, transparent);
+                        frame.transparent = control.transparent_index;
+                        frame.needs_user_input = control.needs_user_input;
+                        frame.dispose = control.disposal;
+                        frame.delay = delay;
+                        frame.graphic_control = Some(control);
+                        self.graphic_control = Some(control);
+                        None
+                    },
+                    Some(ExtensionEvent::SubBlockFinished) => Some(Decoded::SubBlockFinished(id)),
+                    Some(ExtensionEvent::BlockFinished) => Some(Decoded::BlockFinished(id)),
+                    Some(ExtensionEvent::Repetitions(repeat)) => Some(Decoded::Repetitions(repeat)),
+                    None => None,
+                };
+                Ok((consumed, decoded))
+            },
+            ImageBlock => {
+                let frame = self.current.as_mut().ok_or_else(|| DecodingError::format(DecodingFormatErrorKind::InvalidState, "bad state"))?;
+                let (consumed, event) = self.image_data.feed(buf, frame, write_into)?;
+                if let Some(ImageDataEvent::FrameMetadata(_)) = event {
+                    // The palette of the frame is known from here on. A frame without any is
+                    // reported by the caller.
+                    let palette = frame.palette.as_deref().unwrap_or(self.header.global_palette());
+                    let colors = palette.len() / PLTE_CHANNELS;
+                    let padding = if self.check_palette_padding { palette_padding(palette) } else { 0 };
+                    self.padding_start = (padding > 0).then(|| colors - padding);
+                    if frame.transparent.map_or(false, |t| colors > 0 && usize::from(t) >= colors) {
+                        if self.error_on_transparent_outside_palette {
+                            return Err(DecodingError::format(
+                                DecodingFormatErrorKind::TransparentOutsidePalette, "transparent index outside of the palette",
+                            ));
                         }
+                        event!(WARN, index = frame.transparent, "transparent index is outside of the palette");
+                        frame.transparent = None;
                     }
-                } else {
-                    Err(DecodingError::format("unknown block type encountered"))
-                }
-            }
-            ExtensionDataBlock(left) => {
-                if left > 0 {
-                    let n = cmp::min(left, buf.len());
-                    self.memory_limit.check_size(self.ext.data.len() + n)?;
-                    self.ext.data.try_reserve(n).map_err(|_| io::Error::from(io::ErrorKind::Other))?;
-                    self.ext.data.extend_from_slice(&buf[..n]);
-                    goto!(n, ExtensionDataBlock(left - n))
-                } else if b == 0 {
-                    self.ext.is_block_end = true;
-                    if self.ext.id.into_known() == Some(Extension::Application) {
-                        goto!(0, ApplicationExtension, emit Decoded::BlockFinished(self.ext.id))
-                    } else {
-                        goto!(BlockEnd, emit Decoded::BlockFinished(self.ext.id))
-                    }
-                } else {
-                    self.ext.is_block_end = false;
-                    goto!(ExtensionDataBlock(b as usize), emit Decoded::SubBlockFinished(self.ext.id))
                 }
-            }
-            ApplicationExtension => {
-                debug_assert_eq!(0, b);
-                // the parser removes sub-block lenghts, so app name and data are concatenated
-                if self.ext.data.len() >= 15 && &self.ext.data[1..13] == b"NETSCAPE2.0\x01" {
-                    let repeat = &self.ext.data[13..15];
-                    let repeat = u16::from(repeat[0]) | u16::from(repeat[1]) << 8;
-                    goto!(BlockEnd, emit Decoded::Repetitions(if repeat == 0 { Repeat::Infinite } else { Repeat::Finite(repeat) }))
-                } else {
-                    goto!(BlockEnd)
+                if let Some(warning) = self.image_data.take_warning() {
+                    self.warn(warning);
                 }
-            }
-            LocalPalette(left) => {
-                let n = cmp::min(left, buf.len());
-                if left > 0 {
-                    let src = &buf[..n];
-                    if let Some(pal) = self.try_current_frame()?.palette.as_mut() {
-                        // capacity has already been reserved in ImageFlags
-                        if pal.capacity() - pal.len() >= src.len() {
-                            pal.extend_from_slice(src);
-                        }
+                if let (Some(ImageDataEvent::PixelsDecoded(len)), Some(start), OutputBuffer::Slice(pixels)) = (&event, self.padding_start, &*write_into) {
+                    if pixels[..len.get()].iter().any(|&index| usize::from(index) >= start) {
+                        // The rest of the frame doesn't need to be scanned
+                        self.padding_start = None;
+                        self.warn(DecodingWarning::PaletteEntryPaddingReferenced(self.frames - 1));
                     }
-                    goto!(n, LocalPalette(left - n))
-                } else {
-                    goto!(LzwInit(b))
                 }
-            }
-            LzwInit(min_code_size) => {
-                if !self.skip_frame_decoding {
-                    // Reset validates the min code size
-                    self.lzw_reader.reset(min_code_size)?;
-                    goto!(DecodeSubBlock(b as usize), emit Decoded::FrameMetadata(FrameDataType::Pixels))
-                } else {
-                    LzwReader::check_code_size(min_code_size)?;
-                    goto!(CopySubBlock(b as usize), emit Decoded::FrameMetadata(FrameDataType::Lzw { min_code_size }))
+                if self.image_data.is_done() {
+                    // end of image data reached
+                    self.current = None;
+                    self.state = BlockEnd;
                 }
-            }
-            CopySubBlock(left) => {
-                debug_assert!(self.skip_frame_decoding);
-                if left > 0 {
-                    let n = cmp::min(left, buf.len());
-                    let (consumed, copied) = write_into.append(&buf[..n], &self.memory_limit)?;
-                    goto!(consumed, CopySubBlock(left - consumed), emit Decoded::LzwDataCopied(copied))
-                } else if b != 0 {
-                    goto!(CopySubBlock(b as usize))
-                } else {
-                    goto!(0, FrameDecoded)
-                }
-            }
-            DecodeSubBlock(left) => {
-                debug_assert!(!self.skip_frame_decoding);
-                if left > 0 {
-                    let n = cmp::min(left, buf.len());
-                    if self.lzw_reader.has_ended() || matches!(write_into, OutputBuffer::None) {
-                        return goto!(n, DecodeSubBlock(left - n), emit Decoded::Nothing);
-                    }
-
-                    let (mut consumed, bytes_len) = self.lzw_reader.decode_bytes(&buf[..n], write_into)?;
-
-                    // skip if can't make progress (decode would fail if check_for_end_code was set)
-                    if consumed == 0 && bytes_len == 0 {
-                        consumed = n;
-                    }
-
-                    let decoded = if let Some(bytes_len) = NonZeroUsize::new(bytes_len) {
-                        Decoded::BytesDecoded(bytes_len)
-                    } else {
-                        Decoded::Nothing
-                    };
-                    goto!(consumed, DecodeSubBlock(left - consumed), emit decoded)
-                } else if b != 0 {
-                    // decode next sub-block
-                    goto!(DecodeSubBlock(b as usize))
+                Ok((consumed, event.map(|event| match event {
+                    ImageDataEvent::FrameMetadata(data_type) => Decoded::FrameMetadata(data_type),
+                    ImageDataEvent::PixelsDecoded(len) => Decoded::PixelsDecoded(len),
+                    ImageDataEvent::LzwDataCopied(len) => Decoded::LzwDataCopied(len),
+                    ImageDataEvent::DataEnd => Decoded::DataEnd,
+                })))
+            },
+            AfterTrailer(skipped) => {
+                if b == Block::Image as u8 || b == Block::Extension as u8 {
+                    event!(WARN, skipped, "continuing after a trailer");
+                    self.skipped_trailers += 1;
+                    self.trailing_bytes = 0;
+                    goto!(self, 0, BlockEnd)
+                } else if skipped + 1 < TRAILER_WINDOW {
+                    self.trailing_bytes += 1;
+                    goto!(self, AfterTrailer(skipped + 1))
+                } else if self.error_on_trailing_data {
+                    Err(DecodingError::format(DecodingFormatErrorKind::DataAfterTrailer, "data after the trailer"))
                 } else {
-                    let (_, bytes_len) = self.lzw_reader.decode_bytes(&[], write_into)?;
-
-                    if let Some(bytes_len) = NonZeroUsize::new(bytes_len) {
-                        goto!(0, DecodeSubBlock(0), emit Decoded::BytesDecoded(bytes_len))
-                    } else {
-                        goto!(0, FrameDecoded)
-                    }
+                    // Not a file that has been exported twice, the trailer ends it after all
+                    self.trailing_bytes += 1;
+                    goto!(self, Trailer, emit Decoded::Finished)
                 }
-            }
-            U16(next) => goto!(U16Byte1(next, b)),
-            U16Byte1(next, value) => {
-                goto!(self.read_second_byte(next, value, b)?)
-            }
-            FrameDecoded => {
-                // end of image data reached
-                self.current = None;
-                debug_assert_eq!(0, b);
-                goto!(BlockEnd, emit Decoded::DataEnd)
-            }
-            Trailer => goto!(0, Trailer, emit Decoded::Nothing),
+            },
+            Trailer if self.error_on_trailing_data => Err(DecodingError::format(DecodingFormatErrorKind::DataAfterTrailer, "data after the trailer")),
+            Trailer => {
+                self.trailing_bytes += buf.len() as u64;
+                goto!(self, buf.len(), Trailer, emit Decoded::Finished)
+            },
         }
     }
 
-    fn read_second_byte(&mut self, next: U16Value, value: u8, b: u8) -> Result<State, DecodingError> {
-        use self::U16Value::*;
-        let value = (u16::from(b) << 8) | u16::from(value);
-        Ok(match (next, value) {
-            (ScreenWidth, width) => {
-                self.width = width;
-                U16(U16Value::ScreenHeight)
-            },
-            (ScreenHeight, height) => {
-                self.height = height;
-                Byte(ByteValue::GlobalFlags)
-            },
-            (Delay, delay) => {
-                self.try_current_frame()?.delay = delay;
-                self.ext.data.push(value as u8);
-                self.ext.data.push(b);
-                Byte(ByteValue::TransparentIdx)
-            },
-            (ImageLeft, left) => {
-                self.try_current_frame()?.left = left;
-                U16(U16Value::ImageTop)
-            },
-            (ImageTop, top) => {
-                self.try_current_frame()?.top = top;
-                U16(U16Value::ImageWidth)
-            },
-            (ImageWidth, width) => {
-                self.try_current_frame()?.width = width;
-                U16(U16Value::ImageHeight)
-            },
-            (ImageHeight, height) => {
-                self.try_current_frame()?.height = height;
-                Byte(ByteValue::ImageFlags)
-            }
-        })
+    /// Continue decoding from a block after the global palette.
+    ///
+    /// The input must continue at the introducer of that block, which is at `position` and
+    /// belongs to the frame with the index `frame`.
+    pub(crate) fn rewind(&mut self, position: u64, frame: usize) {
+        self.state = BlockEnd;
+        self.trailing_bytes = 0;
+        self.position = position;
+        self.frames = frame;
+        self.current = None;
+        self.graphic_control = None;
+        self.image_descriptor = None;
+        self.extension.reset();
     }
 
-    fn read_control_extension(&mut self, b: u8) -> Result<State, DecodingError> {
-        self.add_frame();
-        self.ext.data.push(b);
-        if b != 4 {
-            return Err(DecodingError::format("control extension has wrong length"));
+    fn warn(&mut self, warning: DecodingWarning) {
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
         }
-        Ok(Byte(ByteValue::ControlFlags))
     }
 
     fn add_frame(&mut self) {
@@ -869,7 +1462,18 @@ impl StreamingDecoder {
     }
 }
 
+/// Copies the part of `data`, which starts at `offset` in the input, that overlaps `window`, which
+/// starts at `start`
+fn copy_overlap(window: &mut [u8], start: u64, offset: u64, data: &[u8]) {
+    let from = start.max(offset);
+    let to = (start + window.len() as u64).min(offset + data.len() as u64);
+    if from < to {
+        window[(from - start) as usize..(to - start) as usize]
+            .copy_from_slice(&data[(from - offset) as usize..(to - offset) as usize]);
+    }
+}
+
 #[test]
 fn error_cast() {
-    let _ : Box<dyn error::Error> = DecodingError::format("testing").into();
+    let _ : Box<dyn error::Error> = DecodingError::format(DecodingFormatErrorKind::InvalidState, "testing").into();
 }
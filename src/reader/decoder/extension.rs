@@ -0,0 +1,555 @@
+//! Extension blocks, whose sub-blocks are collected into one buffer
+
+use alloc::vec::Vec;
+use core::{cmp, mem};
+
+use crate::common::{AnyExtension, Extension, GraphicControl};
+use crate::{MemoryLimit, Repeat};
+
+use super::{DecodingError, DecodingFormatErrorKind, DecodingWarning, Incomplete};
+
+/// Values read by the [`ExtensionParser`]
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum ExtensionEvent {
+    /// All fields of a graphic control extension.
+    Control { flags: u8, delay: u16, transparent: u8 },
+    /// A sub-block has been collected, and more follow.
+    SubBlockFinished,
+    /// The block terminator has been reached.
+    BlockFinished,
+    /// Loop count of the first valid loop sub-block of a looping application extension, after
+    /// `BlockFinished`.
+    Repetitions(Repeat),
+}
+
+/// An extension, interpreted according to its label, see
+/// [`StreamingDecoder::last_extension`](super::StreamingDecoder::last_extension)
+///
+/// The slices borrow the data the decoder has collected, in which the sub-blocks are
+/// concatenated without their lengths.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ExtensionBlock<'a> {
+    /// The text of a comment extension.
+    Comment(&'a [u8]),
+    /// An application extension.
+    Application {
+        /// Name of the application, like `NETSCAPE`. It has 8 bytes in a well-formed file.
+        identifier: &'a [u8],
+        /// Authentication code, like `2.0`. It has 3 bytes in a well-formed file.
+        auth: &'a [u8],
+        /// The sub-blocks after the identifier and the authentication code.
+        data: &'a [u8],
+    },
+    /// A plain text extension.
+    PlainText {
+        /// Position and size of the text grid, size of its cells, and the foreground and
+        /// background color indices. They have 12 bytes in a well-formed file.
+        header: &'a [u8],
+        /// The text.
+        text: &'a [u8],
+    },
+    /// The fields of a graphic control extension.
+    GraphicControl(GraphicControl),
+    /// An extension with a label that the decoder doesn't know, see
+    /// [`DecodeOptions::allow_unknown_extensions`](crate::DecodeOptions::allow_unknown_extensions).
+    Unknown {
+        /// The label after the extension introducer.
+        label: u8,
+        /// The sub-blocks.
+        data: &'a [u8],
+    },
+}
+
+#[derive(Debug, Copy, Clone)]
+enum ExtensionState {
+    /// The next byte is the length of the first sub-block
+    Start(AnyExtension),
+    /// Collects the data, with what is left of the current sub-block
+    Data(usize),
+    /// Emits the loop count of a looping extension, without consuming anything
+    Application,
+    Done,
+}
+use self::ExtensionState::*;
+
+struct ExtensionData {
+    id: AnyExtension,
+    data: Vec<u8>,
+    is_block_end: bool,
+}
+
+/// Identifier of the application extension that has the loop count, with the length of its
+/// sub-block
+const NETSCAPE: &[u8; 12] = b"\x0bNETSCAPE2.0";
+
+/// Sub-blocks of a looping application extension after the identifier, which start with an id.
+///
+/// The id 1 is followed by the loop count, and the id 2 by the buffer size that browsers
+/// ignore.
+#[derive(Debug, Default)]
+struct LoopSubBlocks {
+    /// The identifier is `NETSCAPE2.0`
+    is_looping: bool,
+    /// The count of the first valid loop sub-block
+    count: Option<u16>,
+}
+
+/// Size of the fields of a graphic control extension
+const CONTROL_LEN: u8 = 4;
+
+/// Capacity of the buffer for the data of extensions, which holds a sub-block with its length
+const BUFFER_CAPACITY: usize = 256;
+/// Largest capacity that the buffer keeps after a long extension, which is shrunk back to
+/// `BUFFER_CAPACITY` so that one huge comment doesn't stay allocated as long as the decoder
+const MAX_RETAINED_CAPACITY: usize = 1 << 16;
+
+/// Parses an extension from the byte after its label to the block terminator
+pub(super) struct ExtensionParser {
+    state: ExtensionState,
+    allow_unknown_extensions: bool,
+    /// A graphic control extension may have bytes after its fields
+    allow_oversized_control: bool,
+    /// Collect the data of all extensions, not only the parts the parser needs itself
+    keep_data: bool,
+    memory_limit: MemoryLimit,
+    /// ext buffer
+    ext: ExtensionData,
+    /// Where the data of the current sub-block starts in the buffer
+    sub_block_start: usize,
+    loop_sub_blocks: LoopSubBlocks,
+    /// A deviation that has been accepted in the last call to `feed`
+    warning: Option<DecodingWarning>,
+    /// Longest data that has been collected for one extension
+    peak_len: usize,
+}
+
+impl ExtensionParser {
+    pub fn new(allow_unknown_extensions: bool, allow_oversized_control: bool, keep_data: bool, memory_limit: MemoryLimit) -> Self {
+        Self {
+            state: Done,
+            allow_unknown_extensions,
+            allow_oversized_control,
+            keep_data,
+            memory_limit,
+            ext: ExtensionData {
+                id: AnyExtension(0),
+                data: Vec::with_capacity(BUFFER_CAPACITY), // 0xFF + 1 byte length
+                is_block_end: true,
+            },
+            sub_block_start: 0,
+            loop_sub_blocks: LoopSubBlocks::default(),
+            warning: None,
+            peak_len: 0,
+        }
+    }
+
+    /// Frees most of the buffer if a long extension has grown it, which drops the data of the
+    /// last extension
+    pub fn release_large_buffer(&mut self) {
+        if self.ext.data.capacity() > MAX_RETAINED_CAPACITY {
+            self.ext.data.clear();
+            self.ext.data.shrink_to(BUFFER_CAPACITY);
+        }
+    }
+
+    /// Longest data that has been collected for one extension, in bytes
+    pub fn peak_len(&self) -> usize {
+        self.peak_len
+    }
+
+    /// Starts an extension, after its label has been consumed.
+    ///
+    /// The data of the previous extension is kept until the next byte is fed.
+    pub fn start(&mut self, label: AnyExtension) {
+        self.state = Start(label);
+    }
+
+    /// Collects the sub-blocks of an unknown block as more data of the last extension, starting
+    /// with a sub-block of `len` bytes.
+    pub fn skip_unknown_block(&mut self, len: usize) {
+        self.state = Data(len);
+        self.sub_block_start = self.ext.data.len();
+        self.loop_sub_blocks = LoopSubBlocks::default();
+    }
+
+    /// Forgets the data of the last extension
+    pub fn reset(&mut self) {
+        self.state = Done;
+        self.ext.data.clear();
+        self.ext.is_block_end = true;
+    }
+
+    /// The block terminator has been consumed, the next byte is the introducer of a block
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, Done)
+    }
+
+    /// Label, collected data including the length of the first sub-block, and whether the
+    /// block terminator has been reached
+    pub fn last_ext(&self) -> (AnyExtension, &[u8], bool) {
+        (self.ext.id, &self.ext.data, self.ext.is_block_end)
+    }
+
+    /// The last extension, once its block terminator has been consumed, interpreted without
+    /// parsing it again. `None` if its data hasn't been collected.
+    pub fn last_extension(&self) -> Option<ExtensionBlock<'_>> {
+        if matches!(self.state, Data(_)) {
+            return None;
+        }
+        let is_collected = match self.ext.id.into_known() {
+            Some(Extension::Control) => true,
+            Some(Extension::Application) => self.loop_sub_blocks.is_looping,
+            _ => false,
+        };
+        if !self.keep_data && !is_collected {
+            return None;
+        }
+        let (&first_len, data) = self.ext.data.split_first()?;
+        let (first, rest) = data.split_at(cmp::min(usize::from(first_len), data.len()));
+        Some(match self.ext.id.into_known() {
+            Some(Extension::Comment) => ExtensionBlock::Comment(data),
+            Some(Extension::Application) => {
+                let (identifier, auth) = first.split_at(cmp::min(8, first.len()));
+                ExtensionBlock::Application { identifier, auth, data: rest }
+            },
+            Some(Extension::Text) => ExtensionBlock::PlainText { header: first, text: rest },
+            Some(Extension::Control) => {
+                // The length is there twice, and the fields follow
+                let [flags, delay1, delay2, transparent] = <[u8; 4]>::try_from(data.get(1..5)?).ok()?;
+                let delay = u16::from_le_bytes([delay1, delay2]);
+                ExtensionBlock::GraphicControl(GraphicControl::from_extension(flags, delay, transparent))
+            },
+            None => ExtensionBlock::Unknown { label: self.ext.id.0, data },
+        })
+    }
+
+    pub fn id(&self) -> AnyExtension {
+        self.ext.id
+    }
+
+    /// The deviation that the last call to `feed` has accepted, if any
+    pub fn take_warning(&mut self) -> Option<DecodingWarning> {
+        self.warning.take()
+    }
+
+    /// Name of the part of the extension, for error messages
+    pub fn state_name(&self) -> &'static str {
+        if self.in_control_fields() {
+            "graphic control extension"
+        } else {
+            "extension"
+        }
+    }
+
+    /// The part of the file that input ending here leaves incomplete
+    pub fn incomplete(&self) -> Incomplete {
+        if self.in_control_fields() {
+            Incomplete::GraphicControl
+        } else {
+            Incomplete::Extension
+        }
+    }
+
+    /// The data of the current sub-block is collected. The parser needs the fields of control
+    /// extensions and the sub-blocks of looping extensions, and the rest only if it keeps data.
+    fn collects_data(&self) -> bool {
+        let is_application = self.ext.id.into_known() == Some(Extension::Application);
+        self.keep_data || self.in_control_fields() || is_application && (self.sub_block_start == 1 || self.loop_sub_blocks.is_looping)
+    }
+
+    /// The first sub-block of a graphic control extension is being collected
+    fn in_control_fields(&self) -> bool {
+        // The data of control extensions has always started with the length twice
+        matches!(self.state, Data(_)) && self.ext.id.into_known() == Some(Extension::Control) && self.sub_block_start == 2
+    }
+
+    /// Number of bytes that will be consumed before the extension ends, at least 1
+    pub fn bytes_needed(&self) -> usize {
+        match self.state {
+            // The block terminator follows the sub-block at the latest
+            Data(left) => left + 1,
+            _ => 1,
+        }
+    }
+
+    /// Consumes the start of `buf`, which must not be empty
+    pub fn feed(&mut self, buf: &[u8]) -> Result<(usize, Option<ExtensionEvent>), DecodingError> {
+        let b = buf[0];
+        match self.state {
+            Start(id) => {
+                self.ext.id = id;
+                self.ext.data.clear();
+                self.ext.data.push(b);
+                self.sub_block_start = 1;
+                self.loop_sub_blocks = LoopSubBlocks::default();
+                match Extension::from_u8(id.0) {
+                    Some(Extension::Control) => if b == CONTROL_LEN || b > CONTROL_LEN && self.allow_oversized_control {
+                        if b != CONTROL_LEN {
+                            event!(WARN, len = b, "skipping the bytes after the fields of a control extension");
+                            self.warning = Some(DecodingWarning::OversizedControlExtension(b));
+                        }
+                        // The data of control extensions has always started with the length twice
+                        self.ext.data.push(b);
+                        self.sub_block_start = 2;
+                        goto!(self, Data(b as usize))
+                    } else {
+                        Err(DecodingError::format(DecodingFormatErrorKind::MalformedExtension, "control extension has wrong length"))
+                    },
+                    Some(Extension::Text | Extension::Comment | Extension::Application) => {
+                        goto!(self, Data(b as usize))
+                    },
+                    None if self.allow_unknown_extensions => {
+                        event!(WARN, label = id.0, "skipping unknown extension");
+                        goto!(self, Data(b as usize))
+                    },
+                    None => Err(DecodingError::format(DecodingFormatErrorKind::UnknownExtension, "unknown extension block encountered")),
+                }
+            },
+            Data(left) => {
+                if left > 0 {
+                    let n = cmp::min(left, buf.len());
+                    if self.collects_data() {
+                        self.memory_limit.check_size(self.ext.data.len() + n)?;
+                        self.ext.data.try_reserve(n).map_err(|_| DecodingError::OutOfMemory)?;
+                        self.ext.data.extend_from_slice(&buf[..n]);
+                        self.peak_len = self.peak_len.max(self.ext.data.len());
+                    }
+                    if n == left && self.in_control_fields() {
+                        // The first four bytes are the fields, anything after them is skipped
+                        let (flags, delay1, delay2, transparent) = (self.ext.data[2], self.ext.data[3], self.ext.data[4], self.ext.data[5]);
+                        let delay = u16::from_le_bytes([delay1, delay2]);
+                        goto!(self, n, Data(0), emit ExtensionEvent::Control { flags, delay, transparent })
+                    } else {
+                        goto!(self, n, Data(left - n))
+                    }
+                } else if b == 0 {
+                    self.ext.is_block_end = true;
+                    self.sub_block_finished();
+                    if self.loop_sub_blocks.count.is_some() {
+                        goto!(self, 0, Application, emit ExtensionEvent::BlockFinished)
+                    } else {
+                        goto!(self, Done, emit ExtensionEvent::BlockFinished)
+                    }
+                } else {
+                    self.ext.is_block_end = false;
+                    self.sub_block_finished();
+                    goto!(self, Data(b as usize), emit ExtensionEvent::SubBlockFinished)
+                }
+            },
+            Application => {
+                debug_assert_eq!(0, b);
+                match self.loop_sub_blocks.count.take() {
+                    Some(0) => goto!(self, Done, emit ExtensionEvent::Repetitions(Repeat::Infinite)),
+                    Some(count) => goto!(self, Done, emit ExtensionEvent::Repetitions(Repeat::Finite(count))),
+                    None => goto!(self, Done),
+                }
+            },
+            Done => Err(DecodingError::format(DecodingFormatErrorKind::InvalidState, "bad state")),
+        }
+    }
+
+    /// Checks the sub-block whose data has just been collected, if it belongs to a looping
+    /// extension.
+    ///
+    /// The sub-blocks are only checked, the collected data stays as it is.
+    fn sub_block_finished(&mut self) {
+        let start = mem::replace(&mut self.sub_block_start, self.ext.data.len());
+        if self.ext.id.into_known() != Some(Extension::Application) {
+            return;
+        }
+        if start == 1 {
+            self.loop_sub_blocks.is_looping = self.ext.data[..] == NETSCAPE[..];
+            return;
+        }
+        if !self.loop_sub_blocks.is_looping {
+            return;
+        }
+        // The parser removes the sub-block lengths, so this sub-block is the rest of the data
+        let warning = match self.ext.data[start..] {
+            [1, count1, count2] => {
+                let count = u16::from_le_bytes([count1, count2]);
+                match self.loop_sub_blocks.count {
+                    None => {
+                        self.loop_sub_blocks.count = Some(count);
+                        return;
+                    },
+                    Some(first) if first != count => {
+                        event!(WARN, first, ignored = count, "conflicting loop counts");
+                        DecodingWarning::ConflictingLoopCounts
+                    },
+                    Some(_) => return,
+                }
+            },
+            [1, ..] => {
+                event!(WARN, len = self.ext.data.len() - start, "loop sub-block has the wrong length");
+                DecodingWarning::MalformedLoopCount
+            },
+            [2, ..] => return,
+            [id, ..] => {
+                event!(WARN, id, "skipping unknown sub-block of a looping extension");
+                DecodingWarning::UnknownLoopSubBlock(id)
+            },
+            [] => return,
+        };
+        self.warning = Some(warning);
+    }
+}
+
+#[cfg(test)]
+fn parse_extension(label: u8, data: &[u8]) -> Result<Vec<ExtensionEvent>, DecodingError> {
+    parse_extension_with(false, label, data)
+}
+
+#[cfg(test)]
+fn parse_extension_with(allow_oversized_control: bool, label: u8, data: &[u8]) -> Result<Vec<ExtensionEvent>, DecodingError> {
+    let mut parser = ExtensionParser::new(false, allow_oversized_control, true, MemoryLimit::Unlimited);
+    parser.start(AnyExtension(label));
+    let mut events = Vec::new();
+    let mut pos = 0;
+    while !parser.is_done() {
+        let (consumed, event) = parser.feed(&data[pos..])?;
+        pos += consumed;
+        events.extend(event);
+    }
+    assert_eq!(pos, data.len());
+    Ok(events)
+}
+
+#[test]
+fn control_extension_fields() {
+    assert_eq!(parse_extension(0xF9, &[4, 0b1001, 10, 1, 7, 0]).unwrap(), [
+        ExtensionEvent::Control { flags: 0b1001, delay: 266, transparent: 7 },
+        ExtensionEvent::BlockFinished,
+    ]);
+    assert!(parse_extension(0xF9, &[5, 0, 0, 0, 0, 0, 0]).is_err());
+}
+
+#[test]
+fn oversized_control_extension() {
+    for len in [3, 4, 5, 255] {
+        let mut data = vec![len, 0b1001, 10, 1, 7];
+        data.resize(usize::from(len) + 1, 0xFF);
+        // Another sub-block follows, which is collected as usual
+        data.extend_from_slice(&[1, 0, 0]);
+        let strict = parse_extension_with(false, 0xF9, &data);
+        let lenient = parse_extension_with(true, 0xF9, &data);
+        if len < 4 {
+            assert!(strict.is_err() && lenient.is_err(), "{len}");
+        } else {
+            let fields = ExtensionEvent::Control { flags: 0b1001, delay: 266, transparent: 7 };
+            let events = [fields, ExtensionEvent::SubBlockFinished, ExtensionEvent::BlockFinished];
+            assert_eq!(strict.is_ok(), len == 4, "{len}");
+            assert_eq!(lenient.unwrap(), events, "{len}");
+        }
+    }
+
+    // The fields are emitted once, even if they come one byte at a time
+    let mut parser = ExtensionParser::new(false, true, true, MemoryLimit::Unlimited);
+    parser.start(AnyExtension(0xF9));
+    let data = [6, 0b1001, 10, 1, 7, 0, 0, 0];
+    let mut events = Vec::new();
+    for b in data.chunks(1) {
+        let (consumed, event) = parser.feed(b).unwrap();
+        assert_eq!(consumed, 1);
+        events.extend(event);
+    }
+    assert!(parser.is_done());
+    assert_eq!(events, [ExtensionEvent::Control { flags: 0b1001, delay: 266, transparent: 7 }, ExtensionEvent::BlockFinished]);
+    assert_eq!(parser.take_warning(), Some(DecodingWarning::OversizedControlExtension(6)));
+    assert_eq!(parser.last_ext().1, [6, 6, 0b1001, 10, 1, 7, 0, 0]);
+}
+
+#[test]
+fn looping_application_extension() {
+    let mut data = b"\x0bNETSCAPE2.0".to_vec();
+    data.extend_from_slice(&[3, 1, 5, 0, 0]);
+    assert_eq!(parse_extension(0xFF, &data).unwrap(), [
+        ExtensionEvent::SubBlockFinished,
+        ExtensionEvent::BlockFinished,
+        ExtensionEvent::Repetitions(Repeat::Finite(5)),
+    ]);
+}
+
+#[test]
+fn loop_sub_blocks() {
+    fn parse(sub_blocks: &[&[u8]]) -> (Vec<ExtensionEvent>, Vec<DecodingWarning>) {
+        let mut parser = ExtensionParser::new(false, false, true, MemoryLimit::Unlimited);
+        parser.start(AnyExtension(0xFF));
+        let mut data = NETSCAPE.to_vec();
+        for sub_block in sub_blocks {
+            data.push(sub_block.len() as u8);
+            data.extend_from_slice(sub_block);
+        }
+        data.push(0);
+        let (mut events, mut warnings) = (Vec::new(), Vec::new());
+        let mut pos = 0;
+        while !parser.is_done() {
+            let (consumed, event) = parser.feed(&data[pos..]).unwrap();
+            pos += consumed;
+            events.extend(event.filter(|event| *event != ExtensionEvent::SubBlockFinished));
+            warnings.extend(parser.take_warning());
+        }
+        // The data is kept as it is, without the sub-block lengths
+        assert_eq!(parser.last_ext().1, sub_blocks.iter().fold(NETSCAPE.to_vec(), |data, b| [&data[..], b].concat()));
+        (events, warnings)
+    }
+    let looping = |count| vec![ExtensionEvent::BlockFinished, ExtensionEvent::Repetitions(count)];
+
+    assert_eq!(parse(&[&[1, 0, 0]]), (looping(Repeat::Infinite), vec![]));
+    assert_eq!(parse(&[&[2, 0, 0x10, 0, 0], &[1, 5, 0]]), (looping(Repeat::Finite(5)), vec![]));
+    assert_eq!(parse(&[&[7, 1], &[1, 5, 0]]), (looping(Repeat::Finite(5)), vec![DecodingWarning::UnknownLoopSubBlock(7)]));
+    assert_eq!(parse(&[&[1, 5, 0, 0], &[1, 6, 0]]), (looping(Repeat::Finite(6)), vec![DecodingWarning::MalformedLoopCount]));
+    assert_eq!(parse(&[&[1, 5]]), (vec![ExtensionEvent::BlockFinished], vec![DecodingWarning::MalformedLoopCount]));
+    assert_eq!(parse(&[&[1, 5, 0], &[1, 5, 0], &[1, 6, 0]]), (looping(Repeat::Finite(5)), vec![DecodingWarning::ConflictingLoopCounts]));
+}
+
+#[test]
+fn last_extension_is_interpreted() {
+    let parse = |label: u8, data: &[u8], check: &dyn Fn(Option<ExtensionBlock<'_>>)| {
+        let mut parser = ExtensionParser::new(true, false, true, MemoryLimit::Unlimited);
+        assert_eq!(parser.last_extension(), None);
+        parser.start(AnyExtension(label));
+        let mut pos = 0;
+        while !parser.is_done() {
+            if pos > 0 && pos < data.len() - 1 {
+                assert_eq!(parser.last_extension(), None);
+            }
+            pos += parser.feed(&data[pos..]).unwrap().0;
+        }
+        check(parser.last_extension());
+    };
+    parse(0xFE, b"\x03abc\x02de\x00", &|ext| assert_eq!(ext, Some(ExtensionBlock::Comment(b"abcde"))));
+    parse(0xFF, b"\x0bNETSCAPE2.0\x03\x01\x05\x00\x00", &|ext| assert_eq!(ext, Some(ExtensionBlock::Application {
+        identifier: b"NETSCAPE", auth: b"2.0", data: &[1, 5, 0],
+    })));
+    parse(0x01, b"\x0c000011112345\x02hi\x00", &|ext| assert_eq!(ext, Some(ExtensionBlock::PlainText {
+        header: b"000011112345", text: b"hi",
+    })));
+    parse(0xF9, &[4, 0b1001, 10, 1, 7, 0], &|ext| assert_eq!(ext, Some(ExtensionBlock::GraphicControl(
+        GraphicControl::from_extension(0b1001, 266, 7),
+    ))));
+    parse(0xAB, b"\x02xy\x00", &|ext| assert_eq!(ext, Some(ExtensionBlock::Unknown { label: 0xAB, data: b"xy" })));
+}
+
+#[test]
+fn last_extension_is_none_without_its_data() {
+    let parse = |label: u8, data: &[u8], expected: Option<ExtensionBlock<'_>>| {
+        let mut parser = ExtensionParser::new(true, false, false, MemoryLimit::Unlimited);
+        parser.start(AnyExtension(label));
+        let mut pos = 0;
+        while !parser.is_done() {
+            pos += parser.feed(&data[pos..]).unwrap().0;
+        }
+        assert_eq!(parser.last_extension(), expected);
+    };
+    parse(0xFE, b"\x03abc\x00", None);
+    parse(0xFF, b"\x0bXMP DataXMP\x02ab\x00", None);
+    parse(0xAB, b"\x02xy\x00", None);
+    parse(0xFF, b"\x0bNETSCAPE2.0\x03\x01\x05\x00\x00", Some(ExtensionBlock::Application {
+        identifier: b"NETSCAPE", auth: b"2.0", data: &[1, 5, 0],
+    }));
+    parse(0xF9, &[4, 0b1001, 10, 1, 7, 0], Some(ExtensionBlock::GraphicControl(
+        GraphicControl::from_extension(0b1001, 266, 7),
+    )));
+}
@@ -0,0 +1,242 @@
+//! Signature, logical screen descriptor and global color table
+
+use alloc::vec::Vec;
+use core::cmp;
+
+use crate::common::ScreenDescriptor;
+
+use super::{DecodingError, DecodingFormatErrorKind, DecodingWarning, Incomplete, Version, PLTE_CHANNELS};
+
+/// Values read by the [`HeaderParser`]
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum HeaderEvent {
+    /// Index of the background color in the global palette.
+    BackgroundColor(u8),
+    /// The global palette has been read, which ends the header.
+    GlobalPalette,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum HeaderState {
+    Magic(u8, [u8; 6]),
+    /// Second byte of the screen width, after the first one
+    ScreenWidth(u8),
+    ScreenHeightByte1,
+    ScreenHeight(u8),
+    GlobalFlags,
+    Background { global_flags: u8 },
+    AspectRatio { global_flags: u8 },
+    GlobalPalette(usize),
+    Done,
+}
+use self::HeaderState::*;
+
+/// Parses the file up to the introducer of the first block
+pub(super) struct HeaderParser {
+    state: HeaderState,
+    version: Version,
+    /// Only `87a` and `89a` are valid versions
+    strict_version: bool,
+    screen: ScreenDescriptor,
+    global_color_table: Vec<u8>,
+    /// A deviation that has been accepted in the last call to `feed`
+    warning: Option<DecodingWarning>,
+}
+
+impl HeaderParser {
+    pub fn new(strict_version: bool) -> Self {
+        Self {
+            state: Magic(0, [0; 6]),
+            version: Version::V87a,
+            strict_version,
+            screen: ScreenDescriptor::default(),
+            global_color_table: Vec::new(),
+            warning: None,
+        }
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Fields that haven't been read yet are 0
+    pub fn screen(&self) -> &ScreenDescriptor {
+        &self.screen
+    }
+
+    /// The global palette has been emitted, the next byte is the introducer of a block
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, Done)
+    }
+
+    /// The part of the global palette that has been read so far. It's empty if there is none.
+    pub fn global_palette(&self) -> &[u8] {
+        &self.global_color_table
+    }
+
+    /// The deviation that the last call to `feed` has accepted, if any
+    pub fn take_warning(&mut self) -> Option<DecodingWarning> {
+        self.warning.take()
+    }
+
+    /// Name of the part of the header, for error messages
+    pub fn state_name(&self) -> &'static str {
+        match self.state {
+            Magic(..) => "signature",
+            GlobalPalette(_) => "global palette",
+            Done => "header",
+            _ => "logical screen descriptor",
+        }
+    }
+
+    /// Number of bytes that will be consumed before the header ends, at least 1
+    pub fn bytes_needed(&self) -> usize {
+        match self.state {
+            // The signature and the logical screen descriptor are 13 bytes
+            Magic(i, _) => 13 - usize::from(i),
+            GlobalPalette(left) => left.max(1),
+            _ => 1,
+        }
+    }
+
+    /// The part of the header that input ending here leaves incomplete, `None` once all of it
+    /// has been read
+    pub fn incomplete(&self) -> Option<Incomplete> {
+        match self.state {
+            Magic(i, _) if i < 6 => Some(Incomplete::Signature),
+            GlobalPalette(0) | Done => None,
+            GlobalPalette(_) => Some(Incomplete::GlobalPalette),
+            _ => Some(Incomplete::ScreenDescriptor),
+        }
+    }
+
+    /// Consumes the start of `buf`, which must not be empty
+    pub fn feed(&mut self, buf: &[u8]) -> Result<(usize, Option<HeaderEvent>), DecodingError> {
+        let b = buf[0];
+        match self.state {
+            Magic(i, mut version) => if i < 6 {
+                version[i as usize] = b;
+                goto!(self, Magic(i+1, version))
+            } else if &version[..3] == b"GIF" {
+                self.version = match version[3..] {
+                    [b'8', b'7', b'a'] => Version::V87a,
+                    [b'8', b'9', b'a'] => Version::V89a,
+                    // Browsers don't check the version, so files have ones like `89A`, `90a` or `89`
+                    // followed by junk
+                    [digit1, digit2, byte3] if !self.strict_version && digit1.is_ascii_digit() && digit2.is_ascii_digit() => {
+                        event!(WARN, version = ?&version[3..], "unknown version");
+                        self.warning = Some(DecodingWarning::UnknownVersion([digit1, digit2, byte3]));
+                        if [digit1, digit2] == *b"87" { Version::V87a } else { Version::V89a }
+                    },
+                    _ => return Err(DecodingError::format(DecodingFormatErrorKind::UnsupportedVersion, "malformed GIF header"))
+                };
+                goto!(self, ScreenWidth(b))
+            } else {
+                Err(DecodingError::format(DecodingFormatErrorKind::MalformedHeader, "malformed GIF header"))
+            },
+            ScreenWidth(byte1) => {
+                self.screen.width = u16::from_le_bytes([byte1, b]);
+                goto!(self, ScreenHeightByte1)
+            },
+            ScreenHeightByte1 => goto!(self, ScreenHeight(b)),
+            ScreenHeight(byte1) => {
+                self.screen.height = u16::from_le_bytes([byte1, b]);
+                goto!(self, GlobalFlags)
+            },
+            GlobalFlags => {
+                self.screen.has_global_palette = b & 0x80 != 0;
+                self.screen.color_resolution = ((b >> 4) & 0b111) + 1;
+                self.screen.sort_flag = b & 0x08 != 0;
+                goto!(self, Background { global_flags: b })
+            },
+            Background { global_flags } => {
+                self.screen.bg_index = b;
+                goto!(self, AspectRatio { global_flags }, emit HeaderEvent::BackgroundColor(b))
+            },
+            AspectRatio { global_flags } => {
+                self.screen.aspect_ratio = b;
+                let global_table = global_flags & 0x80 != 0;
+                let table_size = if global_table {
+                    let table_size = PLTE_CHANNELS * (1 << ((global_flags & 0b111) + 1) as usize);
+                    self.global_color_table.try_reserve_exact(table_size).map_err(|_| DecodingError::OutOfMemory)?;
+                    table_size
+                } else {
+                    0usize
+                };
+                goto!(self, GlobalPalette(table_size))
+            },
+            GlobalPalette(left) => {
+                // the global_color_table is guaranteed to have the exact capacity required
+                if left > 0 {
+                    let n = cmp::min(left, buf.len());
+                    if n > self.global_color_table.capacity() - self.global_color_table.len() {
+                        return Err(DecodingError::format(DecodingFormatErrorKind::InvalidState, "global palette exceeds its reserved size"));
+                    }
+                    self.global_color_table.extend_from_slice(&buf[..n]);
+                    goto!(self, n, GlobalPalette(left - n))
+                } else {
+                    // The introducer is left to the caller, it may be the trailer of a file without frames
+                    goto!(self, 0, Done, emit HeaderEvent::GlobalPalette)
+                }
+            },
+            Done => Err(DecodingError::format(DecodingFormatErrorKind::InvalidState, "bad state")),
+        }
+    }
+}
+
+#[test]
+fn header_with_global_palette() {
+    let mut header = b"GIF89a\x03\x01\x02\x00\x80\x01\x00".to_vec();
+    header.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+    header.push(0x3b);
+
+    let mut parser = HeaderParser::new(false);
+    let mut events = Vec::new();
+    let mut pos = 0;
+    while !parser.is_done() {
+        let (consumed, event) = parser.feed(&header[pos..]).unwrap();
+        pos += consumed;
+        events.extend(event);
+    }
+    assert_eq!(pos, header.len() - 1);
+    assert_eq!(events, [
+        HeaderEvent::BackgroundColor(1),
+        HeaderEvent::GlobalPalette,
+    ]);
+    assert_eq!(parser.global_palette(), [1, 2, 3, 4, 5, 6]);
+    assert_eq!(*parser.screen(), ScreenDescriptor {
+        width: 259,
+        height: 2,
+        has_global_palette: true,
+        color_resolution: 1,
+        sort_flag: false,
+        bg_index: 1,
+        aspect_ratio: 0,
+    });
+    assert_eq!(parser.version(), Version::V89a);
+}
+
+#[test]
+fn unknown_versions() {
+    fn parse(version: &[u8; 3], strict_version: bool) -> Result<(Version, Option<DecodingWarning>), DecodingError> {
+        let mut parser = HeaderParser::new(strict_version);
+        // The screen descriptor, and the trailer
+        let header = [&b"GIF"[..], version, b"\x01\x00\x01\x00\x00\x00\x00\x3b"].concat();
+        let mut pos = 0;
+        let mut warning = None;
+        while !parser.is_done() {
+            pos += parser.feed(&header[pos..])?.0;
+            warning = warning.or(parser.take_warning());
+        }
+        Ok((parser.version(), warning))
+    }
+
+    assert_eq!(parse(b"87a", true).unwrap(), (Version::V87a, None));
+    assert_eq!(parse(b"89a", true).unwrap(), (Version::V89a, None));
+    for (version, best_guess) in [(b"89A", Version::V89a), (b"90a", Version::V89a), (b"89\0", Version::V89a), (b"87b", Version::V87a)] {
+        assert_eq!(parse(version, false).unwrap(), (best_guess, Some(DecodingWarning::UnknownVersion(*version))));
+        assert_eq!(parse(version, true).unwrap_err().to_string(), "malformed GIF header");
+    }
+    assert!(parse(b"8xa", false).is_err());
+    assert!(parse(b"\0\0\0", false).is_err());
+}
@@ -0,0 +1,565 @@
+//! Image descriptor, local color table and the LZW data of a frame
+
+use alloc::vec::Vec;
+use core::{cmp, mem};
+use core::num::NonZeroUsize;
+
+use crate::common::{validate_frame_in_screen, Block, Frame, Rect};
+use crate::reader::converter::interlace_pass_rows;
+use crate::{FrameConsistency, MemoryLimit};
+
+use super::lzw_stats::{CodeCounter, LzwStats};
+use super::{DecodingError, DecodingFormatErrorKind, DecodingWarning, FrameDataType, Incomplete, LzwReader, OutputBuffer, PLTE_CHANNELS};
+
+/// Values read by the [`ImageDataReader`]
+#[derive(Debug)]
+pub(super) enum ImageDataEvent {
+    /// The frame's metadata is complete, and its data follows.
+    FrameMetadata(FrameDataType),
+    /// Pixels have been written into the output buffer.
+    PixelsDecoded(NonZeroUsize),
+    /// LZW data has been copied into the output buffer.
+    LzwDataCopied(usize),
+    /// The block terminator at the end of the data has been consumed.
+    DataEnd,
+}
+
+/// Fields of the image descriptor before the flags
+#[derive(Debug, Copy, Clone)]
+enum DescriptorField {
+    Left,
+    Top,
+    Width,
+    Height,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum ImageDataState {
+    Descriptor(DescriptorField),
+    /// Second byte of a descriptor field, after the first one
+    DescriptorByte2(DescriptorField, u8),
+    ImageFlags,
+    LocalPalette(usize),
+    LzwInit(u8),
+    /// Decompresses LZW
+    DecodeSubBlock(usize),
+    /// Keeps LZW compressed
+    CopySubBlock(usize),
+    /// Discards the rest of the frame, see `skip_frame_data`
+    SkipSubBlock(usize),
+    FrameDecoded,
+    Done,
+}
+use self::ImageDataState::*;
+
+/// Skips over whole sub-blocks in `buf`, starting with `left` bytes of the current one.
+///
+/// Returns the number of bytes consumed, and what is left of the current sub-block, or `None` if
+/// the block terminator has been reached. The terminator itself is not consumed. The data of the
+/// sub-blocks is passed to `skipped`, without their lengths.
+fn skip_sub_blocks(buf: &[u8], mut left: usize, mut skipped: impl FnMut(&[u8])) -> (usize, Option<usize>) {
+    let mut pos = 0;
+    loop {
+        let n = cmp::min(left, buf.len() - pos);
+        skipped(&buf[pos..pos + n]);
+        pos += n;
+        left -= n;
+        if left > 0 {
+            return (pos, Some(left));
+        }
+        match buf.get(pos) {
+            None => return (pos, Some(0)),
+            Some(0) => return (pos, None),
+            Some(&len) => {
+                pos += 1;
+                left = usize::from(len);
+            },
+        }
+    }
+}
+
+/// A frame that extends past the logical screen, whose pixels outside of it are discarded
+#[derive(Debug, Copy, Clone)]
+struct Clamp {
+    /// Declared width and height
+    width: usize,
+    height: u16,
+    /// Width and height within the screen
+    visible_width: usize,
+    visible_height: usize,
+    interlaced: bool,
+    /// Row in file order of the next pixel of the LZW output
+    row: usize,
+    /// Column of the next pixel of the LZW output
+    column: usize,
+}
+
+impl Clamp {
+    /// Cuts `frame` to its `visible` part
+    fn new(frame: &mut Frame<'_>, visible: Rect) -> Self {
+        let clamp = Self {
+            width: frame.width.into(),
+            height: frame.height,
+            visible_width: visible.width.into(),
+            visible_height: visible.height.into(),
+            interlaced: frame.interlaced,
+            row: 0,
+            column: 0,
+        };
+        frame.width = clamp.visible_width as u16;
+        frame.height = clamp.visible_height as u16;
+        clamp
+    }
+
+    /// Row of the frame that the LZW output is in
+    fn frame_row(&self) -> usize {
+        if !self.interlaced {
+            return self.row;
+        }
+        let mut row = self.row;
+        for (rows, (first_row, step)) in interlace_pass_rows(self.height).into_iter().zip([(0, 8), (4, 8), (2, 4), (1, 2)]) {
+            if row < rows {
+                return first_row + row * step;
+            }
+            row -= rows;
+        }
+        // Pixels past the end of the frame
+        usize::MAX
+    }
+
+    /// Moves the visible pixels of the next LZW output to its start, and returns their number
+    fn retain_visible(&mut self, pixels: &mut [u8]) -> usize {
+        if self.width == 0 {
+            return 0;
+        }
+        let mut retained = 0;
+        let mut pos = 0;
+        while pos < pixels.len() {
+            let n = cmp::min(self.width - self.column, pixels.len() - pos);
+            if self.frame_row() < self.visible_height {
+                let visible_end = cmp::min(self.column + n, self.visible_width);
+                if let Some(len) = visible_end.checked_sub(self.column) {
+                    pixels.copy_within(pos..pos + len, retained);
+                    retained += len;
+                }
+            }
+            pos += n;
+            self.column += n;
+            if self.column == self.width {
+                self.column = 0;
+                self.row += 1;
+            }
+        }
+        retained
+    }
+}
+
+/// Reads an image block from the byte after its introducer to the block terminator
+pub(super) struct ImageDataReader {
+    state: ImageDataState,
+    lzw_reader: LzwReader,
+    skip_frame_decoding: bool,
+    /// The length of each sub-block copied by `CopySubBlock` is copied too, and the terminator
+    copy_sub_block_lengths: bool,
+    /// LZW minimum code size of the current frame
+    min_code_size: u8,
+    frame_consistency: FrameConsistency,
+    memory_limit: MemoryLimit,
+    /// Width and height of the logical screen
+    screen_size: (u16, u16),
+    /// Set if the current frame has been cut to the screen
+    clamp: Option<Clamp>,
+    /// Set if statistics of the LZW data are collected
+    code_counter: Option<CodeCounter>,
+    /// Allocation of an earlier local palette, for the next one
+    spare_palette: Vec<u8>,
+    /// A block introducer after the end code may replace the block terminator
+    allow_missing_terminator: bool,
+    /// A deviation that has been accepted in the last call to `feed`
+    warning: Option<DecodingWarning>,
+}
+
+impl ImageDataReader {
+    pub fn new(lzw_reader: LzwReader, skip_frame_decoding: bool, frame_consistency: FrameConsistency, memory_limit: MemoryLimit, collect_lzw_stats: bool, allow_missing_terminator: bool) -> Self {
+        Self {
+            state: Done,
+            lzw_reader,
+            skip_frame_decoding,
+            copy_sub_block_lengths: false,
+            min_code_size: 0,
+            frame_consistency,
+            memory_limit,
+            screen_size: (0, 0),
+            clamp: None,
+            code_counter: collect_lzw_stats.then(CodeCounter::new),
+            spare_palette: Vec::new(),
+            allow_missing_terminator,
+            warning: None,
+        }
+    }
+
+    /// The deviation accepted in the last call to `feed`, if any
+    pub fn take_warning(&mut self) -> Option<DecodingWarning> {
+        self.warning.take()
+    }
+
+    /// Keeps the allocation of a palette that is no longer used for the next local palette
+    pub fn recycle_palette(&mut self, mut palette: Vec<u8>) {
+        if palette.capacity() > self.spare_palette.capacity() {
+            palette.clear();
+            self.spare_palette = palette;
+        }
+    }
+
+    /// Statistics of the LZW data of the current or last frame, see `StreamingDecoder::lzw_stats`
+    pub fn lzw_stats(&self) -> Option<LzwStats> {
+        self.code_counter.as_ref().map(CodeCounter::stats)
+    }
+
+    /// Passes LZW data to the code counter, if there is one
+    fn count_codes(&mut self, data: &[u8]) {
+        if let Some(counter) = &mut self.code_counter {
+            counter.feed(data);
+        }
+    }
+
+    /// Starts an image block with the first byte after its introducer, which has been consumed.
+    ///
+    /// The frame is checked against the size of the logical screen.
+    pub fn start(&mut self, first_byte: u8, screen_size: (u16, u16)) {
+        self.state = DescriptorByte2(DescriptorField::Left, first_byte);
+        self.screen_size = screen_size;
+    }
+
+    /// The block terminator has been consumed, the next byte is the introducer of a block
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, Done)
+    }
+
+    /// See `StreamingDecoder::skip_frame_data`
+    pub fn skip_frame_data(&mut self) -> bool {
+        match self.state {
+            DecodeSubBlock(left) | CopySubBlock(left) | SkipSubBlock(left) => {
+                self.state = SkipSubBlock(left);
+                true
+            },
+            FrameDecoded => true,
+            _ => false,
+        }
+    }
+
+    /// See `StreamingDecoder::copy_frame_data`
+    pub fn copy_frame_data(&mut self, sub_block_lengths: bool) -> Result<(u8, u8), DecodingError> {
+        let left = match self.state {
+            DecodeSubBlock(left) | CopySubBlock(left) => left,
+            _ => return Err(DecodingError::format(DecodingFormatErrorKind::InvalidState, "bad state")),
+        };
+        if self.clamp.is_some() {
+            return Err(DecodingError::format(DecodingFormatErrorKind::InvalidState, "data of a clamped frame can't be copied"));
+        }
+        self.state = CopySubBlock(left);
+        self.copy_sub_block_lengths = sub_block_lengths;
+        Ok((self.min_code_size, left as u8))
+    }
+
+    /// Discards the pixels of the output that are outside of the screen, if the frame has been
+    /// clamped. Returns the number of pixels left.
+    fn retain_visible(&mut self, write_into: &mut OutputBuffer<'_>, pixels: usize) -> usize {
+        match (&mut self.clamp, write_into) {
+            (Some(clamp), OutputBuffer::Slice(buf)) => clamp.retain_visible(&mut buf[..pixels]),
+            _ => pixels,
+        }
+    }
+
+    /// Whether `b`, where the length of a sub-block or the block terminator belongs, is the
+    /// introducer of the next block of a file that leaves out the terminator
+    fn is_missing_terminator(&self, b: u8) -> bool {
+        self.allow_missing_terminator && self.lzw_reader.has_ended() && Block::from_u8(b).is_some()
+    }
+
+    /// Name of the part of the image block, for error messages
+    pub fn state_name(&self) -> &'static str {
+        match self.state {
+            Descriptor(_) | DescriptorByte2(..) | ImageFlags => "image descriptor",
+            LocalPalette(_) => "local palette",
+            LzwInit(_) => "LZW code size",
+            _ => "image data",
+        }
+    }
+
+    /// The part of the image block that input ending here leaves incomplete
+    pub fn incomplete(&self) -> Incomplete {
+        match self.state {
+            Descriptor(_) | DescriptorByte2(..) | ImageFlags => Incomplete::ImageDescriptor,
+            // The LZW code size is next
+            LocalPalette(0) => Incomplete::ImageData,
+            LocalPalette(_) => Incomplete::LocalPalette,
+            _ => Incomplete::ImageData,
+        }
+    }
+
+    /// Number of bytes that will be consumed before the image block ends, at least 1
+    pub fn bytes_needed(&self) -> usize {
+        match self.state {
+            // The LZW code size follows the palette, and the block terminator the sub-block
+            LocalPalette(left) | DecodeSubBlock(left) | CopySubBlock(left) | SkipSubBlock(left) => left + 1,
+            _ => 1,
+        }
+    }
+
+    /// Consumes the start of `buf`, which must not be empty.
+    ///
+    /// The metadata is stored in `frame`, and the frame data is written into `write_into`.
+    pub fn feed(
+        &mut self,
+        buf: &[u8],
+        frame: &mut Frame<'static>,
+        write_into: &mut OutputBuffer<'_>,
+    ) -> Result<(usize, Option<ImageDataEvent>), DecodingError> {
+        let b = buf[0];
+        match self.state {
+            Descriptor(field) => goto!(self, DescriptorByte2(field, b)),
+            DescriptorByte2(field, byte1) => {
+                let value = u16::from_le_bytes([byte1, b]);
+                match field {
+                    DescriptorField::Left => {
+                        frame.left = value;
+                        goto!(self, Descriptor(DescriptorField::Top))
+                    },
+                    DescriptorField::Top => {
+                        frame.top = value;
+                        goto!(self, Descriptor(DescriptorField::Width))
+                    },
+                    DescriptorField::Width => {
+                        frame.width = value;
+                        goto!(self, Descriptor(DescriptorField::Height))
+                    },
+                    DescriptorField::Height => {
+                        frame.height = value;
+                        goto!(self, ImageFlags)
+                    },
+                }
+            },
+            ImageFlags => {
+                let local_table = (b & 0b1000_0000) != 0;
+                let interlaced = (b & 0b0100_0000) != 0;
+                let table_size = b & 0b0000_0111;
+
+                frame.interlaced = interlaced;
+                frame.descriptor_flags = b;
+                self.clamp = None;
+                // Consistency checks.
+                let rect = Rect::from(&*frame);
+                match validate_frame_in_screen(rect, self.screen_size, self.frame_consistency) {
+                    Err(_) => {
+                        return Err(DecodingError::format(DecodingFormatErrorKind::FrameOutOfBounds, "frame descriptor is out-of-bounds"))
+                    },
+                    // The LZW data is returned as it is
+                    Ok(_) if self.skip_frame_decoding => {},
+                    Ok(visible) if visible != rect => {
+                        self.clamp = Some(Clamp::new(frame, visible));
+                    },
+                    Ok(_) => {},
+                }
+
+                if local_table {
+                    let pal_len = PLTE_CHANNELS * (1 << (table_size + 1));
+                    frame.palette.get_or_insert_with(|| mem::take(&mut self.spare_palette))
+                        .try_reserve_exact(pal_len).map_err(|_| DecodingError::OutOfMemory)?;
+                    goto!(self, LocalPalette(pal_len))
+                } else {
+                    goto!(self, LocalPalette(0))
+                }
+            },
+            LocalPalette(left) => {
+                if left > 0 {
+                    let n = cmp::min(left, buf.len());
+                    // capacity has already been reserved in ImageFlags
+                    let pal = frame.palette.as_mut()
+                        .filter(|pal| pal.capacity() - pal.len() >= n)
+                        .ok_or_else(|| DecodingError::format(DecodingFormatErrorKind::InvalidState, "local palette exceeds its reserved size"))?;
+                    pal.extend_from_slice(&buf[..n]);
+                    goto!(self, n, LocalPalette(left - n))
+                } else if frame.palette.as_ref().map_or(0, Vec::len) != frame.declared_palette_len().unwrap_or(0) * PLTE_CHANNELS {
+                    Err(DecodingError::format(DecodingFormatErrorKind::InvalidState, "local palette doesn't have its declared size"))
+                } else {
+                    goto!(self, LzwInit(b))
+                }
+            },
+            LzwInit(min_code_size) => {
+                self.min_code_size = min_code_size;
+                self.copy_sub_block_lengths = false;
+                let decode = !self.skip_frame_decoding && frame.width > 0 && frame.height > 0;
+                if decode {
+                    // Reset validates the min code size
+                    self.lzw_reader.reset(min_code_size)?;
+                } else {
+                    LzwReader::check_code_size(min_code_size)?;
+                }
+                if let Some(counter) = &mut self.code_counter {
+                    counter.reset(min_code_size);
+                }
+                if decode {
+                    goto!(self, DecodeSubBlock(b as usize), emit ImageDataEvent::FrameMetadata(FrameDataType::Pixels))
+                } else if !self.skip_frame_decoding {
+                    // Any pixels in the data of a frame without pixels are discarded
+                    goto!(self, SkipSubBlock(b as usize), emit ImageDataEvent::FrameMetadata(FrameDataType::Pixels))
+                } else {
+                    goto!(self, CopySubBlock(b as usize), emit ImageDataEvent::FrameMetadata(FrameDataType::Lzw { min_code_size }))
+                }
+            },
+            CopySubBlock(left) => {
+                if matches!(write_into, OutputBuffer::None) {
+                    return match skip_sub_blocks(buf, left, |data| self.count_codes(data)) {
+                        (consumed, Some(left)) => goto!(self, consumed, CopySubBlock(left)),
+                        (consumed, None) => goto!(self, consumed, FrameDecoded),
+                    };
+                }
+                if left > 0 {
+                    let n = cmp::min(left, buf.len());
+                    let (consumed, copied) = write_into.append(&buf[..n], &self.memory_limit)?;
+                    self.count_codes(&buf[..consumed]);
+                    goto!(self, consumed, CopySubBlock(left - consumed), emit ImageDataEvent::LzwDataCopied(copied))
+                } else {
+                    if self.copy_sub_block_lengths {
+                        write_into.append(&[b], &self.memory_limit)?;
+                    }
+                    if b != 0 {
+                        goto!(self, CopySubBlock(b as usize))
+                    } else {
+                        goto!(self, 0, FrameDecoded)
+                    }
+                }
+            },
+            SkipSubBlock(left) => match skip_sub_blocks(buf, left, |data| self.count_codes(data)) {
+                (consumed, Some(left)) => goto!(self, consumed, SkipSubBlock(left)),
+                (consumed, None) => goto!(self, consumed, FrameDecoded),
+            },
+            DecodeSubBlock(left) => {
+                debug_assert!(!self.skip_frame_decoding);
+                if matches!(write_into, OutputBuffer::None) && self.allow_missing_terminator {
+                    // Only the LZW decoder knows where the data ends if the terminator may be
+                    // missing, so it runs on a scratch buffer whose pixels are dropped
+                    let mut scratch = [0; 256];
+                    loop {
+                        match self.feed(buf, frame, &mut OutputBuffer::Slice(&mut scratch))? {
+                            (0, Some(ImageDataEvent::PixelsDecoded(_))) => {},
+                            (consumed, Some(ImageDataEvent::PixelsDecoded(_))) => return Ok((consumed, None)),
+                            result => return Ok(result),
+                        }
+                    }
+                }
+                if matches!(write_into, OutputBuffer::None) {
+                    // The pixels are discarded, so the LZW decoder doesn't need to run.
+                    // The end code is only checked if the frame has been partially decoded.
+                    let check_end_code = self.lzw_reader.check_for_end_code && self.lzw_reader.has_decoded;
+                    match skip_sub_blocks(buf, left, |data| self.count_codes(data)) {
+                        (consumed, Some(left)) => return goto!(self, consumed, DecodeSubBlock(left)),
+                        (consumed, None) if !check_end_code => return goto!(self, consumed, FrameDecoded),
+                        // Stop at the terminator, where the LZW decoder checks for the end code
+                        (consumed, None) if consumed > 0 => return goto!(self, consumed, DecodeSubBlock(0)),
+                        (_, None) => {},
+                    }
+                }
+                if left > 0 {
+                    let n = cmp::min(left, buf.len());
+                    if self.lzw_reader.has_ended() {
+                        self.count_codes(&buf[..n]);
+                        return goto!(self, n, DecodeSubBlock(left - n));
+                    }
+
+                    let (mut consumed, mut pixels) = (0, 0);
+                    // Pixels outside of the screen are dropped, so decode until there's something
+                    // to return, or until the input is used up
+                    while consumed == 0 && pixels == 0 {
+                        let decoded = self.lzw_reader.decode_bytes(&buf[..n], write_into)?;
+                        consumed = decoded.0;
+                        pixels = self.retain_visible(write_into, decoded.1);
+                        // skip if can't make progress (decode would fail if check_for_end_code was set)
+                        if consumed == 0 && decoded.1 == 0 {
+                            event!(WARN, size = n, "skipping LZW data that can't be decoded");
+                            consumed = n;
+                        }
+                    }
+                    self.count_codes(&buf[..consumed]);
+
+                    self.state = DecodeSubBlock(left - consumed);
+                    Ok((consumed, NonZeroUsize::new(pixels).map(ImageDataEvent::PixelsDecoded)))
+                } else if b != 0 && self.allow_missing_terminator && Block::from_u8(b).is_some() && !self.lzw_reader.has_ended() {
+                    // The end code may still be buffered in the LZW decoder, and whether it has
+                    // been reached decides if `b` is the length of a sub-block
+                    loop {
+                        let pixels = self.lzw_reader.decode_buffered(write_into)?;
+                        if pixels == 0 {
+                            let next = if self.lzw_reader.has_ended() { DecodeSubBlock(0) } else { DecodeSubBlock(b as usize) };
+                            return goto!(self, usize::from(!self.lzw_reader.has_ended()), next);
+                        }
+                        if let Some(pixels) = NonZeroUsize::new(self.retain_visible(write_into, pixels)) {
+                            return Ok((0, Some(ImageDataEvent::PixelsDecoded(pixels))));
+                        }
+                    }
+                } else if b != 0 && !self.is_missing_terminator(b) {
+                    // decode next sub-block
+                    goto!(self, DecodeSubBlock(b as usize))
+                } else {
+                    if b != 0 {
+                        event!(WARN, introducer = b, "image data without a block terminator");
+                        self.warning = Some(DecodingWarning::MissingBlockTerminator);
+                    }
+                    // Flush what the LZW decoder has buffered, dropping what is outside of the screen
+                    loop {
+                        let (_, pixels) = self.lzw_reader.decode_bytes(&[], write_into)?;
+                        if pixels == 0 {
+                            return goto!(self, 0, FrameDecoded);
+                        }
+                        if let Some(pixels) = NonZeroUsize::new(self.retain_visible(write_into, pixels)) {
+                            self.state = DecodeSubBlock(0);
+                            return Ok((0, Some(ImageDataEvent::PixelsDecoded(pixels))));
+                        }
+                    }
+                }
+            },
+            FrameDecoded => {
+                // end of image data reached. Without a terminator, the next block starts here.
+                debug_assert!(b == 0 || self.is_missing_terminator(b));
+                goto!(self, usize::from(b == 0), Done, emit ImageDataEvent::DataEnd)
+            },
+            Done => Err(DecodingError::format(DecodingFormatErrorKind::InvalidState, "bad state")),
+        }
+    }
+}
+
+#[cfg(test)]
+fn read_image(data: &[u8], options: &crate::DecodeOptions) -> Result<(Frame<'static>, Vec<u8>), DecodingError> {
+    let lzw_reader = LzwReader::new(options.check_for_end_code);
+    let mut reader = ImageDataReader::new(lzw_reader, false, options.frame_consistency, MemoryLimit::Unlimited, false, options.allow_missing_block_terminator);
+    let mut frame = Frame::default();
+    let mut pixels = Vec::new();
+    let mut out = [0; 16];
+    reader.start(data[0], (4, 4));
+    let mut pos = 1;
+    while !reader.is_done() {
+        let (consumed, event) = reader.feed(&data[pos..], &mut frame, &mut OutputBuffer::Slice(&mut out))?;
+        pos += consumed;
+        if let Some(ImageDataEvent::PixelsDecoded(len)) = event {
+            pixels.extend_from_slice(&out[..len.get()]);
+        }
+    }
+    assert_eq!(pos, data.len());
+    Ok((frame, pixels))
+}
+
+#[test]
+fn image_data_of_small_frame() {
+    // 2x1 frame at (1, 2), with the pixels 1 and 0, encoded with a minimum code size of 2
+    let data = [1, 0, 2, 0, 2, 0, 1, 0, 0, 2, 2, 0x0c, 0x0a, 0];
+    let (frame, pixels) = read_image(&data, &crate::DecodeOptions::new()).unwrap();
+    assert_eq!((frame.left, frame.top, frame.width, frame.height), (1, 2, 2, 1));
+    assert_eq!(pixels, [1, 0]);
+
+    let mut options = crate::DecodeOptions::new();
+    options.check_frame_consistency(true);
+    let mut data = data;
+    data[0] = 3;
+    assert!(read_image(&data, &options).is_err());
+}
@@ -0,0 +1,124 @@
+//! Statistics of LZW data, from its codes alone
+
+/// Statistics of the LZW data of a frame, see [`DecodeOptions::collect_lzw_stats`](crate::DecodeOptions::collect_lzw_stats)
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct LzwStats {
+    /// Width of the widest code in bits, from the minimum code size plus 1 to 12.
+    pub max_code_size: u8,
+    /// Number of clear codes, including the one at the start of the data.
+    pub clear_codes: usize,
+    /// Number of codes, including clear codes and the end code.
+    pub codes: usize,
+}
+
+/// Splits LZW data into codes to count them, without decoding the pixels.
+///
+/// Only the size of the code table is tracked, because it determines the width of the next code.
+/// Codes after the end code are ignored.
+pub(super) struct CodeCounter {
+    min_code_size: u8,
+    code_size: u8,
+    /// The code that the next entry of the table gets
+    next_code: u16,
+    /// A code has been read since the last clear code, so the next one adds an entry
+    has_prefix: bool,
+    ended: bool,
+    /// Bits of the input that don't make up a whole code yet, starting at the lowest bit
+    bits: u32,
+    bit_count: u8,
+    stats: LzwStats,
+}
+
+impl CodeCounter {
+    pub fn new() -> Self {
+        let mut counter = Self {
+            min_code_size: 0,
+            code_size: 0,
+            next_code: 0,
+            has_prefix: false,
+            ended: false,
+            bits: 0,
+            bit_count: 0,
+            stats: LzwStats::default(),
+        };
+        counter.reset(2);
+        counter
+    }
+
+    /// Starts the data of a frame, with a valid minimum code size
+    pub fn reset(&mut self, min_code_size: u8) {
+        self.min_code_size = min_code_size;
+        self.clear_table();
+        self.ended = false;
+        self.bits = 0;
+        self.bit_count = 0;
+        self.stats = LzwStats::default();
+    }
+
+    fn clear_table(&mut self) {
+        self.code_size = self.min_code_size + 1;
+        self.next_code = (1 << self.min_code_size) + 2;
+        self.has_prefix = false;
+    }
+
+    /// Reads the codes in the next bytes of the LZW data, without the sub-block lengths
+    pub fn feed(&mut self, data: &[u8]) {
+        for &byte in data {
+            if self.ended {
+                return;
+            }
+            self.bits |= u32::from(byte) << self.bit_count;
+            self.bit_count += 8;
+            while self.bit_count >= self.code_size && !self.ended {
+                let code = (self.bits & ((1 << self.code_size) - 1)) as u16;
+                self.bits >>= self.code_size;
+                self.bit_count -= self.code_size;
+                self.count(code);
+            }
+        }
+    }
+
+    fn count(&mut self, code: u16) {
+        let clear_code = 1 << self.min_code_size;
+        self.stats.codes += 1;
+        self.stats.max_code_size = self.stats.max_code_size.max(self.code_size);
+        if code == clear_code {
+            self.stats.clear_codes += 1;
+            self.clear_table();
+        } else if code == clear_code + 1 {
+            self.ended = true;
+        } else {
+            // Every code after the first one adds an entry, until the table is full
+            if self.has_prefix && self.next_code < 1 << 12 {
+                self.next_code += 1;
+                if self.next_code == 1 << self.code_size && self.code_size < 12 {
+                    self.code_size += 1;
+                }
+            }
+            self.has_prefix = true;
+        }
+    }
+
+    pub fn stats(&self) -> LzwStats {
+        self.stats
+    }
+}
+
+#[test]
+fn counts_codes_of_pixels_lzw() {
+    // clear, 0, 1, clear, 1, 0, end, with 3 bits each
+    let mut counter = CodeCounter::new();
+    counter.feed(&[0x44, 0x18]);
+    assert_eq!(counter.stats(), LzwStats { max_code_size: 3, clear_codes: 2, codes: 5 });
+    counter.feed(&[0x14, 0xFF]);
+    assert_eq!(counter.stats(), LzwStats { max_code_size: 3, clear_codes: 2, codes: 7 });
+}
+
+#[test]
+fn code_size_grows_with_the_table() {
+    // clear, 0, 0, 0 with 3 bits, and end with 4 bits, because the third 0 fills the table
+    let mut counter = CodeCounter::new();
+    let bits: u32 = 4 | 5 << 12;
+    counter.feed(&bits.to_le_bytes()[..2]);
+    assert_eq!(counter.stats(), LzwStats { max_code_size: 4, clear_codes: 1, codes: 5 });
+}
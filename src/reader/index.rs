@@ -0,0 +1,206 @@
+use alloc::vec::Vec;
+use core::iter::FusedIterator;
+use core::ops::Range;
+use core::time::Duration;
+use no_std_io::io;
+
+use crate::common::Frame;
+use crate::Repeat;
+
+use super::{DecodeOptions, Decoder, DecodingError, FrameIndexEntry, UsageError};
+
+/// Number of bytes at the start and at the end of the file that `Index::validate_against` compares
+const SIGNATURE_LEN: usize = 32;
+
+/// Positions and metadata of all frames of a GIF in memory, built with one scan of the file.
+///
+/// Queries don't parse the file again. With the `serde` feature, the index can be stored next to
+/// the file, and given to [`Decoder::set_frame_index`](crate::Decoder::set_frame_index) to seek
+/// without scanning the file first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Index {
+    len: u64,
+    signature: u64,
+    width: u16,
+    height: u16,
+    repeat: Repeat,
+    frames: Vec<FrameIndexEntry>,
+}
+
+impl Index {
+    /// Scans the whole file once. The pixel data is skipped without decoding it.
+    pub fn build(data: &[u8]) -> Result<Self, DecodingError> {
+        let mut decoder = DecodeOptions::new().read_info_buffered(io::Cursor::new(data))?;
+        let frames = decoder.build_frame_index()?.to_vec();
+        Ok(Self {
+            len: data.len() as u64,
+            signature: signature(data),
+            width: decoder.width(),
+            height: decoder.height(),
+            repeat: decoder.repeat(),
+            frames,
+        })
+    }
+
+    /// Returns `true` if `data` looks like the file the index has been built from.
+    ///
+    /// It's a quick check of the length and of some bytes at the start and at the end, so a file
+    /// that has been changed only in between isn't noticed.
+    #[must_use]
+    pub fn validate_against(&self, data: &[u8]) -> bool {
+        data.len() as u64 == self.len && signature(data) == self.signature
+    }
+
+    /// Width of the image
+    #[must_use]
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Height of the image
+    #[must_use]
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Number of loop repetitions
+    #[must_use]
+    pub fn repeat(&self) -> Repeat {
+        self.repeat
+    }
+
+    /// Number of frames
+    #[must_use]
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns `true` if there's more than one frame
+    #[must_use]
+    pub fn is_animated(&self) -> bool {
+        self.frames.len() > 1
+    }
+
+    /// Sum of the delays of all frames, i.e. the duration of one loop
+    #[must_use]
+    pub fn duration(&self) -> Duration {
+        let delay: u64 = self.frames.iter().map(|frame| u64::from(frame.delay)).sum();
+        Duration::from_millis(delay * 10)
+    }
+
+    /// Metadata of the frame with the given index, counting from 0
+    #[must_use]
+    pub fn frame_meta(&self, index: usize) -> Option<&FrameIndexEntry> {
+        self.frames.get(index)
+    }
+
+    /// Bytes of the frame with the given index, from its first extension to the end of its image data
+    #[must_use]
+    pub fn frame_byte_range(&self, index: usize) -> Option<Range<u64>> {
+        self.frames.get(index).map(|frame| frame.offset..frame.end)
+    }
+
+    /// Metadata of all frames in file order
+    #[must_use]
+    pub fn entries(&self) -> &[FrameIndexEntry] {
+        &self.frames
+    }
+
+    /// Decodes the frames of `source`, the file the index has been built from, by seeking to
+    /// their offsets.
+    ///
+    /// The iterator knows its length without decoding anything, and can go backwards, e.g. to
+    /// show the last frame. Frames are returned as they are in the file in both directions, not
+    /// drawn over the earlier ones.
+    ///
+    /// Each frame is decoded on its own, so an error only affects its frame. If `source` doesn't
+    /// [match](Self::validate_against) the index, every frame is an error.
+    #[must_use]
+    pub fn frames<'a>(&'a self, source: &'a [u8], options: &DecodeOptions) -> IndexedFrames<'a> {
+        IndexedFrames {
+            index: self,
+            source,
+            options: options.clone(),
+            decoder: None,
+            front: 0,
+            back: self.frames.len(),
+        }
+    }
+}
+
+/// Iterator over the decoded frames of a file with an [`Index`], see [`Index::frames`]
+pub struct IndexedFrames<'a> {
+    index: &'a Index,
+    source: &'a [u8],
+    options: DecodeOptions,
+    /// Created for the first frame
+    decoder: Option<Decoder<io::Cursor<&'a [u8]>>>,
+    /// The frames that haven't been returned yet
+    front: usize,
+    back: usize,
+}
+
+impl<'a> IndexedFrames<'a> {
+    fn open(&self) -> Result<Decoder<io::Cursor<&'a [u8]>>, DecodingError> {
+        if !self.index.validate_against(self.source) {
+            return Err(UsageError::FrameIndexMismatch.into());
+        }
+        let mut decoder = self.options.clone().read_info_buffered(io::Cursor::new(self.source))?;
+        decoder.set_frame_index(self.index)?;
+        Ok(decoder)
+    }
+
+    fn decode(&mut self, frame: usize) -> Result<Frame<'static>, DecodingError> {
+        let decoder = match self.decoder.take() {
+            Some(decoder) => decoder,
+            None => self.open()?,
+        };
+        let decoder = self.decoder.insert(decoder);
+        decoder.seek_to_frame(frame)?;
+        if decoder.read_next_frame()?.is_none() {
+            return Err(UsageError::FrameIndexMismatch.into());
+        }
+        Ok(decoder.take_current_frame())
+    }
+}
+
+impl Iterator for IndexedFrames<'_> {
+    type Item = Result<Frame<'static>, DecodingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.front += 1;
+        Some(self.decode(self.front - 1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front;
+        (len, Some(len))
+    }
+}
+
+impl DoubleEndedIterator for IndexedFrames<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        Some(self.decode(self.back))
+    }
+}
+
+impl ExactSizeIterator for IndexedFrames<'_> {}
+
+impl FusedIterator for IndexedFrames<'_> {}
+
+/// FNV-1a hash of the first and the last bytes
+fn signature(data: &[u8]) -> u64 {
+    let head = &data[..data.len().min(SIGNATURE_LEN)];
+    let tail = &data[data.len().saturating_sub(SIGNATURE_LEN)..];
+    head.iter().chain(tail).fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
@@ -1,4 +1,6 @@
 use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::vec;
 use alloc::vec::Vec;
 use no_std_io::io;
 use core::iter::FusedIterator;
@@ -14,18 +16,29 @@ use core::num::NonZeroU64;
 use core::convert::{TryFrom, TryInto};
 
 use crate::Repeat;
-use crate::common::{Block, Frame};
+use crate::common::{Block, DisposalMethod, Frame, GraphicControl, PixelFormat, ScreenDescriptor};
+pub use crate::common::FrameConsistency;
 
 mod decoder;
 mod converter;
+mod slice_decoder;
+mod index;
+mod probe;
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+mod async_decoder;
 
 pub use self::decoder::{
-    PLTE_CHANNELS, StreamingDecoder, Decoded, DecodingError, DecodingFormatError,
-    Version, FrameDataType, OutputBuffer, FrameDecoder
+    PLTE_CHANNELS, StreamingDecoder, Decoded, DecodingError, DecodingFormatError, DecodingFormatErrorKind,
+    Version, FrameDataType, OutputBuffer, FrameDecoder, LzwStats, DecodingWarning, ExtensionBlock, Incomplete, UsageError
 };
 
-use self::converter::PixelConverter;
+use self::converter::{fallback_palette, PixelConverter, PixelSource, FALLBACK_PALETTE_COLORS};
 pub use self::converter::ColorOutput;
+pub use self::slice_decoder::SliceDecoder;
+pub use self::index::{Index, IndexedFrames};
+pub use self::probe::{probe, GifInfo};
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+pub use self::async_decoder::AsyncDecoder;
 
 #[derive(Clone, Debug)]
 /// The maximum amount of memory the decoder is allowed to use for each frame
@@ -60,33 +73,31 @@ impl MemoryLimit {
                 if size as u64 <= limit.get() {
                     Ok(())
                 } else {
-                    Err(DecodingError::format("memory limit reached"))
+                    event!(WARN, size, limit = limit.get(), "memory limit reached");
+                    Err(DecodingError::format(DecodingFormatErrorKind::MemoryLimitExceeded, "memory limit reached"))
                 }
             },
         }
     }
 
-    fn buffer_size(&self, color: ColorOutput, width: u16, height: u16) -> Option<usize> {
-        let pixels = u64::from(width) * u64::from(height);
-
-        let bytes_per_pixel = match color {
-            ColorOutput::Indexed => 1,
-            ColorOutput::RGBA => 4,
+    fn buffer_size(&self, color: ColorOutput, frame: &Frame<'_>) -> Result<usize, DecodingError> {
+        let total_bytes = match color {
+            ColorOutput::Indexed => frame.required_bytes_indexed(),
+            ColorOutput::RGBA => frame.required_bytes_rgba(),
+            ColorOutput::RGB => frame.required_bytes_rgb(),
         };
-
-        // This cannot overflow because the maximum possible value is 16GiB, well within u64 range
-        let total_bytes = pixels * bytes_per_pixel;
-
         // On 32-bit platforms the size of the output buffer may not be representable
-        let usize_bytes = usize::try_from(total_bytes).ok()?;
+        let total_bytes = total_bytes
+            .ok_or(DecodingError::DimensionsTooLarge { width: frame.width, height: frame.height })?;
 
         match self {
-            Self::Unlimited => Some(usize_bytes),
+            Self::Unlimited => Ok(total_bytes),
             Self::Bytes(limit) => {
-                if total_bytes > limit.get() {
-                    None
+                if total_bytes as u64 > limit.get() {
+                    event!(WARN, size = total_bytes, limit = limit.get(), "frame exceeds the memory limit");
+                    Err(io::Error::new(io::ErrorKind::Other, "image is too large").into())
                 } else {
-                    Some(usize_bytes)
+                    Ok(total_bytes)
                 }
             },
         }
@@ -98,10 +109,28 @@ impl MemoryLimit {
 pub struct DecodeOptions {
     memory_limit: MemoryLimit,
     color_output: ColorOutput,
-    check_frame_consistency: bool,
+    matte_color: Option<[u8; 3]>,
+    frame_consistency: FrameConsistency,
     skip_frame_decoding: bool,
+    keep_lzw_sub_blocks: bool,
+    collect_lzw_stats: bool,
     check_for_end_code: bool,
     allow_unknown_blocks: bool,
+    allow_unknown_extensions: bool,
+    allow_oversized_control_extension: bool,
+    keep_extension_data: bool,
+    continue_past_trailer: bool,
+    error_on_trailing_data: bool,
+    error_on_repeated_graphic_control: bool,
+    error_on_index_outside_palette: bool,
+    error_on_transparent_outside_palette: bool,
+    check_palette_padding: bool,
+    strict_version: bool,
+    allow_missing_color_table: bool,
+    allow_missing_trailer: bool,
+    allow_missing_block_terminator: bool,
+    allow_truncated: bool,
+    deinterlace: bool,
 }
 
 impl Default for DecodeOptions {
@@ -118,19 +147,63 @@ impl DecodeOptions {
         Self {
             memory_limit: MemoryLimit::Bytes(50_000_000.try_into().unwrap()), // 50 MB
             color_output: ColorOutput::Indexed,
-            check_frame_consistency: false,
+            matte_color: None,
+            frame_consistency: FrameConsistency::Ignore,
             skip_frame_decoding: false,
+            keep_lzw_sub_blocks: false,
+            collect_lzw_stats: false,
             check_for_end_code: false,
             allow_unknown_blocks: false,
+            allow_unknown_extensions: false,
+            allow_oversized_control_extension: false,
+            keep_extension_data: true,
+            continue_past_trailer: false,
+            error_on_trailing_data: false,
+            error_on_repeated_graphic_control: false,
+            error_on_index_outside_palette: false,
+            error_on_transparent_outside_palette: false,
+            check_palette_padding: false,
+            strict_version: false,
+            allow_missing_color_table: true,
+            allow_missing_trailer: true,
+            allow_missing_block_terminator: false,
+            allow_truncated: false,
+            deinterlace: true,
         }
     }
 
+    /// Creates a decoder builder with the defaults recommended for new code, which the next
+    /// major version is going to use.
+    ///
+    /// They differ from [`Self::new`] in:
+    /// *   [`Self::set_frame_consistency`] is [`FrameConsistency::Clamp`], so no frame has pixels
+    ///     outside of the logical screen, like browsers show them.
+    /// *   [`Self::error_on_index_outside_palette`] is turned on, so every pixel of a decoded
+    ///     frame has a color.
+    ///
+    /// All other options have their usual defaults.
+    #[must_use]
+    pub fn v2_defaults() -> Self {
+        let mut options = Self::new();
+        options.set_frame_consistency(FrameConsistency::Clamp);
+        options.error_on_index_outside_palette(true);
+        options
+    }
+
     /// Configure how color data is decoded.
     #[inline]
     pub fn set_color_output(&mut self, color: ColorOutput) {
         self.color_output = color;
     }
 
+    /// Configure the color of transparent pixels in [`ColorOutput::RGB`], which has no alpha.
+    ///
+    /// The default is `None`, which uses the background color of the global palette, or black if
+    /// the file has no valid background color.
+    pub fn set_matte_color(&mut self, color: Option<[u8; 3]>) {
+        self.matte_color = color;
+    }
+
     /// Configure a memory limit for decoding.
     pub fn set_memory_limit(&mut self, limit: MemoryLimit) {
         self.memory_limit = limit;
@@ -146,8 +219,24 @@ impl DecodeOptions {
     /// When turned off, frames may be arbitrarily larger or offset in relation to the screen. Many
     /// other decoder libraries handle this in highly divergent ways. This moves all checks to the
     /// caller, for example to emulate a specific style.
+    ///
+    /// Turning it on is the same as [`FrameConsistency::Error`], turning it off the same as
+    /// [`FrameConsistency::Ignore`].
     pub fn check_frame_consistency(&mut self, check: bool) {
-        self.check_frame_consistency = check;
+        self.frame_consistency = if check { FrameConsistency::Error } else { FrameConsistency::Ignore };
+    }
+
+    /// Configure how frames that don't fit within the screen descriptor are handled.
+    ///
+    /// The default is [`FrameConsistency::Ignore`].
+    ///
+    /// With [`FrameConsistency::Clamp`], such frames are cut to the screen like most browsers and
+    /// image editors display them. The frame's width and height are reduced before its metadata
+    /// is returned, and only the pixels within the screen are decoded. This doesn't apply to frames
+    /// whose LZW data is returned instead of pixels, which keep their size, and whose data can't be
+    /// read with [`Decoder::read_lzw_frame`] once they have been clamped.
+    pub fn set_frame_consistency(&mut self, consistency: FrameConsistency) {
+        self.frame_consistency = consistency;
     }
 
     /// Configure whether to skip decoding frames.
@@ -162,6 +251,32 @@ impl DecodeOptions {
         self.skip_frame_decoding = skip;
     }
 
+    /// Configure whether [`Decoder::read_lzw_frame`] keeps the sub-blocks of the LZW data.
+    ///
+    /// The default is false.
+    ///
+    /// When turned off, the sub-blocks are concatenated into one LZW stream. When turned on, the
+    /// data is exactly as in the file, starting with the length of the first sub-block and ending
+    /// with the empty terminator sub-block, e.g. to copy it into another file byte by byte.
+    pub fn keep_lzw_sub_blocks(&mut self, keep: bool) {
+        self.keep_lzw_sub_blocks = keep;
+    }
+
+    /// Configure if statistics of the LZW data of each frame are collected.
+    ///
+    /// The default is `false`.
+    ///
+    /// When turned on, [`Decoder::last_frame_lzw_stats`] has the width of the widest code, and
+    /// the number of clear codes and of all codes. The codes are counted as the data is read,
+    /// also if it's copied with [`Self::skip_frame_decoding`] or skipped, without building the
+    /// dictionary. E.g. a frame with a single clear code and 12 bit codes comes from an encoder
+    /// that keeps using a full table.
+    ///
+    /// When turned off, no statistics are collected, and reading the data costs nothing extra.
+    pub fn collect_lzw_stats(&mut self, collect: bool) {
+        self.collect_lzw_stats = collect;
+    }
+
     /// Configure if LZW encoded blocks must end with a marker end code.
     ///
     /// The default is `false`.
@@ -192,18 +307,422 @@ impl DecodeOptions {
         self.allow_unknown_blocks = check;
     }
 
+    /// Configure if extensions with an unknown label are allowed to be decoded.
+    ///
+    /// The default is `false`.
+    ///
+    /// When turned on, such extensions are skipped like comments, since their sub-blocks can be
+    /// read without knowing their meaning. Their data is available from
+    /// [`StreamingDecoder::last_ext`](crate::streaming_decoder::StreamingDecoder::last_ext).
+    ///
+    /// When turned off, an extension must be a graphic control, plain text, comment, or
+    /// application extension. Otherwise, the decoded image will return an error.
+    pub fn allow_unknown_extensions(&mut self, allow: bool) {
+        self.allow_unknown_extensions = allow;
+    }
+
+    /// Configure if a graphic control extension may declare more bytes than its fields need.
+    ///
+    /// The default is `false`.
+    ///
+    /// When turned on, a graphic control extension whose first sub-block is longer than 4 bytes
+    /// is read by the first 4 bytes, and the rest of it is skipped. Some encoders pad it, or
+    /// write junk after the fields. The decoder reports it as
+    /// [`DecodingWarning::OversizedControlExtension`]. A sub-block that is too short for the
+    /// fields is still an error.
+    ///
+    /// When turned off, the first sub-block must have exactly 4 bytes. Otherwise, the decoded
+    /// image will return an error.
+    pub fn allow_oversized_control_extension(&mut self, allow: bool) {
+        self.allow_oversized_control_extension = allow;
+    }
+
+    /// Configure if the data of extensions is collected for
+    /// [`StreamingDecoder::last_ext`](crate::streaming_decoder::StreamingDecoder::last_ext).
+    ///
+    /// The default is `true`.
+    ///
+    /// When turned off, the decoder only collects the parts it reads itself, like the fields of
+    /// graphic control extensions and the loop count. Comments and other metadata are skipped
+    /// without buffering them, however long they are. `Decoder` doesn't return extension data,
+    /// so it only saves memory there.
+    ///
+    /// When turned on, the data of each extension is collected until the next one starts.
+    pub fn keep_extension_data(&mut self, keep: bool) {
+        self.keep_extension_data = keep;
+    }
+
+    /// Configure if decoding continues when a trailer is followed by more blocks.
+    ///
+    /// The default is `false`.
+    ///
+    /// When turned on, the decoder looks at the few bytes after each trailer, and if a block
+    /// starts there, continues decoding frames from it. Some editors produce such files by
+    /// exporting twice. [`Decoder::skipped_trailers`] counts how often that happened. The bytes
+    /// it has looked at are consumed, even if no block follows.
+    ///
+    /// When turned off, decoding stops at the first trailer.
+    pub fn continue_past_trailer(&mut self, continue_past_trailer: bool) {
+        self.continue_past_trailer = continue_past_trailer;
+    }
+
+    /// Configure if data after the trailer is an error.
+    ///
+    /// The default is `false`.
+    ///
+    /// When turned on, decoding fails if the input doesn't end right after the trailer, e.g. to
+    /// detect files with other data appended. If [`Self::continue_past_trailer`] is turned on
+    /// too, only data after the last trailer is an error.
+    ///
+    /// When turned off, the data after the trailer is ignored, and not read.
+    /// [`Decoder::trailer_offset`] tells where the file ended.
+    pub fn error_on_trailing_data(&mut self, error_on_trailing_data: bool) {
+        self.error_on_trailing_data = error_on_trailing_data;
+    }
+
+    /// Configure if more than one graphic control extension before an image is an error.
+    ///
+    /// The default is `false`.
+    ///
+    /// When turned on, decoding fails at the second extension. Some optimizers leave the
+    /// extension of a removed frame in front of the next one.
+    ///
+    /// When turned off, the last extension applies to the image, and replaces all fields of the
+    /// earlier ones. The image is only transparent if the last extension has a transparent index.
+    pub fn error_on_repeated_graphic_control(&mut self, error_on_repeated_graphic_control: bool) {
+        self.error_on_repeated_graphic_control = error_on_repeated_graphic_control;
+    }
+
+    /// Configure if a pixel with an index outside of the frame's palette is an error.
+    ///
+    /// The default is `false`.
+    ///
+    /// When turned on, decoding the frame fails at the first such pixel, in both color outputs.
+    /// [`DecodingFormatError::completed_pixels`] tells how many pixels came before it. Frames
+    /// aren't checked if [`Self::skip_frame_decoding`] is turned on.
+    ///
+    /// When turned off, indexed output contains the index as it is, RGBA output has transparent
+    /// black for such pixels, and RGB output has black.
+    pub fn error_on_index_outside_palette(&mut self, error_on_index_outside_palette: bool) {
+        self.error_on_index_outside_palette = error_on_index_outside_palette;
+    }
+
+    /// Configure if a transparent index outside of the frame's palette is an error.
+    ///
+    /// The default is `false`.
+    ///
+    /// When turned on, decoding fails at the start of the frame's data, where its palette is
+    /// known.
+    ///
+    /// When turned off, the frame isn't transparent, and [`Frame::transparent`] is `None`. The
+    /// index is still in the frame's [`GraphicControl`].
+    pub fn error_on_transparent_outside_palette(&mut self, error_on_transparent_outside_palette: bool) {
+        self.error_on_transparent_outside_palette = error_on_transparent_outside_palette;
+    }
+
+    /// Configure if the decoded pixels are checked for indices into the padding of their palette.
+    ///
+    /// The default is `false`.
+    ///
+    /// A palette always has a power of two colors, so encoders fill up shorter ones, usually
+    /// with black. [`Frame::palette_padding`] and
+    /// [`StreamingDecoder::global_palette_padding`](crate::streaming_decoder::StreamingDecoder::global_palette_padding)
+    /// count the black entries at the end of a palette.
+    ///
+    /// When turned on, a frame with a pixel that refers to one of them is reported as
+    /// [`DecodingWarning::PaletteEntryPaddingReferenced`]. Black pixels that the producer meant
+    /// to be black are reported as well, if black is the last color of the palette. Frames
+    /// whose pixels aren't decoded aren't checked, like those read with [`Self::skip_frame_decoding`],
+    /// [`Decoder::read_lzw_frame`] or [`Decoder::skip_frame_data`].
+    ///
+    /// When turned off, the pixels aren't scanned.
+    pub fn check_palette_padding(&mut self, check: bool) {
+        self.check_palette_padding = check;
+    }
+
+    /// Configure if the version in the header must be valid, and match the blocks of the file.
+    ///
+    /// The default is `false`.
+    ///
+    /// When turned on, a version other than `87a` and `89a` is an error. So is a graphic control,
+    /// comment, plain text or application extension if the header has version `87a`.
+    ///
+    /// When turned off, a version that starts with two digits, like `89A` or `90a`, is decoded
+    /// as the closest valid one, and extensions of version `89a` are decoded in files of version
+    /// `87a`. [`Decoder::warnings`] lists both. Browsers don't check the version either, and many
+    /// encoders write `87a` regardless of the blocks that follow.
+    pub fn strict_version(&mut self, strict_version: bool) {
+        self.strict_version = strict_version;
+    }
+
+    /// Configure if a file without a global palette gets a generated one.
+    ///
+    /// The default is `true`.
+    ///
+    /// When turned on, such a file gets a global palette once a frame without a local palette
+    /// is reached, which the frame then uses like browsers do. It has evenly spaced shades of
+    /// gray from black to white, one for each value of the [`ScreenDescriptor::color_resolution`]:
+    /// black and white for 1 bit, and 256 shades for 8 bits. It always has 256 colors, and the
+    /// indices past the shades are white, so that no index is outside of it.
+    /// [`Decoder::has_fallback_palette`] tells it apart from a real one. Files where every frame
+    /// has a local palette keep having no global palette. The background color is an index into
+    /// the fallback palette.
+    ///
+    /// When turned off, a frame without a local palette in such a file is an error, and a file
+    /// without a global palette has no background color.
+    pub fn allow_missing_color_table(&mut self, allow_missing_color_table: bool) {
+        self.allow_missing_color_table = allow_missing_color_table;
+    }
+
+    /// Configure if the file may end without a trailer.
+    ///
+    /// The default is `true`.
+    ///
+    /// When turned on, the end of the input right after a block ends the file like a trailer.
+    /// Many encoders leave the trailer out. The end of the input within a block is still an
+    /// error, unless [`Self::allow_truncated`] is turned on.
+    ///
+    /// When turned off, a missing trailer is an error.
+    pub fn allow_missing_trailer(&mut self, allow_missing_trailer: bool) {
+        self.allow_missing_trailer = allow_missing_trailer;
+    }
+
+    /// Configure if the data of a frame may end without a block terminator.
+    ///
+    /// The default is `false`.
+    ///
+    /// When turned on, and the LZW data of a frame has ended with its end code, an introducer of
+    /// an extension, an image or the trailer where the terminator belongs starts the next block.
+    /// Some encoders leave the terminator out, and browsers accept that. The decoder reports it
+    /// as [`DecodingWarning::MissingBlockTerminator`]. It only looks for the end code in frames
+    /// whose pixels are decoded, so the data of skipped frames still needs a terminator.
+    ///
+    /// When turned off, the byte after the last sub-block is always the length of another one.
+    pub fn allow_missing_block_terminator(&mut self, allow: bool) {
+        self.allow_missing_block_terminator = allow;
+    }
+
+    /// Configure if a file that ends before its trailer is decoded as far as it goes.
+    ///
+    /// The default is `false`.
+    ///
+    /// When turned on, the end of the input ends the animation like the trailer, and
+    /// [`Decoder::is_truncated`] reports it. A frame that is cut off is still returned by
+    /// [`Decoder::read_next_frame`], with the pixels that have been decoded so far. The other
+    /// pixels are transparent black in RGBA, the matte color in RGB, and otherwise the frame's
+    /// transparent index, or the background color, or 0. Browsers display such files the same way.
+    ///
+    /// When turned off, the end of the input is an error.
+    pub fn allow_truncated(&mut self, allow_truncated: bool) {
+        self.allow_truncated = allow_truncated;
+    }
+
+    /// Configure if the rows of interlaced frames are put in display order.
+    ///
+    /// The default is `true`.
+    ///
+    /// When turned on, [`Decoder::read_next_frame`] and [`Decoder::read_into_buffer`] write each
+    /// row of an interlaced frame to its final position, and the returned `Frame::interlaced`
+    /// is `false`.
+    ///
+    /// When turned off, the rows are returned in the order they're stored in the file, and
+    /// `Frame::interlaced` stays `true`. [`Decoder::read_rows`] and
+    /// [`Decoder::read_into_buffer_progressive`] always use the display order.
+    pub fn deinterlace(&mut self, deinterlace: bool) {
+        self.deinterlace = deinterlace;
+    }
+
     /// Reads the logical screen descriptor including the global color palette
     ///
     /// Returns a [`Decoder`]. All decoder configuration has to be done beforehand.
     pub fn read_info<R: Read>(self, r: R) -> Result<Decoder<R>, DecodingError> {
-        Decoder::with_no_init(r, StreamingDecoder::with_options(&self), self).init()
+        Decoder::with_no_init(Input::buffered(r), StreamingDecoder::with_options(&self), self).init()
+    }
+
+    /// Reads the logical screen descriptor including the global color palette
+    ///
+    /// Unlike [`DecodeOptions::read_info`], the data is decoded straight from the buffer of the
+    /// reader, e.g. a `BufReader` or a `Cursor`, without copying it into a buffer of the decoder.
+    pub fn read_info_buffered<R: BufRead>(self, r: R) -> Result<Decoder<R>, DecodingError> {
+        let input = Input::BufRead { reader: r, fill_buf: R::fill_buf, consume: R::consume };
+        Decoder::with_no_init(input, StreamingDecoder::with_options(&self), self).init()
+    }
+
+    /// Reads the logical screen descriptor including the global color palette from a file in memory
+    ///
+    /// Returns a [`SliceDecoder`]. All decoder configuration has to be done beforehand.
+    pub fn read_slice(self, data: &[u8]) -> Result<SliceDecoder<'_>, DecodingError> {
+        SliceDecoder::with_options(data, self)
+    }
+}
+
+/// Reads that return fewer bytes than this are combined in a [`Staging`] buffer
+const STAGING_THRESHOLD: usize = 64;
+const STAGING_SIZE: usize = 512;
+
+/// Combines the bytes of many small reads, so that a reader that returns a few bytes at a time
+/// doesn't have the decoder run once per read.
+///
+/// It only reads as far as the decoder is sure to consume, see `StreamingDecoder::bytes_needed`.
+struct Staging {
+    /// `STAGING_SIZE` bytes, allocated with the decoder so that reads don't allocate
+    buf: Vec<u8>,
+    pos: usize,
+    cap: usize,
+    /// Error of a read after some data had been staged, returned when that data is used up
+    error: Option<io::Error>,
+}
+
+impl Staging {
+    fn new() -> Self {
+        Self { buf: vec![0; STAGING_SIZE], pos: 0, cap: 0, error: None }
+    }
+
+    fn len(&self) -> usize {
+        self.cap - self.pos
+    }
+
+    /// Moves data out of `reader` until `needed` bytes have been staged, the buffer is full,
+    /// or a read returns nothing or fails
+    fn fill<R: Read>(&mut self, reader: &mut io::BufReader<R, 8000>, needed: usize) {
+        self.pos = 0;
+        self.cap = 0;
+        let needed = needed.min(STAGING_SIZE);
+        // The bytes `reader` has buffered already
+        let data = reader.buffer();
+        let n = data.len().min(needed);
+        self.buf[..n].copy_from_slice(&data[..n]);
+        reader.consume(n);
+        self.cap = n;
+        // Then straight from the inner reader, without going through the `BufReader` for each read
+        while self.cap < needed {
+            match reader.get_mut().read(&mut self.buf[self.cap..needed]) {
+                Ok(0) => break,
+                Ok(n) => self.cap += n,
+                Err(err) => {
+                    if !matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted) {
+                        self.error = Some(err);
+                    }
+                    break;
+                },
+            }
+        }
+    }
+}
+
+/// The reader with the decoder's own buffer, or a reader that has a buffer already
+enum Input<R: Read> {
+    /// Boxed, because the buffer is an array inline in the `BufReader`
+    Buffered(Box<io::BufReader<R, 8000>>, Staging),
+    /// `Decoder<R>` only requires `R: Read`, so these keep `BufRead` methods of readers that have them
+    BufRead {
+        reader: R,
+        fill_buf: fn(&mut R) -> io::Result<&[u8]>,
+        consume: fn(&mut R, usize),
+    },
+}
+
+impl<R: Read> Input<R> {
+    fn buffered(reader: R) -> Self {
+        Self::Buffered(Box::new(io::BufReader::new(reader)), Staging::new())
+    }
+
+    #[inline]
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.fill_buf_for(1)
+    }
+
+    /// Like `fill_buf`, but with the number of bytes the decoder is sure to consume, which it may
+    /// read ahead if the reader returns less
+    #[inline]
+    fn fill_buf_for(&mut self, needed: usize) -> io::Result<&[u8]> {
+        match self {
+            Self::Buffered(reader, staging) => {
+                if staging.len() == 0 {
+                    if let Some(err) = staging.error.take() {
+                        return Err(err);
+                    }
+                    let available = reader.fill_buf()?.len();
+                    if available == 0 || available >= needed.min(STAGING_THRESHOLD) {
+                        return Ok(reader.buffer());
+                    }
+                    staging.fill(reader, needed);
+                }
+                Ok(&staging.buf[staging.pos..staging.cap])
+            },
+            Self::BufRead { reader, fill_buf, .. } => fill_buf(reader),
+        }
+    }
+
+    #[inline]
+    fn consume(&mut self, amt: usize) {
+        match self {
+            Self::Buffered(reader, staging) => if staging.len() == 0 {
+                reader.consume(amt);
+            } else {
+                staging.pos += amt;
+            },
+            Self::BufRead { reader, consume, .. } => consume(reader, amt),
+        }
+    }
+
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> where R: Seek {
+        match self {
+            Self::Buffered(reader, staging) => {
+                // Staged bytes have been read from `reader` already
+                let pos = match pos {
+                    io::SeekFrom::Current(offset) => io::SeekFrom::Current(offset - staging.len() as i64),
+                    pos => pos,
+                };
+                let position = reader.seek(pos)?;
+                staging.pos = staging.cap;
+                staging.error = None;
+                Ok(position)
+            },
+            Self::BufRead { reader, .. } => reader.seek(pos),
+        }
+    }
+
+    fn into_inner(self) -> io::BufReader<R, 8000> {
+        match self {
+            // Staged bytes are lost, like the rest of the decoder's state
+            Self::Buffered(reader, _) => *reader,
+            // The reader keeps its own buffered data
+            Self::BufRead { reader, .. } => io::BufReader::new(reader),
+        }
+    }
+
+    fn get_ref(&self) -> &R {
+        match self {
+            Self::Buffered(reader, _) => reader.get_ref(),
+            Self::BufRead { reader, .. } => reader,
+        }
+    }
+
+    fn get_mut(&mut self) -> &mut R {
+        match self {
+            Self::Buffered(reader, _) => reader.get_mut(),
+            Self::BufRead { reader, .. } => reader,
+        }
     }
 }
 
 struct ReadDecoder<R: Read> {
-    reader: io::BufReader<R, 8000>,
+    reader: Input<R>,
     decoder: StreamingDecoder,
     at_eof: bool,
+    /// Number of bytes consumed by the decoder
+    position: u64,
+    /// Position of the trailer that ended decoding
+    trailer_offset: Option<u64>,
+    error_on_trailing_data: bool,
+    allow_missing_trailer: bool,
+    allow_truncated: bool,
+    /// The input ended before the trailer, and `allow_truncated` turned that into the end of file
+    truncated: bool,
+    /// Palette for frames without one in a file without a global palette, see
+    /// `DecodeOptions::allow_missing_color_table`
+    fallback_palette: Option<&'static [u8]>,
 }
 
 impl<R: Read> ReadDecoder<R> {
@@ -211,17 +730,57 @@ impl<R: Read> ReadDecoder<R> {
     fn decode_next(&mut self, write_into: &mut OutputBuffer<'_>) -> Result<Option<Decoded>, DecodingError> {
         while !self.at_eof {
             let (consumed, result) = {
-                let buf = self.reader.fill_buf()?;
+                let buf = self.reader.fill_buf_for(self.decoder.bytes_needed())?;
                 if buf.is_empty() {
-                    return Err(io::ErrorKind::UnexpectedEof.into());
+                    if self.decoder.is_looking_past_trailer() {
+                        self.decoder.finish()?;
+                        self.at_eof = true;
+                        return Ok(None);
+                    }
+                    if self.allow_missing_trailer && self.decoder.finish().is_ok() {
+                        event!(DEBUG, offset = self.position, "file ends without a trailer");
+                        self.at_eof = true;
+                        return Ok(None);
+                    }
+                    if self.allow_truncated {
+                        event!(WARN, offset = self.position, "file is truncated");
+                        self.at_eof = true;
+                        self.truncated = true;
+                        return Ok(None);
+                    }
+                    return Err(self.decoder.unexpected_eof());
                 }
 
                 self.decoder.update(buf, write_into)?
             };
             self.reader.consume(consumed);
+            self.position += consumed as u64;
             match result {
                 Decoded::Nothing => (),
+                Decoded::BlockStart(block) if block != Block::Trailer => {
+                    // The introducer has already been consumed
+                    event!(TRACE, offset = self.position - 1, ?block, "block");
+                    return Ok(Some(result));
+                },
+                Decoded::BlockFinished(_) => {
+                    event!(DEBUG, end = self.position, label = self.decoder.last_ext().0.0, size = self.decoder.last_ext().1.len(), "extension");
+                    return Ok(Some(result));
+                },
                 Decoded::BlockStart(Block::Trailer) => {
+                    // The trailer has already been consumed
+                    self.trailer_offset = Some(self.position - 1);
+                    // With `continue_past_trailer`, the decoder looks for another block first
+                    if !self.decoder.is_looking_past_trailer() {
+                        self.at_eof = true;
+                        if self.error_on_trailing_data {
+                            // The decoder fails on any data after the trailer
+                            let buf = self.reader.fill_buf()?;
+                            self.decoder.update(buf, &mut OutputBuffer::None)?;
+                        }
+                    }
+                },
+                Decoded::Finished => {
+                    // There's no block shortly after the trailer
                     self.at_eof = true;
                 },
                 result => return Ok(Some(result)),
@@ -231,20 +790,67 @@ impl<R: Read> ReadDecoder<R> {
     }
 
     fn into_inner(self) -> io::BufReader<R, 8000> {
-        self.reader
+        self.reader.into_inner()
+    }
+
+    fn get_ref(&self) -> &R {
+        self.reader.get_ref()
+    }
+
+    /// Continue decoding at `position`, which must be at the start of a block after the global
+    /// palette that belongs to the frame with the index `frame`
+    fn rewind(&mut self, position: u64, frame: usize) -> Result<(), DecodingError> where R: Seek {
+        let offset = i64::try_from(i128::from(position) - i128::from(self.position))
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        self.reader.seek(io::SeekFrom::Current(offset))?;
+        self.position = position;
+        self.at_eof = false;
+        self.truncated = false;
+        self.trailer_offset = None;
+        self.decoder.rewind(position, frame);
+        Ok(())
     }
 
-    fn decode_next_bytes(&mut self, out: &mut OutputBuffer<'_>) -> Result<usize, DecodingError> {
+    fn get_mut(&mut self) -> &mut R {
+        self.reader.get_mut()
+    }
+
+}
+
+impl<R: Read> PixelSource for ReadDecoder<R> {
+    fn decode_next_pixels(&mut self, out: &mut OutputBuffer<'_>) -> Result<usize, DecodingError> {
         match self.decode_next(out)? {
-            Some(Decoded::BytesDecoded(len)) => Ok(len.get()),
+            Some(Decoded::PixelsDecoded(len)) => Ok(len.get()),
             Some(Decoded::DataEnd) => Ok(0),
-            _ => Err(DecodingError::format("unexpected data")),
+            None if self.truncated => Ok(0),
+            _ => Err(DecodingError::format(DecodingFormatErrorKind::InvalidState, "unexpected data")),
         }
     }
+
+    fn global_palette(&self) -> Option<&[u8]> {
+        self.decoder.global_palette().or(self.fallback_palette)
+    }
 }
 
 #[allow(dead_code)]
 /// GIF decoder. Create [`DecodeOptions`] to get started, and call [`DecodeOptions::read_info`].
+///
+/// Dropping the decoder never reads from the reader, wherever decoding stopped: in the middle
+/// of a frame, after an error, or after the trailer. Neither the decoder nor its buffer finish
+/// the current block when dropped, so a slow or blocking reader can't stall dropping it.
+///
+/// # Decoding without allocations
+///
+/// Once the first frame has been read, reading more frames with [`Decoder::read_next_frame`],
+/// [`Decoder::read_into_buffer`] or [`Decoder::read_rows`] doesn't allocate, as long as:
+///  - no frame is larger than the ones before it, and none has a larger local palette,
+///  - all frames have the same LZW minimum code size,
+///  - no extension has more than 256 bytes of data,
+///  - [`DecodeOptions::collect_lzw_stats`] and the `tracing` feature are off.
+///
+/// The buffer and the local palette of a frame are reused for the next one, so they are
+/// replaced by [`Decoder::next_frame_info`]. Other methods, like [`Decoder::read_lzw_frame`],
+/// return new allocations.
 pub struct Decoder<R: Read> {
     decoder: ReadDecoder<R>,
     pixel_converter: PixelConverter,
@@ -252,6 +858,20 @@ pub struct Decoder<R: Read> {
     repeat: Repeat,
     current_frame: Frame<'static>,
     current_frame_data_type: FrameDataType,
+    /// `next_frame_info` returned a frame, and none of its data has been read yet
+    current_frame_pending: bool,
+    /// `read_next_frame` has been interrupted by `WouldBlock` after reading this many pixels (or LZW bytes)
+    frame_read_progress: Option<usize>,
+    /// Position of the first block after the global palette
+    first_block_offset: u64,
+    /// Built on request by `build_frame_index`
+    frame_index: Option<Vec<FrameIndexEntry>>,
+    /// Index of the frame returned by the next `next_frame_info`, for tracing
+    next_frame_number: usize,
+    keep_lzw_sub_blocks: bool,
+    allow_missing_color_table: bool,
+    /// Allocation of the row passed to the callback of `read_rows`
+    row_buffer: Vec<u8>,
 }
 
 impl<R> Decoder<R> where R: Read {
@@ -261,6 +881,14 @@ impl<R> Decoder<R> where R: Read {
         DecodeOptions::new().read_info(reader)
     }
 
+    /// Create a new decoder with default options, which decodes straight from the buffer of `reader`
+    ///
+    /// See [`DecodeOptions::read_info_buffered`].
+    #[inline]
+    pub fn new_buffered(reader: R) -> Result<Self, DecodingError> where R: BufRead {
+        DecodeOptions::new().read_info_buffered(reader)
+    }
+
     /// Return a builder that allows configuring limits etc.
     #[must_use]
     #[inline]
@@ -268,29 +896,51 @@ impl<R> Decoder<R> where R: Read {
         DecodeOptions::new()
     }
 
-    fn with_no_init(reader: R, decoder: StreamingDecoder, options: DecodeOptions) -> Self {
+    fn with_no_init(reader: Input<R>, decoder: StreamingDecoder, options: DecodeOptions) -> Self {
         Self {
             decoder: ReadDecoder {
-                reader: io::BufReader::new(reader),
+                reader,
                 decoder,
                 at_eof: false,
+                position: 0,
+                trailer_offset: None,
+                error_on_trailing_data: options.error_on_trailing_data,
+                allow_missing_trailer: options.allow_missing_trailer,
+                allow_truncated: options.allow_truncated,
+                truncated: false,
+                fallback_palette: None,
             },
             bg_color: None,
-            pixel_converter: PixelConverter::new(options.color_output, options.memory_limit),
+            pixel_converter: PixelConverter::new(options.color_output, options.matte_color, options.memory_limit, options.deinterlace, options.error_on_index_outside_palette),
             repeat: Repeat::default(),
             current_frame: Frame::default(),
             current_frame_data_type: FrameDataType::Pixels,
+            current_frame_pending: false,
+            frame_read_progress: None,
+            first_block_offset: 0,
+            frame_index: None,
+            row_buffer: Vec::new(),
+            next_frame_number: 0,
+            keep_lzw_sub_blocks: options.keep_lzw_sub_blocks,
+            allow_missing_color_table: options.allow_missing_color_table,
         }
     }
 
     fn init(mut self) -> Result<Self, DecodingError> {
+        self.read_header()?;
+        Ok(self)
+    }
+
+    /// Decodes everything up to the first frame. Can be repeated after `WouldBlock`.
+    fn read_header(&mut self) -> Result<(), DecodingError> {
         loop {
             match self.decoder.decode_next(&mut OutputBuffer::None)? {
                 Some(Decoded::BackgroundColor(bg_color)) => {
                     self.bg_color = Some(bg_color);
                 }
-                Some(Decoded::GlobalPalette(palette)) => {
-                    self.pixel_converter.set_global_palette(palette.into());
+                Some(Decoded::GlobalPalette) => {
+                    event!(DEBUG, colors = self.decoder.decoder.global_palette().map_or(0, |palette| palette.len() / PLTE_CHANNELS), "global palette");
+                    self.first_block_offset = self.decoder.position;
                 },
                 Some(Decoded::Repetitions(repeat)) => {
                     self.repeat = repeat;
@@ -301,98 +951,286 @@ impl<R> Decoder<R> where R: Read {
                     continue;
                 },
                 None => return Err(DecodingError::format(
-                    "file does not contain any image data"
+                    DecodingFormatErrorKind::NoImageData, "file does not contain any image data"
                 ))
             }
         }
-        // If the background color is invalid, ignore it
-        if let Some(palette) = self.pixel_converter.global_palette() {
-            if self.bg_color.unwrap_or(0) as usize >= (palette.len() / PLTE_CHANNELS) {
-                self.bg_color = None;
-            }
+        // If the background color is invalid, ignore it. Without a global palette, it's an index
+        // into the fallback palette, which has a color for every index.
+        let colors = match self.decoder.decoder.global_palette() {
+            Some(palette) => palette.len() / PLTE_CHANNELS,
+            None if self.allow_missing_color_table => FALLBACK_PALETTE_COLORS,
+            None => 0,
+        };
+        if self.bg_color.map_or(false, |index| usize::from(index) >= colors) {
+            event!(WARN, index = self.bg_color, "background color is outside of the global palette");
+            self.bg_color = None;
         }
-        Ok(self)
+        self.pixel_converter.set_background(self.bg_color);
+        Ok(())
     }
 
     /// Returns the next frame info
+    ///
+    /// Returns `None` after the last frame. From then on, this and all other methods that advance
+    /// to the next frame keep returning `None`, and don't read from the reader anymore.
     pub fn next_frame_info(&mut self) -> Result<Option<&Frame<'static>>, DecodingError> {
+        self.current_frame_pending = false;
+        self.frame_read_progress = None;
+        // The allocations of the previous frame are kept for this one
+        let mut buffer = mem::replace(&mut self.current_frame.buffer, Cow::Borrowed(&[]));
+        if let Some(palette) = self.current_frame.palette.take() {
+            self.decoder.decoder.recycle_palette(palette);
+        }
         loop {
             match self.decoder.decode_next(&mut OutputBuffer::None)? {
                 Some(Decoded::FrameMetadata(frame_data_type)) => {
-                    self.current_frame = self.decoder.decoder.current_frame_mut().take();
+                    self.current_frame = self.decoder.decoder.try_current_frame()?.take();
+                    if let Cow::Owned(vec) = &mut buffer {
+                        vec.clear();
+                        self.current_frame.buffer = mem::take(&mut buffer);
+                    }
                     self.current_frame_data_type = frame_data_type;
                     if self.current_frame.palette.is_none() && self.global_palette().is_none() {
-                        return Err(DecodingError::format(
-                            "no color table available for current frame",
-                        ));
+                        if !self.allow_missing_color_table {
+                            return Err(DecodingError::format(
+                                DecodingFormatErrorKind::MissingColorTable, "no color table available for current frame",
+                            ));
+                        }
+                        // Only generated once a frame needs it, files with local palettes only keep having none
+                        let bits = self.screen_descriptor().color_resolution;
+                        event!(DEBUG, bits, "fallback palette");
+                        self.decoder.fallback_palette = Some(fallback_palette(bits));
                     }
+                    event!(DEBUG, index = self.next_frame_number, offset = self.decoder.position,
+                        left = self.current_frame.left, top = self.current_frame.top,
+                        width = self.current_frame.width, height = self.current_frame.height,
+                        interlaced = self.current_frame.interlaced,
+                        local_palette_colors = self.current_frame.palette.as_ref().map(|palette| palette.len() / PLTE_CHANNELS),
+                        "frame");
+                    self.next_frame_number += 1;
                     break;
                 }
                 Some(_) => (),
                 None => return Ok(None),
             }
         }
+        self.current_frame_pending = true;
         Ok(Some(&self.current_frame))
     }
 
+    /// Edit the metadata of the frame returned by `Self::next_frame_info`, before its data is read.
+    ///
+    /// The changes are visible in the frame returned by a subsequent `Self::read_next_frame`,
+    /// which then reads the data of this frame instead of advancing to the next one.
+    ///
+    /// Returns `None` if there is no such frame, or if reading its data has already started.
+    pub fn pending_frame_mut(&mut self) -> Option<FrameMetadata<'_>> {
+        if self.current_frame_pending {
+            Some(FrameMetadata { frame: &mut self.current_frame })
+        } else {
+            None
+        }
+    }
+
     /// Reads the next frame from the image.
     ///
-    /// Do not call `Self::next_frame_info` beforehand.
-    /// Deinterlaces the result.
+    /// If `Self::next_frame_info` has been called, and none of that frame's data has been read
+    /// yet, this reads that frame. Otherwise it advances to the next frame.
+    /// Deinterlaces the result, unless turned off with `DecodeOptions::deinterlace`.
+    ///
+    /// If the reader fails with `io::ErrorKind::WouldBlock`, the data read so far is kept, and
+    /// calling this again continues with the same frame. After any other error, the pixels read
+    /// so far are discarded, and calling this again continues with the next frame. Reading the
+    /// rest of the frame in another way, e.g. with `Self::read_into_buffer`, discards them too.
+    ///
+    /// Returns `None` after the last frame, like `Self::next_frame_info`. A frame with a width
+    /// or height of 0 has an empty buffer, and any pixels in its data are discarded.
     ///
     /// You can also call `.into_iter()` on the decoder to use it as a regular iterator.
     pub fn read_next_frame(&mut self) -> Result<Option<&Frame<'static>>, DecodingError> {
-        if let Some(_) = self.next_frame_info()? {
-            match self.current_frame_data_type {
-                FrameDataType::Pixels => {
-                    self.pixel_converter.read_frame(&mut self.current_frame, &mut |out| self.decoder.decode_next_bytes(out))?;
-                },
-                FrameDataType::Lzw { min_code_size } => {
-                    let mut vec = if matches!(self.current_frame.buffer, Cow::Owned(_)) {
-                        let mut vec = mem::replace(&mut self.current_frame.buffer, Cow::Borrowed(&[])).into_owned();
+        let mut progress = match self.frame_read_progress.take() {
+            Some(progress) => progress,
+            None if self.current_frame_pending || self.next_frame_info()?.is_some() => 0,
+            None => return Ok(None),
+        };
+        self.current_frame_pending = false;
+        let _span = span!(DEBUG, "frame", index = self.next_frame_number.saturating_sub(1));
+        let result = self.read_current_frame(&mut progress);
+        match &result {
+            // Keep the partially read frame, so that the call can be repeated
+            Err(err) if err.is_would_block() => self.frame_read_progress = Some(progress),
+            // Nothing of a frame that failed may show up later, but the allocation can be reused
+            Err(_) => if let Cow::Owned(buffer) = &mut self.current_frame.buffer {
+                buffer.clear();
+            },
+            Ok(()) => {},
+        }
+        result?;
+        event!(DEBUG, size = self.current_frame.buffer.len(), "frame decoded");
+        Ok(Some(&self.current_frame))
+    }
+
+    fn read_current_frame(&mut self, progress: &mut usize) -> Result<(), DecodingError> {
+        match self.current_frame_data_type {
+            FrameDataType::Pixels => {
+                let result = self.pixel_converter.read_frame(&mut self.current_frame, progress, &mut self.decoder);
+                // The converter reports the pixels that the input ended before as a truncated
+                // image. Any other error is kept, even if the input ended afterwards.
+                let ended_early = matches!(&result, Err(DecodingError::Format(err))
+                    if matches!(err.kind(), DecodingFormatErrorKind::TruncatedImage | DecodingFormatErrorKind::UnexpectedEof(_)));
+                if ended_early && self.decoder.truncated {
+                    self.fill_truncated_frame(*progress);
+                    return Ok(());
+                }
+                result
+            },
+            FrameDataType::Lzw { min_code_size } => {
+                let mut vec = if matches!(self.current_frame.buffer, Cow::Owned(_)) {
+                    let mut vec = mem::replace(&mut self.current_frame.buffer, Cow::Borrowed(&[])).into_owned();
+                    if *progress == 0 {
                         vec.clear();
-                        vec
-                    } else {
-                        Vec::new()
-                    };
+                    }
+                    vec
+                } else {
+                    Vec::new()
+                };
+                if *progress == 0 {
                     // Guesstimate 2bpp
-                    vec.try_reserve(usize::from(self.current_frame.width) * usize::from(self.current_frame.height) / 4)
-                        .map_err(|_| io::Error::from(io::ErrorKind::Other))?;
-                    self.copy_lzw_into_buffer(min_code_size, &mut vec)?;
-                    self.current_frame.buffer = Cow::Owned(vec);
-                },
+                    vec.try_reserve(self.current_frame.required_bytes_indexed().map_or(0, |len| len / 4))
+                        .map_err(|_| DecodingError::OutOfMemory)?;
+                    // `write_lzw_pre_encoded_frame` smuggles `min_code_size` in the first byte.
+                    vec.push(min_code_size);
+                }
+                let result = self.copy_lzw_into_buffer(&mut vec);
+                *progress = vec.len();
+                self.current_frame.buffer = Cow::Owned(vec);
+                self.current_frame.pixel_format = PixelFormat::Lzw;
+                result
+            },
+        }
+    }
+
+    /// Fills the pixels after the first `progress`, which the input ended before
+    fn fill_truncated_frame(&mut self, progress: usize) {
+        let index = self.current_frame.transparent.or(self.bg_color).unwrap_or(0);
+        let mut buffer = mem::take(&mut self.current_frame.buffer).into_owned();
+        self.pixel_converter.fill_rest(&self.current_frame, self.decoder.global_palette(), &mut buffer, progress, index);
+        self.current_frame.buffer = Cow::Owned(buffer);
+        self.current_frame.interlaced &= !self.pixel_converter.deinterlaces();
+    }
+
+    /// Reads the LZW data of the next frame without decoding it, e.g. to copy it into another file.
+    ///
+    /// Returns the LZW minimum code size and the data, in which the sub-blocks are concatenated
+    /// unless [`DecodeOptions::keep_lzw_sub_blocks`] is turned on. Like `Self::read_next_frame`,
+    /// it reads the frame returned by `Self::next_frame_info` if its data hasn't been read yet.
+    pub fn read_lzw_frame(&mut self) -> Result<Option<(u8, Vec<u8>)>, DecodingError> {
+        if !self.current_frame_pending && self.next_frame_info()?.is_none() {
+            return Ok(None);
+        }
+        self.current_frame_pending = false;
+        let (min_code_size, first_sub_block_len) = self.decoder.decoder.copy_frame_data(self.keep_lzw_sub_blocks)?;
+        let mut data = Vec::new();
+        if self.keep_lzw_sub_blocks {
+            data.push(first_sub_block_len);
+        }
+        loop {
+            match self.decoder.decode_next(&mut OutputBuffer::Vec(&mut data))? {
+                Some(Decoded::LzwDataCopied(_)) => {},
+                Some(Decoded::DataEnd) => return Ok(Some((min_code_size, data))),
+                None if self.decoder.truncated => return Ok(Some((min_code_size, data))),
+                _ => return Err(DecodingError::format(DecodingFormatErrorKind::InvalidState, "unexpected data")),
             }
-            Ok(Some(&self.current_frame))
-        } else {
-            Ok(None)
         }
     }
 
-    /// This is private for iterator's use
-    fn take_current_frame(&mut self) -> Option<Frame<'static>> {
-        if self.current_frame.buffer.is_empty() {
-            return None;
+    /// Skips the data of the frame returned by `Self::next_frame_info`, or the rest of a frame
+    /// that has been read partially, without decoding it.
+    ///
+    /// The next call to `Self::next_frame_info` or `Self::read_next_frame` continues with the
+    /// following frame. If no frame is in progress, this does nothing.
+    pub fn skip_frame_data(&mut self) -> Result<(), DecodingError> {
+        self.current_frame_pending = false;
+        self.frame_read_progress = None;
+        if !self.decoder.decoder.skip_frame_data() {
+            return Ok(());
         }
-        Some(self.current_frame.take())
+        loop {
+            match self.decoder.decode_next(&mut OutputBuffer::None)? {
+                Some(Decoded::PixelsDecoded(_) | Decoded::LzwDataCopied(_)) => {},
+                Some(Decoded::DataEnd) => return Ok(()),
+                None if self.decoder.truncated => return Ok(()),
+                _ => return Err(DecodingError::format(DecodingFormatErrorKind::InvalidState, "unexpected data")),
+            }
+        }
+    }
+
+    /// The frame index, if [`Decoder::build_frame_index`] has been called
+    pub fn frame_index(&self) -> Option<&[FrameIndexEntry]> {
+        self.frame_index.as_deref()
+    }
+
+    /// This is private for iterator's use
+    fn take_current_frame(&mut self) -> Frame<'static> {
+        self.current_frame.take()
     }
 
     /// Reads the data of the current frame into a pre-allocated buffer.
     ///
     /// `Self::next_frame_info` needs to be called beforehand.
     /// The length of `buf` must be at least `Self::buffer_size`.
-    /// Deinterlaces the result.
+    /// Deinterlaces the result, unless turned off with `DecodeOptions::deinterlace`.
     pub fn read_into_buffer(&mut self, buf: &mut [u8]) -> Result<(), DecodingError> {
-        self.pixel_converter.read_into_buffer(&mut self.current_frame, buf, &mut |out| self.decoder.decode_next_bytes(out))
+        self.current_frame_pending = false;
+        self.frame_read_progress = None;
+        let _span = span!(DEBUG, "frame", index = self.next_frame_number.saturating_sub(1));
+        self.pixel_converter.read_into_buffer(&self.current_frame, buf, &mut self.decoder)
+    }
+
+    /// Reads the data of the current frame into a pre-allocated buffer, like
+    /// `Self::read_into_buffer`, and shows the progress of interlaced frames.
+    ///
+    /// After each of the four interlace passes, `pass_complete` gets the pass number (1 to 4)
+    /// and the buffer, e.g. to repaint a preview. Short frames can have empty later passes, which
+    /// are still reported. For frames that aren't interlaced, it's never called.
+    pub fn read_into_buffer_progressive(&mut self, buf: &mut [u8], mut pass_complete: impl FnMut(u8, &[u8])) -> Result<(), DecodingError> {
+        self.current_frame_pending = false;
+        self.frame_read_progress = None;
+        let _span = span!(DEBUG, "frame", index = self.next_frame_number.saturating_sub(1));
+        self.pixel_converter.read_into_buffer_by_pass(&self.current_frame, buf, &mut self.decoder, &mut pass_complete)
     }
 
-    fn copy_lzw_into_buffer(&mut self, min_code_size: u8, buf: &mut Vec<u8>) -> Result<(), DecodingError> {
-        // `write_lzw_pre_encoded_frame` smuggles `min_code_size` in the first byte.
-        buf.push(min_code_size);
+    /// Reads the data of the current frame one row at a time, without a buffer for the whole frame.
+    ///
+    /// `Self::next_frame_info` needs to be called beforehand.
+    /// `row_callback` gets the index of each row in the deinterlaced frame, and its pixels in the
+    /// configured `ColorOutput`. Rows of interlaced frames arrive in the order of the passes.
+    ///
+    /// An error returned by `row_callback` stops decoding the frame, and is returned as is.
+    /// The rest of the frame is skipped by the next call to `Self::next_frame_info`.
+    pub fn read_rows<E: From<DecodingError>>(&mut self, mut row_callback: impl FnMut(u16, &[u8]) -> Result<(), E>) -> Result<(), E> {
+        self.current_frame_pending = false;
+        self.frame_read_progress = None;
+        let _span = span!(DEBUG, "frame", index = self.next_frame_number.saturating_sub(1));
+        let line_length = self.pixel_converter.line_length(&self.current_frame);
+        let mut line = mem::take(&mut self.row_buffer);
+        line.clear();
+        line.try_reserve_exact(line_length)
+            .map_err(|_| DecodingError::OutOfMemory)?;
+        line.resize(line_length, 0);
+        let result = self.pixel_converter.read_rows(&self.current_frame, &mut line, &mut self.decoder, &mut row_callback);
+        self.row_buffer = line;
+        result
+    }
+
+    fn copy_lzw_into_buffer(&mut self, buf: &mut Vec<u8>) -> Result<(), DecodingError> {
         loop {
             match self.decoder.decode_next(&mut OutputBuffer::Vec(buf))? {
                 Some(Decoded::LzwDataCopied(_len)) => {},
                 Some(Decoded::DataEnd) => return Ok(()),
-                _ => return Err(DecodingError::format("unexpected data")),
+                None if self.decoder.truncated => return Ok(()),
+                _ => return Err(DecodingError::format(DecodingFormatErrorKind::InvalidState, "unexpected data")),
             }
         }
     }
@@ -400,17 +1238,22 @@ impl<R> Decoder<R> where R: Read {
     /// Reads data of the current frame into a pre-allocated buffer until the buffer has been
     /// filled completely.
     ///
-    /// The buffer length must be an even number of pixels (multiple of 4 if decoding RGBA).
+    /// The buffer length must be an even number of pixels (multiple of 4 if decoding RGBA, of 3 if decoding RGB).
     ///
     /// `Self::next_frame_info` needs to be called beforehand. Returns `true` if the supplied
     /// buffer could be filled completely. Should not be called after `false` had been returned.
     pub fn fill_buffer(&mut self, buf: &mut [u8]) -> Result<bool, DecodingError> {
-        self.pixel_converter.fill_buffer(&mut self.current_frame, buf, &mut |out| self.decoder.decode_next_bytes(out))
+        self.current_frame_pending = false;
+        self.frame_read_progress = None;
+        self.pixel_converter.fill_buffer(&self.current_frame, buf, &mut 0, &mut self.decoder)
     }
 
     /// Output buffer size
+    ///
+    /// Saturates at `usize::MAX` if the frame is too large for this platform, which decoding
+    /// reports as [`DecodingError::DimensionsTooLarge`].
     pub fn buffer_size(&self) -> usize {
-        self.pixel_converter.buffer_size(&self.current_frame).unwrap()
+        self.pixel_converter.buffer_size(&self.current_frame).unwrap_or(usize::MAX)
     }
 
     /// Line length of the current frame
@@ -424,14 +1267,21 @@ impl<R> Decoder<R> where R: Read {
         Ok(match self.current_frame.palette {
             Some(ref table) => table,
             None => self.global_palette().ok_or(DecodingError::format(
-                "no color table available for current frame",
+                DecodingFormatErrorKind::MissingColorTable, "no color table available for current frame",
             ))?,
         })
     }
 
-    /// The global color palette
+    /// The global color palette, or the fallback one, see [`Self::has_fallback_palette`]
     pub fn global_palette(&self) -> Option<&[u8]> {
-        self.pixel_converter.global_palette()
+        self.decoder.global_palette()
+    }
+
+    /// True if the file has no global palette, and `Self::global_palette` is a generated one
+    /// because a frame without a local palette has been reached, see
+    /// [`DecodeOptions::allow_missing_color_table`]
+    pub fn has_fallback_palette(&self) -> bool {
+        self.decoder.fallback_palette.is_some()
     }
 
     /// Width of the image
@@ -446,11 +1296,84 @@ impl<R> Decoder<R> where R: Read {
         self.decoder.decoder.height()
     }
 
+    /// The logical screen descriptor from the header
+    #[inline]
+    pub fn screen_descriptor(&self) -> &ScreenDescriptor {
+        self.decoder.decoder.screen()
+    }
+
+    /// Bits per primary color of the image the file was made from, from 1 to 8, see
+    /// [`ScreenDescriptor::color_resolution`]
+    #[inline]
+    pub fn color_resolution(&self) -> u8 {
+        self.decoder.decoder.screen().color_resolution
+    }
+
+    /// The last graphic control extension that has been decoded, see
+    /// [`StreamingDecoder::last_graphic_control`]
+    #[inline]
+    pub fn last_graphic_control(&self) -> Option<&GraphicControl> {
+        self.decoder.decoder.last_graphic_control()
+    }
+
+    /// Statistics of the LZW data of the frame read last, see
+    /// [`StreamingDecoder::last_frame_lzw_stats`]
+    #[inline]
+    pub fn last_frame_lzw_stats(&self) -> Option<LzwStats> {
+        self.decoder.decoder.last_frame_lzw_stats()
+    }
+
+    /// Deviations that have been accepted so far, see [`StreamingDecoder::warnings`]
+    #[inline]
+    pub fn warnings(&self) -> &[DecodingWarning] {
+        self.decoder.decoder.warnings()
+    }
+
+    /// The signature and the logical screen descriptor as they have been read, see
+    /// [`StreamingDecoder::descriptor_bytes`]
+    #[inline]
+    pub fn descriptor_bytes(&self) -> Option<&[u8; 13]> {
+        self.decoder.decoder.descriptor_bytes()
+    }
+
+    /// The image descriptor of the current frame as it has been read, and its position, see
+    /// [`StreamingDecoder::image_descriptor_bytes`]
+    #[inline]
+    pub fn image_descriptor_bytes(&self) -> Option<(u64, &[u8; 10])> {
+        self.decoder.decoder.image_descriptor_bytes()
+    }
+
     /// Abort decoding and recover the `io::Read` instance
+    ///
+    /// If the decoder was created with [`Decoder::new_buffered`], the returned `BufReader` has
+    /// nothing buffered, and the reader's own buffer holds the data that hasn't been decoded.
+    ///
+    /// # Lost data
+    ///
+    /// Otherwise, if the reader has been returning fewer than 64 bytes per read, the decoder
+    /// combines these reads, and up to 512 bytes of the current block may have been read ahead.
+    /// These bytes are dropped: they are neither in the returned `BufReader` nor left in the
+    /// reader. Wrap the reader in a `BufReader` and use [`Decoder::new_buffered`] to keep them.
     pub fn into_inner(self) -> io::BufReader<R, 8000> {
         self.decoder.into_inner()
     }
 
+    /// Gets a reference to the underlying reader.
+    ///
+    /// The decoder reads ahead, so the position of the reader is usually past the data
+    /// that has been decoded so far.
+    pub fn get_ref(&self) -> &R {
+        self.decoder.get_ref()
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader, or to seek it. The decoder
+    /// keeps its own buffer and parsing state, which will no longer match the stream.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.decoder.get_mut()
+    }
+
     /// Index of the background color in the global palette
     ///
     /// In practice this is not used, and the background is
@@ -464,6 +1387,205 @@ impl<R> Decoder<R> where R: Read {
     pub fn repeat(&self) -> Repeat {
         self.repeat
     }
+
+    /// Number of trailers that decoding has continued past so far.
+    ///
+    /// Always 0 unless [`DecodeOptions::continue_past_trailer`] is turned on. Otherwise, a
+    /// non-zero value means the file is malformed, and some frames may not be intended to be shown.
+    pub fn skipped_trailers(&self) -> usize {
+        self.decoder.decoder.skipped_trailers()
+    }
+
+    /// Position of the trailer in the input, once decoding has reached it.
+    ///
+    /// If the input is longer than `trailer_offset + 1` bytes, there's data after the file, which
+    /// hasn't been decoded. It's the last trailer if [`DecodeOptions::continue_past_trailer`] is
+    /// turned on.
+    pub fn trailer_offset(&self) -> Option<u64> {
+        self.decoder.trailer_offset
+    }
+
+    /// Returns `true` if the input has ended before the trailer.
+    ///
+    /// Always `false` unless [`DecodeOptions::allow_truncated`] is turned on. If the input ended
+    /// inside a frame, it's the frame returned last by `Self::read_next_frame`, and the pixels
+    /// that are missing have been filled in.
+    pub fn is_truncated(&self) -> bool {
+        self.decoder.truncated
+    }
+}
+
+/// Where to find a frame in the file, see [`Decoder::build_frame_index`] and [`Index`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameIndexEntry {
+    /// Byte offset of the first block belonging to the frame, relative to where decoding started.
+    pub offset: u64,
+    /// Byte offset of the image descriptor, which the local palette and the image data follow.
+    pub descriptor: u64,
+    /// Byte offset just past the end of the frame's image data.
+    pub end: u64,
+    /// Offset from the left border of the canvas.
+    pub left: u16,
+    /// Offset from the top border of the canvas.
+    pub top: u16,
+    /// Width of the frame.
+    pub width: u16,
+    /// Height of the frame.
+    pub height: u16,
+    /// Frame delay in units of 10 ms.
+    pub delay: u16,
+    /// Disposal method.
+    pub dispose: DisposalMethod,
+    /// Index of the closest frame at or before this one that covers the whole screen without
+    /// transparency. Rendering the frames from there on gives the complete picture.
+    ///
+    /// A frame before this one that is disposed with [`DisposalMethod::Previous`] isn't a
+    /// keyframe for it, because the canvas goes back to how it was before that frame.
+    pub keyframe: usize,
+}
+
+/// The parts of a frame's metadata that can be changed without invalidating its data.
+///
+/// Returned by [`Decoder::pending_frame_mut`].
+pub struct FrameMetadata<'a> {
+    frame: &'a mut Frame<'static>,
+}
+
+impl FrameMetadata<'_> {
+    /// Frame delay in units of 10 ms.
+    #[must_use]
+    pub fn delay(&self) -> u16 {
+        self.frame.delay
+    }
+
+    /// Change the frame delay, in units of 10 ms.
+    pub fn set_delay(&mut self, delay: u16) {
+        self.frame.delay = delay;
+    }
+
+    /// Disposal method.
+    #[must_use]
+    pub fn dispose(&self) -> DisposalMethod {
+        self.frame.dispose
+    }
+
+    /// Change the disposal method.
+    pub fn set_dispose(&mut self, dispose: DisposalMethod) {
+        self.frame.dispose = dispose;
+    }
+
+    /// True if the frame needs user input to be displayed.
+    #[must_use]
+    pub fn needs_user_input(&self) -> bool {
+        self.frame.needs_user_input
+    }
+
+    /// Change whether the frame needs user input to be displayed.
+    pub fn set_needs_user_input(&mut self, needs_user_input: bool) {
+        self.frame.needs_user_input = needs_user_input;
+    }
+
+    /// Transparent index (if available).
+    #[must_use]
+    pub fn transparent(&self) -> Option<u8> {
+        self.frame.transparent
+    }
+
+    /// Change or remove the transparent index.
+    pub fn set_transparent(&mut self, transparent: Option<u8>) {
+        self.frame.transparent = transparent;
+    }
+}
+
+impl<R> Decoder<R> where R: Read + Seek {
+    /// Seek back to the first frame, so that it is returned by the next call to `Self::read_next_frame`.
+    ///
+    /// The screen descriptor, global palette and loop count are kept and not parsed again.
+    /// This is cheaper than creating a new decoder when looping an animation.
+    pub fn rewind(&mut self) -> Result<(), DecodingError> {
+        self.current_frame_pending = false;
+        self.frame_read_progress = None;
+        self.next_frame_number = 0;
+        self.decoder.rewind(self.first_block_offset, 0)
+    }
+
+    /// Scans the whole file once to record the position of every frame, and rewinds.
+    ///
+    /// The pixel data is skipped without decoding it. Afterwards [`Decoder::seek_to_frame`]
+    /// can jump to any frame directly.
+    pub fn build_frame_index(&mut self) -> Result<&[FrameIndexEntry], DecodingError> {
+        self.rewind()?;
+        let (width, height) = (self.width(), self.height());
+        let mut index = Vec::new();
+        let mut keyframe = 0;
+        loop {
+            let offset = self.decoder.position;
+            let frame = match self.next_frame_info()? {
+                Some(frame) => frame,
+                None => break,
+            };
+            // Nothing from earlier frames can show through a frame covering the whole screen.
+            // Disposing it with `Previous` restores the canvas from before it though, so the
+            // frames after it need the earlier keyframe.
+            let covers_screen = frame.left == 0 && frame.top == 0 && frame.width == width && frame.height == height
+                && frame.transparent.is_none();
+            let own_keyframe = if covers_screen { index.len() } else { keyframe };
+            if covers_screen && frame.dispose != DisposalMethod::Previous {
+                keyframe = own_keyframe;
+            }
+            let mut entry = FrameIndexEntry {
+                offset,
+                descriptor: 0,
+                end: 0,
+                left: frame.left,
+                top: frame.top,
+                width: frame.width,
+                height: frame.height,
+                delay: frame.delay,
+                dispose: frame.dispose,
+                keyframe: own_keyframe,
+            };
+            entry.descriptor = self.image_descriptor_bytes().map_or(offset, |(descriptor, _)| descriptor);
+            self.skip_frame_data()?;
+            entry.end = self.decoder.position;
+            index.try_reserve(1).map_err(|_| DecodingError::OutOfMemory)?;
+            index.push(entry);
+        }
+        self.rewind()?;
+        Ok(self.frame_index.insert(index))
+    }
+
+    /// Uses an index built earlier instead of scanning the file with [`Decoder::build_frame_index`].
+    ///
+    /// The offsets in the index must be relative to where this decoder started reading, which
+    /// they are if it was built by [`Index::build`] on the same file.
+    pub fn set_frame_index(&mut self, index: &Index) -> Result<(), DecodingError> {
+        if (index.width(), index.height()) != (self.width(), self.height()) {
+            return Err(UsageError::FrameIndexMismatch.into());
+        }
+        let mut frames = Vec::new();
+        frames.try_reserve_exact(index.entries().len())
+            .map_err(|_| DecodingError::OutOfMemory)?;
+        frames.extend_from_slice(index.entries());
+        self.frame_index = Some(frames);
+        Ok(())
+    }
+
+    /// Positions the decoder so that the next call to `Self::read_next_frame` returns the frame
+    /// with the given index, counting from 0.
+    ///
+    /// Requires [`Decoder::build_frame_index`] to be called first. Frames are usually drawn on
+    /// top of earlier ones, see [`FrameIndexEntry::keyframe`] to find where to start rendering.
+    pub fn seek_to_frame(&mut self, frame: usize) -> Result<(), DecodingError> {
+        let index = self.frame_index.as_deref().ok_or(UsageError::NoFrameIndex)?;
+        let entry = index.get(frame)
+            .ok_or(UsageError::FrameOutOfRange { frame, frames: index.len() })?;
+        self.current_frame_pending = false;
+        self.frame_read_progress = None;
+        self.next_frame_number = frame;
+        self.decoder.rewind(entry.offset, frame)
+    }
 }
 
 impl<R: Read> IntoIterator for Decoder<R> {
@@ -480,6 +1602,9 @@ impl<R: Read> IntoIterator for Decoder<R> {
 }
 
 /// Use `decoder.into_iter()` to iterate over the frames
+///
+/// The frames are owned, and the iterator ends at the trailer. After the end or an error it
+/// only returns `None`, except after `io::ErrorKind::WouldBlock`, when reading can be retried.
 pub struct DecoderIter<R: Read> {
     inner: Decoder<R>,
     ended: bool,
@@ -488,7 +1613,9 @@ pub struct DecoderIter<R: Read> {
 impl<R: Read> DecoderIter<R> {
     /// Abort decoding and recover the `io::Read` instance
     ///
-    /// Use `for frame in iter.by_ref()` to be able to call this afterwards.
+    /// Use `for frame in iter.by_ref()` to be able to call this afterwards. Like
+    /// [`Decoder::into_inner`], this drops bytes that have been read ahead from a reader that
+    /// returns only a few bytes per read.
     pub fn into_inner(self) -> io::BufReader<R, 8000> {
         self.inner.into_inner()
     }
@@ -502,13 +1629,14 @@ impl<R: Read> Iterator for DecoderIter<R> {
     fn next(&mut self) -> Option<Self::Item> {
         if !self.ended {
             match self.inner.read_next_frame() {
-                Ok(Some(_)) => self.inner.take_current_frame().map(Ok),
+                Ok(Some(_)) => Some(Ok(self.inner.take_current_frame())),
                 Ok(None) => {
                     self.ended = true;
                     None
                 },
                 Err(err) => {
-                    self.ended = true;
+                    // The reader can be retried later
+                    self.ended = !err.is_would_block();
                     Some(Err(err))
                 },
             }
@@ -0,0 +1,64 @@
+use no_std_io::io::Read;
+
+use crate::Repeat;
+
+use super::{DecodeOptions, DecodingError, Version};
+
+/// What a GIF is like, without its pixels, see [`probe`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GifInfo {
+    /// Width of the logical screen
+    pub width: u16,
+    /// Height of the logical screen
+    pub height: u16,
+    /// Number of frames, including a frame that the input ends in
+    pub frame_count: usize,
+    /// Number of loop repetitions
+    pub repeat: Repeat,
+    /// Sum of the delays of all frames in units of 10 ms, i.e. the duration of one loop
+    pub total_duration_cs: u64,
+    /// Version of the GIF standard in the signature
+    pub version: Version,
+    /// `true` if the file has a global palette
+    pub has_global_palette: bool,
+    /// `true` if the input has ended before the trailer. The other fields cover what came
+    /// before.
+    pub truncated: bool,
+}
+
+impl GifInfo {
+    /// Returns `true` if there's more than one frame
+    #[must_use]
+    pub fn is_animated(&self) -> bool {
+        self.frame_count > 1
+    }
+}
+
+/// Reads the metadata of a whole file, skipping the pixel data without decoding it.
+///
+/// No buffers for frames are allocated. A file that ends early is described up to where it
+/// ends, see [`GifInfo::truncated`]. Fails if the input ends within the header, or if the file
+/// is malformed.
+pub fn probe<R: Read>(reader: R) -> Result<GifInfo, DecodingError> {
+    let mut options = DecodeOptions::new();
+    options.allow_truncated(true);
+    let mut decoder = options.read_info(reader)?;
+    let mut frame_count = 0;
+    let mut total_duration_cs = 0;
+    while let Some(frame) = decoder.next_frame_info()? {
+        frame_count += 1;
+        total_duration_cs += u64::from(frame.delay);
+        decoder.skip_frame_data()?;
+    }
+    Ok(GifInfo {
+        width: decoder.width(),
+        height: decoder.height(),
+        frame_count,
+        // The looping extension may come after the first frame
+        repeat: decoder.repeat(),
+        total_duration_cs,
+        version: decoder.decoder.decoder.version(),
+        has_global_palette: decoder.global_palette().is_some() && !decoder.has_fallback_palette(),
+        truncated: decoder.is_truncated(),
+    })
+}
@@ -0,0 +1,362 @@
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+use core::mem;
+use core::ops::Range;
+
+
+use crate::common::{Block, Frame, GraphicControl, PixelFormat, ScreenDescriptor};
+use crate::Repeat;
+
+use super::converter::{fallback_palette, PixelConverter, WithPalette, FALLBACK_PALETTE_COLORS};
+use super::{DecodeOptions, Decoded, DecodingError, DecodingFormatErrorKind, DecodingWarning, FrameDataType, LzwStats, OutputBuffer, StreamingDecoder, PLTE_CHANNELS};
+
+/// Feeds the whole remaining input to the decoder, without any intermediate buffer
+struct SliceInput<'a> {
+    data: &'a [u8],
+    decoder: StreamingDecoder,
+    /// Number of bytes consumed by the decoder
+    position: usize,
+    at_eof: bool,
+    allow_missing_trailer: bool,
+    error_on_trailing_data: bool,
+    /// Position of the trailer
+    trailer_offset: Option<usize>,
+}
+
+impl<'a> SliceInput<'a> {
+    fn decode_next(&mut self, write_into: &mut OutputBuffer<'_>) -> Result<Option<Decoded>, DecodingError> {
+        while !self.at_eof {
+            let buf = &self.data[self.position..];
+            if buf.is_empty() {
+                if self.allow_missing_trailer {
+                    self.decoder.finish()?;
+                    self.at_eof = true;
+                    break;
+                }
+                return Err(self.decoder.unexpected_eof());
+            }
+            let (consumed, result) = self.decoder.update(buf, write_into)?;
+            self.position += consumed;
+            match result {
+                Decoded::Nothing => (),
+                Decoded::BlockStart(Block::Trailer) => {
+                    // The trailer has already been consumed
+                    self.trailer_offset = Some(self.position - 1);
+                    self.at_eof = true;
+                    if self.error_on_trailing_data {
+                        // The decoder fails on any data after the trailer
+                        self.decoder.update(&self.data[self.position..], &mut OutputBuffer::None)?;
+                    }
+                },
+                result => return Ok(Some(result)),
+            }
+        }
+        Ok(None)
+    }
+
+    fn decode_next_pixels(&mut self, out: &mut OutputBuffer<'_>) -> Result<usize, DecodingError> {
+        match self.decode_next(out)? {
+            Some(Decoded::PixelsDecoded(len)) => Ok(len.get()),
+            Some(Decoded::DataEnd) => Ok(0),
+            _ => Err(DecodingError::format(DecodingFormatErrorKind::InvalidState, "unexpected data")),
+        }
+    }
+
+    /// The `len` bytes that end `before_end` bytes before the current position
+    /// The bytes at `range`, which the decoder has consumed
+    fn consumed_bytes(&self, range: Range<u64>) -> &'a [u8] {
+        &self.data[range.start as usize..range.end as usize]
+    }
+}
+
+/// GIF decoder for a file that is already in memory, like a memory-mapped or downloaded file.
+///
+/// Unlike [`Decoder`](crate::Decoder), it doesn't copy the input into a buffer of its own,
+/// and hands out palettes as slices of the input, which live as long as the input does.
+///
+/// The trailer always ends decoding, [`DecodeOptions::continue_past_trailer`] has no effect.
+pub struct SliceDecoder<'a> {
+    input: SliceInput<'a>,
+    pixel_converter: PixelConverter,
+    global_palette: Option<&'a [u8]>,
+    local_palette: Option<&'a [u8]>,
+    bg_color: Option<u8>,
+    repeat: Repeat,
+    current_frame: Frame<'static>,
+    current_frame_data_type: FrameDataType,
+    /// `next_frame_info` returned a frame, and none of its data has been read yet
+    current_frame_pending: bool,
+    allow_missing_color_table: bool,
+}
+
+impl<'a> SliceDecoder<'a> {
+    /// Create a new decoder with default options.
+    #[inline]
+    pub fn new(data: &'a [u8]) -> Result<Self, DecodingError> {
+        DecodeOptions::new().read_slice(data)
+    }
+
+    pub(crate) fn with_options(data: &'a [u8], mut options: DecodeOptions) -> Result<Self, DecodingError> {
+        // The trailer always ends decoding
+        options.continue_past_trailer = false;
+        let mut this = Self {
+            input: SliceInput {
+                data,
+                decoder: StreamingDecoder::with_options(&options),
+                position: 0,
+                at_eof: false,
+                allow_missing_trailer: options.allow_missing_trailer,
+                error_on_trailing_data: options.error_on_trailing_data,
+                trailer_offset: None,
+            },
+            pixel_converter: PixelConverter::new(options.color_output, options.matte_color, options.memory_limit, options.deinterlace, options.error_on_index_outside_palette),
+            global_palette: None,
+            local_palette: None,
+            bg_color: None,
+            repeat: Repeat::default(),
+            current_frame: Frame::default(),
+            current_frame_data_type: FrameDataType::Pixels,
+            current_frame_pending: false,
+            allow_missing_color_table: options.allow_missing_color_table,
+        };
+        this.read_header()?;
+        // If the background color is invalid, ignore it. Without a global palette, it's an index
+        // into the fallback palette, like in `Decoder`.
+        let colors = match this.global_palette {
+            Some(palette) => palette.len() / PLTE_CHANNELS,
+            None if this.allow_missing_color_table => FALLBACK_PALETTE_COLORS,
+            None => 0,
+        };
+        if this.bg_color.map_or(false, |index| usize::from(index) >= colors) {
+            this.bg_color = None;
+        }
+        this.pixel_converter.set_background(this.bg_color);
+        Ok(this)
+    }
+
+    fn read_header(&mut self) -> Result<(), DecodingError> {
+        loop {
+            match self.input.decode_next(&mut OutputBuffer::None)? {
+                Some(Decoded::BackgroundColor(bg_color)) => {
+                    self.bg_color = Some(bg_color);
+                }
+                Some(Decoded::GlobalPalette) => {
+                    self.global_palette = self.input.decoder.global_palette_range()
+                        .map(|range| self.input.consumed_bytes(range));
+                },
+                Some(Decoded::Repetitions(repeat)) => {
+                    self.repeat = repeat;
+                },
+                Some(Decoded::HeaderEnd) => break,
+                Some(_) => continue,
+                None => return Err(DecodingError::format(
+                    DecodingFormatErrorKind::NoImageData, "file does not contain any image data"
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the next frame info, see [`Decoder::next_frame_info`](crate::Decoder::next_frame_info)
+    pub fn next_frame_info(&mut self) -> Result<Option<&Frame<'static>>, DecodingError> {
+        self.current_frame_pending = false;
+        loop {
+            match self.input.decode_next(&mut OutputBuffer::None)? {
+                Some(Decoded::FrameMetadata(frame_data_type)) => {
+                    self.local_palette = self.input.decoder.local_palette_range()
+                        .map(|range| self.input.consumed_bytes(range));
+                    self.current_frame = self.input.decoder.try_current_frame()?.take();
+                    self.current_frame_data_type = frame_data_type;
+                    if self.local_palette.is_none() && self.global_palette.is_none() {
+                        if !self.allow_missing_color_table {
+                            return Err(DecodingError::format(
+                                DecodingFormatErrorKind::MissingColorTable, "no color table available for current frame",
+                            ));
+                        }
+                        // Only generated once a frame needs it, like in `Decoder`
+                        self.global_palette = Some(fallback_palette(self.screen_descriptor().color_resolution));
+                    }
+                    break;
+                }
+                Some(_) => (),
+                None => return Ok(None),
+            }
+        }
+        self.current_frame_pending = true;
+        Ok(Some(&self.current_frame))
+    }
+
+    /// Reads the next frame from the image, see [`Decoder::read_next_frame`](crate::Decoder::read_next_frame)
+    pub fn read_next_frame(&mut self) -> Result<Option<&Frame<'static>>, DecodingError> {
+        if !self.current_frame_pending && self.next_frame_info()?.is_none() {
+            return Ok(None);
+        }
+        self.current_frame_pending = false;
+        match self.current_frame_data_type {
+            FrameDataType::Pixels => {
+                let input = &mut self.input;
+                let mut source = WithPalette { global_palette: self.global_palette, decode: |out: &mut OutputBuffer<'_>| input.decode_next_pixels(out) };
+                let result = self.pixel_converter.read_frame(&mut self.current_frame, &mut 0, &mut source);
+                if result.is_err() {
+                    if let Cow::Owned(buffer) = &mut self.current_frame.buffer {
+                        buffer.clear();
+                    }
+                }
+                result?;
+            },
+            FrameDataType::Lzw { min_code_size } => {
+                let mut vec = match mem::replace(&mut self.current_frame.buffer, Cow::Borrowed(&[])) {
+                    Cow::Owned(mut vec) => {
+                        vec.clear();
+                        vec
+                    },
+                    Cow::Borrowed(_) => Vec::new(),
+                };
+                // `write_lzw_pre_encoded_frame` smuggles `min_code_size` in the first byte.
+                vec.push(min_code_size);
+                loop {
+                    match self.input.decode_next(&mut OutputBuffer::Vec(&mut vec))? {
+                        Some(Decoded::LzwDataCopied(_len)) => {},
+                        Some(Decoded::DataEnd) => break,
+                        _ => return Err(DecodingError::format(DecodingFormatErrorKind::InvalidState, "unexpected data")),
+                    }
+                }
+                self.current_frame.buffer = Cow::Owned(vec);
+                self.current_frame.pixel_format = PixelFormat::Lzw;
+            },
+        }
+        Ok(Some(&self.current_frame))
+    }
+
+    /// Reads the data of the current frame into a pre-allocated buffer, see
+    /// [`Decoder::read_into_buffer`](crate::Decoder::read_into_buffer)
+    pub fn read_into_buffer(&mut self, buf: &mut [u8]) -> Result<(), DecodingError> {
+        self.current_frame_pending = false;
+        let input = &mut self.input;
+        let mut source = WithPalette { global_palette: self.global_palette, decode: |out: &mut OutputBuffer<'_>| input.decode_next_pixels(out) };
+        self.pixel_converter.read_into_buffer(&self.current_frame, buf, &mut source)
+    }
+
+    /// Output buffer size
+    ///
+    /// Saturates at `usize::MAX` if the frame is too large for this platform, which decoding
+    /// reports as [`DecodingError::DimensionsTooLarge`].
+    pub fn buffer_size(&self) -> usize {
+        self.pixel_converter.buffer_size(&self.current_frame).unwrap_or(usize::MAX)
+    }
+
+    /// Line length of the current frame
+    pub fn line_length(&self) -> usize {
+        self.pixel_converter.line_length(&self.current_frame)
+    }
+
+    /// Returns the color palette relevant for the current frame, borrowed from the input unless
+    /// it's the fallback palette
+    #[inline]
+    pub fn palette(&self) -> Result<&'a [u8], DecodingError> {
+        self.local_palette.or(self.global_palette)
+            .ok_or(DecodingError::format(DecodingFormatErrorKind::MissingColorTable, "no color table available for current frame"))
+    }
+
+    /// The local color palette of the current frame, borrowed from the input
+    #[inline]
+    pub fn local_palette(&self) -> Option<&'a [u8]> {
+        self.local_palette
+    }
+
+    /// The global color palette, borrowed from the input unless it's the fallback palette, see
+    /// [`Self::has_fallback_palette`]
+    #[inline]
+    pub fn global_palette(&self) -> Option<&'a [u8]> {
+        self.global_palette
+    }
+
+    /// True if the file has no global palette, and `Self::global_palette` is a generated one
+    /// because a frame without a local palette has been reached, see
+    /// [`DecodeOptions::allow_missing_color_table`]
+    #[inline]
+    pub fn has_fallback_palette(&self) -> bool {
+        self.input.decoder.global_palette().is_none() && self.global_palette.is_some()
+    }
+
+    /// Width of the image
+    #[inline]
+    pub fn width(&self) -> u16 {
+        self.input.decoder.width()
+    }
+
+    /// Height of the image
+    #[inline]
+    pub fn height(&self) -> u16 {
+        self.input.decoder.height()
+    }
+
+    /// The logical screen descriptor from the header
+    #[inline]
+    pub fn screen_descriptor(&self) -> &ScreenDescriptor {
+        self.input.decoder.screen()
+    }
+
+    /// Bits per primary color of the image the file was made from, from 1 to 8, see
+    /// [`ScreenDescriptor::color_resolution`]
+    #[inline]
+    pub fn color_resolution(&self) -> u8 {
+        self.input.decoder.screen().color_resolution
+    }
+
+    /// The last graphic control extension that has been decoded, see
+    /// [`StreamingDecoder::last_graphic_control`]
+    #[inline]
+    pub fn last_graphic_control(&self) -> Option<&GraphicControl> {
+        self.input.decoder.last_graphic_control()
+    }
+
+    /// Statistics of the LZW data of the frame read last, see
+    /// [`StreamingDecoder::last_frame_lzw_stats`]
+    #[inline]
+    pub fn last_frame_lzw_stats(&self) -> Option<LzwStats> {
+        self.input.decoder.last_frame_lzw_stats()
+    }
+
+    /// Deviations that have been accepted so far, see [`StreamingDecoder::warnings`]
+    #[inline]
+    pub fn warnings(&self) -> &[DecodingWarning] {
+        self.input.decoder.warnings()
+    }
+
+    /// The signature and the logical screen descriptor as they have been read, see
+    /// [`StreamingDecoder::descriptor_bytes`]
+    #[inline]
+    pub fn descriptor_bytes(&self) -> Option<&'a [u8; 13]> {
+        self.input.data.get(..13)?.try_into().ok()
+    }
+
+    /// The image descriptor of the current frame, and its position, see
+    /// [`StreamingDecoder::image_descriptor_bytes`]
+    #[inline]
+    pub fn image_descriptor_bytes(&self) -> Option<(usize, &'a [u8; 10])> {
+        let (offset, _) = self.input.decoder.image_descriptor_bytes()?;
+        let offset = offset as usize;
+        Some((offset, self.input.data.get(offset..offset + 10)?.try_into().ok()?))
+    }
+
+    /// Index of the background color in the global palette
+    ///
+    /// In practice this is not used, and the background is
+    /// always transparent
+    pub fn bg_color(&self) -> Option<usize> {
+        self.bg_color.map(|v| v as usize)
+    }
+
+    /// Number of loop repetitions
+    #[inline]
+    pub fn repeat(&self) -> Repeat {
+        self.repeat
+    }
+
+    /// Position of the trailer in the input, once decoding has reached it, see
+    /// [`Decoder::trailer_offset`](crate::Decoder::trailer_offset)
+    pub fn trailer_offset(&self) -> Option<usize> {
+        self.input.trailer_offset
+    }
+}
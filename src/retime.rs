@@ -0,0 +1,124 @@
+//! Changing the delays of an animation without decoding or copying it
+//!
+//! The delay of a frame is stored in the graphic control extension before it. Its two bytes
+//! are rewritten in place, so the file keeps its size and all other bytes.
+use alloc::vec;
+use alloc::vec::Vec;
+use no_std_io::io::{Read, Seek, SeekFrom, Write};
+
+use crate::common::{Block, Extension};
+use crate::reader::{Decoded, DecodeOptions, DecodingError, OutputBuffer, StreamingDecoder};
+
+/// Delay of one frame, before and after retiming
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RetimedFrame {
+    /// Position of the delay in the file, or `None` if the frame has no graphic control
+    /// extension. Such frames keep their delay of 0, because adding an extension would move the
+    /// rest of the file.
+    pub offset: Option<u64>,
+    /// Delay in units of 10 ms, as it was.
+    pub old_delay: u16,
+    /// Delay in units of 10 ms, as it has been written.
+    pub new_delay: u16,
+}
+
+/// What [`scale_delays_in_place`] has changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RetimeReport {
+    /// One entry per frame, in the order of the file.
+    pub frames: Vec<RetimedFrame>,
+    /// Graphic control extensions whose delay doesn't apply to any frame, because another one
+    /// or the end of the file follows them. They are left unchanged.
+    pub unused_control_extensions: usize,
+}
+
+/// Multiplies the delay of every frame by `factor`, rounding to the nearest unit of 10 ms, and
+/// raises the result to at least `min`.
+///
+/// The whole file is parsed before anything is written, so a malformed file is left unchanged.
+/// Frame data is skipped without being decompressed. Delays that would exceed `u16::MAX`
+/// are clamped to it.
+///
+/// `min` applies to delays of 0 as well, which many viewers replace with their own
+/// minimum anyway.
+pub fn scale_delays_in_place(file: &mut (impl Read + Write + Seek), factor: f32, min: u16) -> Result<RetimeReport, DecodingError> {
+    let mut options = DecodeOptions::new();
+    options.allow_unknown_extensions(true);
+    let mut decoder = StreamingDecoder::with_options(&options);
+    let mut report = RetimeReport::default();
+    // Position of the length of the first sub-block of the last extension
+    let mut extension_start = 0;
+    // Position of the delay of the extension that applies to the next frame
+    let mut pending = None;
+
+    let mut buf = vec![0; 8 * 1024];
+    let mut position = stream_position(file)?;
+    'read: loop {
+        let len = file.read(&mut buf)?;
+        if len == 0 {
+            decoder.finish()?;
+            break;
+        }
+        let mut input = &buf[..len];
+        while !input.is_empty() {
+            let (consumed, decoded) = decoder.update(input, &mut OutputBuffer::None)?;
+            input = &input[consumed..];
+            position += consumed as u64;
+            match decoded {
+                Decoded::BlockStart(Block::Extension) => extension_start = position,
+                Decoded::BlockFinished(id) if id.into_known() == Some(Extension::Control) => {
+                    if pending.is_some() {
+                        report.unused_control_extensions += 1;
+                    }
+                    // The length of the block and the flags come before the delay
+                    pending = Some(extension_start + 2);
+                },
+                Decoded::FrameMetadata(_) => {
+                    let old_delay = decoder.try_current_frame()?.delay;
+                    let offset = pending.take();
+                    let new_delay = if offset.is_some() { scale(old_delay, factor, min) } else { old_delay };
+                    report.frames.push(RetimedFrame { offset, old_delay, new_delay });
+                    decoder.skip_frame_data();
+                },
+                Decoded::BlockStart(Block::Trailer) | Decoded::Finished => break 'read,
+                _ => {},
+            }
+        }
+    }
+    if pending.is_some() {
+        report.unused_control_extensions += 1;
+    }
+
+    for frame in &report.frames {
+        if let Some(offset) = frame.offset {
+            if frame.new_delay != frame.old_delay {
+                file.seek(SeekFrom::Start(offset))?;
+                file.write_all(&frame.new_delay.to_le_bytes())?;
+            }
+        }
+    }
+    file.flush()?;
+    Ok(report)
+}
+
+// `Seek::stream_position`, which the `Seek` of `no_std_io` doesn't have
+#[allow(clippy::seek_from_current)]
+fn stream_position(file: &mut impl Seek) -> no_std_io::io::Result<u64> {
+    file.seek(SeekFrom::Current(0))
+}
+
+fn scale(delay: u16, factor: f32, min: u16) -> u16 {
+    // Float to int casts saturate, and turn NaN into 0
+    let scaled = (f32::from(delay) * factor + 0.5) as u16;
+    scaled.max(min)
+}
+
+#[test]
+fn scale_rounds_and_clamps() {
+    assert_eq!(scale(10, 2.0, 0), 20);
+    assert_eq!(scale(3, 0.5, 0), 2);
+    assert_eq!(scale(3, 0.5, 5), 5);
+    assert_eq!(scale(40_000, 2.0, 0), u16::MAX);
+    assert_eq!(scale(10, -1.0, 1), 1);
+    assert_eq!(scale(10, f32::NAN, 0), 0);
+}
@@ -0,0 +1,80 @@
+//! Files assembled byte by byte, for testing how decoders handle them
+//!
+//! The [`Encoder`](crate::Encoder) only writes valid files. The files here are written without
+//! it, so they can have any mistake that has been found in the wild.
+use alloc::vec::Vec;
+
+use crate::common::{Block, Extension};
+
+pub mod pathological;
+
+/// LZW data of the pixels `[0, 1, 1, 0]`, with a minimum code size of 2.
+///
+/// The codes are 3 bits each: clear, 0, 1, clear, 1, 0, end. The second clear code keeps the
+/// table from growing to 4 bit codes.
+const PIXELS_LZW: [u8; 3] = [0x44, 0x18, 0x14];
+
+/// Colors of the global palette, black and white
+const PALETTE: [u8; 6] = [0, 0, 0, 0xFF, 0xFF, 0xFF];
+
+/// The signature, the logical screen descriptor, and the global palette if it isn't empty.
+///
+/// The palette must have a power of two of at least 2 colors.
+fn header(width: u16, height: u16, palette: &[u8]) -> Vec<u8> {
+    let mut out = b"GIF89a".to_vec();
+    out.extend_from_slice(&width.to_le_bytes());
+    out.extend_from_slice(&height.to_le_bytes());
+    out.push(palette_flags(palette));
+    // Background color index and aspect ratio
+    out.extend_from_slice(&[0, 0]);
+    out.extend_from_slice(palette);
+    out
+}
+
+/// Flags of a screen or image descriptor for the palette after it
+fn palette_flags(palette: &[u8]) -> u8 {
+    let colors = palette.len() / 3;
+    debug_assert!(palette.len() == colors * 3 && (colors == 0 || colors >= 2 && colors.is_power_of_two()));
+    if colors == 0 {
+        0
+    } else {
+        0x80 | (colors.trailing_zeros() - 1) as u8
+    }
+}
+
+/// An extension with the data in sub-blocks
+fn extension(out: &mut Vec<u8>, label: u8, data: &[u8]) {
+    out.extend_from_slice(&[Block::Extension as u8, label]);
+    sub_blocks(out, data);
+}
+
+/// A graphic control extension, of the correct size
+fn graphic_control(out: &mut Vec<u8>, flags: u8, delay: u16, transparent: u8) {
+    let [delay1, delay2] = delay.to_le_bytes();
+    out.extend_from_slice(&[Block::Extension as u8, Extension::Control as u8, 4, flags, delay1, delay2, transparent, 0]);
+}
+
+/// An image descriptor, followed by its local palette if it isn't empty
+fn image_descriptor(out: &mut Vec<u8>, left: u16, top: u16, width: u16, height: u16, palette: &[u8]) {
+    out.push(Block::Image as u8);
+    for value in [left, top, width, height] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    out.push(palette_flags(palette));
+    out.extend_from_slice(palette);
+}
+
+/// The minimum code size, followed by the LZW data in sub-blocks
+fn image_data(out: &mut Vec<u8>, min_code_size: u8, lzw: &[u8]) {
+    out.push(min_code_size);
+    sub_blocks(out, lzw);
+}
+
+/// Sub-blocks of at most 255 bytes, and the block terminator
+fn sub_blocks(out: &mut Vec<u8>, data: &[u8]) {
+    for block in data.chunks(255) {
+        out.push(block.len() as u8);
+        out.extend_from_slice(block);
+    }
+    out.push(0);
+}
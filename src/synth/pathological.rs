@@ -0,0 +1,384 @@
+//! One file per kind of malformed data
+//!
+//! Except for [`well_formed`], every file has exactly one deviation from it. All of them have a
+//! screen of 2×2 pixels, a global palette of black and white, and frames with the pixels
+//! `[0, 1, 1, 0]` after a graphic control extension with a delay of 10.
+use alloc::vec::Vec;
+
+use super::{extension, graphic_control, header, image_data, image_descriptor, sub_blocks, PALETTE, PIXELS_LZW};
+use crate::common::{Block, Extension};
+
+/// A graphic control extension, an image descriptor without a local palette and the LZW data
+fn frame(out: &mut Vec<u8>) {
+    graphic_control(out, 0, 10, 0);
+    image_descriptor(out, 0, 0, 2, 2, &[]);
+    image_data(out, 2, &PIXELS_LZW);
+}
+
+/// A looping application extension with the given sub-blocks after the identifier
+fn looping_extension(out: &mut Vec<u8>, sub_blocks: &[&[u8]]) {
+    out.extend_from_slice(&[Block::Extension as u8, Extension::Application as u8]);
+    out.push(11);
+    out.extend_from_slice(b"NETSCAPE2.0");
+    for sub_block in sub_blocks {
+        out.push(sub_block.len() as u8);
+        out.extend_from_slice(sub_block);
+    }
+    out.push(0);
+}
+
+/// The header and a single frame, without the trailer
+fn single_frame() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    frame(&mut out);
+    out
+}
+
+/// The file the others deviate from, with a single frame and a trailer
+#[must_use]
+pub fn well_formed() -> Vec<u8> {
+    let mut out = single_frame();
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// The signature is `PNG` instead of `GIF`.
+#[must_use]
+pub fn malformed_signature() -> Vec<u8> {
+    let mut out = well_formed();
+    out[..3].copy_from_slice(b"PNG");
+    out
+}
+
+/// The version is `88a`, which doesn't exist, but starts with two digits like the valid ones.
+#[must_use]
+pub fn unsupported_version() -> Vec<u8> {
+    let mut out = well_formed();
+    out[3..6].copy_from_slice(b"88a");
+    out
+}
+
+/// The version is `87a`, but the frame has a graphic control extension, which version `89a`
+/// introduced.
+#[must_use]
+pub fn version_87a_with_extension() -> Vec<u8> {
+    let mut out = well_formed();
+    out[3..6].copy_from_slice(b"87a");
+    out
+}
+
+/// The global palette is cut off. The screen descriptor announces 4 colors, and the file ends
+/// after 2 of them.
+#[must_use]
+pub fn truncated_global_palette() -> Vec<u8> {
+    let mut out = header(2, 2, &[0; 4 * 3]);
+    out.truncate(out.len() - 2 * 3);
+    out
+}
+
+/// The background color index is 7, but the global palette has 2 colors.
+#[must_use]
+pub fn background_outside_palette() -> Vec<u8> {
+    let mut out = well_formed();
+    out[11] = 7;
+    out
+}
+
+/// The trailer follows the header, without any frame.
+#[must_use]
+pub fn no_frames() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// Neither the file nor the frame have a palette.
+#[must_use]
+pub fn missing_color_table() -> Vec<u8> {
+    let mut out = header(2, 2, &[]);
+    frame(&mut out);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// The graphic control extension has a block size of 5 instead of 4, with a zero byte after
+/// the transparent index.
+#[must_use]
+pub fn oversized_graphic_control() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    out.extend_from_slice(&[Block::Extension as u8, Extension::Control as u8, 5, 0, 10, 0, 0, 0, 0]);
+    image_descriptor(&mut out, 0, 0, 2, 2, &[]);
+    image_data(&mut out, 2, &PIXELS_LZW);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// The graphic control extension isn't terminated after its fields, but has another sub-block
+/// of one byte before the terminator.
+#[must_use]
+pub fn graphic_control_with_extra_sub_block() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    out.extend_from_slice(&[Block::Extension as u8, Extension::Control as u8, 4, 0, 10, 0, 0, 1, 0xFF, 0]);
+    image_descriptor(&mut out, 0, 0, 2, 2, &[]);
+    image_data(&mut out, 2, &PIXELS_LZW);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// The graphic control extension has the reserved disposal method 5.
+#[must_use]
+pub fn reserved_disposal() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    graphic_control(&mut out, 5 << 2, 10, 0);
+    image_descriptor(&mut out, 0, 0, 2, 2, &[]);
+    image_data(&mut out, 2, &PIXELS_LZW);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// The graphic control extension has the transparent index 200, but the global palette has
+/// 4 colors. Besides black and white, it has red and blue, which the pixels don't use.
+#[must_use]
+pub fn transparent_outside_palette() -> Vec<u8> {
+    let mut palette = PALETTE.to_vec();
+    palette.extend_from_slice(&[0xFF, 0, 0, 0, 0, 0xFF]);
+    let mut out = header(2, 2, &palette);
+    graphic_control(&mut out, 1, 10, 200);
+    image_descriptor(&mut out, 0, 0, 2, 2, &[]);
+    image_data(&mut out, 2, &PIXELS_LZW);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// Another graphic control extension, with the transparent index 1 and a delay of 20, comes
+/// before the one of the frame.
+#[must_use]
+pub fn repeated_graphic_control_transparent_first() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    graphic_control(&mut out, 1, 20, 1);
+    frame(&mut out);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// Another graphic control extension, with the transparent index 1 and a delay of 20, comes
+/// after the one of the frame, right before the image descriptor.
+#[must_use]
+pub fn repeated_graphic_control_transparent_last() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    graphic_control(&mut out, 0, 10, 0);
+    graphic_control(&mut out, 1, 20, 1);
+    image_descriptor(&mut out, 0, 0, 2, 2, &[]);
+    image_data(&mut out, 2, &PIXELS_LZW);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// A looping application extension, which would loop forever, has the identifier
+/// `ANIMEXTS1.0` instead of `NETSCAPE2.0`.
+#[must_use]
+pub fn unrecognized_loop_identifier() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    out.extend_from_slice(&[Block::Extension as u8, Extension::Application as u8]);
+    out.push(11);
+    out.extend_from_slice(b"ANIMEXTS1.0");
+    sub_blocks(&mut out, &[1, 0, 0]);
+    frame(&mut out);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// A looping application extension has a sub-block with the unknown id 7 before the one with
+/// the loop count 3.
+#[must_use]
+pub fn unknown_loop_sub_block() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    looping_extension(&mut out, &[&[7, 1, 2], &[1, 3, 0]]);
+    frame(&mut out);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// The only sub-block of a looping application extension has the loop count 3, followed by an
+/// extra zero byte.
+#[must_use]
+pub fn malformed_loop_count() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    looping_extension(&mut out, &[&[1, 3, 0, 0]]);
+    frame(&mut out);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// A looping application extension has two sub-blocks with a loop count, first 3 and then 0,
+/// which would loop forever.
+#[must_use]
+pub fn conflicting_loop_counts() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    looping_extension(&mut out, &[&[1, 3, 0], &[1, 0, 0]]);
+    frame(&mut out);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// An extension with the unknown label `0xAB` comes before the frame.
+#[must_use]
+pub fn unknown_extension() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    extension(&mut out, 0xAB, &[1, 2, 3]);
+    frame(&mut out);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// A block with the unknown introducer `0x99`, and sub-blocks like an extension, comes before
+/// the frame.
+#[must_use]
+pub fn unknown_block() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    out.push(0x99);
+    sub_blocks(&mut out, &[1, 2, 3]);
+    frame(&mut out);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// The second of two frames is at (1, 1), so it extends past the screen by one pixel on the
+/// right and the bottom.
+#[must_use]
+pub fn frame_out_of_bounds() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    frame(&mut out);
+    graphic_control(&mut out, 0, 10, 0);
+    image_descriptor(&mut out, 1, 1, 2, 2, &[]);
+    image_data(&mut out, 2, &PIXELS_LZW);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// The first of two frames has the pixels `[0, 3, 1, 0]`, but the global palette has 2 colors.
+#[must_use]
+pub fn index_outside_palette() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    graphic_control(&mut out, 0, 10, 0);
+    image_descriptor(&mut out, 0, 0, 2, 2, &[]);
+    // Like `PIXELS_LZW`, with the code 3 instead of the first 1
+    image_data(&mut out, 2, &[0xC4, 0x18, 0x14]);
+    frame(&mut out);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// The first of two frames has a width of 0, but its LZW data still has 4 pixels.
+#[must_use]
+pub fn zero_width_frame() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    graphic_control(&mut out, 0, 10, 0);
+    image_descriptor(&mut out, 0, 0, 0, 2, &[]);
+    image_data(&mut out, 2, &PIXELS_LZW);
+    frame(&mut out);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// The first of two frames has a height of 0, but its LZW data still has 4 pixels.
+#[must_use]
+pub fn zero_height_frame() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    graphic_control(&mut out, 0, 10, 0);
+    image_descriptor(&mut out, 0, 0, 2, 0, &[]);
+    image_data(&mut out, 2, &PIXELS_LZW);
+    frame(&mut out);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// The minimum LZW code size of the frame is 12, but the codes can't be larger than 12 bits.
+#[must_use]
+pub fn invalid_min_code_size() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    graphic_control(&mut out, 0, 10, 0);
+    image_descriptor(&mut out, 0, 0, 2, 2, &[]);
+    image_data(&mut out, 12, &PIXELS_LZW);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// The LZW data starts with the code 7, which isn't in the table yet.
+#[must_use]
+pub fn invalid_lzw_code() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    graphic_control(&mut out, 0, 10, 0);
+    image_descriptor(&mut out, 0, 0, 2, 2, &[]);
+    image_data(&mut out, 2, &[7]);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// The LZW data has all pixels of the frame, but no end code.
+#[must_use]
+pub fn missing_end_code() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    graphic_control(&mut out, 0, 10, 0);
+    image_descriptor(&mut out, 0, 0, 2, 2, &[]);
+    // Bits 2 to 4 of the last byte are the end code
+    let mut lzw = PIXELS_LZW;
+    lzw[2] &= 0b11;
+    image_data(&mut out, 2, &lzw);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// The image descriptor declares a local palette of 4 colors, and the file ends after 2 of them.
+#[must_use]
+pub fn truncated_local_palette() -> Vec<u8> {
+    let mut out = header(2, 2, &PALETTE);
+    graphic_control(&mut out, 0, 10, 0);
+    image_descriptor(&mut out, 0, 0, 2, 2, &[0; 4 * 3]);
+    out.truncate(out.len() - 2 * 3);
+    out
+}
+
+/// The file ends within the first sub-block of the frame data, after 1 of its 3 bytes.
+#[must_use]
+pub fn truncated_frame_data() -> Vec<u8> {
+    let mut out = single_frame();
+    // The code size, the sub-block length and 1 byte of data are left
+    out.truncate(out.len() - 3);
+    out
+}
+
+/// The block terminator after the frame data is left out, so the trailer follows the last
+/// sub-block.
+#[must_use]
+pub fn missing_block_terminator() -> Vec<u8> {
+    let mut out = single_frame();
+    let terminator = out.pop();
+    debug_assert_eq!(terminator, Some(0));
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// The file ends after the frame, without a trailer.
+#[must_use]
+pub fn missing_trailer() -> Vec<u8> {
+    single_frame()
+}
+
+/// A trailer comes between two frames.
+#[must_use]
+pub fn stray_trailer() -> Vec<u8> {
+    let mut out = single_frame();
+    out.push(Block::Trailer as u8);
+    frame(&mut out);
+    out.push(Block::Trailer as u8);
+    out
+}
+
+/// The bytes `PK\x03\x04`, which start a ZIP file, follow the trailer.
+#[must_use]
+pub fn data_after_trailer() -> Vec<u8> {
+    let mut out = well_formed();
+    out.extend_from_slice(b"PK\x03\x04");
+    out
+}
@@ -0,0 +1,26 @@
+//! Instrumentation with `tracing`, which compiles to nothing without the `tracing` feature
+//!
+//! Events and spans carry sizes, offsets and indices, but never pixel or palette data.
+
+/// Emits an event at the given level, e.g. `event!(DEBUG, colors = 2, "global palette")`
+macro_rules! event {
+    ($level:ident, $($args:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::event!(tracing::Level::$level, $($args)*);
+    };
+}
+
+/// Creates a span at the given level and enters it until the returned guard is dropped
+macro_rules! span {
+    ($level:ident, $($args:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        let guard = tracing::span!(tracing::Level::$level, $($args)*).entered();
+        #[cfg(not(feature = "tracing"))]
+        let guard = crate::trace::NoSpan;
+        guard
+    }};
+}
+
+/// Stands in for an entered span when tracing is disabled
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoSpan;
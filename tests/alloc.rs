@@ -0,0 +1,194 @@
+#![cfg(all(feature = "std", not(feature = "tracing")))]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use gif::{AnyExtension, ColorOutput, DecodeOptions, Decoder, Encoder, Extension, Frame, Repeat};
+
+/// Counts the allocations of the current thread, and the bytes they hold. Allocations of
+/// `FAILING_SIZE` bytes fail.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    static LIVE_BYTES: Cell<isize> = const { Cell::new(0) };
+    static FAILING_SIZE: Cell<usize> = const { Cell::new(0) };
+}
+
+fn fails(size: usize) -> bool {
+    size != 0 && FAILING_SIZE.try_with(|failing| failing.get() == size).unwrap_or(false)
+}
+
+fn add_live_bytes(n: isize) {
+    let _ = LIVE_BYTES.try_with(|live| live.set(live.get().wrapping_add(n)));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if fails(layout.size()) {
+            return std::ptr::null_mut();
+        }
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        add_live_bytes(layout.size() as isize);
+        System.alloc(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if fails(new_size) {
+            return std::ptr::null_mut();
+        }
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        add_live_bytes(new_size as isize - layout.size() as isize);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        add_live_bytes(-(layout.size() as isize));
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+const FRAMES: usize = 6;
+const SIZE: u16 = 40;
+
+/// An animation of frames of the same size, with a local palette in each frame if `local_palettes`
+fn uniform_animation(local_palettes: bool) -> Vec<u8> {
+    let palette: Vec<u8> = (0..16u8).flat_map(|i| [i * 16, 255 - i * 16, i]).collect();
+    let mut encoder = Encoder::new(Vec::new(), SIZE, SIZE, &palette).unwrap();
+    encoder.set_repeat(Repeat::Infinite).unwrap();
+    for n in 0..FRAMES {
+        let mut frame = Frame {
+            width: SIZE,
+            height: SIZE,
+            delay: 10,
+            interlaced: n % 2 == 1,
+            buffer: (0..SIZE as usize * SIZE as usize).map(|i| ((i * 7 + n) % 16) as u8).collect(),
+            ..Frame::default()
+        };
+        if local_palettes {
+            frame.palette = Some(palette.iter().rev().copied().collect());
+        }
+        encoder.write_frame(&frame).unwrap();
+    }
+    encoder.into_inner().unwrap()
+}
+
+fn read_info(data: &[u8], color_output: ColorOutput) -> Decoder<&[u8]> {
+    let mut options = DecodeOptions::new();
+    options.set_color_output(color_output);
+    options.read_info(data).unwrap()
+}
+
+#[test]
+fn steady_state_decoding_does_not_allocate() {
+    for local_palettes in [false, true] {
+        let data = uniform_animation(local_palettes);
+
+        for color_output in [ColorOutput::Indexed, ColorOutput::RGBA, ColorOutput::RGB] {
+            let mut decoder = read_info(&data, color_output);
+            decoder.read_next_frame().unwrap().unwrap();
+            let n = allocations(|| {
+                for _ in 1..FRAMES {
+                    decoder.read_next_frame().unwrap().unwrap();
+                }
+            });
+            assert_eq!(n, 0, "read_next_frame, {color_output:?}, local palettes: {local_palettes}");
+        }
+
+        let mut decoder = read_info(&data, ColorOutput::Indexed);
+        let mut buf = vec![0; SIZE as usize * SIZE as usize];
+        decoder.next_frame_info().unwrap().unwrap();
+        decoder.read_into_buffer(&mut buf).unwrap();
+        let n = allocations(|| {
+            for _ in 1..FRAMES {
+                decoder.next_frame_info().unwrap().unwrap();
+                decoder.read_into_buffer(&mut buf).unwrap();
+            }
+        });
+        assert_eq!(n, 0, "read_into_buffer, local palettes: {local_palettes}");
+
+        let mut decoder = read_info(&data, ColorOutput::Indexed);
+        decoder.next_frame_info().unwrap().unwrap();
+        decoder.read_rows(|_, _| Ok::<_, gif::DecodingError>(())).unwrap();
+        let n = allocations(|| {
+            for _ in 1..FRAMES {
+                decoder.next_frame_info().unwrap().unwrap();
+                decoder.read_rows(|_, _| Ok::<_, gif::DecodingError>(())).unwrap();
+            }
+        });
+        assert_eq!(n, 0, "read_rows, local palettes: {local_palettes}");
+    }
+}
+
+#[test]
+fn long_comment_is_released() {
+    let palette: Vec<u8> = (0..16u8).flat_map(|i| [i * 16, 255 - i * 16, i]).collect();
+    let mut encoder = Encoder::new(Vec::new(), SIZE, SIZE, &palette).unwrap();
+    encoder.write_extension_blocks(AnyExtension(Extension::Comment as u8), [&vec![b'x'; 5 << 20][..]]).unwrap();
+    for n in 0..FRAMES {
+        let buffer = (0..SIZE as usize * SIZE as usize).map(|i| ((i + n) % 16) as u8).collect();
+        encoder.write_frame(&Frame { width: SIZE, height: SIZE, buffer, ..Frame::default() }).unwrap();
+    }
+    let data = encoder.into_inner().unwrap();
+
+    for keep in [true, false] {
+        let live = || LIVE_BYTES.with(Cell::get);
+        let before = live();
+        let mut options = DecodeOptions::new();
+        options.keep_extension_data(keep);
+        let mut decoder = options.read_info(&data[..]).unwrap();
+        while decoder.read_next_frame().unwrap().is_some() {}
+        let retained = live() - before;
+        assert!(retained < 1 << 20, "{retained} bytes kept, keep_extension_data: {keep}");
+    }
+}
+
+#[test]
+fn failed_palette_allocation_is_out_of_memory() {
+    // Palettes are at most 768 bytes, so no declared size is too large for the system. Instead,
+    // the allocator refuses allocations of exactly that size.
+    const PALETTE_LEN: usize = 256 * 3;
+    let palette = vec![0; PALETTE_LEN];
+    let small_palette = [0, 0, 0, 255, 255, 255];
+
+    let global = {
+        let mut encoder = Encoder::new(Vec::new(), 2, 2, &palette).unwrap();
+        encoder.write_frame(&Frame { width: 2, height: 2, buffer: vec![0; 4].into(), ..Frame::default() }).unwrap();
+        encoder.into_inner().unwrap()
+    };
+    let local = {
+        let mut encoder = Encoder::new(Vec::new(), 2, 2, &small_palette).unwrap();
+        encoder.write_frame(&Frame {
+            width: 2,
+            height: 2,
+            buffer: vec![0; 4].into(),
+            palette: Some(palette.clone()),
+            ..Frame::default()
+        }).unwrap();
+        encoder.into_inner().unwrap()
+    };
+
+    for data in [global, local] {
+        FAILING_SIZE.with(|failing| failing.set(PALETTE_LEN));
+        let result = DecodeOptions::new().read_info(&data[..]).and_then(|mut decoder| {
+            decoder.read_next_frame()?;
+            Ok(())
+        });
+        FAILING_SIZE.with(|failing| failing.set(0));
+        match result {
+            Err(err @ gif::DecodingError::OutOfMemory) => {
+                assert_eq!(err.to_string(), "out of memory");
+            },
+            other => panic!("{other:?}"),
+        }
+    }
+}
@@ -0,0 +1,126 @@
+#![cfg(feature = "futures-io")]
+
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use std::{fs, io};
+
+use gif::{AsyncDecoder, DecodeOptions};
+
+/// Returns `Pending` before every short read
+struct SlowReader {
+    data: Vec<u8>,
+    pos: usize,
+    ready: bool,
+}
+
+impl futures_io::AsyncRead for SlowReader {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        if !self.ready {
+            self.ready = true;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        self.ready = false;
+        let len = buf.len().min(100).min(self.data.len() - self.pos);
+        buf[..len].copy_from_slice(&self.data[self.pos..self.pos + len]);
+        self.pos += len;
+        Poll::Ready(Ok(len))
+    }
+}
+
+fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = pin!(fut);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+fn sync_frames(data: &[u8]) -> Vec<Vec<u8>> {
+    let mut decoder = DecodeOptions::new().read_info(data).unwrap();
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        frames.push(frame.buffer.to_vec());
+    }
+    frames
+}
+
+#[test]
+fn async_decode_matches_sync() {
+    let data = fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/samples/moon_impact.gif")).unwrap();
+    let expected = sync_frames(&data);
+
+    let reader = SlowReader { data, pos: 0, ready: false };
+    let mut decoder = block_on(AsyncDecoder::from_futures_io(reader, DecodeOptions::new())).unwrap();
+    let mut frames = Vec::new();
+    while let Some(frame) = block_on(decoder.read_next_frame()).unwrap() {
+        frames.push(frame.buffer.to_vec());
+    }
+    assert_eq!(frames, expected);
+}
+
+#[test]
+fn async_decode_is_cancellation_safe() {
+    let data = fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/samples/moon_impact.gif")).unwrap();
+    let expected = sync_frames(&data);
+
+    let reader = SlowReader { data, pos: 0, ready: false };
+    let mut decoder = block_on(AsyncDecoder::from_futures_io(reader, DecodeOptions::new())).unwrap();
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut frames = Vec::new();
+    loop {
+        // Every future is polled once, and dropped if it has to wait for the reader
+        let mut fut = pin!(decoder.read_next_frame());
+        match fut.as_mut().poll(&mut cx) {
+            Poll::Ready(Ok(Some(frame))) => frames.push(frame.buffer.to_vec()),
+            Poll::Ready(Ok(None)) => break,
+            Poll::Ready(Err(err)) => panic!("{err}"),
+            Poll::Pending => continue,
+        }
+    }
+    assert_eq!(frames, expected);
+}
+
+/// Returns `Pending` after `limit` bytes, and panics if it's read after `closed` has been set
+struct ClosingReader<'a> {
+    data: &'a [u8],
+    limit: usize,
+    closed: Rc<Cell<bool>>,
+}
+
+impl futures_io::AsyncRead for ClosingReader<'_> {
+    fn poll_read(mut self: Pin<&mut Self>, _: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        assert!(!self.closed.get(), "read after the decoder has been dropped");
+        let len = buf.len().min(self.limit);
+        if len == 0 {
+            return Poll::Pending;
+        }
+        buf[..len].copy_from_slice(&self.data[..len]);
+        self.data = &self.data[len..];
+        self.limit -= len;
+        Poll::Ready(Ok(len))
+    }
+}
+
+#[test]
+fn dropping_decoder_within_header_does_not_read() {
+    let data = fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/samples/anim-gr.gif")).unwrap();
+    assert_ne!(data[10] & 0x80, 0, "the file has a global palette");
+    let palette_end = 13 + 3 * (2 << (data[10] & 7));
+    let mut cx = Context::from_waker(Waker::noop());
+    // Every point in the header and the global palette. Unlike `read_info`, the future of the
+    // async decoder can be dropped there, with the decoder it has started.
+    for limit in 0..palette_end {
+        let closed = Rc::new(Cell::new(false));
+        let reader = ClosingReader { data: &data, limit, closed: closed.clone() };
+        let mut fut = pin!(AsyncDecoder::from_futures_io(reader, DecodeOptions::new()));
+        assert!(fut.as_mut().poll(&mut cx).is_pending(), "{limit}");
+        closed.set(true);
+        // The future is dropped here
+    }
+}
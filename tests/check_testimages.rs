@@ -9,7 +9,7 @@ use std::io::prelude::*;
 
 const BASE_PATH: [&str; 2] = [".", "tests"];
 
-fn process_images<F>(func: F)
+fn process_images<F>(reference: &str, func: F)
 where F: Fn(PathBuf) -> Result<u32, gif::DecodingError> {
     let base: PathBuf = BASE_PATH.iter().collect();
     let test_suites = &["samples"];
@@ -36,7 +36,7 @@ where F: Fn(PathBuf) -> Result<u32, gif::DecodingError> {
         }
     }
     let mut path = base;
-    path.push("results.txt");
+    path.push(reference);
     let mut ref_results = HashMap::new();
     let mut failures = 0;
     for line in BufReader::new(File::open(path).unwrap()).lines() {
@@ -59,7 +59,7 @@ where F: Fn(PathBuf) -> Result<u32, gif::DecodingError> {
 
 #[test]
 fn render_images() {
-    process_images(|path| {
+    process_images("results.txt", |path| {
         let mut decoder = gif::DecodeOptions::new();
         decoder.set_color_output(gif::ColorOutput::RGBA);
         let file = File::open(path)?;
@@ -77,6 +77,60 @@ fn render_images() {
     });
 }
 
+/// Checksum of the low-level events, with the whole file given to the decoder at once and byte by
+/// byte, when decoding pixels, copying LZW data and discarding the frame data
+#[test]
+fn event_sequences() {
+    use gif::streaming_decoder::{Decoded, OutputBuffer, StreamingDecoder};
+
+    process_images("events.txt", |path| {
+        let data = std::fs::read(path)?;
+        let mut crc = Crc32::new();
+        for (skip_frame_decoding, discard) in [(false, false), (true, false), (false, true)] {
+            for chunk_len in [data.len(), 1] {
+                let mut options = gif::DecodeOptions::new();
+                options.skip_frame_decoding(skip_frame_decoding);
+                let mut decoder = StreamingDecoder::with_options(&options);
+                let mut pixels = [0; 1024];
+                let mut lzw = Vec::new();
+                let mut pos = 0;
+                loop {
+                    let end = data.len().min(pos + chunk_len);
+                    let mut out = if discard {
+                        OutputBuffer::None
+                    } else if skip_frame_decoding {
+                        OutputBuffer::Vec(&mut lzw)
+                    } else {
+                        OutputBuffer::Slice(&mut pixels)
+                    };
+                    let (consumed, decoded) = decoder.update(&data[pos..end], &mut out)?;
+                    pos += consumed;
+                    let event = match decoded {
+                        // The palette is checked as it was when it was part of the event
+                        Decoded::GlobalPalette => format!("GlobalPalette({:?})", decoder.global_palette().unwrap_or_default()),
+                        ref decoded => format!("{decoded:?}"),
+                    };
+                    crc.update(format!("{consumed} {event}\n").as_bytes());
+                    match decoded {
+                        Decoded::PixelsDecoded(len) if !discard => crc.update(&pixels[..len.get()]),
+                        Decoded::FrameMetadata(_) => crc.update(format!("{:?}\n", decoder.current_frame().unwrap()).as_bytes()),
+                        Decoded::SubBlockFinished(_) | Decoded::BlockFinished(_) => {
+                            crc.update(format!("{:?}\n", decoder.last_ext()).as_bytes());
+                        },
+                        Decoded::DataEnd => {
+                            crc.update(&lzw);
+                            lzw.clear();
+                        },
+                        Decoded::Finished => break,
+                        Decoded::Nothing if pos == data.len() => decoder.finish()?,
+                        _ => {},
+                    }
+                }
+            }
+        }
+        Ok(crc.checksum())
+    });
+}
 
 const CRC_TABLE: [u32; 256] = [
     0x00000000, 0x77073096, 0xee0e612c, 0x990951ba, 0x076dc419,
@@ -1,6 +1,6 @@
 #![cfg(feature = "std")]
 
-use gif::{Decoder, DecodeOptions, DisposalMethod, Encoder, Frame};
+use gif::{Decoder, DecodeOptions, DisposalMethod, Encoder, Frame, FrameConsistency, SliceDecoder};
 use std::fs::File;
 
 #[test]
@@ -23,7 +23,7 @@ fn test_simple_indexed() {
 
 #[test]
 fn frame_consistency_is_configurable() {
-    let image = create_image_with_oob_frames();
+    let image = gif::synth::pathological::frame_out_of_bounds();
 
     {
         let options = DecodeOptions::new();
@@ -52,31 +52,63 @@ fn frame_consistency_is_configurable() {
     }
 }
 
-fn create_image_with_oob_frames() -> Vec<u8> {
-    let mut data = vec![];
-    let mut encoder = Encoder::new(&mut data, 2, 2, &[0, 0, 0]).unwrap();
+#[test]
+fn clamp_frames_to_screen() {
+    // A 5x4 screen, with a 4x3 frame at (3, 2), and an interlaced 6x5 frame at (0, 0)
+    let mut options = DecodeOptions::new();
+    options.set_frame_consistency(FrameConsistency::Clamp);
+    let mut decoder = options.clone().read_info(File::open("tests/malformed/out-of-bounds.gif").unwrap()).unwrap();
 
-    let mut frame = Frame {
-        delay: 1,
-        dispose: DisposalMethod::Any,
-        transparent: None,
-        needs_user_input: false,
-        top: 0,
-        left: 0,
-        width: 2,
-        height: 2,
-        interlaced: false,
-        palette: None,
-        buffer: vec![0, 0, 0, 0].into(),
-    };
+    let frame = decoder.read_next_frame().unwrap().unwrap();
+    assert_eq!((frame.left, frame.top, frame.width, frame.height), (3, 2, 2, 2));
+    assert_eq!(&*frame.buffer, [
+        0, 1,
+        1, 2,
+    ]);
+
+    let frame = decoder.read_next_frame().unwrap().unwrap();
+    assert_eq!((frame.left, frame.top, frame.width, frame.height), (0, 0, 5, 4));
+    assert_eq!(&*frame.buffer, [
+        0, 1, 2, 3, 0,
+        2, 3, 0, 1, 2,
+        0, 1, 2, 3, 0,
+        2, 3, 0, 1, 2,
+    ]);
+    assert!(decoder.read_next_frame().unwrap().is_none());
+
+    // The data of a clamped frame doesn't fit its size anymore
+    let mut decoder = options.read_info(File::open("tests/malformed/out-of-bounds.gif").unwrap()).unwrap();
+    assert!(decoder.read_lzw_frame().is_err());
+}
 
-    encoder.write_frame(&frame).unwrap();
-    frame.top = 1;
-    frame.left = 1;
-    encoder.write_frame(&frame).unwrap();
+#[test]
+fn frame_consistency_matches_validate_frame_in_screen() {
+    use gif::{validate_frame_in_screen, Rect};
 
-    drop(encoder);
-    data
+    let screen = (3, 2);
+    for (left, top, width, height) in (0..5).flat_map(|left| (0..4).map(move |top| (left, top)))
+        .flat_map(|(left, top)| (1..5).flat_map(move |width| (1..4).map(move |height| (left, top, width, height)))) {
+        let rect = Rect { left, top, width, height };
+        let mut data = Vec::new();
+        {
+            let mut encoder = Encoder::new(&mut data, screen.0, screen.1, &[0; 6]).unwrap();
+            let frame = Frame::from_indexed_pixels_at(left, top, width, height, vec![0; usize::from(width * height)], None);
+            encoder.write_frame(&frame).unwrap();
+        }
+        for policy in [FrameConsistency::Ignore, FrameConsistency::Error, FrameConsistency::Clamp] {
+            let mut options = DecodeOptions::new();
+            options.set_frame_consistency(policy);
+            let mut decoder = options.read_info(&data[..]).unwrap();
+            match (validate_frame_in_screen(rect, screen, policy), decoder.read_next_frame()) {
+                (Ok(shown), Ok(Some(frame))) => {
+                    assert_eq!(Rect::from(frame), shown, "{policy:?}");
+                    assert_eq!(frame.buffer.len(), usize::from(shown.width) * usize::from(shown.height));
+                },
+                (Err(_), Err(_)) => {},
+                (expected, decoded) => panic!("{rect:?} {policy:?}: {expected:?} {:?}", decoded.map(|frame| frame.map(Rect::from))),
+            }
+        }
+    }
 }
 
 #[test]
@@ -152,6 +184,44 @@ fn rebuild_without_reencode(image: &[u8]) {
     assert_eq!(0, rebuilt.into_inner().buffer().len());
 }
 
+#[test]
+fn read_lzw_frame_matches_skip_frame_decoding() {
+    let image: &[u8] = include_bytes!("samples/gifplayer-muybridge.gif");
+    let mut options = DecodeOptions::new();
+    options.skip_frame_decoding(true);
+    let frames: Vec<_> = options.clone().read_info(image).unwrap()
+        .into_iter().map(Result::unwrap).collect();
+
+    // The frame may or may not have been started with `next_frame_info`
+    let mut decoder = Decoder::new(image).unwrap();
+    for (i, frame) in frames.iter().enumerate() {
+        if i % 2 == 0 {
+            decoder.next_frame_info().unwrap().unwrap();
+        }
+        let (min_code_size, data) = decoder.read_lzw_frame().unwrap().unwrap();
+        assert_eq!(min_code_size, frame.buffer[0]);
+        assert_eq!(data, frame.buffer[1..]);
+    }
+    assert!(decoder.read_lzw_frame().unwrap().is_none());
+
+    options.keep_lzw_sub_blocks(true);
+    let mut decoder = options.read_info(image).unwrap();
+    for frame in &frames {
+        let (_, data) = decoder.read_lzw_frame().unwrap().unwrap();
+        let mut lzw = Vec::new();
+        let mut sub_blocks = &data[..];
+        while let Some((&len, rest)) = sub_blocks.split_first() {
+            sub_blocks = &rest[usize::from(len)..];
+            lzw.extend_from_slice(&rest[..usize::from(len)]);
+            if len == 0 {
+                break;
+            }
+        }
+        assert!(sub_blocks.is_empty());
+        assert_eq!(lzw, frame.buffer[1..]);
+    }
+}
+
 #[test]
 fn check_skip_frame_data() {
     let image: &[u8] = include_bytes!("samples/moon_impact.gif");
@@ -186,3 +256,2003 @@ fn check_skip_frame_data_decode_frame_error() {
     }
     assert!(skipping_decoder.read_next_frame().unwrap().is_none());
 }
+
+#[test]
+fn reader_accessors() {
+    let image: &[u8] = include_bytes!("samples/sample_1.gif");
+    let mut decoder = Decoder::new(image).unwrap();
+    // The whole file fits in the read-ahead buffer
+    assert!(decoder.get_ref().is_empty());
+    assert!(decoder.get_mut().is_empty());
+    assert!(decoder.read_next_frame().unwrap().is_some());
+}
+
+#[test]
+fn edit_pending_frame_metadata() {
+    let image: &[u8] = include_bytes!("samples/moon_impact.gif");
+
+    let mut options = DecodeOptions::new();
+    options.skip_frame_decoding(true);
+    let mut decoder = options.read_info(image).unwrap();
+    let mut encoder = Encoder::new(Vec::new(), decoder.width(), decoder.height(), decoder.global_palette().unwrap_or_default()).unwrap();
+
+    assert!(decoder.pending_frame_mut().is_none());
+    while decoder.next_frame_info().unwrap().is_some() {
+        let mut metadata = decoder.pending_frame_mut().unwrap();
+        metadata.set_delay(metadata.delay() * 2);
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        encoder.write_lzw_pre_encoded_frame(frame).unwrap();
+        assert!(decoder.pending_frame_mut().is_none());
+    }
+    let gif = encoder.into_inner().unwrap();
+
+    let orig = Decoder::new(image).unwrap().into_iter();
+    let retimed = Decoder::new(&gif[..]).unwrap().into_iter();
+    let mut num_frames = 0;
+    for (orig, retimed) in orig.zip(retimed) {
+        let (orig, retimed) = (orig.unwrap(), retimed.unwrap());
+        assert_eq!(orig.delay * 2, retimed.delay);
+        assert_eq!(orig.dispose, retimed.dispose);
+        assert_eq!(orig.buffer, retimed.buffer);
+        num_frames += 1;
+    }
+    assert_eq!(num_frames, 14);
+}
+
+#[test]
+fn rewind_decodes_frames_again() {
+    let image: &[u8] = include_bytes!("samples/moon_impact.gif");
+    let mut decoder = Decoder::new(std::io::Cursor::new(image)).unwrap();
+
+    let mut first_pass = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        first_pass.push(frame.clone());
+    }
+    assert_eq!(first_pass.len(), 14);
+
+    for _ in 0..2 {
+        decoder.rewind().unwrap();
+        let mut num_frames = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            let expected = &first_pass[num_frames];
+            assert_eq!(frame.delay, expected.delay);
+            assert_eq!(frame.palette, expected.palette);
+            assert_eq!(frame.buffer, expected.buffer);
+            num_frames += 1;
+        }
+        assert_eq!(num_frames, first_pass.len());
+    }
+
+    // Rewinding in the middle of a frame
+    decoder.rewind().unwrap();
+    decoder.read_next_frame().unwrap();
+    decoder.next_frame_info().unwrap();
+    decoder.rewind().unwrap();
+    assert_eq!(decoder.read_next_frame().unwrap().unwrap().buffer, first_pass[0].buffer);
+}
+
+#[test]
+fn seek_to_frame_with_index() {
+    use gif::{DecodingError, UsageError};
+
+    let image: &[u8] = include_bytes!("samples/gifplayer-muybridge.gif");
+    let frames: Vec<_> = Decoder::new(image).unwrap().into_iter().map(Result::unwrap).collect();
+
+    let mut decoder = Decoder::new(std::io::Cursor::new(image)).unwrap();
+    assert!(decoder.frame_index().is_none());
+    assert!(matches!(decoder.seek_to_frame(0), Err(DecodingError::Usage(UsageError::NoFrameIndex))));
+
+    let index = decoder.build_frame_index().unwrap();
+    assert_eq!(index.len(), frames.len());
+    assert_eq!(index[0].keyframe, 0);
+    for (entry, frame) in index.iter().zip(&frames) {
+        assert_eq!(entry.delay, frame.delay);
+        assert_eq!(entry.dispose, frame.dispose);
+    }
+
+    // The decoder is rewound after building the index
+    assert_eq!(decoder.read_next_frame().unwrap().unwrap().buffer, frames[0].buffer);
+
+    for &n in &[37, 5, frames.len() - 1, 0, 37] {
+        decoder.seek_to_frame(n).unwrap();
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        assert_eq!(frame.buffer, frames[n].buffer);
+        assert_eq!(frame.delay, frames[n].delay);
+    }
+    assert!(decoder.read_next_frame().unwrap().is_some());
+    let out_of_range = UsageError::FrameOutOfRange { frame: frames.len(), frames: frames.len() };
+    assert!(matches!(decoder.seek_to_frame(frames.len()), Err(DecodingError::Usage(err)) if err == out_of_range));
+}
+
+#[test]
+fn keyframes_skip_frames_disposed_to_previous() {
+    let mut data = Vec::new();
+    {
+        let mut encoder = Encoder::new(&mut data, 4, 4, &[0, 0, 0, 255, 255, 255]).unwrap();
+        let full = |dispose| Frame { width: 4, height: 4, dispose, buffer: vec![1; 16].into(), ..Frame::default() };
+        encoder.write_frame(&full(DisposalMethod::Keep)).unwrap();
+        // Covers the screen, but the canvas goes back to the first frame after it
+        encoder.write_frame(&full(DisposalMethod::Previous)).unwrap();
+        encoder.write_frame(&Frame { left: 1, top: 1, width: 2, height: 2, buffer: vec![0; 4].into(), ..Frame::default() }).unwrap();
+        encoder.write_frame(&full(DisposalMethod::Background)).unwrap();
+        encoder.write_frame(&Frame { width: 1, height: 1, buffer: vec![0; 1].into(), ..Frame::default() }).unwrap();
+    }
+
+    let mut decoder = Decoder::new(std::io::Cursor::new(data)).unwrap();
+    let index = decoder.build_frame_index().unwrap();
+    let keyframes: Vec<_> = index.iter().map(|entry| entry.keyframe).collect();
+    assert_eq!(keyframes, [0, 1, 0, 3, 3]);
+}
+
+#[test]
+fn prebuilt_index_matches_decoding() {
+    let image: &[u8] = include_bytes!("samples/gifplayer-muybridge.gif");
+    let frames: Vec<_> = Decoder::new(image).unwrap().into_iter().map(Result::unwrap).collect();
+
+    let index = gif::Index::build(image).unwrap();
+    #[cfg(feature = "serde")]
+    let index: gif::Index = {
+        let json = serde_json::to_string(&index).unwrap();
+        let reloaded = serde_json::from_str(&json).unwrap();
+        assert_eq!(reloaded, index);
+        reloaded
+    };
+    assert!(index.validate_against(image));
+    assert!(!index.validate_against(&image[..image.len() - 1]));
+    assert!(!index.validate_against(include_bytes!("samples/moon_impact.gif")));
+
+    let decoder = Decoder::new(image).unwrap();
+    assert_eq!((index.width(), index.height()), (decoder.width(), decoder.height()));
+    assert_eq!(index.repeat(), decoder.repeat());
+    assert_eq!(index.frame_count(), frames.len());
+    let delay: u64 = frames.iter().map(|frame| u64::from(frame.delay)).sum();
+    assert_eq!(index.duration(), std::time::Duration::from_millis(delay * 10));
+    for (i, frame) in frames.iter().enumerate() {
+        let meta = index.frame_meta(i).unwrap();
+        assert_eq!((meta.left, meta.top, meta.width, meta.height), (frame.left, frame.top, frame.width, frame.height));
+        assert_eq!(meta.delay, frame.delay);
+        // Each frame's bytes are a GIF of their own after the header and global palette
+        let range = index.frame_byte_range(i).unwrap();
+        let first_block = index.frame_byte_range(0).unwrap().start as usize;
+        let mut gif = image[..first_block].to_vec();
+        gif.extend_from_slice(&image[range.start as usize..range.end as usize]);
+        gif.push(0x3B);
+        let single = Decoder::new(&gif[..]).unwrap().into_iter().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].buffer, frame.buffer);
+    }
+    assert!(index.frame_meta(frames.len()).is_none());
+
+    // Seeking doesn't need to scan the file
+    let mut decoder = Decoder::new(std::io::Cursor::new(image)).unwrap();
+    decoder.set_frame_index(&index).unwrap();
+    assert_eq!(decoder.frame_index(), Some(index.entries()));
+    for &n in &[37, 5, frames.len() - 1, 0] {
+        decoder.seek_to_frame(n).unwrap();
+        assert_eq!(decoder.read_next_frame().unwrap().unwrap().buffer, frames[n].buffer);
+    }
+
+    let mut decoder = Decoder::new(std::io::Cursor::new(include_bytes!("samples/moon_impact.gif"))).unwrap();
+    assert!(matches!(decoder.set_frame_index(&index), Err(gif::DecodingError::Usage(gif::UsageError::FrameIndexMismatch))));
+}
+
+#[test]
+fn indexed_frames_in_both_directions() {
+    let image: &[u8] = include_bytes!("samples/gifplayer-muybridge.gif");
+    let mut options = DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let frames: Vec<_> = options.clone().read_info(image).unwrap().into_iter().map(Result::unwrap).collect();
+    let index = gif::Index::build(image).unwrap();
+    let same = |a: &Frame<'_>, b: &Frame<'_>| {
+        assert_eq!((a.left, a.top, a.width, a.height, a.delay), (b.left, b.top, b.width, b.height, b.delay));
+        assert_eq!(a.buffer, b.buffer);
+    };
+
+    let indexed = index.frames(image, &options);
+    assert_eq!(indexed.len(), frames.len());
+    let forward: Vec<_> = indexed.map(Result::unwrap).collect();
+    assert_eq!(forward.len(), frames.len());
+    forward.iter().zip(&frames).for_each(|(a, b)| same(a, b));
+
+    let backward: Vec<_> = index.frames(image, &options).rev().map(Result::unwrap).collect();
+    assert_eq!(backward.len(), frames.len());
+    backward.iter().zip(frames.iter().rev()).for_each(|(a, b)| same(a, b));
+
+    // Taking frames from both ends meets in the middle
+    let mut indexed = index.frames(image, &options);
+    let (mut front, mut back) = (0, frames.len());
+    while let Some(frame) = indexed.next() {
+        same(&frame.unwrap(), &frames[front]);
+        front += 1;
+        assert_eq!(indexed.len(), back - front);
+        if let Some(frame) = indexed.next_back() {
+            back -= 1;
+            same(&frame.unwrap(), &frames[back]);
+        }
+    }
+    assert_eq!(front, back);
+    assert!(indexed.next_back().is_none());
+
+    // Every frame of another file is an error
+    let other: &[u8] = include_bytes!("samples/moon_impact.gif");
+    let mut indexed = index.frames(other, &options);
+    assert_eq!(indexed.len(), frames.len());
+    assert!(indexed.next_back().unwrap().is_err());
+    assert!(indexed.next().unwrap().is_err());
+}
+
+#[test]
+fn continue_past_trailer_is_configurable() {
+    fn encode(frames: u8) -> Vec<u8> {
+        let mut encoder = Encoder::new(Vec::new(), 2, 2, &[0, 0, 0, 0xFF, 0xFF, 0xFF]).unwrap();
+        for i in 0..frames {
+            encoder.write_frame(&Frame::from_indexed_pixels(2, 2, vec![i & 1; 4], None)).unwrap();
+        }
+        encoder.into_inner().unwrap()
+    }
+    // Screen descriptor and a global palette of 2 colors
+    const HEADER_LEN: usize = 13 + 2 * 3;
+
+    let count_frames = |image: &[u8], continue_past_trailer: bool| {
+        let mut options = DecodeOptions::new();
+        options.continue_past_trailer(continue_past_trailer);
+        let mut decoder = options.clone().read_info(image).unwrap();
+        let mut frames = 0;
+        while decoder.read_next_frame().unwrap().is_some() {
+            frames += 1;
+        }
+        // The same, reading a byte at a time
+        let mut decoder = options.read_info_buffered(std::io::BufReader::with_capacity(1, image)).unwrap();
+        let mut small_reads = 0;
+        while decoder.read_next_frame().unwrap().is_some() {
+            small_reads += 1;
+        }
+        assert_eq!(frames, small_reads);
+        (frames, decoder.skipped_trailers())
+    };
+
+    let mut image = encode(2);
+    assert_eq!(count_frames(&image, true), (2, 0));
+
+    image.extend_from_slice(&encode(3)[HEADER_LEN..]);
+    assert_eq!(count_frames(&image, false), (2, 0));
+    assert_eq!(count_frames(&image, true), (5, 1));
+
+    // A few bytes of garbage after the trailer
+    let mut image = encode(2);
+    image.extend_from_slice(&[0, 0, 0]);
+    image.extend_from_slice(&encode(1)[HEADER_LEN..]);
+    image.extend_from_slice(&[0; 100]);
+    assert_eq!(count_frames(&image, false), (2, 0));
+    assert_eq!(count_frames(&image, true), (3, 1));
+}
+
+#[test]
+fn pixels_decoded_is_independent_of_color_output() {
+    use gif::streaming_decoder::{Block, Decoded, OutputBuffer, StreamingDecoder};
+
+    let data = std::fs::read("tests/samples/moon_impact.gif").unwrap();
+
+    let mut decoder = StreamingDecoder::new();
+    let mut scratch = vec![0; 1 << 16];
+    let mut input = &data[..];
+    let mut frame_pixels = Vec::new();
+    loop {
+        let (consumed, decoded) = decoder.update(input, &mut OutputBuffer::Slice(&mut scratch)).unwrap();
+        input = &input[consumed..];
+        match decoded {
+            Decoded::FrameMetadata(_) => frame_pixels.push(0),
+            Decoded::PixelsDecoded(pixels) => *frame_pixels.last_mut().unwrap() += pixels.get(),
+            Decoded::BlockStart(Block::Trailer) => break,
+            _ => {},
+        }
+    }
+    assert_eq!(frame_pixels.len(), 14);
+
+    for (color_output, channels) in [(gif::ColorOutput::Indexed, 1), (gif::ColorOutput::RGBA, 4), (gif::ColorOutput::RGB, 3)] {
+        let mut options = DecodeOptions::new();
+        options.set_color_output(color_output);
+        let mut decoder = options.read_info(File::open("tests/samples/moon_impact.gif").unwrap()).unwrap();
+        let mut pixels = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!(frame.buffer.len(), usize::from(frame.width) * usize::from(frame.height) * channels);
+            pixels.push(frame.buffer.len() / channels);
+        }
+        assert_eq!(pixels, frame_pixels);
+    }
+}
+
+#[test]
+fn take_current_frame_keeps_decoding() {
+    use gif::streaming_decoder::{Block, Decoded, OutputBuffer, StreamingDecoder};
+
+    let palette: Vec<u8> = (0..12).collect();
+    let mut encoder = Encoder::new(vec![], 2, 2, &[]).unwrap();
+    for n in 0..3u8 {
+        let frame = Frame::from_palette_pixels(2, 2, vec![n, 1, 2, 3], palette.iter().map(|c| c + n).collect::<Vec<_>>(), None);
+        encoder.write_frame(&frame).unwrap();
+    }
+    let data = encoder.into_inner().unwrap();
+
+    let mut decoder = StreamingDecoder::new();
+    let mut input = &data[..];
+    let mut frames = Vec::new();
+    let mut pixels = [0; 64];
+    let mut decoded = 0;
+    loop {
+        let (consumed, event) = decoder.update(input, &mut OutputBuffer::Slice(&mut pixels[decoded..])).unwrap();
+        input = &input[consumed..];
+        match event {
+            Decoded::FrameMetadata(_) => {
+                let frame = decoder.take_current_frame().unwrap();
+                let current = decoder.current_frame().unwrap();
+                assert_eq!((current.width, current.height), (2, 2));
+                assert_eq!(current.palette, None);
+                frames.push(frame);
+                decoded = 0;
+            },
+            Decoded::PixelsDecoded(n) => decoded += n.get(),
+            Decoded::DataEnd => frames.last_mut().unwrap().buffer = pixels[..decoded].to_vec().into(),
+            Decoded::BlockStart(Block::Trailer) => break,
+            _ => {},
+        }
+    }
+
+    let mut expected = Decoder::new(&data[..]).unwrap();
+    for frame in &frames {
+        let expected = expected.read_next_frame().unwrap().unwrap();
+        assert_eq!(frame.palette, expected.palette);
+        assert_eq!(frame.buffer, expected.buffer);
+    }
+    assert_eq!(frames.len(), 3);
+    assert!(decoder.take_current_frame().is_none());
+}
+
+#[test]
+fn extension_data_is_only_kept_on_request() {
+    use gif::streaming_decoder::{Decoded, OutputBuffer, StreamingDecoder};
+
+    let mut encoder = Encoder::new(vec![], 2, 2, &[0, 0, 0, 255, 255, 255]).unwrap();
+    encoder.set_repeat(gif::Repeat::Finite(3)).unwrap();
+    encoder.write_extension_blocks(gif::AnyExtension(0xFE), [&[b'x'; 100_000][..]]).unwrap();
+    encoder.write_frame(&Frame { delay: 7, ..Frame::from_indexed_pixels(2, 2, vec![0, 1, 1, 0], None) }).unwrap();
+    encoder.write_extension_blocks(gif::AnyExtension(0xFE), [&[b'y'; 100_000][..]]).unwrap();
+    let data = encoder.into_inner().unwrap();
+
+    for keep in [true, false] {
+        let mut options = DecodeOptions::new();
+        options.keep_extension_data(keep);
+        let mut decoder = StreamingDecoder::with_options(&options);
+        let mut input = &data[..];
+        let mut comment_len = None;
+        loop {
+            let (consumed, decoded) = decoder.update(input, &mut OutputBuffer::None).unwrap();
+            input = &input[consumed..];
+            match decoded {
+                Decoded::BlockFinished(gif::AnyExtension(0xFE)) => {
+                    comment_len = Some(decoder.last_ext().1.len());
+                    assert_eq!(decoder.last_extension().is_some(), keep);
+                },
+                Decoded::FrameMetadata(_) => assert_eq!(decoder.current_frame().unwrap().delay, 7),
+                // A long comment is dropped once decoding goes on after it, whichever block follows
+                Decoded::BlockStart(_) => assert!(decoder.last_ext().1.len() < 256),
+                Decoded::Finished => break,
+                _ => {},
+            }
+        }
+        // The length of the first sub-block, and the data if it's kept
+        let len = 1 + 100_000;
+        assert_eq!(comment_len, Some(if keep { len } else { 1 }));
+        assert_eq!(decoder.peak_extension_len() >= len, keep);
+
+        let mut decoder = options.read_info(&data[..]).unwrap();
+        assert_eq!(decoder.repeat(), gif::Repeat::Finite(3));
+        assert_eq!(decoder.read_next_frame().unwrap().unwrap().delay, 7);
+    }
+}
+
+#[test]
+fn rgb_output_is_rgba_without_alpha() {
+    let matte = [0x12, 0x34, 0x56];
+    // Transparent pixels get the matte color instead of their palette color
+    let without_alpha = |rgba: &[u8]| -> Vec<u8> {
+        rgba.chunks(4).flat_map(|rgba| if rgba[3] == 0 { matte } else { [rgba[0], rgba[1], rgba[2]] }).collect()
+    };
+    for path in ["tests/samples/interlaced.gif", "tests/samples/moon_impact.gif"] {
+        let data = std::fs::read(path).unwrap();
+        let mut options = DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut rgba = options.clone().read_info(&data[..]).unwrap();
+        options.set_color_output(gif::ColorOutput::RGB);
+        options.set_matte_color(Some(matte));
+        let mut rgb = options.clone().read_info(&data[..]).unwrap();
+        let mut slice_rgb = options.clone().read_slice(&data).unwrap();
+        let mut filled_rgb = options.read_info(&data[..]).unwrap();
+
+        while let Some(frame) = rgba.read_next_frame().unwrap() {
+            let expected = without_alpha(&frame.buffer);
+            assert_eq!(*rgb.read_next_frame().unwrap().unwrap().buffer, *expected);
+            assert_eq!(*slice_rgb.read_next_frame().unwrap().unwrap().buffer, *expected);
+
+            // Buffers of a few pixels at a time, which split the rows
+            let interlaced = filled_rgb.next_frame_info().unwrap().unwrap().interlaced;
+            assert_eq!(filled_rgb.buffer_size(), expected.len());
+            let mut filled = vec![0; expected.len()];
+            for chunk in filled.chunks_mut(3 * 7) {
+                assert!(filled_rgb.fill_buffer(chunk).unwrap());
+            }
+            if !interlaced {
+                assert_eq!(filled, expected);
+            }
+        }
+        assert!(rgb.read_next_frame().unwrap().is_none());
+    }
+}
+
+#[test]
+fn rgb_matte_defaults_to_background_color() {
+    let palette = [0, 0, 0, 0xFF, 0, 0, 0, 0xFF, 0, 0, 0, 0xFF];
+    let mut data = Vec::new();
+    {
+        let mut encoder = Encoder::new(&mut data, 2, 2, &palette).unwrap();
+        encoder.write_frame(&Frame::from_indexed_pixels(2, 2, vec![0, 1, 2, 3], Some(1))).unwrap();
+    }
+    data[11] = 2;
+
+    let mut options = DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGB);
+    let mut decoder = options.clone().read_info(&data[..]).unwrap();
+    assert_eq!(*decoder.read_next_frame().unwrap().unwrap().buffer, [0, 0, 0, 0, 0xFF, 0, 0, 0xFF, 0, 0, 0, 0xFF]);
+    options.set_matte_color(Some([1, 2, 3]));
+    let mut decoder = options.read_info(&data[..]).unwrap();
+    assert_eq!(*decoder.read_next_frame().unwrap().unwrap().buffer, [0, 0, 0, 1, 2, 3, 0, 0xFF, 0, 0, 0, 0xFF]);
+}
+
+#[test]
+fn descriptor_bytes_are_copied_verbatim() {
+    use gif::streaming_decoder::{Block, Decoded, OutputBuffer, StreamingDecoder};
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    fn hash(regions: &[&[u8]]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        regions.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    for path in ["tests/samples/moon_impact.gif", "tests/samples/interlaced.gif"] {
+        let data = std::fs::read(path).unwrap();
+
+        // One byte at a time, so that every descriptor is split across calls
+        let mut decoder = StreamingDecoder::new();
+        let mut input = &data[..];
+        let mut header = None;
+        let mut descriptors = Vec::new();
+        loop {
+            let (consumed, decoded) = decoder.update(&input[..1], &mut OutputBuffer::None).unwrap();
+            input = &input[consumed..];
+            match decoded {
+                Decoded::HeaderEnd => header = Some(*decoder.descriptor_bytes().unwrap()),
+                Decoded::FrameMetadata(_) => {
+                    let (offset, bytes) = decoder.image_descriptor_bytes().unwrap();
+                    descriptors.push((offset as usize, *bytes));
+                },
+                Decoded::BlockStart(Block::Trailer) => break,
+                _ => {},
+            }
+        }
+        let header = header.unwrap();
+        assert!(!descriptors.is_empty());
+
+        let captured: Vec<&[u8]> = [&header[..]].into_iter().chain(descriptors.iter().map(|(_, bytes)| &bytes[..])).collect();
+        let sliced: Vec<&[u8]> = [&data[..13]].into_iter().chain(descriptors.iter().map(|&(offset, _)| &data[offset..offset + 10])).collect();
+        assert_eq!(hash(&captured), hash(&sliced));
+
+        let mut decoder = Decoder::new(&data[..]).unwrap();
+        let mut slice_decoder = SliceDecoder::new(&data).unwrap();
+        assert_eq!(decoder.descriptor_bytes(), Some(&header));
+        assert_eq!(slice_decoder.descriptor_bytes(), Some(&header));
+        for &(offset, bytes) in &descriptors {
+            decoder.next_frame_info().unwrap().unwrap();
+            slice_decoder.next_frame_info().unwrap().unwrap();
+            assert_eq!(decoder.image_descriptor_bytes(), Some((offset as u64, &bytes)));
+            assert_eq!(slice_decoder.image_descriptor_bytes(), Some((offset, &bytes)));
+        }
+    }
+}
+
+#[test]
+fn probe_reads_metadata_only() {
+    use gif::synth::pathological as files;
+
+    let data = std::fs::read("tests/samples/moon_impact.gif").unwrap();
+    let index = gif::Index::build(&data).unwrap();
+    let info = gif::probe(&data[..]).unwrap();
+    assert_eq!((info.width, info.height), (index.width(), index.height()));
+    assert_eq!(info.frame_count, 14);
+    assert!(info.is_animated());
+    assert_eq!(info.repeat, index.repeat());
+    assert_eq!(std::time::Duration::from_millis(info.total_duration_cs * 10), index.duration());
+    assert_eq!(info.version, gif::Version::V89a);
+    assert!(info.has_global_palette);
+    assert!(!info.truncated);
+
+    // The frames before the cut, and the one it's in
+    let info = gif::probe(&data[..data.len() / 2]).unwrap();
+    assert!(info.truncated);
+    assert!(info.frame_count > 0 && info.frame_count < 14);
+    assert_eq!((info.width, info.height), (index.width(), index.height()));
+    assert!(gif::probe(&data[..10]).is_err());
+
+    let info = gif::probe(&files::missing_color_table()[..]).unwrap();
+    assert!(!info.has_global_palette);
+    assert!(!info.is_animated());
+}
+
+#[test]
+fn frame_iterator_is_fused() {
+    let data = std::fs::read("tests/samples/moon_impact.gif").unwrap();
+
+    let frames = Decoder::new(&data[..]).unwrap().into_iter().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(frames.len(), 14);
+    for frame in &frames {
+        assert_eq!(frame.buffer.len(), usize::from(frame.width) * usize::from(frame.height));
+    }
+
+    let mut iter = Decoder::new(&data[..]).unwrap().into_iter();
+    assert_eq!(&*iter.next().unwrap().unwrap().buffer, &*frames[0].buffer);
+    assert_eq!(iter.by_ref().skip(12).count(), 1);
+    assert!(iter.next().is_none());
+    assert!(iter.next().is_none());
+
+    // After an error, the broken stream isn't read again
+    let mut iter = Decoder::new(&data[..data.len() / 2]).unwrap().into_iter();
+    assert!(iter.by_ref().any(|frame| frame.is_err()));
+    assert!(iter.next().is_none());
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn read_rows_matches_frame_buffer() {
+    for (path, color_output) in [
+        ("tests/samples/interlaced.gif", gif::ColorOutput::Indexed),
+        ("tests/samples/interlaced.gif", gif::ColorOutput::RGBA),
+        ("tests/samples/interlaced.gif", gif::ColorOutput::RGB),
+        ("tests/samples/moon_impact.gif", gif::ColorOutput::RGBA),
+    ] {
+        let mut options = DecodeOptions::new();
+        options.set_color_output(color_output);
+        let mut decoder = options.clone().read_info(File::open(path).unwrap()).unwrap();
+        let expected = decoder.read_next_frame().unwrap().unwrap().buffer.to_vec();
+
+        let mut decoder = options.read_info(File::open(path).unwrap()).unwrap();
+        decoder.next_frame_info().unwrap().unwrap();
+        let line_length = decoder.line_length();
+        let mut rows = vec![0; decoder.buffer_size()];
+        let mut count = 0;
+        decoder.read_rows(|row, pixels: &[u8]| {
+            assert_eq!(pixels.len(), line_length);
+            rows[usize::from(row) * line_length..][..line_length].copy_from_slice(pixels);
+            count += 1;
+            Ok::<_, gif::DecodingError>(())
+        }).unwrap();
+        assert_eq!(count, rows.len() / line_length);
+        assert_eq!(rows, expected);
+    }
+}
+
+#[test]
+fn read_rows_callback_error_aborts_frame() {
+    #[derive(Debug)]
+    enum RenderError {
+        Display,
+        Decoding(gif::DecodingError),
+    }
+    impl From<gif::DecodingError> for RenderError {
+        fn from(err: gif::DecodingError) -> Self {
+            Self::Decoding(err)
+        }
+    }
+
+    let frames: Vec<_> = Decoder::new(File::open("tests/samples/moon_impact.gif").unwrap()).unwrap()
+        .into_iter().map(Result::unwrap).collect();
+
+    let mut decoder = Decoder::new(File::open("tests/samples/moon_impact.gif").unwrap()).unwrap();
+    decoder.next_frame_info().unwrap().unwrap();
+    let mut rows = 0;
+    let result = decoder.read_rows(|row, _| {
+        rows += 1;
+        if row == 5 { Err(RenderError::Display) } else { Ok(()) }
+    });
+    assert!(matches!(result, Err(RenderError::Display)));
+    assert_eq!(rows, 6);
+
+    // The rest of the first frame is skipped
+    let frame = decoder.read_next_frame().unwrap().unwrap();
+    assert_eq!(frame.buffer, frames[1].buffer);
+}
+
+#[test]
+fn interlace_pass_progress() {
+    let expected = Decoder::new(File::open("tests/samples/interlaced.gif").unwrap()).unwrap()
+        .read_next_frame().unwrap().unwrap().buffer.to_vec();
+
+    let mut decoder = Decoder::new(File::open("tests/samples/interlaced.gif").unwrap()).unwrap();
+    let width = usize::from(decoder.next_frame_info().unwrap().unwrap().width);
+    let mut buf = vec![0; decoder.buffer_size()];
+    let mut passes = Vec::new();
+    decoder.read_into_buffer_progressive(&mut buf, |pass, preview| {
+        if pass == 1 {
+            // Every 8th row is done
+            for row in (0..expected.len() / width).step_by(8) {
+                assert_eq!(preview[row * width..][..width], expected[row * width..][..width]);
+            }
+        }
+        passes.push(pass);
+    }).unwrap();
+    assert_eq!(passes, [1, 2, 3, 4]);
+    assert_eq!(buf, expected);
+
+    let mut decoder = Decoder::new(File::open("tests/samples/moon_impact.gif").unwrap()).unwrap();
+    decoder.next_frame_info().unwrap().unwrap();
+    let mut buf = vec![0; decoder.buffer_size()];
+    decoder.read_into_buffer_progressive(&mut buf, |_, _| panic!("not interlaced")).unwrap();
+}
+
+#[test]
+fn decode_after_discarded_frames() {
+    let frames: Vec<_> = Decoder::new(File::open("tests/samples/moon_impact.gif").unwrap()).unwrap()
+        .into_iter().map(Result::unwrap).collect();
+
+    let mut decoder = Decoder::new(File::open("tests/samples/moon_impact.gif").unwrap()).unwrap();
+    for (i, expected) in frames.iter().enumerate() {
+        // Every other frame's data is discarded by the following `next_frame_info`
+        decoder.next_frame_info().unwrap().unwrap();
+        if i % 2 == 1 {
+            let frame = decoder.read_next_frame().unwrap().unwrap();
+            assert_eq!(frame.buffer, expected.buffer);
+        }
+    }
+    assert!(decoder.next_frame_info().unwrap().is_none());
+
+    // Frames that are only discarded don't go through the LZW decoder, so there is no end code to check
+    let mut options = DecodeOptions::new();
+    options.check_lzw_end_code(true);
+    let mut decoder = options.read_info(File::open("tests/samples/moon_impact.gif").unwrap()).unwrap();
+    let mut count = 0;
+    while decoder.next_frame_info().unwrap().is_some() {
+        count += 1;
+    }
+    assert_eq!(count, frames.len());
+}
+
+#[test]
+fn skip_frame_data_continues_with_next_frame() {
+    let frames: Vec<_> = Decoder::new(File::open("tests/samples/moon_impact.gif").unwrap()).unwrap()
+        .into_iter().map(Result::unwrap).collect();
+
+    let mut options = DecodeOptions::new();
+    options.check_lzw_end_code(true);
+    let mut decoder = options.read_info(File::open("tests/samples/moon_impact.gif").unwrap()).unwrap();
+    // There's no frame in progress yet
+    decoder.skip_frame_data().unwrap();
+    for (i, expected) in frames.iter().enumerate() {
+        decoder.next_frame_info().unwrap().unwrap();
+        match i % 3 {
+            0 => {
+                let frame = decoder.read_next_frame().unwrap().unwrap();
+                assert_eq!(frame.buffer, expected.buffer);
+                decoder.skip_frame_data().unwrap();
+            },
+            1 => decoder.skip_frame_data().unwrap(),
+            _ => {
+                // Skips the rest of a partially decoded frame
+                let result: Result<(), gif::DecodingError> = decoder.read_rows(|row, _| {
+                    if row == 5 { Err(std::io::ErrorKind::Interrupted.into()) } else { Ok(()) }
+                });
+                assert!(result.is_err());
+                decoder.skip_frame_data().unwrap();
+            },
+        }
+    }
+    assert!(decoder.next_frame_info().unwrap().is_none());
+}
+
+#[test]
+fn largest_frame_size_does_not_overflow() {
+    let data = include_bytes!("../fuzz/corpus/decode/max-dimensions.gif");
+    let mut options = DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(&data[..]).unwrap();
+    let frame = decoder.next_frame_info().unwrap().unwrap();
+    assert_eq!((frame.width, frame.height), (0xFFFF, 0xFFFF));
+    match usize::try_from(0xFFFF_u64 * 0xFFFF * 4) {
+        Ok(size) => {
+            assert_eq!(decoder.buffer_size(), size);
+            // Over the default memory limit
+            assert!(matches!(decoder.read_next_frame(), Err(gif::DecodingError::Io(_))));
+        },
+        Err(_) => {
+            assert_eq!(decoder.buffer_size(), usize::MAX);
+            assert!(matches!(decoder.read_next_frame(), Err(gif::DecodingError::DimensionsTooLarge { width: 0xFFFF, height: 0xFFFF })));
+        },
+    }
+}
+
+#[test]
+fn required_bytes_of_largest_frame() {
+    let frame = gif::Frame { width: 0xFFFF, height: 0xFFFF, ..gif::Frame::default() };
+    assert_eq!(frame.required_bytes_indexed(), Some(0xFFFE_0001));
+    #[cfg(target_pointer_width = "64")]
+    assert_eq!(frame.required_bytes_rgba(), Some(0x3_FFF8_0004));
+    // Tested by the 32-bit job of the CI
+    #[cfg(target_pointer_width = "32")]
+    assert_eq!(frame.required_bytes_rgba(), None);
+}
+
+/// Counts the bytes copied out by `read`, and all calls that read
+struct CountingReader<'a> {
+    data: &'a [u8],
+    copied: usize,
+    reads: usize,
+}
+
+impl<'a> CountingReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, copied: 0, reads: 0 }
+    }
+}
+
+impl std::io::Read for CountingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.data.read(buf)?;
+        self.copied += len;
+        self.reads += 1;
+        Ok(len)
+    }
+}
+
+impl std::io::BufRead for CountingReader<'_> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.reads += 1;
+        Ok(self.data)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.data = &self.data[amt..];
+    }
+}
+
+#[test]
+fn buffered_reader_is_not_copied() {
+    for path in ["tests/samples/moon_impact.gif", "tests/samples/interlaced.gif"] {
+        let data = std::fs::read(path).unwrap();
+        let mut decoder = Decoder::new(CountingReader::new(&data)).unwrap();
+        let mut buffered = Decoder::new_buffered(CountingReader::new(&data)).unwrap();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!(frame.buffer, buffered.read_next_frame().unwrap().unwrap().buffer);
+        }
+        assert!(buffered.read_next_frame().unwrap().is_none());
+        assert_eq!(decoder.get_ref().copied, data.len());
+        assert_eq!(buffered.get_ref().copied, 0);
+    }
+}
+
+#[test]
+fn reading_after_the_end_does_not_read() {
+    let mut encoder = Encoder::new(vec![], 2, 2, &[0, 0, 0, 255, 255, 255]).unwrap();
+    for pixels in [[0, 1, 1, 0], [1, 0, 0, 1]] {
+        encoder.write_frame(&Frame::from_indexed_pixels(2, 2, pixels.to_vec(), None)).unwrap();
+    }
+    let mut data = encoder.into_inner().unwrap();
+    let trailing = [0; 16];
+    data.extend_from_slice(&trailing);
+    for continue_past_trailer in [false, true] {
+        let mut options = DecodeOptions::new();
+        options.continue_past_trailer(continue_past_trailer);
+        let mut decoder = options.read_info_buffered(CountingReader::new(&data)).unwrap();
+        while decoder.read_next_frame().unwrap().is_some() {}
+        let reads = decoder.get_ref().reads;
+        for _ in 0..3 {
+            assert!(decoder.read_next_frame().unwrap().is_none());
+            assert!(decoder.next_frame_info().unwrap().is_none());
+            assert!(decoder.read_lzw_frame().unwrap().is_none());
+            decoder.skip_frame_data().unwrap();
+            let mut buf = vec![0; decoder.buffer_size()];
+            assert!(decoder.read_into_buffer(&mut buf).is_err());
+            assert!(decoder.fill_buffer(&mut buf).is_err());
+        }
+        assert_eq!(decoder.get_ref().reads, reads);
+        // `continue_past_trailer` looks at a few bytes for another block
+        let looked_at = if continue_past_trailer { 8 } else { 0 };
+        assert_eq!(decoder.get_ref().data, &trailing[looked_at..]);
+    }
+
+    let mut decoder = SliceDecoder::new(&data).unwrap();
+    while decoder.read_next_frame().unwrap().is_some() {}
+    for _ in 0..3 {
+        assert!(decoder.read_next_frame().unwrap().is_none());
+        assert!(decoder.next_frame_info().unwrap().is_none());
+    }
+}
+
+#[test]
+fn streaming_decoder_finishes_after_trailer() {
+    use gif::streaming_decoder::{Block, Decoded, OutputBuffer, StreamingDecoder};
+
+    let data = std::fs::read("tests/samples/moon_impact.gif").unwrap();
+    let mut decoder = StreamingDecoder::new();
+    let mut input = &data[..];
+    let mut trailers = 0;
+    let mut trailer_end = 0;
+    loop {
+        let (consumed, decoded) = decoder.update(input, &mut OutputBuffer::None).unwrap();
+        input = &input[consumed..];
+        match decoded {
+            Decoded::BlockStart(Block::Trailer) => {
+                trailers += 1;
+                trailer_end = data.len() - input.len();
+            },
+            Decoded::Finished => break,
+            _ => {},
+        }
+    }
+    assert_eq!(trailers, 1);
+    // The file has a comment after the trailer, which the first `Finished` has discarded
+    assert!(input.is_empty());
+    assert!(trailer_end < data.len());
+    assert_eq!(decoder.trailing_bytes() as usize, data.len() - trailer_end);
+    for _ in 0..3 {
+        assert!(matches!(decoder.update(input, &mut OutputBuffer::None).unwrap(), (0, Decoded::Finished)));
+    }
+    assert!(matches!(decoder.update(&[], &mut OutputBuffer::None).unwrap(), (0, Decoded::Finished)));
+}
+
+#[test]
+fn junk_after_trailer_is_consumed() {
+    use gif::streaming_decoder::{Decoded, OutputBuffer, StreamingDecoder};
+
+    let mut data = std::fs::read("tests/samples/sample_1.gif").unwrap();
+    let len = data.len();
+    data.extend((0..1024).map(|i| i as u8));
+
+    // Feeds the input until it's used up, like a generic driver that doesn't know about `Finished`
+    let mut decoder = StreamingDecoder::new();
+    let mut input = &data[..];
+    let mut calls = 0;
+    while !input.is_empty() {
+        let (consumed, _) = decoder.update(input, &mut OutputBuffer::None).unwrap();
+        input = &input[consumed..];
+        calls += 1;
+        assert!(calls < 10_000);
+    }
+    assert_eq!(decoder.trailing_bytes(), 1024);
+    assert!(matches!(decoder.update(&[1, 2, 3], &mut OutputBuffer::None).unwrap(), (3, Decoded::Finished)));
+    assert_eq!(decoder.trailing_bytes(), 1027);
+
+    let mut options = DecodeOptions::new();
+    options.error_on_trailing_data(true);
+    let mut decoder = StreamingDecoder::with_options(&options);
+    let mut input = &data[..];
+    let err = loop {
+        match decoder.update(input, &mut OutputBuffer::None) {
+            Ok((consumed, _)) => input = &input[consumed..],
+            Err(gif::DecodingError::Format(err)) => break err,
+            Err(err) => panic!("{err}"),
+        }
+    };
+    assert_eq!(err.kind(), gif::DecodingFormatErrorKind::DataAfterTrailer);
+    assert_eq!(err.offset(), Some(len as u64));
+}
+
+#[test]
+fn truncated_frame_is_filled() {
+    let (width, height) = (64, 48);
+    let pixels: Vec<u8> = (0..width * height).map(|i| (i % 7 + 1) as u8).collect();
+    for interlaced in [false, true] {
+        let mut encoder = Encoder::new(vec![], width as u16, height as u16, &[0; 3 * 8]).unwrap();
+        let frame = Frame { interlaced, ..Frame::from_indexed_pixels(width as u16, height as u16, pixels.clone(), None) };
+        encoder.write_frame(&frame).unwrap();
+        let first_frame_end = encoder.get_ref().len();
+        encoder.write_frame(&frame).unwrap();
+        let data = encoder.into_inner().unwrap();
+        // Cut off in the middle of the second frame
+        let data = &data[..(first_frame_end + data.len()) / 2];
+
+        let mut decoder = Decoder::new(data).unwrap();
+        assert!(decoder.read_next_frame().unwrap().is_some());
+        assert!(decoder.read_next_frame().is_err());
+
+        let mut options = DecodeOptions::new();
+        options.allow_truncated(true);
+        let mut decoder = options.read_info(data).unwrap();
+        assert_eq!(&*decoder.read_next_frame().unwrap().unwrap().buffer, &pixels[..]);
+        assert!(!decoder.is_truncated());
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        assert!(!frame.interlaced);
+        // The missing pixels have the background color
+        let missing = frame.buffer.iter().filter(|&&index| index == 0).count();
+        assert!(missing > 0 && missing < pixels.len());
+        for (&index, &expected) in frame.buffer.iter().zip(&pixels) {
+            assert!(index == expected || index == 0);
+        }
+        if !interlaced {
+            assert!(frame.buffer[pixels.len() - missing..].iter().all(|&index| index == 0));
+        }
+        assert!(decoder.is_truncated());
+        assert!(decoder.read_next_frame().unwrap().is_none());
+        assert!(decoder.next_frame_info().unwrap().is_none());
+    }
+}
+
+#[test]
+fn truncated_between_frames_ends_animation() {
+    let data = gif::synth::pathological::missing_trailer();
+
+    let mut options = DecodeOptions::new();
+    options.allow_missing_trailer(false);
+    options.allow_truncated(true);
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(&data[..]).unwrap();
+    assert_eq!(decoder.read_next_frame().unwrap().unwrap().buffer.len(), 16);
+    assert!(decoder.read_next_frame().unwrap().is_none());
+    assert!(decoder.is_truncated());
+}
+
+#[test]
+fn missing_trailer_ends_animation() {
+    let mut encoder = Encoder::new(vec![], 2, 2, &[0, 0, 0, 255, 255, 255]).unwrap();
+    for pixels in [[0, 1, 1, 0], [1, 0, 0, 1]] {
+        encoder.write_frame(&Frame::from_indexed_pixels(2, 2, pixels.to_vec(), None)).unwrap();
+    }
+    let mut data = encoder.into_inner().unwrap();
+    assert_eq!(data.pop(), Some(0x3B));
+
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    assert!(decoder.read_next_frame().unwrap().is_some());
+    assert!(decoder.read_next_frame().unwrap().is_some());
+    assert!(decoder.read_next_frame().unwrap().is_none());
+    assert!(decoder.read_next_frame().unwrap().is_none());
+    assert!(!decoder.is_truncated());
+
+    let mut decoder = SliceDecoder::new(&data).unwrap();
+    assert!(decoder.read_next_frame().unwrap().is_some());
+    assert!(decoder.read_next_frame().unwrap().is_some());
+    assert!(decoder.read_next_frame().unwrap().is_none());
+
+    let mut options = DecodeOptions::new();
+    options.allow_missing_trailer(false);
+    let mut decoder = options.read_info(&data[..]).unwrap();
+    assert!(decoder.read_next_frame().unwrap().is_some());
+    assert!(decoder.read_next_frame().unwrap().is_some());
+    assert!(decoder.read_next_frame().is_err());
+
+    // The end of the input within a block is still an error
+    data.pop();
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    assert!(decoder.read_next_frame().unwrap().is_some());
+    assert!(decoder.read_next_frame().is_err());
+    let mut decoder = SliceDecoder::new(&data).unwrap();
+    assert!(decoder.read_next_frame().unwrap().is_some());
+    assert!(decoder.read_next_frame().is_err());
+}
+
+#[test]
+fn data_after_trailer() {
+    use gif::streaming_decoder::{Block, Decoded, OutputBuffer, StreamingDecoder};
+
+    let mut encoder = Encoder::new(vec![], 2, 2, &[0, 0, 0, 255, 255, 255]).unwrap();
+    encoder.write_frame(&Frame::from_indexed_pixels(2, 2, vec![0, 1, 1, 0], None)).unwrap();
+    let data = encoder.into_inner().unwrap();
+    let mut polyglot = data.clone();
+    polyglot.extend_from_slice(b"PK\x03\x04");
+
+    let mut decoder = Decoder::new(&polyglot[..]).unwrap();
+    while decoder.read_next_frame().unwrap().is_some() {}
+    assert_eq!(decoder.trailer_offset(), Some(data.len() as u64 - 1));
+    let mut decoder = SliceDecoder::new(&polyglot).unwrap();
+    while decoder.read_next_frame().unwrap().is_some() {}
+    assert_eq!(decoder.trailer_offset(), Some(data.len() - 1));
+
+    let mut options = DecodeOptions::new();
+    options.error_on_trailing_data(true);
+    let mut decoder = options.clone().read_info(&polyglot[..]).unwrap();
+    assert!(decoder.read_next_frame().unwrap().is_some());
+    assert!(decoder.read_next_frame().is_err());
+    let mut decoder = options.clone().read_slice(&polyglot).unwrap();
+    assert!(decoder.read_next_frame().unwrap().is_some());
+    assert!(decoder.read_next_frame().is_err());
+
+    let mut decoder = options.clone().read_info(&data[..]).unwrap();
+    while decoder.read_next_frame().unwrap().is_some() {}
+    let mut decoder = options.clone().read_slice(&data).unwrap();
+    while decoder.read_next_frame().unwrap().is_some() {}
+
+    let mut decoder = StreamingDecoder::with_options(&options);
+    let mut input = &polyglot[..];
+    loop {
+        let (consumed, decoded) = decoder.update(input, &mut OutputBuffer::None).unwrap();
+        input = &input[consumed..];
+        if let Decoded::BlockStart(Block::Trailer) = decoded {
+            break;
+        }
+    }
+    assert_eq!(input, b"PK\x03\x04");
+    assert!(decoder.update(input, &mut OutputBuffer::None).is_err());
+}
+
+#[test]
+fn unknown_extension_is_skipped() {
+    use gif::streaming_decoder::{Block, Decoded, OutputBuffer, StreamingDecoder};
+
+    let mut encoder = Encoder::new(vec![], 2, 2, &[0, 0, 0, 255, 255, 255]).unwrap();
+    encoder.write_extension_blocks(gif::AnyExtension(0xAB), [&[1, 2, 3][..]]).unwrap();
+    encoder.write_frame(&Frame::from_indexed_pixels(2, 2, vec![0, 1, 1, 0], None)).unwrap();
+    encoder.write_extension_blocks(gif::AnyExtension(0xAB), [&[4; 300][..]]).unwrap();
+    encoder.write_frame(&Frame::from_indexed_pixels(2, 2, vec![1, 0, 0, 1], None)).unwrap();
+    let data = encoder.into_inner().unwrap();
+
+    assert!(Decoder::new(&data[..]).is_err());
+
+    let mut options = DecodeOptions::new();
+    options.allow_unknown_extensions(true);
+    let mut decoder = options.clone().read_info(&data[..]).unwrap();
+    assert_eq!(&*decoder.read_next_frame().unwrap().unwrap().buffer, &[0, 1, 1, 0]);
+    assert_eq!(&*decoder.read_next_frame().unwrap().unwrap().buffer, &[1, 0, 0, 1]);
+    assert!(decoder.read_next_frame().unwrap().is_none());
+
+    let mut decoder = StreamingDecoder::with_options(&options);
+    let mut input = &data[..];
+    let mut extensions = Vec::new();
+    loop {
+        let (consumed, decoded) = decoder.update(input, &mut OutputBuffer::None).unwrap();
+        input = &input[consumed..];
+        match decoded {
+            Decoded::BlockFinished(gif::AnyExtension(0xAB)) => extensions.push(decoder.last_ext().1.len()),
+            Decoded::BlockStart(Block::Trailer) => break,
+            _ => {},
+        }
+    }
+    // The data starts with the length of the first sub-block
+    assert_eq!(extensions, [4, 301]);
+}
+
+#[test]
+fn slice_decoder_matches_decoder() {
+    for path in ["tests/samples/moon_impact.gif", "tests/samples/interlaced.gif", "tests/samples/sample_big.gif"] {
+        let data = std::fs::read(path).unwrap();
+        let mut decoder = Decoder::new(File::open(path).unwrap()).unwrap();
+        let mut slice_decoder = SliceDecoder::new(&data).unwrap();
+        assert_eq!(slice_decoder.global_palette(), decoder.global_palette());
+        assert_eq!(slice_decoder.bg_color(), decoder.bg_color());
+        assert_eq!(slice_decoder.repeat(), decoder.repeat());
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            let slice_frame = slice_decoder.read_next_frame().unwrap().unwrap();
+            assert_eq!(slice_frame.buffer, frame.buffer);
+            assert_eq!(slice_frame.palette, frame.palette);
+            assert_eq!(slice_decoder.local_palette(), frame.palette.as_deref());
+        }
+        assert!(slice_decoder.read_next_frame().unwrap().is_none());
+    }
+}
+
+#[test]
+fn background_color_index_is_stored() {
+    let palette = [0, 0, 0, 0xFF, 0, 0, 0, 0xFF, 0, 0, 0, 0xFF];
+    let mut data = Vec::new();
+    {
+        let mut encoder = Encoder::new(&mut data, 2, 2, &palette).unwrap();
+        encoder.write_frame(&Frame::from_indexed_pixels(2, 2, vec![0, 1, 2, 3], None)).unwrap();
+    }
+    // The index follows the signature, the screen size and the flags
+    data[11] = 3;
+
+    let decoder = Decoder::new(&data[..]).unwrap();
+    let slice_decoder = SliceDecoder::new(&data).unwrap();
+    assert_eq!(decoder.bg_color(), Some(3));
+    assert_eq!(slice_decoder.bg_color(), Some(3));
+    let index = decoder.bg_color().unwrap() * 3;
+    assert_eq!(decoder.global_palette().unwrap()[index..index + 3], [0, 0, 0xFF]);
+}
+
+#[test]
+fn slice_decoder_palettes_outlive_decoder() {
+    let mut encoder = Encoder::new(vec![], 2, 1, &[1, 2, 3]).unwrap();
+    encoder.write_frame(&Frame { palette: Some(vec![4, 5, 6, 7, 8, 9]), ..Frame::from_indexed_pixels(2, 1, vec![0, 1], None) }).unwrap();
+    let data = encoder.into_inner().unwrap();
+
+    let (global, local) = {
+        let mut decoder = SliceDecoder::new(&data).unwrap();
+        decoder.next_frame_info().unwrap().unwrap();
+        (decoder.global_palette().unwrap(), decoder.palette().unwrap())
+    };
+    // The encoder pads palettes to a power of two
+    assert_eq!(global, [1, 2, 3, 0, 0, 0]);
+    assert_eq!(local, [4, 5, 6, 7, 8, 9]);
+    // Both are borrowed from the input, not copied
+    assert!(data.as_ptr_range().contains(&global.as_ptr()));
+    assert!(data.as_ptr_range().contains(&local.as_ptr()));
+}
+
+#[test]
+fn format_errors_have_context() {
+    use gif::streaming_decoder::{OutputBuffer, StreamingDecoder};
+    use gif::{DecodingError, DecodingFormatErrorKind};
+
+    let mut encoder = Encoder::new(vec![], 2, 2, &[0, 0, 0, 255, 255, 255]).unwrap();
+    encoder.write_frame(&Frame::from_indexed_pixels(2, 2, vec![0, 1, 1, 0], None)).unwrap();
+    encoder.write_frame(&Frame::from_indexed_pixels(2, 2, vec![1, 0, 0, 1], None)).unwrap();
+    let mut data = encoder.into_inner().unwrap();
+    // Replace the trailer by an unknown block
+    let introducer = data.len() - 1;
+    data[introducer] = 0x99;
+    data.push(0);
+
+    let check = |err: DecodingError| match err {
+        DecodingError::Format(err) => {
+            // The block type is checked once the byte after it is read
+            assert_eq!(err.offset(), Some(introducer as u64 + 1));
+            assert_eq!(err.frame_index(), Some(2));
+            assert_eq!(err.state(), Some("block introducer"));
+            assert_eq!(err.kind(), DecodingFormatErrorKind::UnknownBlock);
+        },
+        err => panic!("{err:?}"),
+    };
+
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    assert!(decoder.read_next_frame().unwrap().is_some());
+    assert!(decoder.read_next_frame().unwrap().is_some());
+    check(decoder.read_next_frame().unwrap_err());
+
+    // The offset doesn't depend on how the input is split
+    let mut decoder = StreamingDecoder::new();
+    let mut input = &data[..];
+    let err = loop {
+        match decoder.update(&input[..1], &mut OutputBuffer::None) {
+            Ok((consumed, _)) => input = &input[consumed..],
+            Err(err) => break err,
+        }
+    };
+    check(err);
+
+    // Errors in the header have no frame
+    let mut decoder = StreamingDecoder::new();
+    let err = decoder.update(b"GIF9xa\0\0\0\0", &mut OutputBuffer::None).unwrap_err();
+    match err {
+        DecodingError::Format(err) => {
+            assert_eq!(err.offset(), Some(6));
+            assert_eq!(err.frame_index(), None);
+            assert_eq!(err.state(), Some("signature"));
+            assert_eq!(err.kind(), DecodingFormatErrorKind::UnsupportedVersion);
+        },
+        err => panic!("{err:?}"),
+    }
+}
+
+#[test]
+fn format_errors_have_kinds() {
+    use gif::synth::pathological as files;
+    use gif::{DecodingError, DecodingFormatErrorKind};
+
+    fn kind(data: &[u8]) -> DecodingFormatErrorKind {
+        let err = match Decoder::new(data) {
+            Ok(mut decoder) => loop {
+                match decoder.read_next_frame() {
+                    Ok(Some(_)) => {},
+                    Ok(None) => panic!("no error"),
+                    Err(err) => break err,
+                }
+            },
+            Err(err) => err,
+        };
+        match err {
+            DecodingError::Format(err) => err.kind(),
+            err => panic!("{err:?}"),
+        }
+    }
+
+    assert_eq!(kind(&files::malformed_signature()), DecodingFormatErrorKind::MalformedHeader);
+    assert_eq!(kind(&files::unknown_block()), DecodingFormatErrorKind::UnknownBlock);
+    assert_eq!(kind(&files::invalid_min_code_size()), DecodingFormatErrorKind::InvalidMinCodeSize);
+    assert_eq!(kind(&files::invalid_lzw_code()), DecodingFormatErrorKind::InvalidLzwCode);
+}
+
+#[test]
+fn screen_descriptor() {
+    use gif::streaming_decoder::{Decoded, OutputBuffer, StreamingDecoder};
+    use gif::ScreenDescriptor;
+
+    let mut data = b"GIF89a\x03\x00\x02\x00".to_vec();
+    // A sorted global palette of 2 colors, 4 bits per primary color
+    data.extend_from_slice(&[0b1011_1000, 1, 49]);
+    data.extend_from_slice(&[0, 0, 0, 255, 255, 255, 0x3b]);
+    let expected = ScreenDescriptor {
+        width: 3,
+        height: 2,
+        has_global_palette: true,
+        color_resolution: 4,
+        sort_flag: true,
+        bg_index: 1,
+        aspect_ratio: 49,
+    };
+
+    assert_eq!(*Decoder::new(&data[..]).unwrap().screen_descriptor(), expected);
+    assert_eq!(*SliceDecoder::new(&data).unwrap().screen_descriptor(), expected);
+
+    let mut decoder = StreamingDecoder::new();
+    let mut input = &data[..];
+    loop {
+        assert_eq!(decoder.screen_descriptor(), None);
+        assert_eq!(decoder.global_palette(), None);
+        let (consumed, decoded) = decoder.update(input, &mut OutputBuffer::None).unwrap();
+        input = &input[consumed..];
+        if let Decoded::GlobalPalette = decoded {
+            break;
+        }
+    }
+    assert_eq!(decoder.screen_descriptor(), Some(&expected));
+    // The decoder keeps the palette after the event
+    assert_eq!(decoder.global_palette(), Some(&[0, 0, 0, 255, 255, 255][..]));
+    decoder.update(input, &mut OutputBuffer::None).unwrap();
+    assert_eq!(decoder.global_palette(), Some(&[0, 0, 0, 255, 255, 255][..]));
+}
+
+#[test]
+fn graphic_control() {
+    use gif::GraphicControl;
+
+    let mut data = vec![];
+    {
+        let mut encoder = Encoder::new(&mut data, 1, 1, &[0, 0, 0, 0xFF, 0xFF, 0xFF]).unwrap();
+        let frame = Frame { delay: 7, dispose: DisposalMethod::Background, ..Frame::from_indexed_pixels(1, 1, vec![1], Some(0)) };
+        encoder.write_frame(&frame).unwrap();
+    }
+    // A graphic control extension with reserved disposal 5 and user input, which no image follows
+    let trailer = data.pop().unwrap();
+    data.extend_from_slice(&[0x21, 0xF9, 4, 0b0001_0110, 3, 0, 9, 0, trailer]);
+
+    let first = GraphicControl {
+        disposal: DisposalMethod::Background,
+        needs_user_input: false,
+        transparent_index: Some(0),
+        delay_cs: 7,
+        flags: 0b0000_1001,
+    };
+    let last = GraphicControl {
+        disposal: DisposalMethod::Any,
+        needs_user_input: true,
+        transparent_index: None,
+        delay_cs: 3,
+        flags: 0b0001_0110,
+    };
+
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    assert_eq!(decoder.read_next_frame().unwrap().unwrap().graphic_control, Some(first));
+    assert_eq!(decoder.last_graphic_control(), Some(&first));
+    assert!(decoder.read_next_frame().unwrap().is_none());
+    assert_eq!(decoder.last_graphic_control(), Some(&last));
+
+    let mut decoder = SliceDecoder::new(&data).unwrap();
+    while decoder.read_next_frame().unwrap().is_some() {}
+    assert_eq!(decoder.last_graphic_control(), Some(&last));
+
+    // Frames without an extension have none
+    let mut data = vec![];
+    Encoder::new(&mut data, 1, 1, &[0; 6]).unwrap()
+        .write_frame(&Frame::from_indexed_pixels(1, 1, vec![0], None)).unwrap();
+    assert_eq!(data[19..22], [0x21, 0xF9, 4]);
+    data.drain(19..27);
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    assert_eq!(decoder.read_next_frame().unwrap().unwrap().graphic_control, None);
+    assert_eq!(decoder.last_graphic_control(), None);
+}
+
+#[test]
+fn repeated_graphic_control_last_one_wins() {
+    use gif::synth::pathological as files;
+
+    let data = files::repeated_graphic_control_transparent_first();
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    let frame = decoder.read_next_frame().unwrap().unwrap();
+    assert_eq!((frame.delay, frame.transparent), (10, None));
+    assert_eq!(frame.graphic_control.unwrap().flags, 0);
+
+    let data = files::repeated_graphic_control_transparent_last();
+    let mut decoder = SliceDecoder::new(&data).unwrap();
+    let frame = decoder.read_next_frame().unwrap().unwrap();
+    assert_eq!((frame.delay, frame.transparent), (20, Some(1)));
+    assert_eq!(frame.graphic_control.unwrap().transparent_index, Some(1));
+
+    let mut options = DecodeOptions::new();
+    options.error_on_repeated_graphic_control(true);
+    let data = files::well_formed();
+    let mut decoder = options.read_info(&data[..]).unwrap();
+    assert!(decoder.read_next_frame().unwrap().is_some());
+}
+
+#[test]
+fn zero_sized_frames_are_empty() {
+    use gif::synth::pathological as files;
+
+    for data in [files::zero_width_frame(), files::zero_height_frame()] {
+        let mut decoder = Decoder::new(&data[..]).unwrap();
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        assert_eq!(frame.width * frame.height, 0);
+        assert!(frame.buffer.is_empty());
+        assert_eq!(*decoder.read_next_frame().unwrap().unwrap().buffer, [0, 1, 1, 0]);
+        assert!(decoder.read_next_frame().unwrap().is_none());
+
+        let frames: Vec<_> = Decoder::new(&data[..]).unwrap().into_iter().map(Result::unwrap).collect();
+        assert_eq!(frames.len(), 2);
+        assert!(frames[0].buffer.is_empty());
+
+        let mut decoder = SliceDecoder::new(&data).unwrap();
+        assert!(decoder.read_next_frame().unwrap().unwrap().buffer.is_empty());
+        assert_eq!(*decoder.read_next_frame().unwrap().unwrap().buffer, [0, 1, 1, 0]);
+
+        let mut options = DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(&data[..]).unwrap();
+        decoder.next_frame_info().unwrap().unwrap();
+        assert_eq!(decoder.buffer_size(), 0);
+        decoder.read_into_buffer(&mut []).unwrap();
+        decoder.next_frame_info().unwrap().unwrap();
+        let mut buf = vec![0; decoder.buffer_size()];
+        decoder.read_into_buffer(&mut buf).unwrap();
+        assert_eq!(buf, [0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0xFF]);
+    }
+}
+
+#[test]
+fn missing_color_table_falls_back_to_grayscale() {
+    use gif::synth::pathological as files;
+    use gif::ColorOutput;
+
+    let mut options = DecodeOptions::new();
+    options.set_color_output(ColorOutput::RGBA);
+    let data = files::missing_color_table();
+    let mut decoder = options.clone().read_info(&data[..]).unwrap();
+    // Generated only once a frame needs it
+    assert!(!decoder.has_fallback_palette());
+    assert_eq!(decoder.global_palette(), None);
+    let black_white = [0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0xFF];
+    assert_eq!(*decoder.read_next_frame().unwrap().unwrap().buffer, black_white);
+    assert!(decoder.has_fallback_palette());
+    // The screen descriptor has a color resolution of 1 bit, and the indices past it are white
+    let palette = decoder.global_palette().unwrap();
+    assert_eq!(palette.len(), 256 * 3);
+    assert_eq!(palette[..6], [0, 0, 0, 0xFF, 0xFF, 0xFF]);
+    assert!(palette[6..].iter().all(|&value| value == 0xFF));
+    let mut decoder = options.clone().read_slice(&data).unwrap();
+    assert!(!decoder.has_fallback_palette());
+    decoder.next_frame_info().unwrap().unwrap();
+    assert!(decoder.has_fallback_palette());
+    assert_eq!(decoder.palette().unwrap()[..6], [0, 0, 0, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(decoder.palette().unwrap().len(), 256 * 3);
+    assert_eq!(*decoder.read_next_frame().unwrap().unwrap().buffer, black_white);
+
+    // The background color is an index into the fallback palette, so every index is valid
+    let mut with_background = data.clone();
+    with_background[11] = 200;
+    assert_eq!(options.clone().read_info(&with_background[..]).unwrap().bg_color(), Some(200));
+    assert_eq!(options.clone().read_slice(&with_background).unwrap().bg_color(), Some(200));
+
+    // 8 bits get 256 shades, so the indices 0 and 1 are both nearly black
+    let mut data = data;
+    data[10] = 0x70;
+    let mut decoder = options.clone().read_info(&data[..]).unwrap();
+    assert_eq!(decoder.read_next_frame().unwrap().unwrap().buffer[..8], [0, 0, 0, 0xFF, 1, 1, 1, 0xFF]);
+    assert_eq!(decoder.global_palette().unwrap().len(), 256 * 3);
+    let mut decoder = SliceDecoder::new(&data).unwrap();
+    decoder.next_frame_info().unwrap().unwrap();
+    assert_eq!(decoder.global_palette().unwrap().len(), 256 * 3);
+
+    let data = files::well_formed();
+    assert!(!options.clone().read_info(&data[..]).unwrap().has_fallback_palette());
+    assert!(!SliceDecoder::new(&data).unwrap().has_fallback_palette());
+
+    // Files where every frame has a local palette, like the ones the encoder writes without a
+    // global palette, don't get one
+    let mut data = Vec::new();
+    {
+        let mut encoder = Encoder::new(&mut data, 2, 2, &[]).unwrap();
+        let palette = Some(vec![0, 0, 0, 0xFF, 0xFF, 0xFF]);
+        encoder.write_frame(&Frame { width: 2, height: 2, palette, buffer: vec![0, 1, 1, 0].into(), ..Frame::default() }).unwrap();
+    }
+    let mut decoder = options.clone().read_info(&data[..]).unwrap();
+    while decoder.read_next_frame().unwrap().is_some() {}
+    assert!(!decoder.has_fallback_palette());
+    assert_eq!(decoder.global_palette(), None);
+    let mut decoder = SliceDecoder::new(&data).unwrap();
+    while decoder.read_next_frame().unwrap().is_some() {}
+    assert!(!decoder.has_fallback_palette());
+    assert_eq!(decoder.global_palette(), None);
+
+    options.allow_missing_color_table(false);
+    let data = files::missing_color_table();
+    let mut decoder = options.clone().read_info(&data[..]).unwrap();
+    assert!(decoder.read_next_frame().is_err());
+    assert!(!decoder.has_fallback_palette());
+    assert_eq!(decoder.global_palette(), None);
+    // Without any palette, the background color means nothing
+    assert_eq!(options.clone().read_info(&with_background[..]).unwrap().bg_color(), None);
+    assert_eq!(options.read_slice(&with_background).unwrap().bg_color(), None);
+}
+
+#[test]
+fn index_outside_palette() {
+    use gif::synth::pathological as files;
+    use gif::{ColorOutput, DecodingError, DecodingFormatErrorKind as Kind};
+
+    #[track_caller]
+    fn assert_failed_at_pixel_1(err: DecodingError) {
+        match err {
+            DecodingError::Format(err) => {
+                assert_eq!(err.kind(), Kind::IndexOutsidePalette);
+                assert_eq!(err.completed_pixels(), Some(1));
+            },
+            err => panic!("{err:?}"),
+        }
+    }
+
+    let data = files::index_outside_palette();
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    assert_eq!(*decoder.read_next_frame().unwrap().unwrap().buffer, [0, 3, 1, 0]);
+
+    // Such pixels are transparent black, also in a buffer that had other pixels before
+    let mut options = DecodeOptions::new();
+    options.set_color_output(ColorOutput::RGBA);
+    let mut decoder = options.clone().read_info(&data[..]).unwrap();
+    decoder.next_frame_info().unwrap().unwrap();
+    let mut buf = vec![0xAA; decoder.buffer_size()];
+    decoder.read_into_buffer(&mut buf).unwrap();
+    assert_eq!(buf[..8], [0, 0, 0, 0xFF, 0, 0, 0, 0]);
+
+    for color_output in [ColorOutput::Indexed, ColorOutput::RGBA] {
+        let mut options = DecodeOptions::new();
+        options.set_color_output(color_output);
+        let expected = options.clone().read_info(&files::well_formed()[..]).unwrap()
+            .read_next_frame().unwrap().unwrap().buffer.to_vec();
+        options.error_on_index_outside_palette(true);
+        assert_eq!(options.clone().read_info(&files::well_formed()[..]).unwrap()
+            .read_next_frame().unwrap().unwrap().buffer, expected);
+
+        // The next frame is decoded as usual
+        let mut decoder = options.clone().read_info(&data[..]).unwrap();
+        assert_failed_at_pixel_1(decoder.read_next_frame().unwrap_err());
+        assert_eq!(decoder.read_next_frame().unwrap().unwrap().buffer, expected);
+        assert!(decoder.read_next_frame().unwrap().is_none());
+
+        let mut decoder = options.clone().read_slice(&data).unwrap();
+        assert_failed_at_pixel_1(decoder.read_next_frame().unwrap_err());
+        assert_eq!(decoder.read_next_frame().unwrap().unwrap().buffer, expected);
+
+        let mut decoder = options.clone().read_info(&data[..]).unwrap();
+        decoder.next_frame_info().unwrap().unwrap();
+        let mut buf = vec![0; decoder.buffer_size()];
+        assert_failed_at_pixel_1(decoder.read_into_buffer(&mut buf).unwrap_err());
+        decoder.next_frame_info().unwrap().unwrap();
+        decoder.read_into_buffer(&mut buf).unwrap();
+        assert_eq!(buf, expected);
+
+        let mut decoder = options.read_info(&data[..]).unwrap();
+        decoder.next_frame_info().unwrap().unwrap();
+        let err = decoder.read_rows(|_, _| Ok::<_, DecodingError>(())).unwrap_err();
+        assert_failed_at_pixel_1(err);
+    }
+}
+
+#[test]
+fn retime_in_place() {
+    use gif::retime::scale_delays_in_place;
+
+    let original = std::fs::read("tests/samples/gifplayer-muybridge.gif").unwrap();
+    // A graphic control extension without a frame, right before the trailer
+    let mut data = original[..original.len() - 1].to_vec();
+    data.extend_from_slice(&[0x21, 0xF9, 4, 0, 7, 0, 0, 0, 0x3b]);
+
+    let mut retimed = data.clone();
+    let report = scale_delays_in_place(&mut std::io::Cursor::new(&mut retimed[..]), 2.0, 3).unwrap();
+    assert_eq!(report.unused_control_extensions, 1);
+
+    // Only the bytes of changed delays differ
+    let mut expected = data.clone();
+    for frame in &report.frames {
+        let offset = frame.offset.unwrap() as usize;
+        assert_eq!(frame.new_delay, (frame.old_delay * 2).max(3));
+        expected[offset..offset + 2].copy_from_slice(&frame.new_delay.to_le_bytes());
+    }
+    assert_eq!(retimed, expected);
+
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    let mut retimed_decoder = Decoder::new(&retimed[..]).unwrap();
+    for frame in &report.frames {
+        let before = decoder.read_next_frame().unwrap().unwrap().clone();
+        let after = retimed_decoder.read_next_frame().unwrap().unwrap();
+        assert_eq!(before.delay, frame.old_delay);
+        assert_eq!(after.delay, frame.new_delay);
+        assert_eq!(after.buffer, before.buffer);
+    }
+    assert!(retimed_decoder.read_next_frame().unwrap().is_none());
+
+    // A malformed file is left unchanged
+    let truncated = &data[..data.len() / 2];
+    let mut file = truncated.to_vec();
+    assert!(scale_delays_in_place(&mut std::io::Cursor::new(&mut file[..]), 2.0, 0).is_err());
+    assert_eq!(file, truncated);
+}
+
+#[test]
+fn current_frame_outside_of_frames() {
+    use gif::streaming_decoder::{Decoded, OutputBuffer, StreamingDecoder};
+
+    let mut decoder = StreamingDecoder::new();
+    assert!(decoder.current_frame().is_none());
+    assert!(decoder.current_frame_mut().is_none());
+
+    let data = std::fs::read("tests/samples/sample_1.gif").unwrap();
+    let mut input = &data[..];
+    loop {
+        let (consumed, decoded) = decoder.update(input, &mut OutputBuffer::None).unwrap();
+        input = &input[consumed..];
+        match decoded {
+            Decoded::FrameMetadata(_) => assert_eq!(decoder.current_frame().unwrap().width, 10),
+            Decoded::DataEnd => assert!(decoder.current_frame().is_none()),
+            Decoded::Finished => break,
+            _ => {},
+        }
+    }
+    assert!(decoder.current_frame().is_none());
+}
+
+#[test]
+fn pathological_files_have_one_deviation() {
+    use gif::synth::pathological as files;
+    use gif::{DecodingError, DecodingFormatErrorKind as Kind};
+
+    #[derive(Debug, PartialEq)]
+    enum Deviation {
+        Format(Kind),
+        Eof,
+    }
+
+    // Number of frames, or how decoding fails
+    fn decode(data: &[u8], options: &DecodeOptions) -> Result<usize, Deviation> {
+        let deviation = |err| match err {
+            DecodingError::Format(err) if matches!(err.kind(), Kind::UnexpectedEof(_)) => Deviation::Eof,
+            DecodingError::Format(err) => Deviation::Format(err.kind()),
+            err => panic!("{err:?}"),
+        };
+        let mut decoder = options.clone().read_info(data).map_err(deviation)?;
+        let mut frames = 0;
+        while let Some(frame) = decoder.read_next_frame().map_err(deviation)? {
+            if frame.width == 0 || frame.height == 0 {
+                assert!(frame.buffer.is_empty());
+            } else if *frame.buffer != [0, 1, 1, 0] {
+                assert!(decoder.is_truncated());
+            }
+            frames += 1;
+        }
+        Ok(frames)
+    }
+
+    let no_option = |_: &mut DecodeOptions| {};
+    type Case = (fn() -> Vec<u8>, Result<usize, Deviation>, fn(&mut DecodeOptions), Result<usize, Deviation>);
+    // Each file, how it decodes by default, an option that affects its deviation, and how it
+    // decodes with that option
+    let cases: [Case; 25] = [
+        (files::well_formed, Ok(1), no_option, Ok(1)),
+        (files::malformed_signature, Err(Deviation::Format(Kind::MalformedHeader)), no_option, Err(Deviation::Format(Kind::MalformedHeader))),
+        (files::unsupported_version, Ok(1), |o| o.strict_version(true), Err(Deviation::Format(Kind::UnsupportedVersion))),
+        (files::version_87a_with_extension, Ok(1), |o| o.strict_version(true), Err(Deviation::Format(Kind::VersionMismatch))),
+        (files::truncated_global_palette, Err(Deviation::Eof), |o| o.allow_truncated(true), Err(Deviation::Format(Kind::NoImageData))),
+        (files::no_frames, Ok(0), no_option, Ok(0)),
+        (files::missing_color_table, Ok(1), |o| o.allow_missing_color_table(false), Err(Deviation::Format(Kind::MissingColorTable))),
+        (files::oversized_graphic_control, Err(Deviation::Format(Kind::MalformedExtension)), |o| o.allow_oversized_control_extension(true), Ok(1)),
+        (files::transparent_outside_palette, Ok(1), |o| o.error_on_transparent_outside_palette(true), Err(Deviation::Format(Kind::TransparentOutsidePalette))),
+        (files::repeated_graphic_control_transparent_first, Ok(1), |o| o.error_on_repeated_graphic_control(true), Err(Deviation::Format(Kind::RepeatedGraphicControl))),
+        (files::repeated_graphic_control_transparent_last, Ok(1), |o| o.error_on_repeated_graphic_control(true), Err(Deviation::Format(Kind::RepeatedGraphicControl))),
+        (files::unknown_extension, Err(Deviation::Format(Kind::UnknownExtension)), |o| o.allow_unknown_extensions(true), Ok(1)),
+        (files::unknown_block, Err(Deviation::Format(Kind::UnknownBlock)), |o| o.allow_unknown_blocks(true), Ok(1)),
+        (files::frame_out_of_bounds, Ok(2), |o| o.check_frame_consistency(true), Err(Deviation::Format(Kind::FrameOutOfBounds))),
+        (files::zero_width_frame, Ok(2), no_option, Ok(2)),
+        (files::zero_height_frame, Ok(2), no_option, Ok(2)),
+        (files::invalid_min_code_size, Err(Deviation::Format(Kind::InvalidMinCodeSize)), no_option, Err(Deviation::Format(Kind::InvalidMinCodeSize))),
+        (files::invalid_lzw_code, Err(Deviation::Format(Kind::InvalidLzwCode)), no_option, Err(Deviation::Format(Kind::InvalidLzwCode))),
+        (files::missing_end_code, Ok(1), |o| o.check_lzw_end_code(true), Err(Deviation::Format(Kind::NoEndCode))),
+        (files::truncated_local_palette, Err(Deviation::Eof), |o| o.allow_truncated(true), Ok(0)),
+        (files::truncated_frame_data, Err(Deviation::Eof), |o| o.allow_truncated(true), Ok(1)),
+        (files::missing_block_terminator, Err(Deviation::Eof), |o| o.allow_missing_block_terminator(true), Ok(1)),
+        (files::missing_trailer, Ok(1), |o| o.allow_missing_trailer(false), Err(Deviation::Eof)),
+        (files::stray_trailer, Ok(1), |o| o.continue_past_trailer(true), Ok(2)),
+        (files::data_after_trailer, Ok(1), |o| o.error_on_trailing_data(true), Err(Deviation::Format(Kind::DataAfterTrailer))),
+    ];
+    for (i, (file, default, option, with_option)) in cases.into_iter().enumerate() {
+        let data = file();
+        let mut options = DecodeOptions::new();
+        assert_eq!(decode(&data, &options), default, "file {i}");
+        option(&mut options);
+        assert_eq!(decode(&data, &options), with_option, "file {i}");
+    }
+
+    // A frame with half of its local palette isn't returned
+    let data = files::truncated_local_palette();
+    let mut options = DecodeOptions::new();
+    options.allow_truncated(true);
+    let mut decoder = options.read_info(&data[..]).unwrap();
+    assert!(decoder.read_next_frame().unwrap().is_none());
+    assert!(decoder.is_truncated());
+
+    // Deviations that are accepted without an option
+    let data = files::unsupported_version();
+    let decoder = Decoder::new(&data[..]).unwrap();
+    assert_eq!(decoder.warnings(), [gif::DecodingWarning::UnknownVersion(*b"88a")]);
+    assert_eq!(SliceDecoder::new(&data).unwrap().warnings(), [gif::DecodingWarning::UnknownVersion(*b"88a")]);
+
+    let data = files::background_outside_palette();
+    assert_eq!(decode(&data, &DecodeOptions::new()), Ok(1));
+    assert_eq!(Decoder::new(&data[..]).unwrap().bg_color(), None);
+    assert_eq!(Decoder::new(&files::well_formed()[..]).unwrap().bg_color(), Some(0));
+
+    // The byte after the fields is skipped
+    let data = files::oversized_graphic_control();
+    let mut options = DecodeOptions::new();
+    options.allow_oversized_control_extension(true);
+    let mut decoder = options.read_info(&data[..]).unwrap();
+    assert_eq!(decoder.read_next_frame().unwrap().unwrap().delay, 10);
+    assert_eq!(decoder.warnings(), [gif::DecodingWarning::OversizedControlExtension(5)]);
+
+    let data = files::graphic_control_with_extra_sub_block();
+    assert_eq!(decode(&data, &DecodeOptions::new()), Ok(1));
+    assert_eq!(Decoder::new(&data[..]).unwrap().read_next_frame().unwrap().unwrap().delay, 10);
+
+    let data = files::reserved_disposal();
+    assert_eq!(decode(&data, &DecodeOptions::new()), Ok(1));
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    let frame = decoder.read_next_frame().unwrap().unwrap();
+    assert_eq!(frame.dispose, DisposalMethod::Any);
+    assert_eq!(frame.graphic_control.unwrap().flags, 5 << 2);
+
+    // The frame isn't transparent, but the extension is kept as it is
+    let data = files::transparent_outside_palette();
+    let mut options = DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(&data[..]).unwrap();
+    let frame = decoder.read_next_frame().unwrap().unwrap();
+    assert_eq!(frame.transparent, None);
+    assert_eq!(frame.graphic_control.unwrap().transparent_index, Some(200));
+    assert!(frame.buffer.chunks(4).all(|rgba| rgba[3] == 0xFF));
+    let mut decoder = SliceDecoder::new(&data).unwrap();
+    assert_eq!(decoder.read_next_frame().unwrap().unwrap().transparent, None);
+
+    let data = files::unrecognized_loop_identifier();
+    assert_eq!(decode(&data, &DecodeOptions::new()), Ok(1));
+    assert_eq!(Decoder::new(&data[..]).unwrap().repeat(), gif::Repeat::default());
+    let mut netscape = data;
+    let identifier = netscape.windows(11).position(|w| w == b"ANIMEXTS1.0").unwrap();
+    netscape[identifier..identifier + 11].copy_from_slice(b"NETSCAPE2.0");
+    assert_eq!(Decoder::new(&netscape[..]).unwrap().repeat(), gif::Repeat::Infinite);
+}
+
+#[test]
+fn lzw_stats_of_frames() {
+    use gif::synth::pathological as files;
+    use gif::LzwStats;
+
+    let data = files::well_formed();
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    decoder.read_next_frame().unwrap().unwrap();
+    assert_eq!(decoder.last_frame_lzw_stats(), None);
+
+    // clear, 0, 1, clear, 1, 0, end
+    let pixels_lzw = LzwStats { max_code_size: 3, clear_codes: 2, codes: 7 };
+    let mut options = DecodeOptions::new();
+    options.collect_lzw_stats(true);
+    let mut decoder = options.clone().read_info(&data[..]).unwrap();
+    assert_eq!(decoder.last_frame_lzw_stats(), Some(LzwStats::default()));
+    decoder.read_next_frame().unwrap().unwrap();
+    assert_eq!(decoder.last_frame_lzw_stats(), Some(pixels_lzw));
+    let mut decoder = options.clone().read_slice(&data).unwrap();
+    decoder.read_next_frame().unwrap().unwrap();
+    assert_eq!(decoder.last_frame_lzw_stats(), Some(pixels_lzw));
+
+    // A frame of one color gets codes for ever longer runs, and no clear code after the first
+    let mut encoder = Encoder::new(vec![], 64, 64, &[0, 0, 0, 255, 255, 255]).unwrap();
+    encoder.write_frame(&Frame::from_indexed_pixels(64, 64, vec![0; 64 * 64], None)).unwrap();
+    // Noise fills the table of 12 bit codes, so the encoder clears it
+    let mut state = 1u32;
+    let noise: Vec<u8> = (0..256 * 256).map(|_| {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (state >> 16) as u8
+    }).collect();
+    encoder.write_frame(&Frame::from_indexed_pixels(256, 256, noise, None)).unwrap();
+    let data = encoder.into_inner().unwrap();
+
+    for skip in [false, true] {
+        let mut options = DecodeOptions::new();
+        options.collect_lzw_stats(true);
+        options.skip_frame_decoding(skip);
+        let mut decoder = options.read_info(&data[..]).unwrap();
+        decoder.read_next_frame().unwrap().unwrap();
+        assert_eq!(decoder.last_frame_lzw_stats(), Some(LzwStats { max_code_size: 7, clear_codes: 1, codes: 93 }));
+        decoder.read_next_frame().unwrap().unwrap();
+        let stats = decoder.last_frame_lzw_stats().unwrap();
+        assert_eq!(stats.max_code_size, 12);
+        assert!(stats.clear_codes > 1, "{stats:?}");
+    }
+}
+
+#[test]
+fn extensions_in_version_87a_are_warnings() {
+    use gif::synth::pathological as files;
+    use gif::{DecodingWarning, Extension};
+
+    let data = files::version_87a_with_extension();
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    assert_eq!(decoder.read_next_frame().unwrap().unwrap().delay, 10);
+    assert_eq!(decoder.warnings(), [DecodingWarning::V87aWithExtension(Extension::Control)]);
+    let mut decoder = SliceDecoder::new(&data).unwrap();
+    decoder.read_next_frame().unwrap().unwrap();
+    assert_eq!(decoder.warnings(), [DecodingWarning::V87aWithExtension(Extension::Control)]);
+
+    // Each kind of extension is listed once, in the order they come in
+    let mut data = data;
+    data.pop();
+    data.extend_from_slice(&[0x21, 0xFE, 1, b'x', 0, 0x21, 0xF9, 4, 0, 10, 0, 0, 0, 0x3B]);
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    while decoder.read_next_frame().unwrap().is_some() {}
+    assert_eq!(decoder.warnings(), [
+        DecodingWarning::V87aWithExtension(Extension::Control),
+        DecodingWarning::V87aWithExtension(Extension::Comment),
+    ]);
+
+    assert!(Decoder::new(&files::well_formed()[..]).unwrap().warnings().is_empty());
+}
+
+#[test]
+fn missing_block_terminator() {
+    use gif::synth::pathological as files;
+    use gif::DecodingWarning;
+
+    let mut options = DecodeOptions::new();
+    options.allow_missing_block_terminator(true);
+    let data = files::missing_block_terminator();
+    let mut decoder = options.clone().read_info(&data[..]).unwrap();
+    assert_eq!(*decoder.read_next_frame().unwrap().unwrap().buffer, [0, 1, 1, 0]);
+    assert!(decoder.read_next_frame().unwrap().is_none());
+    assert_eq!(decoder.warnings(), [DecodingWarning::MissingBlockTerminator]);
+
+    // The next frame starts where the terminator belongs
+    let well_formed = files::well_formed();
+    // After the header and the global palette of 2 colors, without the trailer
+    let frame = &well_formed[13 + 2 * 3..well_formed.len() - 1];
+    let mut two_frames = data.clone();
+    two_frames.pop();
+    two_frames.extend_from_slice(frame);
+    two_frames.push(0x3B);
+    let mut decoder = options.clone().read_info(&two_frames[..]).unwrap();
+    assert_eq!(*decoder.read_next_frame().unwrap().unwrap().buffer, [0, 1, 1, 0]);
+    assert_eq!(*decoder.read_next_frame().unwrap().unwrap().buffer, [0, 1, 1, 0]);
+    assert!(decoder.read_next_frame().unwrap().is_none());
+    assert_eq!(decoder.warnings(), [DecodingWarning::MissingBlockTerminator]);
+
+    // Anything but a block introducer is still the length of a sub-block, which the file ends in
+    let mut data = data;
+    *data.last_mut().unwrap() = 0x3A;
+    let mut decoder = options.read_info(&data[..]).unwrap();
+    assert!(decoder.read_next_frame().is_err());
+    assert!(decoder.warnings().is_empty());
+}
+
+#[test]
+fn end_of_input_names_the_incomplete_block() {
+    use gif::streaming_decoder::{OutputBuffer, StreamingDecoder};
+    use gif::synth::pathological as files;
+    use gif::{DecodingFormatErrorKind, Incomplete};
+
+    let data = files::well_formed();
+    // The header and the global palette take 19 bytes, the graphic control extension 8, and the
+    // image descriptor 10
+    let cases = [
+        (3, Incomplete::Signature, "file ends within the signature"),
+        (8, Incomplete::ScreenDescriptor, "file ends within the logical screen descriptor"),
+        (15, Incomplete::GlobalPalette, "file ends within the global palette"),
+        (19, Incomplete::Trailer, "file ends without a trailer"),
+        (20, Incomplete::Extension, "file ends within an extension"),
+        (22, Incomplete::GraphicControl, "file ends within a graphic control extension"),
+        (27, Incomplete::Trailer, "file ends without a trailer"),
+        (28, Incomplete::ImageDescriptor, "file ends within an image descriptor"),
+        (32, Incomplete::ImageDescriptor, "file ends within an image descriptor"),
+        (39, Incomplete::ImageData, "file ends within the data of a frame"),
+        (data.len() - 1, Incomplete::Trailer, "file ends without a trailer"),
+    ];
+    let mut options = DecodeOptions::new();
+    options.allow_missing_trailer(false);
+    for (len, incomplete, message) in cases {
+        let input = &data[..len];
+        let check = |err: gif::DecodingError| match err {
+            gif::DecodingError::Format(err) => {
+                assert_eq!(err.kind(), DecodingFormatErrorKind::UnexpectedEof(incomplete), "{len}");
+                assert_eq!(err.offset(), Some(len as u64), "{len}");
+                assert_eq!(err.to_string(), message, "{len}");
+            },
+            err => panic!("{len}: {err:?}"),
+        };
+        let read = || -> Result<(), gif::DecodingError> {
+            let mut decoder = options.clone().read_info(input)?;
+            while decoder.read_next_frame()?.is_some() {}
+            Ok(())
+        };
+        check(read().unwrap_err());
+        let slice = || -> Result<(), gif::DecodingError> {
+            let mut decoder = options.clone().read_slice(input)?;
+            while decoder.read_next_frame()?.is_some() {}
+            Ok(())
+        };
+        check(slice().unwrap_err());
+
+        let mut decoder = StreamingDecoder::new();
+        let mut scratch = [0; 16];
+        let mut rest = input;
+        while !rest.is_empty() {
+            let (consumed, _) = decoder.update(rest, &mut OutputBuffer::Slice(&mut scratch)).unwrap();
+            rest = &rest[consumed..];
+        }
+        match decoder.finish() {
+            Ok(()) => assert_eq!(incomplete, Incomplete::Trailer, "{len}"),
+            Err(err) => check(err),
+        }
+    }
+}
+
+#[test]
+fn loop_sub_block_rules() {
+    use gif::synth::pathological as files;
+    use gif::{DecodingWarning, Repeat};
+
+    let cases: [(fn() -> Vec<u8>, Repeat, DecodingWarning); 3] = [
+        (files::unknown_loop_sub_block, Repeat::Finite(3), DecodingWarning::UnknownLoopSubBlock(7)),
+        (files::malformed_loop_count, Repeat::default(), DecodingWarning::MalformedLoopCount),
+        (files::conflicting_loop_counts, Repeat::Finite(3), DecodingWarning::ConflictingLoopCounts),
+    ];
+    for (file, repeat, warning) in cases {
+        let data = file();
+        let mut decoder = Decoder::new(&data[..]).unwrap();
+        assert_eq!(decoder.repeat(), repeat);
+        assert_eq!(decoder.warnings(), [warning]);
+        assert_eq!(*decoder.read_next_frame().unwrap().unwrap().buffer, [0, 1, 1, 0]);
+        let decoder = SliceDecoder::new(&data).unwrap();
+        assert_eq!(decoder.repeat(), repeat);
+        assert_eq!(decoder.warnings(), [warning]);
+    }
+}
+
+#[test]
+fn palette_padding_references() {
+    use gif::DecodingWarning;
+
+    // The encoder pads both palettes with a black fourth color
+    let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+    let encode = |indices: [u8; 4], local: bool| {
+        let mut encoder = Encoder::new(Vec::new(), 2, 2, &palette).unwrap();
+        let frame = Frame {
+            width: 2,
+            height: 2,
+            buffer: indices.to_vec().into(),
+            palette: local.then(|| palette.to_vec()),
+            ..Frame::default()
+        };
+        encoder.write_frame(&frame).unwrap();
+        encoder.write_frame(&frame).unwrap();
+        encoder.into_inner().unwrap()
+    };
+
+    for local in [false, true] {
+        for (indices, referenced) in [([0, 1, 2, 0], false), ([0, 1, 3, 0], true)] {
+            let data = encode(indices, local);
+            for color_output in [gif::ColorOutput::Indexed, gif::ColorOutput::RGBA] {
+                let mut options = DecodeOptions::new();
+                options.set_color_output(color_output);
+                options.check_palette_padding(true);
+                let mut decoder = options.read_info(&data[..]).unwrap();
+                for _ in 0..2 {
+                    let frame = decoder.read_next_frame().unwrap().unwrap();
+                    assert_eq!(frame.palette_padding(), if local { 1 } else { 0 });
+                }
+                assert!(decoder.read_next_frame().unwrap().is_none());
+                // Each frame is listed
+                let expected: &[DecodingWarning] = if referenced {
+                    &[DecodingWarning::PaletteEntryPaddingReferenced(0), DecodingWarning::PaletteEntryPaddingReferenced(1)]
+                } else {
+                    &[]
+                };
+                assert_eq!(decoder.warnings(), expected, "{indices:?} {local}");
+            }
+
+            // The pixels aren't scanned by default
+            let mut decoder = Decoder::new(&data[..]).unwrap();
+            decoder.read_next_frame().unwrap().unwrap();
+            assert!(decoder.warnings().is_empty());
+        }
+    }
+
+    let mut decoder = gif::streaming_decoder::StreamingDecoder::new();
+    let data = encode([0; 4], false);
+    let mut rest = &data[..];
+    while decoder.global_palette().is_none() {
+        let (consumed, _) = decoder.update(rest, &mut gif::streaming_decoder::OutputBuffer::None).unwrap();
+        rest = &rest[consumed..];
+    }
+    assert_eq!(decoder.global_palette_padding(), 1);
+}
+
+#[test]
+fn frame_decoder_small_frame() {
+    let mut frame = Frame::from_rows(&[[0, 1, 2, 1], [1, 1, 3, 3]]).unwrap();
+    let pixels = frame.buffer.to_vec();
+    frame.make_lzw_pre_encoded();
+    // All of the LZW data is consumed before the last pixels come out
+    gif::streaming_decoder::FrameDecoder::new(DecodeOptions::new()).decode_lzw_encoded_frame(&mut frame).unwrap();
+    assert_eq!(&*frame.buffer, &pixels[..]);
+}
+
+#[test]
+fn pixel_format_of_frames() {
+    use gif::streaming_decoder::FrameDecoder;
+    use gif::{ColorOutput, EncodingError, EncodingFormatError, PixelFormat};
+
+    let data = gif::synth::pathological::well_formed();
+    for (color_output, format) in [(ColorOutput::Indexed, PixelFormat::Indexed), (ColorOutput::RGBA, PixelFormat::Rgba), (ColorOutput::RGB, PixelFormat::Rgb)] {
+        let mut options = DecodeOptions::new();
+        options.set_color_output(color_output);
+        let mut decoder = options.clone().read_info(&data[..]).unwrap();
+        assert_eq!(decoder.next_frame_info().unwrap().unwrap().pixel_format, PixelFormat::Unknown);
+        assert_eq!(decoder.read_next_frame().unwrap().unwrap().pixel_format, format);
+        let mut decoder = options.clone().read_slice(&data).unwrap();
+        assert_eq!(decoder.read_next_frame().unwrap().unwrap().pixel_format, format);
+
+        options.skip_frame_decoding(true);
+        let mut decoder = options.clone().read_info(&data[..]).unwrap();
+        let mut frame = decoder.read_next_frame().unwrap().unwrap().clone();
+        assert_eq!(frame.pixel_format, PixelFormat::Lzw);
+        let mut decoder = options.clone().read_slice(&data).unwrap();
+        assert_eq!(decoder.read_next_frame().unwrap().unwrap().pixel_format, PixelFormat::Lzw);
+
+        options.skip_frame_decoding(false);
+        let mut frame_decoder = FrameDecoder::new(options);
+        frame_decoder.set_global_palette(vec![0, 0, 0, 255, 255, 255]);
+        frame_decoder.decode_lzw_encoded_frame(&mut frame).unwrap();
+        assert_eq!(frame.pixel_format, format);
+        // The pixels can't be decoded again
+        let err = frame_decoder.decode_lzw_encoded_frame(&mut frame).unwrap_err();
+        assert!(matches!(err, gif::DecodingError::Format(err) if err.kind() == gif::DecodingFormatErrorKind::UnexpectedPixelFormat));
+    }
+
+    assert_eq!(Frame::default().pixel_format, PixelFormat::Unknown);
+    let mut frame = Frame::from_indexed_pixels(2, 2, vec![0, 1, 1, 0], None);
+    assert_eq!(frame.pixel_format, PixelFormat::Indexed);
+    assert_eq!(Frame::from_palette_pixels(2, 2, vec![0, 1, 1, 0], vec![0; 6], None).pixel_format, PixelFormat::Indexed);
+
+    // The encoder refuses frames that hold something else than it takes
+    let mut encoder = Encoder::new(Vec::new(), 2, 2, &[0, 0, 0, 255, 255, 255]).unwrap();
+    assert!(matches!(
+        encoder.write_lzw_pre_encoded_frame(&frame),
+        Err(EncodingError::Format(EncodingFormatError::UnexpectedPixelFormat(PixelFormat::Indexed))),
+    ));
+    frame.make_lzw_pre_encoded();
+    assert_eq!(frame.pixel_format, PixelFormat::Lzw);
+    assert!(matches!(
+        encoder.write_frame(&frame),
+        Err(EncodingError::Format(EncodingFormatError::UnexpectedPixelFormat(PixelFormat::Lzw))),
+    ));
+    encoder.write_lzw_pre_encoded_frame(&frame).unwrap();
+    let mut decoder = DecodeOptions::new().read_info(&data[..]).unwrap();
+    let mut options = DecodeOptions::new();
+    options.set_color_output(ColorOutput::RGBA);
+    let rgba = options.read_info(&data[..]).unwrap().read_next_frame().unwrap().unwrap().clone();
+    assert!(matches!(
+        encoder.write_frame(&rgba),
+        Err(EncodingError::Format(EncodingFormatError::UnexpectedPixelFormat(PixelFormat::Rgba))),
+    ));
+    encoder.write_frame(decoder.read_next_frame().unwrap().unwrap()).unwrap();
+}
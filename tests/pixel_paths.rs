@@ -0,0 +1,218 @@
+//! Every combination of interlacing, transparency and a frame offset, decoded through each way
+//! of reading pixels, and compared with a slow renderer of the original indices.
+#![cfg(feature = "std")]
+
+use gif::{ColorOutput, DecodeOptions, Encoder, Frame};
+
+const SCREEN: (u16, u16) = (9, 13);
+const SIZE: (u16, u16) = (5, 11);
+const PALETTE: [u8; 12] = [0, 0, 0, 0xFF, 0, 0, 0, 0xFF, 0, 0, 0, 0xFF];
+/// Index of the full screen frame under the tested frame
+const BACKGROUND: u8 = 3;
+const TRANSPARENT: u8 = 2;
+
+struct Case {
+    interlaced: bool,
+    transparent: bool,
+    offset: bool,
+}
+
+impl Case {
+    fn all() -> impl Iterator<Item = Self> {
+        (0..8).map(|bits| Self { interlaced: bits & 1 != 0, transparent: bits & 2 != 0, offset: bits & 4 != 0 })
+    }
+
+    fn position(&self) -> (u16, u16) {
+        if self.offset { (3, 2) } else { (0, 0) }
+    }
+
+    fn transparent(&self) -> Option<u8> {
+        if self.transparent { Some(TRANSPARENT) } else { None }
+    }
+
+    /// Indices of the tested frame in display order, with different values in every row and column
+    fn indices() -> Vec<u8> {
+        (0..SIZE.1).flat_map(|y| (0..SIZE.0).map(move |x| ((x * 3 + y * 5) % 4) as u8)).collect()
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let (left, top) = self.position();
+        let mut encoder = Encoder::new(vec![], SCREEN.0, SCREEN.1, &PALETTE).unwrap();
+        let background = vec![BACKGROUND; usize::from(SCREEN.0) * usize::from(SCREEN.1)];
+        encoder.write_frame(&Frame::from_indexed_pixels(SCREEN.0, SCREEN.1, background, None)).unwrap();
+        let mut frame = Frame::from_indexed_pixels_at(left, top, SIZE.0, SIZE.1, Self::indices(), self.transparent());
+        frame.interlaced = self.interlaced;
+        encoder.write_frame(&frame).unwrap();
+        encoder.into_inner().unwrap()
+    }
+
+    /// The screen after both frames, drawn one pixel at a time from the indices
+    fn reference(&self) -> Vec<[u8; 4]> {
+        let color = |index: u8| {
+            let rgb = &PALETTE[usize::from(index) * 3..][..3];
+            [rgb[0], rgb[1], rgb[2], 0xFF]
+        };
+        let mut screen = vec![color(BACKGROUND); usize::from(SCREEN.0) * usize::from(SCREEN.1)];
+        let (left, top) = self.position();
+        let indices = Self::indices();
+        for y in 0..SIZE.1 {
+            for x in 0..SIZE.0 {
+                let index = indices[usize::from(y) * usize::from(SIZE.0) + usize::from(x)];
+                if Some(index) != self.transparent() {
+                    screen[usize::from(top + y) * usize::from(SCREEN.0) + usize::from(left + x)] = color(index);
+                }
+            }
+        }
+        screen
+    }
+
+    /// Draws decoded RGBA frames, in display order, onto the screen
+    fn draw(&self, frames: &[(u16, u16, u16, Vec<u8>)]) -> Vec<[u8; 4]> {
+        let mut screen = vec![[0; 4]; usize::from(SCREEN.0) * usize::from(SCREEN.1)];
+        for (left, top, width, rgba) in frames {
+            for (i, pixel) in rgba.chunks(4).enumerate() {
+                if pixel[3] != 0 {
+                    let (x, y) = (usize::from(*left) + i % usize::from(*width), usize::from(*top) + i / usize::from(*width));
+                    screen[y * usize::from(SCREEN.0) + x].copy_from_slice(pixel);
+                }
+            }
+        }
+        screen
+    }
+}
+
+/// Rows of an interlaced frame in the order in which they are stored
+fn interlaced_rows(height: u16) -> Vec<usize> {
+    let height = usize::from(height);
+    [(0, 8), (4, 8), (2, 4), (1, 2)].iter()
+        .flat_map(|&(first, step)| (first..height).step_by(step))
+        .collect()
+}
+
+fn rgba_options() -> DecodeOptions {
+    let mut options = DecodeOptions::new();
+    options.set_color_output(ColorOutput::RGBA);
+    options
+}
+
+#[test]
+fn read_next_frame() {
+    for case in Case::all() {
+        let data = case.encode();
+        let mut decoder = rgba_options().read_info(&data[..]).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push((frame.left, frame.top, frame.width, frame.buffer.to_vec()));
+        }
+        assert_eq!(case.draw(&frames), case.reference());
+    }
+}
+
+#[test]
+fn slice_decoder() {
+    for case in Case::all() {
+        let data = case.encode();
+        let mut decoder = rgba_options().read_slice(&data).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push((frame.left, frame.top, frame.width, frame.buffer.to_vec()));
+        }
+        assert_eq!(case.draw(&frames), case.reference());
+    }
+}
+
+#[test]
+fn read_into_buffer() {
+    for case in Case::all() {
+        let data = case.encode();
+        let mut decoder = rgba_options().read_info(&data[..]).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.next_frame_info().unwrap() {
+            let (left, top, width) = (frame.left, frame.top, frame.width);
+            let mut buf = vec![0; decoder.buffer_size()];
+            decoder.read_into_buffer(&mut buf).unwrap();
+            frames.push((left, top, width, buf));
+        }
+        assert_eq!(case.draw(&frames), case.reference());
+    }
+}
+
+#[test]
+fn read_into_buffer_progressive() {
+    for case in Case::all() {
+        let data = case.encode();
+        let mut decoder = rgba_options().read_info(&data[..]).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.next_frame_info().unwrap() {
+            let (left, top, width, interlaced) = (frame.left, frame.top, frame.width, frame.interlaced);
+            let mut buf = vec![0; decoder.buffer_size()];
+            let mut passes = 0;
+            decoder.read_into_buffer_progressive(&mut buf, |_, _| passes += 1).unwrap();
+            assert_eq!(passes, if interlaced { 4 } else { 0 });
+            frames.push((left, top, width, buf));
+        }
+        assert_eq!(case.draw(&frames), case.reference());
+    }
+}
+
+#[test]
+fn read_rows() {
+    for case in Case::all() {
+        let data = case.encode();
+        let mut decoder = rgba_options().read_info(&data[..]).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.next_frame_info().unwrap() {
+            let (left, top, width) = (frame.left, frame.top, frame.width);
+            let mut buf = vec![0; decoder.buffer_size()];
+            let line_length = decoder.line_length();
+            decoder.read_rows(|row, pixels: &[u8]| {
+                buf[usize::from(row) * line_length..][..line_length].copy_from_slice(pixels);
+                Ok::<_, gif::DecodingError>(())
+            }).unwrap();
+            frames.push((left, top, width, buf));
+        }
+        assert_eq!(case.draw(&frames), case.reference());
+    }
+}
+
+#[test]
+fn rows_in_file_order() {
+    for case in Case::all() {
+        let data = case.encode();
+        let mut options = rgba_options();
+        options.deinterlace(false);
+        let mut decoder = options.read_info(&data[..]).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            let line_length = usize::from(frame.width) * 4;
+            let mut buf = frame.buffer.to_vec();
+            if frame.interlaced {
+                for (stored, row) in interlaced_rows(frame.height).into_iter().enumerate() {
+                    buf[row * line_length..][..line_length].copy_from_slice(&frame.buffer[stored * line_length..][..line_length]);
+                }
+            }
+            frames.push((frame.left, frame.top, frame.width, buf));
+        }
+        assert_eq!(case.draw(&frames), case.reference());
+    }
+}
+
+#[test]
+fn indexed_output() {
+    for case in Case::all() {
+        let data = case.encode();
+        let mut decoder = DecodeOptions::new().read_info(&data[..]).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            let rgba = frame.buffer.iter().flat_map(|&index| match frame.transparent {
+                Some(transparent) if transparent == index => [0; 4],
+                _ => {
+                    let rgb = &PALETTE[usize::from(index) * 3..][..3];
+                    [rgb[0], rgb[1], rgb[2], 0xFF]
+                },
+            }).collect();
+            frames.push((frame.left, frame.top, frame.width, rgba));
+        }
+        assert_eq!(case.draw(&frames), case.reference());
+    }
+}
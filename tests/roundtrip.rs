@@ -68,7 +68,7 @@ fn round_trip_from_image(original: &[u8]) {
 
     let mut encoder = Encoder::new(vec![], width, height, &global_palette).unwrap();
     encoder.set_repeat(repeat).unwrap();
-    encoder.write_raw_extension(AnyExtension(gif::Extension::Comment as _), &[b"hello"]).unwrap();
+    encoder.write_extension_blocks(AnyExtension(gif::Extension::Comment as _), [&b"hello"[..]]).unwrap();
     for frame in &frames {
         encoder.write_frame(frame).unwrap();
     }
@@ -202,3 +202,910 @@ fn palette_fail() {
     f.buffer = [1][..].into();
     assert!(matches!(encoder.write_frame(&f), Err(gif::EncodingError::Format(gif::EncodingFormatError::MissingColorPalette))));
 }
+
+#[test]
+fn palette_of_partial_colors_fails() {
+    let rgba = [0xFF, 0, 0, 0xFF, 0, 0xFF, 0, 0xFF];
+    match Encoder::new(vec![], 1, 1, &rgba) {
+        Err(err @ gif::EncodingError::Format(gif::EncodingFormatError::InvalidPaletteLength { len: 8 })) => {
+            assert!(err.to_string().contains("8 bytes"), "{err}");
+        },
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+
+    let mut encoder = Encoder::new(vec![], 1, 1, &[]).unwrap();
+    let mut f = Frame::default();
+    f.width = 1;
+    f.height = 1;
+    f.buffer = [1][..].into();
+    f.palette = Some(rgba.to_vec());
+    assert!(matches!(encoder.write_frame(&f), Err(gif::EncodingError::Format(gif::EncodingFormatError::InvalidPaletteLength { len: 8 }))));
+}
+
+#[test]
+fn file_without_frames() {
+    for palette in [&[][..], &[0xFF, 0, 0, 0, 0xFF, 0][..]] {
+        let data = Encoder::new(vec![], 3, 2, palette).unwrap().into_inner().unwrap();
+        let mut expected = b"GIF89a\x03\x00\x02\x00".to_vec();
+        if palette.is_empty() {
+            expected.extend_from_slice(&[0, 0, 0]);
+        } else {
+            expected.extend_from_slice(&[0x80, 0, 0]);
+            expected.extend_from_slice(palette);
+        }
+        expected.push(0x3B);
+        assert_eq!(data, expected);
+
+        let mut decoder = Decoder::new(&*data).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (3, 2));
+        assert_eq!(decoder.global_palette().is_some(), !palette.is_empty());
+        assert!(decoder.next_frame_info().unwrap().is_none());
+        assert!(decoder.read_next_frame().unwrap().is_none());
+        assert!(!decoder.has_fallback_palette());
+
+        let index = gif::Index::build(&data).unwrap();
+        assert_eq!(index.frame_count(), 0);
+        assert!(!index.is_animated());
+        assert_eq!(index.duration(), std::time::Duration::ZERO);
+    }
+}
+
+#[test]
+fn palette_of_colors() {
+    let colors = [[0xFF, 0, 0], [0, 0xFF, 0], [0, 0, 0xFF]];
+    let palette = gif::Palette::new(&colors);
+    assert_eq!(palette.len(), 3);
+    assert_eq!(palette, gif::Palette::from_rgb(&[0xFF, 0, 0, 0, 0xFF, 0, 0, 0, 0xFF]).unwrap());
+
+    let mut data = vec![];
+    {
+        let mut encoder = Encoder::with_palette(&mut data, 1, 1, palette).unwrap();
+        encoder.write_frame(&Frame { width: 1, height: 1, buffer: [2][..].into(), ..Frame::default() }).unwrap();
+    }
+    let decoder = Decoder::new(&*data).unwrap();
+    assert!(decoder.global_palette().unwrap()[..9].iter().copied().eq(palette.colors().flatten()));
+}
+
+#[test]
+fn shared_local_palette() {
+    let palette = [0, 0, 0, 0xFF, 0, 0, 0, 0xFF, 0];
+    let frames: Vec<Frame> = (0..4u8)
+        .map(|i| Frame::from_indexed_pixels(2, 2, vec![i % 3, 1, 2, 0], None))
+        .collect();
+
+    let mut cloned = vec![];
+    {
+        let mut encoder = Encoder::new(&mut cloned, 2, 2, &[0xFF; 6]).unwrap();
+        for frame in &frames {
+            encoder.write_frame(&Frame { palette: Some(palette.to_vec()), ..frame.clone() }).unwrap();
+        }
+    }
+    let mut shared = vec![];
+    {
+        let mut encoder = Encoder::new(&mut shared, 2, 2, &[0xFF; 6]).unwrap();
+        encoder.set_shared_local_palette(Some(gif::Palette::from_rgb(&palette).unwrap())).unwrap();
+        for frame in &frames {
+            encoder.write_frame(&Frame { shared_palette: true, ..frame.clone() }).unwrap();
+        }
+    }
+    assert_eq!(shared, cloned);
+
+    let mut options = DecodeOptions::new();
+    options.set_color_output(ColorOutput::RGBA);
+    let mut decoder = options.read_info(&*shared).unwrap();
+    for frame in &frames {
+        let decoded = decoder.read_next_frame().unwrap().unwrap();
+        let expected: Vec<u8> = frame.buffer.iter()
+            .flat_map(|&index| [palette[index as usize * 3], palette[index as usize * 3 + 1], palette[index as usize * 3 + 2], 0xFF])
+            .collect();
+        assert_eq!(&*decoded.buffer, &expected[..]);
+    }
+
+    // Frames with a palette of their own, and unmarked frames, are unaffected
+    let mut data = vec![];
+    {
+        let mut encoder = Encoder::new(&mut data, 2, 2, &[0xFF; 6]).unwrap();
+        encoder.set_shared_local_palette(Some(gif::Palette::from_rgb(&palette).unwrap())).unwrap();
+        encoder.write_frame(&Frame { palette: Some(vec![1, 2, 3, 4, 5, 6]), shared_palette: true, ..frames[0].clone() }).unwrap();
+        encoder.write_frame(&frames[0]).unwrap();
+        // Marked frames need the shared palette
+        encoder.set_shared_local_palette(None).unwrap();
+        let err = encoder.write_frame(&Frame { shared_palette: true, ..frames[0].clone() }).unwrap_err();
+        assert!(matches!(err, gif::EncodingError::Format(gif::EncodingFormatError::MissingColorPalette)), "{err}");
+    }
+    let mut decoder = Decoder::new(&*data).unwrap();
+    assert_eq!(decoder.read_next_frame().unwrap().unwrap().palette.as_deref(), Some(&[1, 2, 3, 4, 5, 6][..]));
+    assert_eq!(decoder.read_next_frame().unwrap().unwrap().palette, None);
+}
+
+#[test]
+fn local_palette_flags_round_trip() {
+    let sorted = Frame {
+        dispose: gif::DisposalMethod::Any,
+        descriptor_flags: 0b0010_0000,
+        ..Frame::from_palette_pixels(2, 2, vec![0, 1, 2, 3], vec![9; 12], None)
+    };
+    let global = Frame { dispose: gif::DisposalMethod::Any, ..Frame::from_indexed_pixels(2, 2, vec![1, 0, 0, 1], None) };
+    let mut data = vec![];
+    {
+        let mut encoder = Encoder::new(&mut data, 2, 2, &[0, 0, 0, 0xFF, 0xFF, 0xFF]).unwrap();
+        encoder.write_frame(&sorted).unwrap();
+        encoder.write_frame(&global).unwrap();
+    }
+
+    let mut decoder = Decoder::new(&*data).unwrap();
+    let mut frames = vec![];
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        frames.push(frame.clone());
+    }
+    assert_eq!(frames[0].descriptor_flags, 0b1010_0001);
+    assert!(!frames[0].uses_global_palette());
+    assert_eq!(frames[0].declared_palette_len(), Some(4));
+    assert_eq!(frames[1].descriptor_flags, 0);
+    assert!(frames[1].uses_global_palette());
+    assert_eq!(frames[1].declared_palette_len(), None);
+
+    // Re-encoding keeps the sort flag and which frame has a local palette
+    let mut data2 = vec![];
+    {
+        let mut encoder = Encoder::new(&mut data2, 2, 2, decoder.global_palette().unwrap()).unwrap();
+        for frame in &frames {
+            encoder.write_frame(frame).unwrap();
+        }
+    }
+    assert_eq!(data, data2);
+}
+
+#[test]
+fn palette_sort_flags_round_trip() {
+    let sorted = Frame {
+        dispose: gif::DisposalMethod::Any,
+        descriptor_flags: 0b0010_0000,
+        ..Frame::from_palette_pixels(2, 2, vec![0, 1, 2, 3], vec![9; 12], None)
+    };
+    let mut data = vec![];
+    {
+        let mut encoder = Encoder::new(&mut data, 2, 2, &[0, 0, 0, 0xFF, 0xFF, 0xFF]).unwrap();
+        encoder.write_frame(&sorted).unwrap();
+    }
+    // The encoder leaves the sort flag of the global palette unset by default
+    assert_eq!(data[10], 0b1000_0000);
+    data[10] |= 0b0000_1000;
+
+    let mut decoder = Decoder::new(&*data).unwrap();
+    let screen = *decoder.screen_descriptor();
+    assert!(screen.sort_flag);
+    let frame = decoder.read_next_frame().unwrap().unwrap().clone();
+    assert!(frame.palette_sorted());
+    assert_eq!(frame.declared_palette_len(), Some(4));
+
+    let mut profile = gif::CompatProfile::new();
+    profile.global_palette_sorted(screen.sort_flag);
+    profile.color_resolution(Some(screen.color_resolution - 1));
+    let mut data2 = vec![];
+    {
+        let palette = gif::Palette::from_rgb(decoder.global_palette().unwrap()).unwrap();
+        let mut encoder = Encoder::with_compat_profile(&mut data2, 2, 2, palette, profile).unwrap();
+        encoder.write_frame(&frame).unwrap();
+    }
+    assert_eq!(data, data2);
+
+    // Frames without a local palette aren't sorted, whatever the flag says
+    let frame = Frame { descriptor_flags: 0b0010_0000, ..Frame::default() };
+    assert!(!frame.palette_sorted());
+}
+
+#[test]
+#[allow(deprecated)]
+fn extension_blocks_framing() {
+    fn encode(write: impl FnOnce(&mut Encoder<Vec<u8>>)) -> Vec<u8> {
+        let mut encoder = Encoder::new(vec![], 1, 1, &[]).unwrap();
+        let header = encoder.get_ref().len();
+        write(&mut encoder);
+        encoder.get_ref()[header..].to_vec()
+    }
+
+    let long = [7; 600];
+    let cases: [&[&[u8]]; 5] = [&[], &[b"hello"], &[&[], b"a", &[]], &[&long, b"tail"], &[&[0; 255], &[0; 256]]];
+    for blocks in cases {
+        let raw = encode(|e| e.write_raw_extension(AnyExtension(0xAB), blocks).unwrap());
+        let new = encode(|e| e.write_extension_blocks(AnyExtension(0xAB), blocks.iter().copied()).unwrap());
+        assert_eq!(raw, new);
+    }
+    assert_eq!(encode(|e| e.write_extension_blocks(AnyExtension(0xFE), []).unwrap()), [0x21, 0xFE, 0]);
+    assert_eq!(encode(|e| e.write_extension_blocks(AnyExtension(0xFE), [&[1; 256][..]]).unwrap()).len(), 2 + 1 + 255 + 1 + 1 + 1);
+
+    // The extensions of `ExtensionData` have the same framing
+    let control = gif::ExtensionData::new_control_ext(300, gif::DisposalMethod::Background, false, Some(5));
+    assert_eq!(
+        encode(|e| e.write_extension(control).unwrap()),
+        [0x21, 0xF9, 4, 0b0000_1001, 44, 1, 5, 0],
+    );
+    let mut looping = vec![0x21, 0xFF, 11];
+    looping.extend_from_slice(b"NETSCAPE2.0");
+    looping.extend_from_slice(&[3, 1, 7, 1, 0]);
+    assert_eq!(encode(|e| e.set_repeat(gif::Repeat::Finite(263)).unwrap()), looping);
+    assert_eq!(
+        encode(|e| e.write_extension_blocks(AnyExtension(0xFF), [&b"NETSCAPE2.0"[..], &[1, 7, 1]]).unwrap()),
+        looping,
+    );
+}
+
+#[test]
+fn compat_profile_layout() {
+    let frame = Frame { dispose: gif::DisposalMethod::Any, ..Frame::from_indexed_pixels(1, 1, vec![1], None) };
+    let encode = |profile: gif::CompatProfile| {
+        let mut data = vec![];
+        let mut encoder = Encoder::with_compat_profile(&mut data, 1, 1, gif::Palette::new(&[[0, 0, 0], [0xFF, 0xFF, 0xFF]]), profile).unwrap();
+        encoder.write_frame(&frame).unwrap();
+        drop(encoder);
+        data
+    };
+
+    let mut data = vec![];
+    Encoder::new(&mut data, 1, 1, &[0, 0, 0, 0xFF, 0xFF, 0xFF]).unwrap().write_frame(&frame).unwrap();
+    assert_eq!(encode(gif::CompatProfile::new()), data);
+
+    let mut profile = gif::CompatProfile::new();
+    profile.sub_block_size(1);
+    profile.always_write_control_ext(false);
+    profile.full_palettes(true);
+    profile.color_resolution(Some(0));
+    let mut expected = b"GIF89a\x01\x00\x01\x00\x87\x00\x00".to_vec();
+    expected.extend_from_slice(&[0, 0, 0, 0xFF, 0xFF, 0xFF]);
+    expected.resize(expected.len() + 254 * 3, 0);
+    // No control extension, and one byte per sub-block
+    expected.extend_from_slice(b"\x2C\x00\x00\x00\x00\x01\x00\x01\x00\x00");
+    expected.extend_from_slice(b"\x02\x01\x4C\x01\x01\x00\x3B");
+    assert_eq!(encode(profile), expected);
+}
+
+/// Compares the output of each preset with a fixture in `tests/compat`, which lists the bytes
+/// in hex with `#` comments
+#[test]
+fn compat_profile_presets() {
+    let palette = [[0, 0, 0], [0xFF, 0, 0], [0, 0, 0xFF]];
+    let local_palette = [0xFF, 0xFF, 0xFF, 0, 0xFF, 0];
+    let frames = [
+        Frame { dispose: gif::DisposalMethod::Any, ..Frame::from_indexed_pixels(2, 2, vec![0, 1, 2, 1], None) },
+        Frame {
+            delay: 10,
+            dispose: gif::DisposalMethod::Background,
+            ..Frame::from_palette_pixels(2, 2, vec![0, 1, 1, 0], local_palette.to_vec(), Some(0))
+        },
+    ];
+    let presets = [
+        ("gifsicle", gif::CompatProfile::gifsicle()),
+        ("old_netscape", gif::CompatProfile::old_netscape()),
+        ("strict_89a", gif::CompatProfile::strict_89a()),
+        ("this_crate_legacy", gif::CompatProfile::this_crate_legacy()),
+    ];
+    for (name, profile) in presets {
+        let mut data = vec![];
+        let mut encoder = Encoder::with_compat_profile(&mut data, 2, 2, gif::Palette::new(&palette), profile).unwrap();
+        for frame in &frames {
+            encoder.write_frame(frame).unwrap();
+        }
+        drop(encoder);
+
+        let fixture = std::fs::read_to_string(format!("{}/tests/compat/{name}.hex", env!("CARGO_MANIFEST_DIR"))).unwrap();
+        let expected: Vec<u8> = fixture.lines()
+            .map(|line| line.split('#').next().unwrap())
+            .flat_map(str::split_whitespace)
+            .map(|byte| u8::from_str_radix(byte, 16).unwrap())
+            .collect();
+        assert_eq!(data, expected, "{name}");
+    }
+}
+
+#[test]
+fn color_resolution_round_trip() {
+    let frame = Frame::from_indexed_pixels(1, 1, vec![1], None);
+    let encode = |resolution: Option<u8>| {
+        let mut profile = gif::CompatProfile::new();
+        profile.color_resolution(resolution);
+        let mut data = vec![];
+        let mut encoder = Encoder::with_compat_profile(&mut data, 1, 1, gif::Palette::new(&[[0, 0, 0], [0xFF, 0xFF, 0xFF]]), profile).unwrap();
+        encoder.write_frame(&frame).unwrap();
+        drop(encoder);
+        data
+    };
+
+    // The default is the size field of the global palette
+    assert_eq!(Decoder::new(&*encode(None)).unwrap().color_resolution(), 1);
+    for field in 0..8 {
+        let data = encode(Some(field));
+        let decoder = Decoder::new(&*data).unwrap();
+        assert_eq!(decoder.color_resolution(), field + 1);
+        assert_eq!(decoder.screen_descriptor().color_resolution, field + 1);
+        assert_eq!(gif::SliceDecoder::new(&data).unwrap().color_resolution(), field + 1);
+        assert_eq!(encode(Some(decoder.color_resolution() - 1)), data);
+    }
+}
+
+#[test]
+fn frame_outside_canvas() {
+    fn encode(check: bool, left: u16, top: u16) -> Result<Vec<u8>, gif::EncodingError> {
+        let mut encoder = Encoder::new(vec![], 4, 4, &[0, 0, 0]).unwrap();
+        encoder.check_frame_consistency(check);
+        for i in 0..2 {
+            let mut f = Frame::default();
+            f.width = 2;
+            f.height = 2;
+            f.buffer = [0; 4][..].into();
+            if i == 1 {
+                f.left = left;
+                f.top = top;
+            }
+            encoder.write_frame(&f).unwrap();
+        }
+        encoder.into_inner()
+    }
+
+    assert!(encode(true, 2, 2).is_ok());
+    assert!(encode(false, 3, 0).is_ok());
+    match encode(true, 3, 0) {
+        Err(gif::EncodingError::FrameOutsideCanvas { frame_index, rect, canvas }) => {
+            assert_eq!(frame_index, 1);
+            assert_eq!(rect, gif::Rect { left: 3, top: 0, width: 2, height: 2 });
+            assert_eq!(canvas, (4, 4));
+        },
+        other => panic!("unexpected result {other:?}"),
+    }
+    assert!(matches!(encode(true, 0, 0xFFFF), Err(gif::EncodingError::FrameOutsideCanvas { .. })));
+}
+
+#[test]
+fn canvas_grows_to_contain_frames() {
+    use std::io::Cursor;
+
+    fn encode(prefix: &[u8], width: u16, height: u16, frames: &[(u16, u16)]) -> Result<Vec<u8>, gif::EncodingError> {
+        let mut w = Cursor::new(prefix.to_vec());
+        w.set_position(prefix.len() as u64);
+        let mut encoder = Encoder::new(w, width, height, &[0, 0, 0, 0xFF, 0xFF, 0xFF])?;
+        for &(left, top) in frames {
+            encoder.write_frame(&Frame { left, top, ..Frame::from_indexed_pixels(2, 3, vec![1; 6], None) })?;
+        }
+        let w = encoder.into_inner_grown()?;
+        assert_eq!(w.position(), w.get_ref().len() as u64);
+        Ok(w.into_inner()[prefix.len()..].to_vec())
+    }
+    let screen = |data: &[u8]| {
+        let decoder = Decoder::new(data).unwrap();
+        (decoder.width(), decoder.height())
+    };
+
+    // The size given to the encoder stays if the frames fit
+    assert_eq!(screen(&encode(&[], 4, 4, &[(0, 0), (2, 1)]).unwrap()), (4, 4));
+    // Growing it
+    assert_eq!(screen(&encode(&[], 4, 4, &[(0, 0), (3, 0)]).unwrap()), (5, 4));
+    assert_eq!(screen(&encode(b"prefix", 4, 4, &[(0, 0), (3, 2)]).unwrap()), (5, 5));
+    // Inferring it
+    assert_eq!(screen(&encode(&[], 0, 0, &[(1, 0), (0, 4)]).unwrap()), (3, 7));
+    assert_eq!(screen(&encode(&[], 0, 0, &[]).unwrap()), (0, 0));
+    match encode(&[], 0, 0, &[(0, 0), (0xFFFF, 0)]) {
+        Err(gif::EncodingError::FrameOutsideCanvas { frame_index, canvas, .. }) => {
+            assert_eq!(frame_index, 1);
+            assert_eq!(canvas, (0xFFFF, 0xFFFF));
+        },
+        other => panic!("unexpected result {other:?}"),
+    }
+}
+
+#[test]
+fn frame_consistency_check_matches_validate_frame_in_screen() {
+    use gif::{validate_frame_in_screen, FrameConsistency, Rect};
+
+    let screen = (3, 2);
+    for (left, top, width, height) in (0..5).flat_map(|left| (0..4).map(move |top| (left, top)))
+        .flat_map(|(left, top)| (1..5).flat_map(move |width| (1..4).map(move |height| (left, top, width, height)))) {
+        let rect = Rect { left, top, width, height };
+        let mut encoder = Encoder::new(vec![], screen.0, screen.1, &[0; 6]).unwrap();
+        encoder.check_frame_consistency(true);
+        let frame = Frame::from_indexed_pixels_at(left, top, width, height, vec![0; usize::from(width * height)], None);
+        encoder.write_frame(&frame).unwrap();
+        let expected = validate_frame_in_screen(rect, screen, FrameConsistency::Error);
+        assert_eq!(encoder.into_inner().is_ok(), expected.is_ok(), "{rect:?}");
+    }
+}
+
+#[test]
+#[cfg(feature = "color_quant")]
+fn truecolor_frame_stack() {
+    const WIDTH: u16 = 32;
+    const HEIGHT: u16 = 32;
+    // Every pixel has a different color
+    let mut rgba: Vec<u8> = (0..WIDTH as u32 * HEIGHT as u32)
+        .flat_map(|i| [(i * 7) as u8, (i >> 2) as u8, (i * 13) as u8, 0xFF])
+        .collect();
+    rgba[3] = 0;
+
+    fn decode_composite(gif: &[u8]) -> (Vec<u8>, Vec<u16>) {
+        let mut options = DecodeOptions::new();
+        options.set_color_output(ColorOutput::RGBA);
+        let mut decoder = options.read_info(gif).unwrap();
+        let mut canvas = vec![0; WIDTH as usize * HEIGHT as usize * 4];
+        let mut delays = vec![];
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!(frame.dispose, gif::DisposalMethod::Keep);
+            delays.push(frame.delay);
+            for (y, line) in frame.buffer.chunks_exact(frame.width as usize * 4).enumerate() {
+                for (x, pix) in line.chunks_exact(4).enumerate() {
+                    if pix[3] != 0 {
+                        let offset = ((frame.top as usize + y) * WIDTH as usize + frame.left as usize + x) * 4;
+                        canvas[offset..offset + 4].copy_from_slice(pix);
+                    }
+                }
+            }
+        }
+        (canvas, delays)
+    }
+
+    let mut encoder = Encoder::new(vec![], WIDTH, HEIGHT, &[]).unwrap();
+    assert_eq!(encoder.write_truecolor_frame(WIDTH, HEIGHT, &rgba, 8, 50).unwrap(), 4);
+    let (canvas, delays) = decode_composite(&encoder.into_inner().unwrap());
+    assert_eq!(delays, [0, 0, 0, 50]);
+    let mut expected = rgba.clone();
+    expected[..4].copy_from_slice(&[0; 4]);
+    assert_eq!(canvas, expected);
+
+    // Not enough frames for an exact palette in each. Each channel is off by at most 25.
+    #[track_caller]
+    fn assert_within_bound(canvas: &[u8], expected: &[u8]) {
+        for (pix, expected) in canvas.chunks_exact(4).zip(expected.chunks_exact(4)) {
+            if expected[3] == 0 {
+                assert_eq!(pix, [0; 4]);
+            } else {
+                assert_eq!(pix[3], 0xFF);
+                assert!(pix[..3].iter().zip(expected).all(|(&a, &b)| a.abs_diff(b) <= 25), "{pix:?} for {expected:?}");
+            }
+        }
+    }
+    let mut encoder = Encoder::new(vec![], WIDTH, HEIGHT, &[]).unwrap();
+    assert_eq!(encoder.write_truecolor_frame(WIDTH, HEIGHT, &rgba, 3, 50).unwrap(), 3);
+    let (canvas, delays) = decode_composite(&encoder.into_inner().unwrap());
+    assert_eq!(delays, [0, 0, 50]);
+    assert_within_bound(&canvas, &expected);
+
+    // Colors spread over the RGB cube, with 16 levels of red and green and 4 of blue
+    let mut spread: Vec<u8> = (0..WIDTH as u32 * HEIGHT as u32)
+        .flat_map(|i| [(i % 16 * 17) as u8, (i / 16 % 16 * 17) as u8, (i / 256 * 17) as u8, 0xFF])
+        .collect();
+    spread[7] = 0;
+    let mut expected = spread.clone();
+    expected[4..8].copy_from_slice(&[0; 4]);
+    let mut encoder = Encoder::new(vec![], WIDTH, HEIGHT, &[]).unwrap();
+    assert_eq!(encoder.write_truecolor_frame(WIDTH, HEIGHT, &spread, 1, 0).unwrap(), 1);
+    let (canvas, _) = decode_composite(&encoder.into_inner().unwrap());
+    assert_within_bound(&canvas, &expected);
+
+    // A smooth gradient with 1024 close colors
+    let gradient: Vec<u8> = (0..WIDTH as u32 * HEIGHT as u32)
+        .flat_map(|i| [(i / 4) as u8, (i / 4) as u8, (i % 4 * 8) as u8, 0xFF])
+        .collect();
+    let mut encoder = Encoder::new(vec![], WIDTH, HEIGHT, &[]).unwrap();
+    assert_eq!(encoder.write_truecolor_frame(WIDTH, HEIGHT, &gradient, 1, 0).unwrap(), 1);
+    let (canvas, _) = decode_composite(&encoder.into_inner().unwrap());
+    assert_within_bound(&canvas, &gradient);
+
+    // Nothing to write without pixels
+    let mut encoder = Encoder::new(vec![], WIDTH, HEIGHT, &[]).unwrap();
+    assert_eq!(encoder.write_truecolor_frame(WIDTH, 0, &[], 3, 50).unwrap(), 0);
+    assert_eq!(encoder.write_truecolor_frame(0, HEIGHT, &[], 3, 50).unwrap(), 0);
+    let data = encoder.into_inner().unwrap();
+    assert!(Decoder::new(&data[..]).unwrap().next_frame_info().unwrap().is_none());
+}
+
+#[test]
+fn frame_offsets_roundtrip() {
+    let corners = [(0, 0), (8, 0), (0, 7), (8, 7)];
+    let mut encoder = Encoder::new(vec![], 10, 9, &[0, 0, 0, 0xFF, 0xFF, 0xFF]).unwrap();
+    encoder.check_frame_consistency(true);
+    for (i, &(left, top)) in corners.iter().enumerate() {
+        encoder.write_frame(&Frame::from_indexed_pixels_at(left, top, 2, 2, vec![i as u8 % 2; 4], None)).unwrap();
+    }
+    #[cfg(feature = "color_quant")]
+    encoder.write_frame(&Frame::from_rgba_at(9, 8, 1, 1, &mut [1, 2, 3, 0xFF])).unwrap();
+    let data = encoder.into_inner().unwrap();
+
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    for (i, &(left, top)) in corners.iter().enumerate() {
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        assert_eq!((frame.left, frame.top, frame.width, frame.height), (left, top, 2, 2));
+        assert_eq!(&*frame.buffer, &[i as u8 % 2; 4]);
+    }
+    #[cfg(feature = "color_quant")]
+    {
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        assert_eq!((frame.left, frame.top, frame.width, frame.height), (9, 8, 1, 1));
+        assert_eq!(&frame.palette.as_ref().unwrap()[..3], &[1, 2, 3]);
+    }
+    assert!(decoder.read_next_frame().unwrap().is_none());
+}
+
+#[test]
+fn frame_offset_at_max_edge() {
+    let frame = Frame::from_indexed_pixels_at(0xFFFE, 0, 1, 1, vec![0], None);
+    assert_eq!(frame.left + frame.width, 0xFFFF);
+}
+
+#[test]
+#[should_panic]
+fn frame_offset_beyond_max_edge() {
+    let _ = Frame::from_indexed_pixels_at(0xFFFF, 0, 1, 1, vec![0], None);
+}
+
+#[test]
+fn pad_first_frame() {
+    use gif::PadFirstFrame;
+
+    fn encode(pad: Option<PadFirstFrame>, first: Frame<'_>) -> Vec<Frame<'static>> {
+        let mut encoder = Encoder::new(vec![], 10, 8, &[0, 0, 0, 0xFF, 0xFF, 0xFF]).unwrap();
+        encoder.pad_first_frame(pad);
+        encoder.write_frame(&first).unwrap();
+        encoder.write_frame(&Frame { delay: 5, ..Frame::from_indexed_pixels_at(1, 1, 2, 2, vec![0; 4], None) }).unwrap();
+        let data = encoder.into_inner().unwrap();
+        Decoder::new(&data[..]).unwrap().into_iter().map(Result::unwrap).collect()
+    }
+    let patch = Frame { delay: 7, ..Frame::from_indexed_pixels_at(2, 2, 4, 4, vec![0; 16], None) };
+
+    let frames = encode(Some(PadFirstFrame::Index(1)), patch.clone());
+    assert_eq!(frames.len(), 3);
+    assert_eq!(frames.iter().map(|f| f.delay).sum::<u16>(), 12);
+    let pad = &frames[0];
+    assert_eq!((pad.left, pad.top, pad.width, pad.height, pad.delay), (0, 0, 10, 8, 0));
+    assert_eq!(pad.dispose, gif::DisposalMethod::Keep);
+    // Compositing the padding and the first frame covers the whole canvas
+    let mut canvas = [[None; 10]; 8];
+    for frame in &frames[..2] {
+        for (canvas_row, row) in canvas[usize::from(frame.top)..].iter_mut().zip(frame.rows()) {
+            for (pixel, &index) in canvas_row[usize::from(frame.left)..].iter_mut().zip(row) {
+                *pixel = Some(index);
+            }
+        }
+    }
+    assert!(canvas.iter().flatten().all(|&index| index.is_some()));
+    assert_eq!(canvas.iter().flatten().filter(|&&index| index == Some(1)).count(), 80 - 16);
+
+    let frames = encode(Some(PadFirstFrame::Color([1, 2, 3])), patch.clone());
+    assert_eq!(&frames[0].palette.as_ref().unwrap()[..3], &[1, 2, 3]);
+    assert!(frames[0].buffer.iter().all(|&index| index == 0));
+
+    assert_eq!(encode(None, patch.clone()).len(), 2);
+    let full = Frame::from_indexed_pixels(10, 8, vec![0; 80], None);
+    assert_eq!(encode(Some(PadFirstFrame::Index(1)), full).len(), 2);
+
+    // Errors count only the frames given to the encoder
+    let mut encoder = Encoder::new(vec![], 10, 8, &[0, 0, 0, 0xFF, 0xFF, 0xFF]).unwrap();
+    encoder.pad_first_frame(Some(PadFirstFrame::Index(1)));
+    encoder.check_frame_consistency(true);
+    encoder.write_frame(&patch).unwrap();
+    encoder.write_frame(&Frame::from_indexed_pixels_at(9, 7, 2, 2, vec![0; 4], None)).unwrap();
+    match encoder.into_inner() {
+        Err(gif::EncodingError::FrameOutsideCanvas { frame_index, .. }) => assert_eq!(frame_index, 1),
+        other => panic!("{other:?}"),
+    }
+}
+
+#[test]
+fn interlaced_runs_roundtrip() {
+    // Long runs make the LZW decoder hold back pixels between the short row-sized reads
+    for (width, height) in [(3, 4), (25, 21), (1, 100)] {
+        let pixels = vec![1; usize::from(width) * usize::from(height)];
+        let mut encoder = Encoder::new(vec![], width, height, &[0; 3 * 23]).unwrap();
+        encoder.write_frame(&Frame { interlaced: true, ..Frame::from_indexed_pixels(width, height, pixels.clone(), None) }).unwrap();
+        let data = encoder.into_inner().unwrap();
+        let mut decoder = Decoder::new(&data[..]).unwrap();
+        assert_eq!(decoder.read_next_frame().unwrap().unwrap().buffer, pixels);
+    }
+}
+
+#[test]
+fn deinterlace_is_configurable() {
+    let rows: Vec<[u8; 5]> = (0..11).map(|row| [row; 5]).collect();
+    let frame = Frame::from_rows(&rows).unwrap();
+    let pixels = frame.buffer.to_vec();
+    let encode = |interlaced: bool| {
+        let mut encoder = Encoder::new(vec![], frame.width, frame.height, &[0; 3 * 16]).unwrap();
+        encoder.write_frame(&Frame { interlaced, ..frame.clone() }).unwrap();
+        encoder.into_inner().unwrap()
+    };
+    let decode = |data: &[u8], deinterlace: bool| {
+        let mut options = DecodeOptions::new();
+        options.deinterlace(deinterlace);
+        let mut decoder = options.read_info(data).unwrap();
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        (frame.interlaced, frame.buffer.to_vec())
+    };
+
+    let (interlaced, progressive) = (encode(true), encode(false));
+    assert_eq!(decode(&interlaced, true), (false, pixels.clone()));
+    assert_eq!(decode(&progressive, true), (false, pixels.clone()));
+    assert_eq!(decode(&progressive, false), (false, pixels.clone()));
+
+    let (still_interlaced, file_order) = decode(&interlaced, false);
+    assert!(still_interlaced);
+    let rows: Vec<u8> = file_order.chunks_exact(5).map(|row| row[0]).collect();
+    assert_eq!(rows, [0, 8, 4, 2, 6, 10, 1, 3, 5, 7, 9]);
+}
+
+#[test]
+fn sink_observers() {
+    use gif::{BlockKind, CompatProfile, Palette, Repeat, SinkObserver};
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::Hasher;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Observed {
+        hasher: DefaultHasher,
+        len: usize,
+        blocks: Vec<(BlockKind, usize)>,
+    }
+
+    struct Hashing(Arc<Mutex<Observed>>);
+
+    impl SinkObserver for Hashing {
+        fn on_bytes(&mut self, bytes: &[u8]) {
+            let mut observed = self.0.lock().unwrap();
+            observed.hasher.write(bytes);
+            observed.len += bytes.len();
+        }
+
+        fn on_block_boundary(&mut self, kind: BlockKind) {
+            let mut observed = self.0.lock().unwrap();
+            let len = observed.len;
+            observed.blocks.push((kind, len));
+        }
+    }
+
+    let observed = Arc::new(Mutex::new(Observed::default()));
+    let observers: Vec<Box<dyn SinkObserver>> = vec![Box::new(Hashing(observed.clone()))];
+    let palette = [0, 0, 0, 255, 255, 255];
+    let mut encoder = Encoder::with_sink_observers(vec![], 2, 2, Palette::from_rgb(&palette).unwrap(), CompatProfile::new(), observers).unwrap();
+    encoder.set_repeat(Repeat::Infinite).unwrap();
+    encoder.write_frame(&Frame { delay: 10, ..Frame::from_indexed_pixels(2, 2, vec![0, 1, 1, 0], None) }).unwrap();
+    let data = encoder.into_inner().unwrap();
+
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&data);
+    let observed = observed.lock().unwrap();
+    assert_eq!(observed.hasher.finish(), hasher.finish());
+    let kinds: Vec<_> = observed.blocks.iter().map(|&(kind, _)| kind).collect();
+    assert_eq!(kinds, [BlockKind::Header, BlockKind::Extension, BlockKind::Extension, BlockKind::Image, BlockKind::Trailer]);
+    // The header ends after the global palette, and the trailer at the end of the file
+    assert_eq!(observed.blocks[0].1, 13 + palette.len());
+    assert_eq!(observed.blocks[4].1, data.len());
+}
+
+#[test]
+#[cfg(panic = "unwind")]
+fn panicking_sink_observer() {
+    use gif::{CompatProfile, Palette, SinkObserver};
+
+    struct Panicking;
+
+    impl SinkObserver for Panicking {
+        fn on_bytes(&mut self, bytes: &[u8]) {
+            // The image descriptor
+            assert_ne!(bytes.first(), Some(&0x2C));
+        }
+    }
+
+    let observers: Vec<Box<dyn SinkObserver>> = vec![Box::new(Panicking)];
+    let mut encoder = Encoder::with_sink_observers(vec![], 2, 2, Palette::from_rgb(&[0; 6]).unwrap(), CompatProfile::new(), observers).unwrap();
+    let frame = Frame::from_indexed_pixels(2, 2, vec![0, 1, 1, 0], None);
+    assert!(matches!(encoder.write_frame(&frame), Err(gif::EncodingError::Io(_))));
+    // Everything fails afterwards
+    assert!(encoder.write_extension_blocks(AnyExtension(0xFE), [&b"comment"[..]]).is_err());
+    assert!(encoder.into_inner().is_err());
+}
+
+#[test]
+fn drop_ignores_write_errors() {
+    /// Accepts the first `left` bytes, and fails afterwards
+    struct Full {
+        left: usize,
+    }
+
+    impl std::io::Write for Full {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.left == 0 {
+                return Err(std::io::ErrorKind::WriteZero.into());
+            }
+            let len = buf.len().min(self.left);
+            self.left -= len;
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // The header fits, the trailer doesn't
+    let encoder = Encoder::new(Full { left: 13 + 6 }, 2, 2, &[0; 6]).unwrap();
+    drop(encoder);
+    let mut encoder = Encoder::new(Full { left: 13 + 6 }, 2, 2, &[0; 6]).unwrap();
+    assert!(encoder.write_frame(&Frame::from_indexed_pixels(2, 2, vec![0, 1, 1, 0], None)).is_err());
+    drop(encoder);
+}
+
+/// RGBA pixels of every frame, and the global palette
+fn rgba_frames(data: &[u8]) -> (Vec<Vec<u8>>, Vec<u8>) {
+    let mut options = DecodeOptions::new();
+    options.set_color_output(ColorOutput::RGBA);
+    let mut decoder = options.read_info(data).unwrap();
+    let mut frames = vec![];
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        frames.push(frame.buffer.to_vec());
+    }
+    (frames, decoder.global_palette().unwrap().to_vec())
+}
+
+#[test]
+fn replace_colors() {
+    use gif::edit::{replace_colors, EditError, ReplaceMode};
+
+    const ORANGE: [u8; 3] = [0xFF, 0x80, 0x00];
+    const BLUE: [u8; 3] = [0x00, 0x00, 0xFF];
+    let global = [0xFF, 0, 0, 0xFF, 0x80, 0x00, 0, 0, 0xFF, 0, 0, 0];
+    let encode = |transparent_blue: bool| {
+        let mut data = vec![];
+        let mut encoder = Encoder::new(&mut data, 4, 2, &global).unwrap();
+        encoder.write_frame(&Frame { interlaced: true, ..Frame::from_rows(&[[0, 1, 2, 1], [1, 1, 3, 3]]).unwrap() }).unwrap();
+        encoder.write_frame(&Frame::from_palette_pixels(2, 2, vec![0, 1, 1, 0], [ORANGE, [0xFF; 3]].concat(), None)).unwrap();
+        if transparent_blue {
+            encoder.write_frame(&Frame::from_indexed_pixels(2, 1, vec![1, 2], Some(2))).unwrap();
+        }
+        drop(encoder);
+        data
+    };
+    // Colors of opaque pixels that have `from`, changed to `to`
+    let expected = |frames: Vec<Vec<u8>>, to: [u8; 3]| -> Vec<Vec<u8>> {
+        frames.into_iter().map(|mut frame| {
+            for pixel in frame.chunks_exact_mut(4) {
+                if pixel[..3] == ORANGE && pixel[3] == 0xFF {
+                    pixel[..3].copy_from_slice(&to);
+                }
+            }
+            frame
+        }).collect()
+    };
+
+    // A color that isn't in any palette only changes the bytes of the palettes
+    let data = encode(false);
+    let new_orange = [0xF0, 0x70, 0x10];
+    let edited = replace_colors(&data, &[(ORANGE, new_orange)], ReplaceMode::PaletteOnly).unwrap();
+    assert_eq!(edited.len(), data.len());
+    assert_eq!(edited.iter().zip(&data).filter(|(a, b)| a != b).count(), 6);
+    assert_eq!(rgba_frames(&edited).0, expected(rgba_frames(&data).0, new_orange));
+
+    // Blue is in the global palette already
+    assert!(matches!(
+        replace_colors(&data, &[(ORANGE, BLUE)], ReplaceMode::PaletteOnly),
+        Err(EditError::RemapRequired { frame: None })
+    ));
+    let edited = replace_colors(&data, &[(ORANGE, BLUE)], ReplaceMode::Remap).unwrap();
+    let (frames, palette) = rgba_frames(&edited);
+    assert_eq!(frames, expected(rgba_frames(&data).0, BLUE));
+    // The unused entry keeps its color, so blue is in the palette once
+    assert_eq!(palette, global);
+
+    // Red and orange can't both become green without two green entries
+    const GREEN: [u8; 3] = [0x00, 0xFF, 0x00];
+    let both = [([0xFF, 0, 0], GREEN), (ORANGE, GREEN)];
+    assert!(matches!(
+        replace_colors(&data, &both, ReplaceMode::PaletteOnly),
+        Err(EditError::RemapRequired { frame: None })
+    ));
+    let edited = replace_colors(&data, &both, ReplaceMode::Remap).unwrap();
+    let (frames, palette) = rgba_frames(&edited);
+    let mut red_to_green = rgba_frames(&data).0;
+    for pixel in red_to_green.iter_mut().flat_map(|frame| frame.chunks_exact_mut(4)) {
+        if pixel == [0xFF, 0, 0, 0xFF] {
+            pixel[..3].copy_from_slice(&GREEN);
+        }
+    }
+    assert_eq!(frames, expected(red_to_green, GREEN));
+    assert_eq!(palette, [&GREEN[..], &global[3..]].concat());
+
+    // Pixels can't be moved to the transparent index, so the palette gets blue twice
+    let data = encode(true);
+    let edited = replace_colors(&data, &[(ORANGE, BLUE)], ReplaceMode::Remap).unwrap();
+    let (frames, palette) = rgba_frames(&edited);
+    assert_eq!(frames, expected(rgba_frames(&data).0, BLUE));
+    assert_eq!(palette[3..6], BLUE);
+
+    assert!(matches!(
+        replace_colors(&data, &[(ORANGE, BLUE), (ORANGE, new_orange)], ReplaceMode::Remap),
+        Err(EditError::ConflictingReplacements(ORANGE))
+    ));
+    // Replacing a color with itself changes nothing
+    assert_eq!(replace_colors(&data, &[(BLUE, BLUE)], ReplaceMode::PaletteOnly).unwrap(), data);
+}
+
+#[test]
+fn frame_rows_roundtrip() {
+    let rows = vec![vec![0, 1, 2], vec![3, 2, 1], vec![1, 1, 0], vec![3, 3, 3]];
+    let frame = Frame::from_rows(&rows).unwrap();
+    let mut encoder = Encoder::new(vec![], frame.width, frame.height, &[0; 3 * 4]).unwrap();
+    encoder.write_frame(&Frame { interlaced: true, ..frame }).unwrap();
+    let data = encoder.into_inner().unwrap();
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    let decoded = decoder.read_next_frame().unwrap().unwrap();
+    assert_eq!((decoded.width, decoded.height), (3, 4));
+    assert!(decoded.rows().eq(rows.iter().map(Vec::as_slice)));
+
+    let ragged = [&[0, 1][..], &[2]];
+    assert_eq!(Frame::from_rows(&ragged).unwrap_err(), gif::FrameError::RaggedRow { row: 1, len: 1, width: 2 });
+}
+
+#[test]
+fn promote_first_local_palette() {
+    use gif::{CompatProfile, Palette, Repeat};
+
+    const RED: [u8; 3] = [0xFF, 0, 0];
+    const GREEN: [u8; 3] = [0, 0xFF, 0];
+    const BLUE: [u8; 3] = [0, 0, 0xFF];
+    let mut profile = CompatProfile::new();
+    profile.promote_first_local_palette(true);
+    let mut encoder = Encoder::with_compat_profile(vec![], 2, 2, Palette::default(), profile.clone()).unwrap();
+    // Held back with the header
+    encoder.set_repeat(Repeat::Infinite).unwrap();
+    assert!(encoder.get_ref().is_empty());
+    encoder.write_frame(&Frame::from_palette_pixels(2, 2, vec![0, 1, 1, 0], [RED, GREEN].concat(), None)).unwrap();
+    // Needs a local palette, because blue isn't in the global one
+    encoder.write_frame(&Frame::from_palette_pixels(2, 2, vec![1, 0, 0, 1], [GREEN, BLUE].concat(), None)).unwrap();
+    // Red is the first color of the global palette
+    encoder.write_frame(&Frame::from_palette_pixels(2, 2, vec![0; 4], RED.to_vec(), None)).unwrap();
+    let data = encoder.into_inner().unwrap();
+
+    let decoder = Decoder::new(&data[..]).unwrap();
+    assert_eq!(decoder.global_palette(), Some(&[RED, GREEN].concat()[..]));
+    assert_eq!(decoder.repeat(), Repeat::Infinite);
+    let palettes: Vec<_> = decoder.into_iter().map(|frame| frame.unwrap().palette).collect();
+    assert_eq!(palettes, [None, Some([GREEN, BLUE].concat()), None]);
+
+    let (frames, _) = rgba_frames(&data);
+    let rgba = |colors: [[u8; 3]; 4]| -> Vec<u8> { colors.iter().flat_map(|&[r, g, b]| [r, g, b, 0xFF]).collect() };
+    assert_eq!(frames, [rgba([RED, GREEN, GREEN, RED]), rgba([BLUE, GREEN, GREEN, BLUE]), rgba([RED; 4])]);
+
+    // Without frames, the file has no global palette
+    let encoder = Encoder::with_compat_profile(vec![], 2, 2, Palette::default(), profile.clone()).unwrap();
+    let data = encoder.into_inner().unwrap();
+    assert_eq!(data, b"GIF89a\x02\x00\x02\x00\x00\x00\x00\x3B");
+
+    // A given global palette is kept
+    let mut encoder = Encoder::with_compat_profile(vec![], 2, 2, Palette::new(&[BLUE, GREEN]), profile).unwrap();
+    encoder.write_frame(&Frame::from_palette_pixels(2, 2, vec![0, 1, 1, 0], [RED, GREEN].concat(), None)).unwrap();
+    let data = encoder.into_inner().unwrap();
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    assert_eq!(decoder.global_palette(), Some(&[BLUE, GREEN].concat()[..]));
+    assert!(decoder.read_next_frame().unwrap().unwrap().palette.is_some());
+}
+
+#[test]
+fn uniform_frames_round_trip() {
+    // Across sub-blocks and changes of the code size
+    let sizes = [(1, 1), (1, 255), (17, 15), (64, 64), (300, 200), (1000, 1000)];
+    for index in [0, 3, 200] {
+        let palette: Vec<u8> = (0..=255).flat_map(|i| [i, i, i]).collect();
+        for (width, height) in sizes {
+            for interlaced in [false, true] {
+                let pixels = vec![index; usize::from(width) * usize::from(height)];
+                let frame = Frame { interlaced, ..Frame::from_indexed_pixels(width, height, pixels.clone(), None) };
+                let mut encoder = Encoder::new(vec![], width, height, &palette).unwrap();
+                encoder.write_frame(&frame).unwrap();
+                let data = encoder.into_inner().unwrap();
+
+                let mut options = DecodeOptions::new();
+                options.set_color_output(ColorOutput::Indexed);
+                options.check_lzw_end_code(true);
+                let mut decoder = options.read_info(&*data).unwrap();
+                let decoded = decoder.read_next_frame().unwrap().unwrap();
+                assert_eq!(decoded.buffer, pixels, "{width}x{height} of {index}");
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+#![cfg(feature = "std")]
+//! Encoding random frames, and decoding them again, must give back the same frames
+
+use gif::{CompatProfile, DecodeOptions, DisposalMethod, Encoder, Frame, Palette, PixelFormat};
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+/// Canvas and frames of a random image
+#[derive(Debug)]
+struct Image {
+    width: u16,
+    height: u16,
+    global_palette: Vec<u8>,
+    frames: Vec<Frame<'static>>,
+}
+
+fn palette(max_colors: usize) -> impl Strategy<Value = Vec<u8>> {
+    (1..=max_colors).prop_flat_map(|colors| vec(any::<u8>(), colors * 3))
+}
+
+fn disposal() -> impl Strategy<Value = DisposalMethod> {
+    prop_oneof![
+        Just(DisposalMethod::Any),
+        Just(DisposalMethod::Keep),
+        Just(DisposalMethod::Background),
+        Just(DisposalMethod::Previous),
+    ]
+}
+
+fn frame(canvas_width: u16, canvas_height: u16, global_colors: usize) -> impl Strategy<Value = Frame<'static>> {
+    (1..=canvas_width, 1..=canvas_height, proptest::option::of(palette(256)))
+        .prop_flat_map(move |(width, height, palette)| {
+            let colors = palette.as_ref().map_or(global_colors, |palette| palette.len() / 3);
+            let index = (0..colors).prop_map(|index| index as u8);
+            (
+                (0..=canvas_width - width, 0..=canvas_height - height),
+                vec(index.clone(), usize::from(width) * usize::from(height)),
+                proptest::option::of(index),
+                (disposal(), any::<u16>(), any::<bool>(), any::<bool>()),
+                Just((width, height, palette)),
+            )
+        })
+        .prop_map(|((left, top), pixels, transparent, (dispose, delay, needs_user_input, interlaced), (width, height, palette))| {
+            Frame {
+                left,
+                top,
+                width,
+                height,
+                palette,
+                shared_palette: false,
+                transparent,
+                dispose,
+                delay,
+                needs_user_input,
+                interlaced,
+                graphic_control: None,
+                descriptor_flags: 0,
+                buffer: pixels.into(),
+                pixel_format: PixelFormat::Indexed,
+            }
+        })
+}
+
+fn image() -> impl Strategy<Value = Image> {
+    (1..=128u16, 1..=128u16, palette(256))
+        .prop_flat_map(|(width, height, global_palette)| {
+            let frames = vec(frame(width, height, global_palette.len() / 3), 1..4);
+            (Just(width), Just(height), Just(global_palette), frames)
+        })
+        .prop_map(|(width, height, global_palette, frames)| Image { width, height, global_palette, frames })
+}
+
+/// The encoder pads palettes to a power of two
+fn assert_palette_prefix(decoded: &[u8], original: &[u8]) -> Result<(), TestCaseError> {
+    prop_assert!(decoded.len() >= original.len());
+    prop_assert_eq!(&decoded[..original.len()], original);
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(128))]
+
+    #[test]
+    fn encode_decode_roundtrip(image in image()) {
+        round_trip(&image, CompatProfile::new(), DecodeOptions::new())?;
+    }
+
+    #[test]
+    fn encode_decode_roundtrip_v2_defaults(image in image()) {
+        round_trip(&image, CompatProfile::v2_defaults(), DecodeOptions::v2_defaults())?;
+    }
+}
+
+/// Encodes `image` with `profile`, and checks that decoding it with `options` gives back its frames
+fn round_trip(image: &Image, profile: CompatProfile, options: DecodeOptions) -> Result<(), TestCaseError> {
+    let palette = Palette::from_rgb(&image.global_palette).unwrap();
+    let mut encoder = Encoder::with_compat_profile(vec![], image.width, image.height, palette, profile).unwrap();
+    encoder.check_frame_consistency(true);
+    for frame in &image.frames {
+        encoder.write_frame(frame).unwrap();
+    }
+    let data = encoder.into_inner().unwrap();
+
+    let mut decoder = options.read_info(&data[..]).unwrap();
+    prop_assert_eq!((decoder.width(), decoder.height()), (image.width, image.height));
+    assert_palette_prefix(decoder.global_palette().unwrap(), &image.global_palette)?;
+    for original in &image.frames {
+        let decoded = decoder.read_next_frame().unwrap().unwrap();
+        prop_assert_eq!(&decoded.buffer, &original.buffer);
+        prop_assert_eq!((decoded.left, decoded.top), (original.left, original.top));
+        prop_assert_eq!((decoded.width, decoded.height), (original.width, original.height));
+        prop_assert_eq!(decoded.delay, original.delay);
+        prop_assert_eq!(decoded.dispose, original.dispose);
+        prop_assert_eq!(decoded.transparent, original.transparent);
+        prop_assert_eq!(decoded.needs_user_input, original.needs_user_input);
+        match (&decoded.palette, &original.palette) {
+            (Some(decoded), Some(original)) => assert_palette_prefix(decoded, original)?,
+            (None, None) => {},
+            (decoded, original) => prop_assert!(false, "palette {:?} decoded as {:?}", original, decoded),
+        }
+    }
+    prop_assert!(decoder.read_next_frame().unwrap().is_none());
+    Ok(())
+}
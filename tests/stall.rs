@@ -1,6 +1,6 @@
 #![cfg(feature = "std")]
 
-use std::{fs, sync::mpsc, thread, time::Duration, io};
+use std::{cell::Cell, fs, rc::Rc, sync::mpsc, thread, time::Duration, io};
 
 #[test]
 fn try_decode_crash_regression() {
@@ -23,24 +23,115 @@ fn decode_on_timer(data: Vec<u8>) {
     let (send, recv) = mpsc::channel();
 
     thread::spawn(move || {
-        let result = decode(&data);
-        send.send(result).expect("still waiting");
+        decode(&data);
+        send.send(()).expect("still waiting");
     });
 
     let _ = recv.recv_timeout(Duration::from_secs(1))
         .expect("any result");
 }
 
-fn decode(data: &[u8]) -> Result<(), gif::DecodingError> {
+fn decode(data: &[u8]) {
     let mut options = gif::DecodeOptions::new();
     options.set_color_output(gif::ColorOutput::RGBA);
+    let _ = decode_with(options.clone(), data);
 
+    options.set_frame_consistency(gif::FrameConsistency::Clamp);
+    let _ = decode_with(options.clone(), data);
+    for chunk_len in [1, 7, data.len()] {
+        update_loop(&options, data, chunk_len);
+    }
+}
+
+fn decode_with(options: gif::DecodeOptions, data: &[u8]) -> Result<(), gif::DecodingError> {
     let mut decoder = options.read_info(data)?;
     while let Some(_frame) = decoder.read_next_frame()? {}
 
     Ok(())
 }
 
+/// Feeds the input to `update` like a naive caller, which only stops at the end, and checks that
+/// every call makes progress
+fn update_loop(options: &gif::DecodeOptions, data: &[u8], chunk_len: usize) {
+    use gif::streaming_decoder::{Decoded, OutputBuffer, StreamingDecoder};
+
+    let mut decoder = StreamingDecoder::with_options(options);
+    let mut buf = vec![0; 64];
+    let mut input = data;
+    while !input.is_empty() {
+        let chunk = &input[..input.len().min(chunk_len)];
+        match decoder.update(chunk, &mut OutputBuffer::Slice(&mut buf)) {
+            Ok((_, Decoded::Finished)) | Err(_) => return,
+            Ok((consumed, decoded)) => {
+                assert!(consumed > 0 || !matches!(decoded, Decoded::Nothing), "no progress at {} bytes before the end", input.len());
+                input = &input[consumed..];
+            },
+        }
+    }
+}
+
+/// The decoder checks that its work is linear in the input and the declared pixels in builds
+/// with debug assertions or the `work_bound` feature, so this only has to run it over every kind
+/// of file
+#[test]
+fn work_is_linear() {
+    use gif::synth::pathological as files;
+
+    let mut inputs: Vec<Vec<u8>> = Vec::new();
+    for dir in ["samples", "crashtest", "stall", "malformed"] {
+        let entries = fs::read_dir(format!("{}/tests/{dir}", env!("CARGO_MANIFEST_DIR"))).unwrap();
+        for entry in entries {
+            inputs.push(fs::read(entry.unwrap().path()).unwrap());
+        }
+    }
+    let pathological: [fn() -> Vec<u8>; 33] = [
+        files::well_formed, files::malformed_signature, files::unsupported_version,
+        files::version_87a_with_extension, files::truncated_global_palette, files::background_outside_palette,
+        files::no_frames, files::missing_color_table, files::oversized_graphic_control,
+        files::graphic_control_with_extra_sub_block, files::reserved_disposal, files::transparent_outside_palette,
+        files::repeated_graphic_control_transparent_first, files::repeated_graphic_control_transparent_last,
+        files::unrecognized_loop_identifier, files::unknown_loop_sub_block, files::malformed_loop_count,
+        files::conflicting_loop_counts, files::unknown_extension, files::unknown_block,
+        files::frame_out_of_bounds, files::index_outside_palette, files::zero_width_frame,
+        files::zero_height_frame, files::invalid_min_code_size, files::invalid_lzw_code,
+        files::missing_end_code, files::truncated_local_palette, files::truncated_frame_data,
+        files::missing_block_terminator, files::missing_trailer, files::stray_trailer,
+        files::data_after_trailer,
+    ];
+    inputs.extend(pathological.iter().map(|file| file()));
+
+    let mut lenient = gif::DecodeOptions::new();
+    lenient.set_frame_consistency(gif::FrameConsistency::Clamp);
+    lenient.allow_unknown_blocks(true);
+    lenient.allow_unknown_extensions(true);
+    lenient.allow_truncated(true);
+    lenient.continue_past_trailer(true);
+    lenient.allow_missing_block_terminator(true);
+    lenient.allow_oversized_control_extension(true);
+    let mut skipping = gif::DecodeOptions::new();
+    skipping.skip_frame_decoding(true);
+
+    for data in &inputs {
+        for options in [gif::DecodeOptions::new(), lenient.clone(), skipping.clone()] {
+            let _ = decode_with(options.clone(), data);
+            for chunk_len in [1, 7, data.len()] {
+                update_loop(&options, data, chunk_len);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "work_bound")]
+#[test]
+#[should_panic(expected = "steps for")]
+fn work_bound_is_checked() {
+    use gif::streaming_decoder::{OutputBuffer, StreamingDecoder};
+
+    let mut decoder = StreamingDecoder::new();
+    decoder.set_work_bound(0);
+    let _ = decoder.update(include_bytes!("samples/sample_1.gif"), &mut OutputBuffer::None);
+}
+
 #[test]
 fn test_truncated_file() {
     let data = include_bytes!("../tests/samples/anim-gr.gif");
@@ -110,3 +201,255 @@ impl io::Read for ChoppedReader<'_> {
         Ok(n)
     }
 }
+
+/// Returns `WouldBlock` before every chunk, once the header has been read
+struct NonBlockingReader<'a> {
+    chunk_len: usize,
+    data: &'a [u8],
+    header_len: usize,
+    blocked: bool,
+}
+
+impl io::Read for NonBlockingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.header_len > 0 {
+            let n = self.data.len().min(buf.len()).min(self.header_len);
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            self.header_len -= n;
+            return Ok(n);
+        }
+        self.blocked = !self.blocked;
+        if self.blocked {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let n = self.data.len().min(buf.len()).min(self.chunk_len);
+        buf[..n].copy_from_slice(&self.data[..n]);
+        self.data = &self.data[n..];
+        Ok(n)
+    }
+}
+
+fn is_would_block(err: &gif::DecodingError) -> bool {
+    matches!(err, gif::DecodingError::Io(err) if err.kind() == io::ErrorKind::WouldBlock)
+}
+
+#[track_caller]
+fn decode_would_block(data: &[u8], chunk_len: usize, mut options: gif::DecodeOptions) {
+    let expected: Vec<_> = options.clone().read_info(data).unwrap()
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+
+    // The header can't be retried
+    let header_len = 1000;
+    let mut r = NonBlockingReader { chunk_len, data, header_len, blocked: false };
+    let mut decoder = options.clone().read_info(&mut r).unwrap();
+    let mut frames = Vec::new();
+    loop {
+        match decoder.read_next_frame() {
+            Ok(Some(frame)) => frames.push(frame.clone()),
+            Ok(None) => break,
+            Err(err) => assert!(is_would_block(&err), "{err}"),
+        }
+    }
+    assert_eq!(frames.len(), expected.len());
+    for (frame, expected) in frames.iter().zip(&expected) {
+        assert_eq!(frame.buffer, expected.buffer);
+    }
+
+    // The iterator can be retried as well
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut r = NonBlockingReader { chunk_len, data, header_len, blocked: true };
+    let mut iter = options.read_info(&mut r).unwrap().into_iter();
+    let mut num_frames = 0;
+    while let Some(frame) = iter.next() {
+        match frame {
+            Ok(_) => num_frames += 1,
+            Err(err) => assert!(is_would_block(&err), "{err}"),
+        }
+    }
+    assert_eq!(num_frames, expected.len());
+}
+
+#[test]
+fn resume_after_would_block() {
+    let mut options = gif::DecodeOptions::new();
+    for &chunk_len in &[1, 7, 100] {
+        decode_would_block(include_bytes!("../tests/samples/moon_impact.gif"), chunk_len, options.clone());
+        decode_would_block(include_bytes!("../tests/samples/interlaced.gif"), chunk_len, options.clone());
+    }
+
+    options.set_color_output(gif::ColorOutput::RGBA);
+    decode_would_block(include_bytes!("../tests/samples/interlaced.gif"), 5, options.clone());
+
+    options.skip_frame_decoding(true);
+    decode_would_block(include_bytes!("../tests/samples/moon_impact.gif"), 3, options);
+}
+
+/// Returns `WouldBlock` after `limit` bytes, and panics if it's read after `closed` has been set
+struct ClosingReader<'a> {
+    data: &'a [u8],
+    limit: usize,
+    closed: Rc<Cell<bool>>,
+}
+
+impl io::BufRead for ClosingReader<'_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        assert!(!self.closed.get(), "read after the decoder has been dropped");
+        if self.limit == 0 && !self.data.is_empty() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        Ok(&self.data[..self.data.len().min(self.limit)])
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.data = &self.data[n..];
+        self.limit -= n;
+    }
+}
+
+impl io::Read for ClosingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = io::BufRead::fill_buf(self)?;
+        let n = data.len().min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        io::BufRead::consume(self, n);
+        Ok(n)
+    }
+}
+
+#[test]
+fn dropping_decoder_does_not_read() {
+    let data = include_bytes!("../tests/samples/anim-gr.gif");
+    // Every point in the header, the global palette, the frames, and after the trailer
+    for limit in 0..=data.len() {
+        for buffered in [false, true] {
+            let closed = Rc::new(Cell::new(false));
+            let r = ClosingReader { data, limit, closed: closed.clone() };
+            let options = gif::DecodeOptions::new();
+            let decoder = if buffered { options.read_info_buffered(r) } else { options.read_info(r) };
+            match decoder {
+                Ok(mut decoder) => {
+                    while let Ok(Some(_)) = decoder.read_next_frame() {}
+                    closed.set(true);
+                    drop(decoder);
+                },
+                Err(err) => {
+                    closed.set(true);
+                    drop(err);
+                },
+            }
+        }
+    }
+}
+
+/// Returns one byte per read, like a pipe, and panics instead of blocking once the file has been read
+struct LiveStream<'a> {
+    data: &'a [u8],
+}
+
+impl io::Read for LiveStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        assert!(!self.data.is_empty(), "read past the end of the file, which would block");
+        buf[0] = self.data[0];
+        self.data = &self.data[1..];
+        Ok(1)
+    }
+}
+
+#[test]
+fn small_reads_do_not_read_past_the_trailer() {
+    for data in [
+        &include_bytes!("../tests/samples/moon_impact.gif")[..],
+        &include_bytes!("../tests/samples/interlaced.gif")[..],
+    ] {
+        let expected: Vec<_> = gif::DecodeOptions::new().read_info(data).unwrap()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        let frames: Vec<_> = gif::DecodeOptions::new().read_info(LiveStream { data }).unwrap()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(frames.len(), expected.len());
+        for (frame, expected) in frames.iter().zip(&expected) {
+            assert_eq!(frame.buffer, expected.buffer);
+        }
+    }
+}
+
+/// Fails with `error` once `cancel_after` bytes have been read after it has been set, like a
+/// reader that is cancelled from another thread
+struct CancellableReader<'a> {
+    data: &'a [u8],
+    error: io::ErrorKind,
+    cancel_after: Rc<Cell<Option<usize>>>,
+}
+
+impl io::BufRead for CancellableReader<'_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let left = self.cancel_after.get();
+        if left == Some(0) {
+            self.cancel_after.set(None);
+            return Err(self.error.into());
+        }
+        Ok(&self.data[..self.data.len().min(left.unwrap_or(64)).min(64)])
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.data = &self.data[n..];
+        self.cancel_after.set(self.cancel_after.get().map(|left| left - n));
+    }
+}
+
+impl io::Read for CancellableReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = io::BufRead::fill_buf(self)?;
+        let n = data.len().min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        io::BufRead::consume(self, n);
+        Ok(n)
+    }
+}
+
+#[test]
+fn aborted_frames_leave_no_pixels_behind() {
+    let data = include_bytes!("../tests/samples/moon_impact.gif");
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let expected: Vec<_> = options.clone().read_info(&data[..]).unwrap()
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+
+    for error in [io::ErrorKind::Other, io::ErrorKind::WouldBlock] {
+        let cancel_after = Rc::new(Cell::new(None));
+        let r = CancellableReader { data, error, cancel_after: cancel_after.clone() };
+        let mut decoder = options.clone().read_info_buffered(r).unwrap();
+        assert_eq!(decoder.read_next_frame().unwrap().unwrap().buffer, expected[0].buffer);
+
+        // Fail in the middle of the data of the second frame
+        decoder.next_frame_info().unwrap().unwrap();
+        cancel_after.set(Some(200));
+        match decoder.read_next_frame() {
+            Err(gif::DecodingError::Io(err)) => assert_eq!(err.kind(), error),
+            other => panic!("{other:?}"),
+        }
+        if error == io::ErrorKind::WouldBlock {
+            // Reading the rest of the frame into a buffer of its full size leaves the buffer short
+            let mut buf = vec![0; decoder.buffer_size()];
+            assert!(decoder.read_into_buffer(&mut buf).is_err());
+        }
+
+        // Decoding continues with the third frame, without any pixels of the second one
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push(frame.clone());
+        }
+        assert_eq!(frames.len(), expected.len() - 2);
+        for (frame, expected) in frames.iter().zip(&expected[2..]) {
+            assert_eq!(frame.buffer, expected.buffer);
+        }
+    }
+}
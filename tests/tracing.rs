@@ -0,0 +1,103 @@
+#![cfg(feature = "tracing")]
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use gif::{Decoder, Encoder, Frame, Repeat};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// Records every event as `span: message field=value ...`
+#[derive(Clone, Default)]
+struct Capture {
+    spans: Arc<Mutex<Vec<&'static str>>>,
+    entered: Arc<Mutex<Vec<Id>>>,
+    events: Arc<Mutex<Vec<String>>>,
+}
+
+#[derive(Default)]
+struct Fields {
+    message: String,
+    fields: String,
+}
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        } else {
+            self.fields += &format!(" {}={value:?}", field.name());
+        }
+    }
+}
+
+impl Subscriber for Capture {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut spans = self.spans.lock().unwrap();
+        spans.push(span.metadata().name());
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        if *event.metadata().level() == tracing::Level::TRACE {
+            return;
+        }
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let span = match self.entered.lock().unwrap().last() {
+            Some(id) => self.spans.lock().unwrap()[id.into_u64() as usize - 1],
+            None => "",
+        };
+        self.events.lock().unwrap().push(format!("{span}: {}{}", fields.message, fields.fields));
+    }
+
+    fn enter(&self, span: &Id) {
+        self.entered.lock().unwrap().push(span.clone());
+    }
+
+    fn exit(&self, _: &Id) {
+        self.entered.lock().unwrap().pop();
+    }
+}
+
+fn two_frames() -> Vec<u8> {
+    let mut encoder = Encoder::new(vec![], 2, 2, &[0, 0, 0, 0xFF, 0xFF, 0xFF]).unwrap();
+    encoder.set_repeat(Repeat::Infinite).unwrap();
+    encoder.write_frame(&Frame::from_indexed_pixels(2, 2, vec![0, 1, 1, 0], None)).unwrap();
+    encoder.write_frame(&Frame { palette: Some(vec![0; 3 * 4]), ..Frame::from_indexed_pixels_at(1, 1, 1, 1, vec![3], None) }).unwrap();
+    encoder.into_inner().unwrap()
+}
+
+#[test]
+fn events_of_two_frames() {
+    let capture = Capture::default();
+    let data = tracing::subscriber::with_default(capture.clone(), two_frames);
+    let written = std::mem::take(&mut *capture.events.lock().unwrap());
+    assert_eq!(written.len(), 2);
+    assert!(written.iter().all(|event| event.starts_with("frame: image data written size=")), "{written:?}");
+
+    tracing::subscriber::with_default(capture.clone(), || {
+        let mut decoder = Decoder::new(&data[..]).unwrap();
+        while decoder.read_next_frame().unwrap().is_some() {}
+    });
+    let events = capture.events.lock().unwrap();
+    assert_eq!(*events, [
+        ": global palette colors=2",
+        ": extension end=37 label=255 size=15",
+        ": extension end=46 label=249 size=6",
+        ": frame index=0 offset=58 left=0 top=0 width=2 height=2 interlaced=false",
+        "frame: frame decoded size=4",
+        ": extension end=70 label=249 size=6",
+        ": frame index=1 offset=94 left=1 top=1 width=1 height=1 interlaced=false local_palette_colors=4",
+        "frame: frame decoded size=1",
+    ]);
+}